@@ -0,0 +1,28 @@
+//! Board drawing without the cache: `cargo bench --bench render`.
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use kamachess::game::{draw_board_image, CoordinateStyle, Position, RenderOptions};
+use std::str::FromStr;
+
+const MIDDLEGAME: &str = "r1bq1rk1/pp2bppp/2n1pn2/3p4/2PP4/2N1PN2/PP3PPP/R2QKB1R w KQ - 0 9";
+
+fn bench_draw_board(c: &mut Criterion) {
+    let start = Position::default();
+    let middlegame = Position::from_str(MIDDLEGAME).unwrap();
+    let outside = RenderOptions::default();
+    let inside = RenderOptions {
+        flip_board: true,
+        coordinates: CoordinateStyle::Inside,
+        ..RenderOptions::default()
+    };
+
+    c.bench_function("draw_board/start", |b| {
+        b.iter(|| draw_board_image(black_box(&start), &outside))
+    });
+    c.bench_function("draw_board/middlegame_flipped_inside", |b| {
+        b.iter(|| draw_board_image(black_box(&middlegame), &inside))
+    });
+}
+
+criterion_group!(benches, bench_draw_board);
+criterion_main!(benches);
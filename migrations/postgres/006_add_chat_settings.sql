@@ -0,0 +1,4 @@
+CREATE TABLE IF NOT EXISTS chat_settings (
+    chat_id BIGINT PRIMARY KEY,
+    coordinates TEXT NOT NULL DEFAULT 'outside'
+);
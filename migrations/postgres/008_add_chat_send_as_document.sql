@@ -0,0 +1 @@
+ALTER TABLE chat_settings ADD COLUMN send_as_document BIGINT NOT NULL DEFAULT 0;
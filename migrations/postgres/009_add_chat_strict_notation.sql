@@ -0,0 +1 @@
+ALTER TABLE chat_settings ADD COLUMN strict_notation BIGINT NOT NULL DEFAULT 0;
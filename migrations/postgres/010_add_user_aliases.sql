@@ -0,0 +1,9 @@
+CREATE TABLE IF NOT EXISTS user_aliases (
+    id BIGSERIAL PRIMARY KEY,
+    user_id BIGINT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    username TEXT NOT NULL,
+    changed_at TEXT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_user_aliases_username
+    ON user_aliases(username);
@@ -0,0 +1,12 @@
+CREATE TABLE IF NOT EXISTS seeks (
+    id BIGSERIAL PRIMARY KEY,
+    chat_id BIGINT NOT NULL,
+    user_id BIGINT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    message_id BIGINT,
+    confirm_moves BIGINT NOT NULL DEFAULT 0,
+    created_at TEXT NOT NULL,
+    expires_at TEXT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_seeks_expires_at
+    ON seeks(expires_at);
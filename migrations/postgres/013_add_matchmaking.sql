@@ -0,0 +1,7 @@
+ALTER TABLE games ADD COLUMN IF NOT EXISTS peer_chat_id BIGINT;
+ALTER TABLE game_messages ADD COLUMN IF NOT EXISTS chat_id BIGINT;
+CREATE TABLE IF NOT EXISTS match_queue (
+    user_id BIGINT PRIMARY KEY REFERENCES users(id) ON DELETE CASCADE,
+    chat_id BIGINT NOT NULL,
+    queued_at TEXT NOT NULL
+);
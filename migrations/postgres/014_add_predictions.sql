@@ -0,0 +1,22 @@
+ALTER TABLE chat_settings ADD COLUMN IF NOT EXISTS prediction_polls BIGINT NOT NULL DEFAULT 0;
+CREATE TABLE IF NOT EXISTS prediction_polls (
+    poll_id TEXT PRIMARY KEY,
+    game_id BIGINT NOT NULL REFERENCES games(id) ON DELETE CASCADE,
+    chat_id BIGINT NOT NULL,
+    message_id BIGINT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS idx_prediction_polls_game_id
+    ON prediction_polls(game_id);
+CREATE TABLE IF NOT EXISTS predictions (
+    poll_id TEXT NOT NULL REFERENCES prediction_polls(poll_id) ON DELETE CASCADE,
+    user_id BIGINT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    choice BIGINT NOT NULL,
+    PRIMARY KEY(poll_id, user_id)
+);
+CREATE TABLE IF NOT EXISTS prediction_stats (
+    chat_id BIGINT NOT NULL,
+    user_id BIGINT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    correct BIGINT NOT NULL DEFAULT 0,
+    total BIGINT NOT NULL DEFAULT 0,
+    PRIMARY KEY(chat_id, user_id)
+);
@@ -0,0 +1,18 @@
+CREATE TABLE IF NOT EXISTS audit_log (
+    id BIGSERIAL PRIMARY KEY,
+    telegram_id BIGINT,
+    chat_id BIGINT NOT NULL,
+    game_id BIGINT,
+    command TEXT NOT NULL,
+    outcome TEXT NOT NULL,
+    created_at TEXT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_audit_log_chat_id
+    ON audit_log(chat_id, id);
+
+CREATE INDEX IF NOT EXISTS idx_audit_log_game_id
+    ON audit_log(game_id);
+
+CREATE INDEX IF NOT EXISTS idx_audit_log_telegram_id
+    ON audit_log(telegram_id, id);
@@ -0,0 +1,2 @@
+ALTER TABLE games ADD COLUMN IF NOT EXISTS left_player_id BIGINT REFERENCES users(id);
+ALTER TABLE games ADD COLUMN IF NOT EXISTS left_at TEXT;
@@ -0,0 +1 @@
+ALTER TABLE chat_settings ADD COLUMN IF NOT EXISTS tap_moves BIGINT NOT NULL DEFAULT 0;
@@ -0,0 +1,39 @@
+-- Finished games moved out of games/moves by the archive job. The columns mirror
+-- games and moves; a migration adding a column to those tables adds it here too.
+CREATE TABLE IF NOT EXISTS games_archive (
+    id BIGINT PRIMARY KEY,
+    chat_id BIGINT NOT NULL,
+    white_user_id BIGINT NOT NULL,
+    black_user_id BIGINT NOT NULL,
+    current_fen TEXT NOT NULL,
+    turn TEXT NOT NULL,
+    status TEXT NOT NULL,
+    result TEXT,
+    started_at TEXT NOT NULL,
+    ended_at TEXT,
+    last_message_id BIGINT,
+    draw_proposed_by BIGINT,
+    draw_proposal_message_id BIGINT,
+    confirm_moves BIGINT NOT NULL DEFAULT 0,
+    pending_move TEXT,
+    pending_move_message_id BIGINT,
+    peer_chat_id BIGINT,
+    left_player_id BIGINT,
+    left_at TEXT
+);
+
+CREATE TABLE IF NOT EXISTS moves_archive (
+    id BIGINT PRIMARY KEY,
+    game_id BIGINT NOT NULL,
+    move_number BIGINT NOT NULL,
+    uci TEXT NOT NULL,
+    san TEXT,
+    played_by BIGINT NOT NULL,
+    played_at TEXT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_games_archive_chat_players
+    ON games_archive(chat_id, white_user_id, black_user_id);
+
+CREATE INDEX IF NOT EXISTS idx_moves_archive_game_number
+    ON moves_archive(game_id, move_number);
@@ -0,0 +1 @@
+ALTER TABLE chat_settings ADD COLUMN IF NOT EXISTS pgn_files BIGINT NOT NULL DEFAULT 0;
@@ -0,0 +1,5 @@
+CREATE TABLE IF NOT EXISTS chat_champions (
+    chat_id BIGINT PRIMARY KEY,
+    user_id BIGINT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    since TEXT NOT NULL
+);
@@ -0,0 +1,2 @@
+ALTER TABLE chat_settings ADD COLUMN IF NOT EXISTS standings_min_games BIGINT;
+ALTER TABLE chat_settings ADD COLUMN IF NOT EXISTS standings_inactive_days BIGINT;
@@ -0,0 +1,2 @@
+ALTER TABLE moves ADD COLUMN IF NOT EXISTS comment TEXT;
+ALTER TABLE moves_archive ADD COLUMN IF NOT EXISTS comment TEXT;
@@ -0,0 +1,5 @@
+ALTER TABLE chat_settings ADD COLUMN IF NOT EXISTS draw_offer_gap BIGINT;
+ALTER TABLE games ADD COLUMN IF NOT EXISTS white_draw_offer_ply BIGINT;
+ALTER TABLE games ADD COLUMN IF NOT EXISTS black_draw_offer_ply BIGINT;
+ALTER TABLE games_archive ADD COLUMN IF NOT EXISTS white_draw_offer_ply BIGINT;
+ALTER TABLE games_archive ADD COLUMN IF NOT EXISTS black_draw_offer_ply BIGINT;
@@ -0,0 +1,4 @@
+ALTER TABLE games ADD COLUMN IF NOT EXISTS paused BIGINT NOT NULL DEFAULT 0;
+ALTER TABLE games ADD COLUMN IF NOT EXISTS pause_requested_by BIGINT;
+ALTER TABLE games_archive ADD COLUMN IF NOT EXISTS paused BIGINT NOT NULL DEFAULT 0;
+ALTER TABLE games_archive ADD COLUMN IF NOT EXISTS pause_requested_by BIGINT;
@@ -0,0 +1,3 @@
+ALTER TABLE games ADD COLUMN confirm_moves INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE games ADD COLUMN pending_move TEXT;
+ALTER TABLE games ADD COLUMN pending_move_message_id INTEGER;
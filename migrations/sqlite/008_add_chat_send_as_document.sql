@@ -0,0 +1 @@
+ALTER TABLE chat_settings ADD COLUMN send_as_document INTEGER NOT NULL DEFAULT 0;
@@ -0,0 +1 @@
+ALTER TABLE chat_settings ADD COLUMN strict_notation INTEGER NOT NULL DEFAULT 0;
@@ -0,0 +1,10 @@
+CREATE TABLE IF NOT EXISTS user_aliases (
+    id INTEGER PRIMARY KEY,
+    user_id INTEGER NOT NULL,
+    username TEXT NOT NULL,
+    changed_at TEXT NOT NULL,
+    FOREIGN KEY(user_id) REFERENCES users(id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_user_aliases_username
+    ON user_aliases(username);
@@ -0,0 +1,2 @@
+ALTER TABLE chat_settings ADD COLUMN win_template TEXT;
+ALTER TABLE chat_settings ADD COLUMN draw_template TEXT;
@@ -0,0 +1,8 @@
+ALTER TABLE games ADD COLUMN peer_chat_id INTEGER;
+ALTER TABLE game_messages ADD COLUMN chat_id INTEGER;
+CREATE TABLE IF NOT EXISTS match_queue (
+    user_id INTEGER PRIMARY KEY,
+    chat_id INTEGER NOT NULL,
+    queued_at TEXT NOT NULL,
+    FOREIGN KEY(user_id) REFERENCES users(id) ON DELETE CASCADE
+);
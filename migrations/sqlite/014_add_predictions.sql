@@ -0,0 +1,26 @@
+ALTER TABLE chat_settings ADD COLUMN prediction_polls INTEGER NOT NULL DEFAULT 0;
+CREATE TABLE IF NOT EXISTS prediction_polls (
+    poll_id TEXT PRIMARY KEY,
+    game_id INTEGER NOT NULL,
+    chat_id INTEGER NOT NULL,
+    message_id INTEGER NOT NULL,
+    FOREIGN KEY(game_id) REFERENCES games(id) ON DELETE CASCADE
+);
+CREATE INDEX IF NOT EXISTS idx_prediction_polls_game_id
+    ON prediction_polls(game_id);
+CREATE TABLE IF NOT EXISTS predictions (
+    poll_id TEXT NOT NULL,
+    user_id INTEGER NOT NULL,
+    choice INTEGER NOT NULL,
+    PRIMARY KEY(poll_id, user_id),
+    FOREIGN KEY(poll_id) REFERENCES prediction_polls(poll_id) ON DELETE CASCADE,
+    FOREIGN KEY(user_id) REFERENCES users(id) ON DELETE CASCADE
+);
+CREATE TABLE IF NOT EXISTS prediction_stats (
+    chat_id INTEGER NOT NULL,
+    user_id INTEGER NOT NULL,
+    correct INTEGER NOT NULL DEFAULT 0,
+    total INTEGER NOT NULL DEFAULT 0,
+    PRIMARY KEY(chat_id, user_id),
+    FOREIGN KEY(user_id) REFERENCES users(id) ON DELETE CASCADE
+);
@@ -0,0 +1 @@
+ALTER TABLE chat_settings ADD COLUMN announcements INTEGER NOT NULL DEFAULT 1;
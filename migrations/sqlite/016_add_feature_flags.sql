@@ -0,0 +1,7 @@
+CREATE TABLE IF NOT EXISTS feature_flags (
+    name TEXT NOT NULL,
+    chat_id INTEGER NOT NULL DEFAULT 0,
+    enabled INTEGER NOT NULL,
+    updated_at TEXT NOT NULL,
+    PRIMARY KEY(name, chat_id)
+);
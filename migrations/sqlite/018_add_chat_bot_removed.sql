@@ -0,0 +1 @@
+ALTER TABLE chat_settings ADD COLUMN bot_removed INTEGER NOT NULL DEFAULT 0;
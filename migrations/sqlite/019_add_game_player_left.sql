@@ -0,0 +1,2 @@
+ALTER TABLE games ADD COLUMN left_player_id INTEGER REFERENCES users(id);
+ALTER TABLE games ADD COLUMN left_at TEXT;
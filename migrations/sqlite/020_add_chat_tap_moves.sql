@@ -0,0 +1 @@
+ALTER TABLE chat_settings ADD COLUMN tap_moves INTEGER NOT NULL DEFAULT 0;
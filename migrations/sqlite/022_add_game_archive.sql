@@ -0,0 +1,39 @@
+-- Finished games moved out of games/moves by the archive job. The columns mirror
+-- games and moves; a migration adding a column to those tables adds it here too.
+CREATE TABLE IF NOT EXISTS games_archive (
+    id INTEGER PRIMARY KEY,
+    chat_id INTEGER NOT NULL,
+    white_user_id INTEGER NOT NULL,
+    black_user_id INTEGER NOT NULL,
+    current_fen TEXT NOT NULL,
+    turn TEXT NOT NULL,
+    status TEXT NOT NULL,
+    result TEXT,
+    started_at TEXT NOT NULL,
+    ended_at TEXT,
+    last_message_id INTEGER,
+    draw_proposed_by INTEGER,
+    draw_proposal_message_id INTEGER,
+    confirm_moves INTEGER NOT NULL DEFAULT 0,
+    pending_move TEXT,
+    pending_move_message_id INTEGER,
+    peer_chat_id INTEGER,
+    left_player_id INTEGER,
+    left_at TEXT
+);
+
+CREATE TABLE IF NOT EXISTS moves_archive (
+    id INTEGER PRIMARY KEY,
+    game_id INTEGER NOT NULL,
+    move_number INTEGER NOT NULL,
+    uci TEXT NOT NULL,
+    san TEXT,
+    played_by INTEGER NOT NULL,
+    played_at TEXT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_games_archive_chat_players
+    ON games_archive(chat_id, white_user_id, black_user_id);
+
+CREATE INDEX IF NOT EXISTS idx_moves_archive_game_number
+    ON moves_archive(game_id, move_number);
@@ -0,0 +1,10 @@
+-- Lookups of a game by one of its board messages. games(chat_id, status),
+-- moves(game_id, move_number) and users(username) are already indexed by 001_init.
+CREATE INDEX IF NOT EXISTS idx_games_last_message
+    ON games(last_message_id);
+
+CREATE INDEX IF NOT EXISTS idx_games_peer_chat
+    ON games(peer_chat_id);
+
+CREATE INDEX IF NOT EXISTS idx_game_messages_message_id
+    ON game_messages(message_id);
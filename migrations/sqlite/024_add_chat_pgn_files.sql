@@ -0,0 +1 @@
+ALTER TABLE chat_settings ADD COLUMN pgn_files INTEGER NOT NULL DEFAULT 0;
@@ -0,0 +1,6 @@
+CREATE TABLE IF NOT EXISTS chat_champions (
+    chat_id INTEGER PRIMARY KEY,
+    user_id INTEGER NOT NULL,
+    since TEXT NOT NULL,
+    FOREIGN KEY(user_id) REFERENCES users(id) ON DELETE CASCADE
+);
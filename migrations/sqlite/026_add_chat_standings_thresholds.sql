@@ -0,0 +1,2 @@
+ALTER TABLE chat_settings ADD COLUMN standings_min_games INTEGER;
+ALTER TABLE chat_settings ADD COLUMN standings_inactive_days INTEGER;
@@ -0,0 +1,2 @@
+ALTER TABLE moves ADD COLUMN comment TEXT;
+ALTER TABLE moves_archive ADD COLUMN comment TEXT;
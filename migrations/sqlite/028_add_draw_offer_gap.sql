@@ -0,0 +1,5 @@
+ALTER TABLE chat_settings ADD COLUMN draw_offer_gap INTEGER;
+ALTER TABLE games ADD COLUMN white_draw_offer_ply INTEGER;
+ALTER TABLE games ADD COLUMN black_draw_offer_ply INTEGER;
+ALTER TABLE games_archive ADD COLUMN white_draw_offer_ply INTEGER;
+ALTER TABLE games_archive ADD COLUMN black_draw_offer_ply INTEGER;
@@ -0,0 +1,4 @@
+ALTER TABLE games ADD COLUMN paused INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE games ADD COLUMN pause_requested_by INTEGER;
+ALTER TABLE games_archive ADD COLUMN paused INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE games_archive ADD COLUMN pause_requested_by INTEGER;
@@ -0,0 +1,184 @@
+//! Circuit breaker for Telegram calls. After several network errors or 5xx responses in a
+//! row, calls fail straight away for a cooldown instead of each waiting on a dead API;
+//! then a single call is let through to probe it. Every failed probe doubles the cooldown.
+
+use std::env;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
+
+const DEFAULT_THRESHOLD: u32 = 5;
+const DEFAULT_COOLDOWN_SECS: u64 = 30;
+const MAX_COOLDOWN: Duration = Duration::from_secs(600);
+
+#[derive(Debug, Clone, Copy)]
+pub struct BreakerConfig {
+    /// Failures in a row that open the circuit; 0 never opens it.
+    pub threshold: u32,
+    pub cooldown: Duration,
+}
+
+impl BreakerConfig {
+    /// Reads `TELEGRAM_BREAKER_THRESHOLD` and `TELEGRAM_BREAKER_COOLDOWN_SECS`.
+    pub fn from_env() -> Self {
+        Self {
+            threshold: env::var("TELEGRAM_BREAKER_THRESHOLD")
+                .ok()
+                .and_then(|v| v.trim().parse().ok())
+                .unwrap_or(DEFAULT_THRESHOLD),
+            cooldown: Duration::from_secs(
+                env::var("TELEGRAM_BREAKER_COOLDOWN_SECS")
+                    .ok()
+                    .and_then(|v| v.trim().parse().ok())
+                    .filter(|secs| *secs > 0)
+                    .unwrap_or(DEFAULT_COOLDOWN_SECS),
+            ),
+        }
+    }
+}
+
+impl Default for BreakerConfig {
+    fn default() -> Self {
+        Self {
+            threshold: DEFAULT_THRESHOLD,
+            cooldown: Duration::from_secs(DEFAULT_COOLDOWN_SECS),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct BreakerState {
+    failures: u32,
+    /// Set while the circuit is open: calls are refused until then.
+    open_until: Option<Instant>,
+    cooldown: Duration,
+}
+
+#[derive(Debug)]
+pub struct CircuitBreaker {
+    config: BreakerConfig,
+    state: Mutex<BreakerState>,
+}
+
+impl CircuitBreaker {
+    pub fn new(config: BreakerConfig) -> Self {
+        Self {
+            config,
+            state: Mutex::new(BreakerState {
+                failures: 0,
+                open_until: None,
+                cooldown: config.cooldown,
+            }),
+        }
+    }
+
+    /// Whether a call may go out at `now`. Once the cooldown is over one probe goes
+    /// through, and the next waits another cooldown unless the probe succeeds first.
+    pub fn allow(&self, now: Instant) -> bool {
+        let mut state = self.state.lock().unwrap();
+        match state.open_until {
+            Some(until) if now < until => false,
+            Some(_) => {
+                state.open_until = Some(now + state.cooldown);
+                true
+            }
+            None => true,
+        }
+    }
+
+    pub fn record_success(&self) {
+        let mut state = self.state.lock().unwrap();
+        if state.open_until.take().is_some() {
+            info!("Telegram API is answering again; calls resumed");
+        }
+        state.failures = 0;
+        state.cooldown = self.config.cooldown;
+    }
+
+    pub fn record_failure(&self, now: Instant) {
+        if self.config.threshold == 0 {
+            return;
+        }
+        let mut state = self.state.lock().unwrap();
+        if state.open_until.is_some() {
+            // The probe failed
+            state.cooldown = (state.cooldown * 2).min(MAX_COOLDOWN);
+        } else {
+            state.failures += 1;
+            if state.failures < self.config.threshold {
+                return;
+            }
+        }
+        state.open_until = Some(now + state.cooldown);
+        warn!(
+            failures = state.failures,
+            cooldown_secs = state.cooldown.as_secs(),
+            "Telegram API keeps failing; pausing calls"
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn breaker(threshold: u32) -> CircuitBreaker {
+        CircuitBreaker::new(BreakerConfig {
+            threshold,
+            cooldown: Duration::from_secs(10),
+        })
+    }
+
+    #[test]
+    fn test_opens_after_threshold() {
+        let breaker = breaker(3);
+        let start = Instant::now();
+        for _ in 0..2 {
+            breaker.record_failure(start);
+            assert!(breaker.allow(start));
+        }
+        breaker.record_failure(start);
+        assert!(!breaker.allow(start));
+        assert!(!breaker.allow(start + Duration::from_secs(9)));
+    }
+
+    #[test]
+    fn test_success_resets_count() {
+        let breaker = breaker(2);
+        let start = Instant::now();
+        breaker.record_failure(start);
+        breaker.record_success();
+        breaker.record_failure(start);
+        assert!(breaker.allow(start));
+    }
+
+    #[test]
+    fn test_probe_after_cooldown() {
+        let breaker = breaker(1);
+        let start = Instant::now();
+        breaker.record_failure(start);
+
+        // One probe, then closed again until it answers
+        let later = start + Duration::from_secs(10);
+        assert!(breaker.allow(later));
+        assert!(!breaker.allow(later));
+
+        // A failed probe doubles the cooldown
+        breaker.record_failure(later);
+        assert!(!breaker.allow(later + Duration::from_secs(19)));
+        let probe = later + Duration::from_secs(20);
+        assert!(breaker.allow(probe));
+        breaker.record_success();
+        assert!(breaker.allow(probe));
+    }
+
+    #[test]
+    fn test_zero_threshold_never_opens() {
+        let breaker = breaker(0);
+        let start = Instant::now();
+        for _ in 0..10 {
+            breaker.record_failure(start);
+        }
+        assert!(breaker.allow(start));
+    }
+}
@@ -1,3 +1,7 @@
+mod breaker;
+mod http;
 pub mod telegram;
 
-pub use telegram::TelegramApi;
+pub use breaker::BreakerConfig;
+pub use http::HttpConfig;
+pub use telegram::{TelegramApi, WebhookOptions};
@@ -1,43 +1,176 @@
-use crate::models::{Message, SendMessageRequest, TelegramResponse, Update};
-use anyhow::{anyhow, Result};
+use crate::game::ImageFormat;
+use crate::models::{
+    ChatInfo, ChatMember, InlineKeyboardMarkup, Message, PollMessage, SendMessageRequest,
+    TelegramResponse, Update, User,
+};
+use crate::error::{KamaError, Result};
+use super::breaker::{BreakerConfig, CircuitBreaker};
+use super::http::HttpConfig;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tracing::warn;
+
+/// Options for `setWebhook` besides the URL.
+#[derive(Debug, Clone, Default)]
+pub struct WebhookOptions {
+    /// Sent back by Telegram in a header with every update.
+    pub secret_token: Option<String>,
+    /// PEM of a self-signed certificate for the webhook's host, uploaded so Telegram trusts it.
+    pub certificate: Option<Vec<u8>>,
+    /// Discard updates that queued up while no webhook was answering.
+    pub drop_pending_updates: bool,
+    /// Most simultaneous webhook connections from Telegram (1-100; Telegram's default is 40).
+    pub max_connections: Option<u32>,
+}
 
 #[derive(Clone)]
 pub struct TelegramApi {
     client: reqwest::Client,
     base_url: String,
+    /// Shared by all clones, so every caller backs off together.
+    breaker: Arc<CircuitBreaker>,
+    max_upload_bytes: usize,
+    /// The client's limit per call, extended for long polls.
+    request_timeout: Duration,
+    slow_call: Option<Duration>,
 }
 
+/// Telegram's own Bot API server.
+pub const CLOUD_API_URL: &str = "https://api.telegram.org";
+/// Largest file the cloud Bot API accepts for upload.
+const CLOUD_MAX_UPLOAD_BYTES: usize = 50 * 1024 * 1024;
+/// Largest file a local Bot API server (`telegram-bot-api --local`) accepts.
+const LOCAL_MAX_UPLOAD_BYTES: usize = 2000 * 1024 * 1024;
+
 impl TelegramApi {
     pub fn new(token: String) -> Self {
-        Self {
-            client: reqwest::Client::new(),
-            base_url: format!("https://api.telegram.org/bot{}", token),
-        }
+        Self::with_server(CLOUD_API_URL, &token)
+    }
+
+    /// A client for the Bot API server at `server`, e.g. a local `telegram-bot-api` at
+    /// `http://localhost:8081`. Any server other than Telegram's gets the local upload limit.
+    pub fn with_server(server: &str, token: &str) -> Self {
+        let server = server.trim_end_matches('/');
+        let max_upload_bytes = if server == CLOUD_API_URL {
+            CLOUD_MAX_UPLOAD_BYTES
+        } else {
+            LOCAL_MAX_UPLOAD_BYTES
+        };
+        Self::with_client(
+            format!("{}/bot{}", server, token),
+            BreakerConfig::from_env(),
+            max_upload_bytes,
+        )
     }
 
     /// Creates a TelegramApi instance with a custom base URL.
     /// This is primarily used for testing with mock servers.
     pub fn new_with_base_url(base_url: String) -> Self {
+        Self::with_client(base_url, BreakerConfig::default(), CLOUD_MAX_UPLOAD_BYTES)
+    }
+
+    fn with_client(base_url: String, breaker: BreakerConfig, max_upload_bytes: usize) -> Self {
+        let http = HttpConfig::default();
         Self {
-            client: reqwest::Client::new(),
+            client: http.build_client().expect("HTTP client with default settings"),
             base_url,
+            breaker: Arc::new(CircuitBreaker::new(breaker)),
+            max_upload_bytes,
+            request_timeout: http.request_timeout,
+            slow_call: http.slow_call,
         }
     }
 
-    pub async fn send_message(&self, chat_id: i64, reply_to: i64, text: &str) -> Result<i64> {
-        let url = format!("{}/sendMessage", self.base_url);
-        let body = SendMessageRequest {
+    /// Largest file the configured server accepts; bigger uploads fail without being sent.
+    pub fn max_upload_bytes(&self) -> usize {
+        self.max_upload_bytes
+    }
+
+    /// Rebuilds the HTTP client with `config`, e.g. to go through a proxy.
+    pub fn with_http(mut self, config: &HttpConfig) -> Result<Self> {
+        self.client = config.build_client()?;
+        self.request_timeout = config.request_timeout;
+        self.slow_call = config.slow_call;
+        Ok(self)
+    }
+
+    /// Replaces the circuit breaker settings.
+    pub fn with_breaker(mut self, config: BreakerConfig) -> Self {
+        self.breaker = Arc::new(CircuitBreaker::new(config));
+        self
+    }
+
+    /// Sends a request unless the circuit breaker has paused calls, and tells the breaker
+    /// how it went. Only network errors and 5xx responses count as failures. Calls slower
+    /// than `slow_call` are logged.
+    async fn send(&self, request: reqwest::RequestBuilder) -> Result<reqwest::Response> {
+        if !self.breaker.allow(Instant::now()) {
+            return Err(KamaError::Telegram("Telegram API unavailable, calls paused".to_string()));
+        }
+        let request = request.build()?;
+        // The URL holds the token, so only the method name is logged
+        let method = request
+            .url()
+            .path_segments()
+            .and_then(|mut segments| segments.next_back())
+            .unwrap_or_default()
+            .to_string();
+        let started = Instant::now();
+        let result = self.client.execute(request).await;
+        let elapsed = started.elapsed();
+        // getUpdates waits for updates on purpose
+        if self.slow_call.is_some_and(|threshold| elapsed > threshold) && method != "getUpdates" {
+            warn!(method = %method, elapsed_ms = elapsed.as_millis() as u64, "Slow Telegram call");
+        }
+        match result {
+            Ok(response) if response.status().is_server_error() => {
+                self.breaker.record_failure(Instant::now());
+                Ok(response)
+            }
+            Ok(response) => {
+                self.breaker.record_success();
+                Ok(response)
+            }
+            Err(e) => {
+                self.breaker.record_failure(Instant::now());
+                Err(e.into())
+            }
+        }
+    }
+
+    pub async fn send_message(&self, chat_id: i64, reply_to: Option<i64>, text: &str) -> Result<i64> {
+        self.send_message_request(SendMessageRequest {
+            chat_id,
+            text: text.to_string(),
+            reply_to_message_id: reply_to,
+            parse_mode: Some("HTML".to_string()),
+            reply_markup: None,
+        })
+        .await
+    }
+
+    pub async fn send_message_with_keyboard(
+        &self,
+        chat_id: i64,
+        reply_to: i64,
+        text: &str,
+        keyboard: InlineKeyboardMarkup,
+    ) -> Result<i64> {
+        self.send_message_request(SendMessageRequest {
             chat_id,
             text: text.to_string(),
             reply_to_message_id: Some(reply_to),
             parse_mode: Some("HTML".to_string()),
-        };
+            reply_markup: Some(keyboard),
+        })
+        .await
+    }
+
+    async fn send_message_request(&self, body: SendMessageRequest) -> Result<i64> {
+        let url = format!("{}/sendMessage", self.base_url);
 
         let resp: TelegramResponse<Message> = self
-            .client
-            .post(&url)
-            .json(&body)
-            .send()
+            .send(self.client.post(&url).json(&body))
             .await?
             .json()
             .await?;
@@ -46,12 +179,12 @@ impl TelegramApi {
             let error_msg = resp
                 .description
                 .unwrap_or_else(|| "sendMessage failed".to_string());
-            return Err(anyhow!("Telegram API error: {}", error_msg));
+            return Err(KamaError::Telegram(error_msg));
         }
 
         Ok(resp
             .result
-            .ok_or_else(|| anyhow!("Telegram API error: missing result in response"))?
+            .ok_or_else(|| KamaError::Telegram("missing result in response".to_string()))?
             .message_id)
     }
 
@@ -60,29 +193,122 @@ impl TelegramApi {
         chat_id: i64,
         reply_to: Option<i64>,
         caption: &str,
-        png: Vec<u8>,
+        image: Vec<u8>,
+        format: ImageFormat,
+        keyboard: Option<&InlineKeyboardMarkup>,
+    ) -> Result<i64> {
+        let name = format!("board.{}", format.extension());
+        self.send_file("sendPhoto", "photo", chat_id, reply_to, caption, &name, format.mime_type(), image, keyboard)
+            .await
+    }
+
+    /// Sends the image as a file so Telegram delivers it without recompression.
+    pub async fn send_document(
+        &self,
+        chat_id: i64,
+        reply_to: Option<i64>,
+        caption: &str,
+        image: Vec<u8>,
+        format: ImageFormat,
+        keyboard: Option<&InlineKeyboardMarkup>,
+    ) -> Result<i64> {
+        let name = format!("board.{}", format.extension());
+        self.send_file("sendDocument", "document", chat_id, reply_to, caption, &name, format.mime_type(), image, keyboard)
+            .await
+    }
+
+    /// Sends any file, such as a game's PGN, under the given name.
+    pub async fn send_attachment(
+        &self,
+        chat_id: i64,
+        reply_to: Option<i64>,
+        caption: &str,
+        file_name: &str,
+        mime_type: &str,
+        contents: Vec<u8>,
+    ) -> Result<i64> {
+        self.send_file("sendDocument", "document", chat_id, reply_to, caption, file_name, mime_type, contents, None)
+            .await
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    async fn send_file(
+        &self,
+        method: &str,
+        field: &'static str,
+        chat_id: i64,
+        reply_to: Option<i64>,
+        caption: &str,
+        file_name: &str,
+        mime_type: &str,
+        contents: Vec<u8>,
+        keyboard: Option<&InlineKeyboardMarkup>,
+    ) -> Result<i64> {
+        if contents.len() > self.max_upload_bytes {
+            return Err(KamaError::Telegram(format!(
+                "{} is {} bytes, over the {} byte upload limit",
+                file_name,
+                contents.len(),
+                self.max_upload_bytes
+            )));
+        }
+        let part = reqwest::multipart::Part::bytes(contents)
+            .file_name(file_name.to_string())
+            .mime_str(mime_type)?;
+        self.send_part(method, field, chat_id, reply_to, caption, part, keyboard).await
+    }
+
+    /// Sends a file from disk as a document, streaming it instead of reading it into memory.
+    pub async fn send_attachment_file(
+        &self,
+        chat_id: i64,
+        reply_to: Option<i64>,
+        caption: &str,
+        path: &std::path::Path,
+        file_name: &str,
+        mime_type: &str,
+    ) -> Result<i64> {
+        let file = tokio::fs::File::open(path).await?;
+        let len = file.metadata().await?.len();
+        if len > self.max_upload_bytes as u64 {
+            return Err(KamaError::Telegram(format!(
+                "{} is {} bytes, over the {} byte upload limit",
+                file_name, len, self.max_upload_bytes
+            )));
+        }
+        let part = reqwest::multipart::Part::stream_with_length(file, len)
+            .file_name(file_name.to_string())
+            .mime_str(mime_type)?;
+        self.send_part("sendDocument", "document", chat_id, reply_to, caption, part, None).await
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    async fn send_part(
+        &self,
+        method: &str,
+        field: &'static str,
+        chat_id: i64,
+        reply_to: Option<i64>,
+        caption: &str,
+        part: reqwest::multipart::Part,
+        keyboard: Option<&InlineKeyboardMarkup>,
     ) -> Result<i64> {
-        let url = format!("{}/sendPhoto", self.base_url);
+        let url = format!("{}/{}", self.base_url, method);
         let mut form = reqwest::multipart::Form::new()
             .text("chat_id", chat_id.to_string())
             .text("caption", caption.to_string())
             .text("parse_mode", "HTML".to_string())
-            .part(
-                "photo",
-                reqwest::multipart::Part::bytes(png)
-                    .file_name("board.png")
-                    .mime_str("image/png")?,
-            );
+            .part(field, part);
 
         if let Some(reply_to) = reply_to {
             form = form.text("reply_to_message_id", reply_to.to_string());
         }
+        if let Some(keyboard) = keyboard {
+            form = form.text("reply_markup", serde_json::json!(keyboard).to_string());
+        }
 
         let resp: TelegramResponse<Message> = self
-            .client
-            .post(&url)
-            .multipart(form)
-            .send()
+            .send(self.client.post(&url).multipart(form))
             .await?
             .json()
             .await?;
@@ -90,13 +316,13 @@ impl TelegramApi {
         if !resp.ok {
             let error_msg = resp
                 .description
-                .unwrap_or_else(|| "sendPhoto failed".to_string());
-            return Err(anyhow!("Telegram API error: {}", error_msg));
+                .unwrap_or_else(|| format!("{} failed", method));
+            return Err(KamaError::Telegram(error_msg));
         }
 
         Ok(resp
             .result
-            .ok_or_else(|| anyhow!("Telegram API error: missing result in response"))?
+            .ok_or_else(|| KamaError::Telegram("missing result in response".to_string()))?
             .message_id)
     }
 
@@ -108,10 +334,7 @@ impl TelegramApi {
         });
 
         let resp: TelegramResponse<serde_json::Value> = self
-            .client
-            .post(&url)
-            .json(&body)
-            .send()
+            .send(self.client.post(&url).json(&body))
             .await?
             .json()
             .await?;
@@ -126,12 +349,249 @@ impl TelegramApi {
             {
                 return Ok(());
             }
-            return Err(anyhow!("Telegram API error: {}", error_msg));
+            return Err(KamaError::Telegram(error_msg));
+        }
+
+        Ok(())
+    }
+
+    /// Replaces the text of a previously sent message, dropping its inline keyboard.
+    pub async fn edit_message_text(&self, chat_id: i64, message_id: i64, text: &str) -> Result<()> {
+        let url = format!("{}/editMessageText", self.base_url);
+        let body = serde_json::json!({
+            "chat_id": chat_id,
+            "message_id": message_id,
+            "text": text,
+            "parse_mode": "HTML",
+        });
+
+        let resp: TelegramResponse<serde_json::Value> = self
+            .send(self.client.post(&url).json(&body))
+            .await?
+            .json()
+            .await?;
+
+        if !resp.ok {
+            let error_msg = resp
+                .description
+                .unwrap_or_else(|| "editMessageText failed".to_string());
+            // Editing to identical content is not an error for our purposes
+            if error_msg.contains("message is not modified") {
+                return Ok(());
+            }
+            return Err(KamaError::Telegram(error_msg));
         }
 
         Ok(())
     }
 
+    /// Replaces the inline keyboard of a previously sent message; `None` removes it.
+    pub async fn edit_message_reply_markup(
+        &self,
+        chat_id: i64,
+        message_id: i64,
+        keyboard: Option<&InlineKeyboardMarkup>,
+    ) -> Result<()> {
+        let url = format!("{}/editMessageReplyMarkup", self.base_url);
+        let mut body = serde_json::json!({
+            "chat_id": chat_id,
+            "message_id": message_id,
+        });
+        if let Some(keyboard) = keyboard {
+            body["reply_markup"] = serde_json::json!(keyboard);
+        }
+
+        let resp: TelegramResponse<serde_json::Value> = self
+            .send(self.client.post(&url).json(&body))
+            .await?
+            .json()
+            .await?;
+
+        if !resp.ok {
+            let error_msg = resp
+                .description
+                .unwrap_or_else(|| "editMessageReplyMarkup failed".to_string());
+            if error_msg.contains("message is not modified") {
+                return Ok(());
+            }
+            return Err(KamaError::Telegram(error_msg));
+        }
+
+        Ok(())
+    }
+
+    /// Sends a non-anonymous poll, so each vote arrives as a `poll_answer` update.
+    pub async fn send_poll(
+        &self,
+        chat_id: i64,
+        reply_to: Option<i64>,
+        question: &str,
+        options: &[String],
+    ) -> Result<PollMessage> {
+        let url = format!("{}/sendPoll", self.base_url);
+        let mut body = serde_json::json!({
+            "chat_id": chat_id,
+            "question": question,
+            "options": options.iter().map(|text| serde_json::json!({ "text": text })).collect::<Vec<_>>(),
+            "is_anonymous": false,
+        });
+        if let Some(reply_to) = reply_to {
+            body["reply_to_message_id"] = serde_json::json!(reply_to);
+        }
+
+        let resp: TelegramResponse<PollMessage> = self
+            .send(self.client.post(&url).json(&body))
+            .await?
+            .json()
+            .await?;
+
+        if !resp.ok {
+            return Err(KamaError::Telegram(
+                resp.description.unwrap_or_else(|| "sendPoll failed".to_string()),
+            ));
+        }
+
+        resp.result
+            .ok_or_else(|| KamaError::Telegram("missing result in response".to_string()))
+    }
+
+    pub async fn stop_poll(&self, chat_id: i64, message_id: i64) -> Result<()> {
+        let url = format!("{}/stopPoll", self.base_url);
+        let body = serde_json::json!({
+            "chat_id": chat_id,
+            "message_id": message_id,
+        });
+
+        let resp: TelegramResponse<serde_json::Value> = self
+            .send(self.client.post(&url).json(&body))
+            .await?
+            .json()
+            .await?;
+
+        if !resp.ok {
+            return Err(KamaError::Telegram(
+                resp.description.unwrap_or_else(|| "stopPoll failed".to_string()),
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Sets the bot's reaction on a message, replacing any earlier one.
+    pub async fn set_message_reaction(&self, chat_id: i64, message_id: i64, emoji: &str) -> Result<()> {
+        let url = format!("{}/setMessageReaction", self.base_url);
+        let body = serde_json::json!({
+            "chat_id": chat_id,
+            "message_id": message_id,
+            "reaction": [{ "type": "emoji", "emoji": emoji }],
+        });
+
+        let resp: TelegramResponse<serde_json::Value> = self
+            .send(self.client.post(&url).json(&body))
+            .await?
+            .json()
+            .await?;
+
+        if !resp.ok {
+            return Err(KamaError::Telegram(
+                resp.description
+                    .unwrap_or_else(|| "setMessageReaction failed".to_string()),
+            ));
+        }
+
+        Ok(())
+    }
+
+    pub async fn answer_callback_query(&self, callback_query_id: &str, text: Option<&str>) -> Result<()> {
+        let url = format!("{}/answerCallbackQuery", self.base_url);
+        let mut body = serde_json::json!({
+            "callback_query_id": callback_query_id,
+        });
+
+        if let Some(text) = text {
+            body["text"] = serde_json::json!(text);
+        }
+
+        let resp: TelegramResponse<serde_json::Value> = self
+            .send(self.client.post(&url).json(&body))
+            .await?
+            .json()
+            .await?;
+
+        if !resp.ok {
+            let error_msg = resp
+                .description
+                .unwrap_or_else(|| "answerCallbackQuery failed".to_string());
+            return Err(KamaError::Telegram(error_msg));
+        }
+
+        Ok(())
+    }
+
+    /// The bot's own account; a cheap check that the token and server work.
+    pub async fn get_me(&self) -> Result<User> {
+        let url = format!("{}/getMe", self.base_url);
+
+        let resp: TelegramResponse<User> = self.send(self.client.post(&url)).await?.json().await?;
+
+        if !resp.ok {
+            let error_msg = resp
+                .description
+                .unwrap_or_else(|| "getMe failed".to_string());
+            return Err(KamaError::Telegram(error_msg));
+        }
+
+        resp.result
+            .ok_or_else(|| KamaError::Telegram("missing result in response".to_string()))
+    }
+
+    /// Looks up a chat by id or `@username`. Telegram only answers for users the bot
+    /// has already seen, so a failure here is expected for strangers.
+    pub async fn get_chat(&self, chat: &str) -> Result<ChatInfo> {
+        let url = format!("{}/getChat", self.base_url);
+        let body = serde_json::json!({ "chat_id": chat });
+
+        let resp: TelegramResponse<ChatInfo> = self
+            .send(self.client.post(&url).json(&body))
+            .await?
+            .json()
+            .await?;
+
+        if !resp.ok {
+            let error_msg = resp
+                .description
+                .unwrap_or_else(|| "getChat failed".to_string());
+            return Err(KamaError::Telegram(error_msg));
+        }
+
+        resp.result
+            .ok_or_else(|| KamaError::Telegram("missing result in response".to_string()))
+    }
+
+    pub async fn get_chat_member(&self, chat_id: i64, user_id: i64) -> Result<ChatMember> {
+        let url = format!("{}/getChatMember", self.base_url);
+        let body = serde_json::json!({
+            "chat_id": chat_id,
+            "user_id": user_id,
+        });
+
+        let resp: TelegramResponse<ChatMember> = self
+            .send(self.client.post(&url).json(&body))
+            .await?
+            .json()
+            .await?;
+
+        if !resp.ok {
+            let error_msg = resp
+                .description
+                .unwrap_or_else(|| "getChatMember failed".to_string());
+            return Err(KamaError::Telegram(error_msg));
+        }
+
+        resp.result
+            .ok_or_else(|| KamaError::Telegram("missing result in response".to_string()))
+    }
+
     pub async fn get_updates(&self, offset: Option<i64>, timeout: i32) -> Result<Vec<Update>> {
         let url = format!("{}/getUpdates", self.base_url);
         let mut params = vec![("timeout", timeout.to_string())];
@@ -140,10 +600,12 @@ impl TelegramApi {
         }
 
         let resp: TelegramResponse<Vec<Update>> = self
-            .client
-            .get(&url)
-            .query(&params)
-            .send()
+            .send(
+                self.client
+                    .get(&url)
+                    .query(&params)
+                    .timeout(self.request_timeout + Duration::from_secs(timeout.max(0) as u64)),
+            )
             .await?
             .json()
             .await?;
@@ -152,27 +614,51 @@ impl TelegramApi {
             let error_msg = resp
                 .description
                 .unwrap_or_else(|| "getUpdates failed".to_string());
-            return Err(anyhow!("Telegram API error: {}", error_msg));
+            return Err(KamaError::Telegram(error_msg));
         }
 
         Ok(resp.result.unwrap_or_default())
     }
 
-    pub async fn set_webhook(&self, url: &str, secret_token: Option<&str>) -> Result<()> {
+    /// Registers the webhook with the given options.
+    pub async fn set_webhook(&self, url: &str, options: &WebhookOptions) -> Result<()> {
         let url_endpoint = format!("{}/setWebhook", self.base_url);
-        let mut body = serde_json::json!({
-            "url": url,
-        });
-
-        if let Some(token) = secret_token {
-            body["secret_token"] = serde_json::json!(token);
+        let mut params = serde_json::Map::new();
+        params.insert("url".to_string(), serde_json::json!(url));
+        if let Some(token) = &options.secret_token {
+            params.insert("secret_token".to_string(), serde_json::json!(token));
+        }
+        if options.drop_pending_updates {
+            params.insert("drop_pending_updates".to_string(), serde_json::json!(true));
+        }
+        if let Some(max) = options.max_connections {
+            params.insert("max_connections".to_string(), serde_json::json!(max));
         }
 
+        let request = match &options.certificate {
+            Some(pem) => {
+                // Multipart fields are all text
+                let mut form = reqwest::multipart::Form::new();
+                for (name, value) in params {
+                    let value = match value {
+                        serde_json::Value::String(text) => text,
+                        other => other.to_string(),
+                    };
+                    form = form.text(name, value);
+                }
+                form = form.part(
+                    "certificate",
+                    reqwest::multipart::Part::bytes(pem.clone())
+                        .file_name("certificate.pem")
+                        .mime_str("application/x-pem-file")?,
+                );
+                self.client.post(&url_endpoint).multipart(form)
+            }
+            None => self.client.post(&url_endpoint).json(&params),
+        };
+
         let resp: TelegramResponse<serde_json::Value> = self
-            .client
-            .post(&url_endpoint)
-            .json(&body)
-            .send()
+            .send(request)
             .await?
             .json()
             .await?;
@@ -181,7 +667,7 @@ impl TelegramApi {
             let error_msg = resp
                 .description
                 .unwrap_or_else(|| "setWebhook failed".to_string());
-            return Err(anyhow!("Telegram API error: {}", error_msg));
+            return Err(KamaError::Telegram(error_msg));
         }
 
         Ok(())
@@ -191,10 +677,7 @@ impl TelegramApi {
         let url = format!("{}/deleteWebhook", self.base_url);
 
         let resp: TelegramResponse<serde_json::Value> = self
-            .client
-            .post(&url)
-            .json(&serde_json::json!({}))
-            .send()
+            .send(self.client.post(&url).json(&serde_json::json!({})))
             .await?
             .json()
             .await?;
@@ -203,7 +686,7 @@ impl TelegramApi {
             let error_msg = resp
                 .description
                 .unwrap_or_else(|| "deleteWebhook failed".to_string());
-            return Err(anyhow!("Telegram API error: {}", error_msg));
+            return Err(KamaError::Telegram(error_msg));
         }
 
         Ok(())
@@ -213,9 +696,7 @@ impl TelegramApi {
         let url = format!("{}/getWebhookInfo", self.base_url);
 
         let resp: TelegramResponse<serde_json::Value> = self
-            .client
-            .get(&url)
-            .send()
+            .send(self.client.get(&url))
             .await?
             .json()
             .await?;
@@ -224,11 +705,10 @@ impl TelegramApi {
             let error_msg = resp
                 .description
                 .unwrap_or_else(|| "getWebhookInfo failed".to_string());
-            return Err(anyhow!("Telegram API error: {}", error_msg));
+            return Err(KamaError::Telegram(error_msg));
         }
 
-        Ok(resp
-            .result
-            .ok_or_else(|| anyhow!("Telegram API error: missing result in response"))?)
+        resp.result
+            .ok_or_else(|| KamaError::Telegram("missing result in response".to_string()))
     }
 }
@@ -0,0 +1,80 @@
+//! Moves finished games older than a configured age into the `games_archive` and
+//! `moves_archive` tables, keeping the live tables small. History, head-to-head stats,
+//! game numbers and the API read from both, so archiving is invisible to players.
+
+use std::env;
+use std::time::Duration;
+
+use chrono::{DateTime, Months, Utc};
+use sqlx::{Any, Pool};
+use tracing::{info, warn};
+
+use crate::db;
+use crate::error::Result;
+
+const DEFAULT_INTERVAL_HOURS: u64 = 24;
+
+#[derive(Debug, Clone)]
+pub struct ArchiveConfig {
+    /// Games that ended more than this many months ago are archived.
+    pub after_months: u32,
+    pub interval: Duration,
+}
+
+impl ArchiveConfig {
+    /// Reads `ARCHIVE_AFTER_MONTHS` and `ARCHIVE_INTERVAL_HOURS`. Returns `None` when the
+    /// age is unset or zero, which disables archiving.
+    pub fn from_env() -> Option<Self> {
+        let after_months = env::var("ARCHIVE_AFTER_MONTHS")
+            .ok()
+            .and_then(|v| v.trim().parse().ok())
+            .filter(|n| *n > 0)?;
+        let hours = env::var("ARCHIVE_INTERVAL_HOURS")
+            .ok()
+            .and_then(|v| v.trim().parse().ok())
+            .filter(|n| *n > 0)
+            .unwrap_or(DEFAULT_INTERVAL_HOURS);
+        Some(Self {
+            after_months,
+            interval: Duration::from_secs(hours * 3600),
+        })
+    }
+}
+
+/// The moment before which finished games are archived.
+pub fn cutoff(now: DateTime<Utc>, after_months: u32) -> DateTime<Utc> {
+    now.checked_sub_months(Months::new(after_months))
+        .unwrap_or(DateTime::<Utc>::MIN_UTC)
+}
+
+/// Archives games that ended more than `after_months` months ago and returns how many moved.
+pub async fn archive_games(pool: &Pool<Any>, after_months: u32) -> Result<u64> {
+    db::archive_finished_games(pool, cutoff(Utc::now(), after_months)).await
+}
+
+/// Archives old games on startup and then once per interval. Failures are logged and
+/// retried on the next tick.
+pub async fn run_archive_task(pool: Pool<Any>, config: ArchiveConfig) {
+    let mut ticker = tokio::time::interval(config.interval);
+    loop {
+        ticker.tick().await;
+        match archive_games(&pool, config.after_months).await {
+            Ok(0) => {}
+            Ok(count) => info!(count = count, "Finished games archived"),
+            Err(e) => warn!(error = %e, "Game archiving failed"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn test_cutoff() {
+        let now = Utc.with_ymd_and_hms(2024, 5, 31, 12, 0, 0).unwrap();
+        assert_eq!(cutoff(now, 3), Utc.with_ymd_and_hms(2024, 2, 29, 12, 0, 0).unwrap());
+        assert_eq!(cutoff(now, 12), Utc.with_ymd_and_hms(2023, 5, 31, 12, 0, 0).unwrap());
+    }
+}
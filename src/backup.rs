@@ -0,0 +1,212 @@
+//! Scheduled database backups. SQLite databases are copied with `VACUUM INTO`, which
+//! gives a consistent snapshot while the bot keeps writing; other databases need a dump
+//! command (e.g. `pg_dump`). An optional upload command ships each backup elsewhere, such
+//! as an S3-compatible bucket through `aws s3 cp` or `rclone`.
+
+use std::env;
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Utc};
+use croner::Cron;
+use sqlx::{Any, Pool};
+use tokio::process::Command;
+use tracing::{info, warn};
+
+use crate::error::{KamaError, Result};
+
+const DEFAULT_DIR: &str = "backups";
+const DEFAULT_KEEP: usize = 7;
+const FILE_PREFIX: &str = "kamachess-";
+
+#[derive(Debug, Clone)]
+pub struct BackupConfig {
+    pub schedule: Cron,
+    pub dir: PathBuf,
+    /// Newest backups kept in `dir`; older ones are deleted after each run.
+    pub keep: usize,
+    /// Shell command writing the dump to `{file}`, with `DATABASE_URL` in its environment.
+    pub dump_command: Option<String>,
+    /// Shell command run on `{file}` after a successful dump.
+    pub upload_command: Option<String>,
+}
+
+impl BackupConfig {
+    /// Reads `BACKUP_SCHEDULE` (a five-field cron expression, in UTC), `BACKUP_DIR`,
+    /// `BACKUP_KEEP`, `BACKUP_COMMAND` and `BACKUP_UPLOAD_COMMAND`. Returns `None` when no
+    /// schedule is set, which disables backups.
+    pub fn from_env() -> Result<Option<Self>> {
+        let Some(schedule) = env::var("BACKUP_SCHEDULE").ok().filter(|s| !s.trim().is_empty()) else {
+            return Ok(None);
+        };
+        let schedule = Cron::new(schedule.trim())
+            .parse()
+            .map_err(|e| KamaError::Invalid(format!("BACKUP_SCHEDULE: {}", e)))?;
+        let command = |name: &str| env::var(name).ok().filter(|c| !c.trim().is_empty());
+
+        Ok(Some(Self {
+            schedule,
+            dir: env::var("BACKUP_DIR")
+                .ok()
+                .filter(|d| !d.is_empty())
+                .map_or_else(|| PathBuf::from(DEFAULT_DIR), PathBuf::from),
+            keep: env::var("BACKUP_KEEP")
+                .ok()
+                .and_then(|v| v.trim().parse().ok())
+                .filter(|n| *n > 0)
+                .unwrap_or(DEFAULT_KEEP),
+            dump_command: command("BACKUP_COMMAND"),
+            upload_command: command("BACKUP_UPLOAD_COMMAND"),
+        }))
+    }
+}
+
+/// Runs a backup at every time the schedule matches. Failures are logged and the next
+/// run goes ahead as planned.
+pub async fn run_backup_task(pool: Pool<Any>, database_url: String, config: BackupConfig) {
+    loop {
+        let now = Utc::now();
+        let next = match config.schedule.find_next_occurrence(&now, false) {
+            Ok(next) => next,
+            Err(e) => {
+                warn!(error = %e, "Backup schedule has no next run; backups stopped");
+                return;
+            }
+        };
+        let wait = (next - now).to_std().unwrap_or_default();
+        tokio::time::sleep(wait).await;
+
+        match run_backup(&pool, &database_url, &config, Utc::now()).await {
+            Ok(path) => info!(path = %path.display(), "Database backup written"),
+            Err(e) => warn!(error = %e, "Database backup failed"),
+        }
+    }
+}
+
+/// Writes one backup stamped with `at`, uploads it if configured and prunes old ones.
+pub async fn run_backup(
+    pool: &Pool<Any>,
+    database_url: &str,
+    config: &BackupConfig,
+    at: DateTime<Utc>,
+) -> Result<PathBuf> {
+    tokio::fs::create_dir_all(&config.dir).await?;
+    let is_sqlite = database_url.starts_with("sqlite");
+    let extension = if is_sqlite { "db" } else { "sql" };
+    let path = config
+        .dir
+        .join(format!("{}{}.{}", FILE_PREFIX, at.format("%Y%m%d-%H%M%S"), extension));
+
+    match &config.dump_command {
+        Some(command) => run_command(command, &path, database_url).await?,
+        None if is_sqlite => {
+            let target = path
+                .to_str()
+                .ok_or_else(|| KamaError::Invalid("BACKUP_DIR must be valid UTF-8".to_string()))?;
+            // VACUUM doesn't take bound parameters, so the path goes in as a quoted literal
+            let statement = format!("VACUUM INTO '{}'", target.replace('\'', "''"));
+            sqlx::raw_sql(&statement).execute(pool).await?;
+        }
+        None => {
+            return Err(KamaError::Invalid(
+                "BACKUP_COMMAND is required for databases other than SQLite".to_string(),
+            ))
+        }
+    }
+
+    if let Some(command) = &config.upload_command {
+        run_command(command, &path, database_url).await?;
+    }
+
+    let removed = prune_backups(&config.dir, config.keep)?;
+    if removed > 0 {
+        info!(removed = removed, "Old database backups removed");
+    }
+    Ok(path)
+}
+
+async fn run_command(command: &str, file: &Path, database_url: &str) -> Result<()> {
+    let command = command.replace("{file}", &file.display().to_string());
+    let status = Command::new("sh")
+        .arg("-c")
+        .arg(&command)
+        .env("DATABASE_URL", database_url)
+        .status()
+        .await?;
+    if !status.success() {
+        return Err(KamaError::Invalid(format!("backup command exited with {}", status)));
+    }
+    Ok(())
+}
+
+/// Deletes all but the `keep` newest backups in `dir`. The timestamp in the file names
+/// sorts them oldest first.
+fn prune_backups(dir: &Path, keep: usize) -> Result<usize> {
+    let mut backups: Vec<PathBuf> = std::fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| {
+            path.file_name()
+                .and_then(|name| name.to_str())
+                .is_some_and(|name| name.starts_with(FILE_PREFIX))
+        })
+        .collect();
+    backups.sort();
+
+    let excess = backups.len().saturating_sub(keep);
+    for path in &backups[..excess] {
+        std::fs::remove_file(path)?;
+    }
+    Ok(excess)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("kamachess-backup-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_prune_backups_keeps_newest() {
+        let dir = temp_dir("prune");
+        for stamp in ["20260101-030000", "20260102-030000", "20260103-030000"] {
+            std::fs::write(dir.join(format!("{}{}.db", FILE_PREFIX, stamp)), b"").unwrap();
+        }
+        std::fs::write(dir.join("notes.txt"), b"").unwrap();
+
+        assert_eq!(prune_backups(&dir, 2).unwrap(), 1);
+        assert!(!dir.join("kamachess-20260101-030000.db").exists());
+        assert!(dir.join("kamachess-20260103-030000.db").exists());
+        assert!(dir.join("notes.txt").exists());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_sqlite_backup() {
+        // VACUUM INTO is a no-op for in-memory databases, so this one lives on disk
+        let dir = temp_dir("sqlite");
+        let database_url = format!("sqlite://{}?mode=rwc", dir.join("source.db").display());
+        sqlx::any::install_default_drivers();
+        let pool = sqlx::any::AnyPoolOptions::new()
+            .max_connections(1)
+            .connect(&database_url)
+            .await
+            .unwrap();
+        crate::db::run_migrations(&pool, &database_url).await.unwrap();
+
+        let config = BackupConfig {
+            schedule: Cron::new("0 3 * * *").parse().unwrap(),
+            dir: dir.join("backups"),
+            keep: 1,
+            dump_command: None,
+            upload_command: Some(format!("cp {{file}} {}", dir.join("uploaded.db").display())),
+        };
+        let path = run_backup(&pool, &database_url, &config, Utc::now()).await.unwrap();
+        assert!(std::fs::metadata(&path).unwrap().len() > 0);
+        assert!(dir.join("uploaded.db").exists());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
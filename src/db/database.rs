@@ -1,7 +1,10 @@
-use crate::models::{DbUser, GameRow, HistoryRow, User};
-use anyhow::Result;
-use chrono::Utc;
-use sqlx::{Any, Pool, Row};
+use crate::models::{
+    AuditEntry, ChatSettings, ChatStanding, DbUser, GameResult, GameRow, GameStatus, GameSummary, HistoryRow, PairRecord, SeekRow, ThinkTime,
+    Turn, User,
+};
+use crate::error::{KamaError, Result};
+use chrono::{DateTime, Utc};
+use sqlx::{Any, FromRow, Pool, Row};
 use std::collections::HashMap;
 
 pub async fn run_migrations(pool: &Pool<Any>, database_url: &str) -> Result<()> {
@@ -24,6 +27,131 @@ pub async fn run_migrations(pool: &Pool<Any>, database_url: &str) -> Result<()>
         ))
         .execute(pool)
         .await;
+        let _ = sqlx::raw_sql(include_str!(
+            "../../migrations/postgres/005_add_move_confirmation.sql"
+        ))
+        .execute(pool)
+        .await;
+        let _ = sqlx::raw_sql(include_str!(
+            "../../migrations/postgres/006_add_chat_settings.sql"
+        ))
+        .execute(pool)
+        .await;
+        let _ = sqlx::raw_sql(include_str!(
+            "../../migrations/postgres/007_add_chat_orientation.sql"
+        ))
+        .execute(pool)
+        .await;
+        let _ = sqlx::raw_sql(include_str!(
+            "../../migrations/postgres/008_add_chat_send_as_document.sql"
+        ))
+        .execute(pool)
+        .await;
+        let _ = sqlx::raw_sql(include_str!(
+            "../../migrations/postgres/009_add_chat_strict_notation.sql"
+        ))
+        .execute(pool)
+        .await;
+        let _ = sqlx::raw_sql(include_str!(
+            "../../migrations/postgres/010_add_user_aliases.sql"
+        ))
+        .execute(pool)
+        .await;
+        let _ = sqlx::raw_sql(include_str!(
+            "../../migrations/postgres/011_add_chat_end_templates.sql"
+        ))
+        .execute(pool)
+        .await;
+        let _ = sqlx::raw_sql(include_str!(
+            "../../migrations/postgres/012_add_seeks.sql"
+        ))
+        .execute(pool)
+        .await;
+        let _ = sqlx::raw_sql(include_str!(
+            "../../migrations/postgres/013_add_matchmaking.sql"
+        ))
+        .execute(pool)
+        .await;
+        let _ = sqlx::raw_sql(include_str!(
+            "../../migrations/postgres/014_add_predictions.sql"
+        ))
+        .execute(pool)
+        .await;
+        let _ = sqlx::raw_sql(include_str!(
+            "../../migrations/postgres/015_add_chat_announcements.sql"
+        ))
+        .execute(pool)
+        .await;
+        let _ = sqlx::raw_sql(include_str!(
+            "../../migrations/postgres/016_add_feature_flags.sql"
+        ))
+        .execute(pool)
+        .await;
+        let _ = sqlx::raw_sql(include_str!(
+            "../../migrations/postgres/017_add_audit_log.sql"
+        ))
+        .execute(pool)
+        .await;
+        let _ = sqlx::raw_sql(include_str!(
+            "../../migrations/postgres/018_add_chat_bot_removed.sql"
+        ))
+        .execute(pool)
+        .await;
+        let _ = sqlx::raw_sql(include_str!(
+            "../../migrations/postgres/019_add_game_player_left.sql"
+        ))
+        .execute(pool)
+        .await;
+        let _ = sqlx::raw_sql(include_str!(
+            "../../migrations/postgres/020_add_chat_tap_moves.sql"
+        ))
+        .execute(pool)
+        .await;
+        let _ = sqlx::raw_sql(include_str!(
+            "../../migrations/postgres/021_add_chat_max_games.sql"
+        ))
+        .execute(pool)
+        .await;
+        let _ = sqlx::raw_sql(include_str!(
+            "../../migrations/postgres/022_add_game_archive.sql"
+        ))
+        .execute(pool)
+        .await;
+        let _ = sqlx::raw_sql(include_str!(
+            "../../migrations/postgres/023_add_message_indexes.sql"
+        ))
+        .execute(pool)
+        .await;
+        let _ = sqlx::raw_sql(include_str!(
+            "../../migrations/postgres/024_add_chat_pgn_files.sql"
+        ))
+        .execute(pool)
+        .await;
+        let _ = sqlx::raw_sql(include_str!(
+            "../../migrations/postgres/025_add_chat_champions.sql"
+        ))
+        .execute(pool)
+        .await;
+        let _ = sqlx::raw_sql(include_str!(
+            "../../migrations/postgres/026_add_chat_standings_thresholds.sql"
+        ))
+        .execute(pool)
+        .await;
+        let _ = sqlx::raw_sql(include_str!(
+            "../../migrations/postgres/027_add_move_comments.sql"
+        ))
+        .execute(pool)
+        .await;
+        let _ = sqlx::raw_sql(include_str!(
+            "../../migrations/postgres/028_add_draw_offer_gap.sql"
+        ))
+        .execute(pool)
+        .await;
+        let _ = sqlx::raw_sql(include_str!(
+            "../../migrations/postgres/029_add_game_pause.sql"
+        ))
+        .execute(pool)
+        .await;
     } else {
         sqlx::raw_sql(include_str!("../../migrations/sqlite/001_init.sql"))
             .execute(pool)
@@ -43,6 +171,131 @@ pub async fn run_migrations(pool: &Pool<Any>, database_url: &str) -> Result<()>
         ))
         .execute(pool)
         .await;
+        let _ = sqlx::raw_sql(include_str!(
+            "../../migrations/sqlite/005_add_move_confirmation.sql"
+        ))
+        .execute(pool)
+        .await;
+        let _ = sqlx::raw_sql(include_str!(
+            "../../migrations/sqlite/006_add_chat_settings.sql"
+        ))
+        .execute(pool)
+        .await;
+        let _ = sqlx::raw_sql(include_str!(
+            "../../migrations/sqlite/007_add_chat_orientation.sql"
+        ))
+        .execute(pool)
+        .await;
+        let _ = sqlx::raw_sql(include_str!(
+            "../../migrations/sqlite/008_add_chat_send_as_document.sql"
+        ))
+        .execute(pool)
+        .await;
+        let _ = sqlx::raw_sql(include_str!(
+            "../../migrations/sqlite/009_add_chat_strict_notation.sql"
+        ))
+        .execute(pool)
+        .await;
+        let _ = sqlx::raw_sql(include_str!(
+            "../../migrations/sqlite/010_add_user_aliases.sql"
+        ))
+        .execute(pool)
+        .await;
+        let _ = sqlx::raw_sql(include_str!(
+            "../../migrations/sqlite/011_add_chat_end_templates.sql"
+        ))
+        .execute(pool)
+        .await;
+        let _ = sqlx::raw_sql(include_str!(
+            "../../migrations/sqlite/012_add_seeks.sql"
+        ))
+        .execute(pool)
+        .await;
+        let _ = sqlx::raw_sql(include_str!(
+            "../../migrations/sqlite/013_add_matchmaking.sql"
+        ))
+        .execute(pool)
+        .await;
+        let _ = sqlx::raw_sql(include_str!(
+            "../../migrations/sqlite/014_add_predictions.sql"
+        ))
+        .execute(pool)
+        .await;
+        let _ = sqlx::raw_sql(include_str!(
+            "../../migrations/sqlite/015_add_chat_announcements.sql"
+        ))
+        .execute(pool)
+        .await;
+        let _ = sqlx::raw_sql(include_str!(
+            "../../migrations/sqlite/016_add_feature_flags.sql"
+        ))
+        .execute(pool)
+        .await;
+        let _ = sqlx::raw_sql(include_str!(
+            "../../migrations/sqlite/017_add_audit_log.sql"
+        ))
+        .execute(pool)
+        .await;
+        let _ = sqlx::raw_sql(include_str!(
+            "../../migrations/sqlite/018_add_chat_bot_removed.sql"
+        ))
+        .execute(pool)
+        .await;
+        let _ = sqlx::raw_sql(include_str!(
+            "../../migrations/sqlite/019_add_game_player_left.sql"
+        ))
+        .execute(pool)
+        .await;
+        let _ = sqlx::raw_sql(include_str!(
+            "../../migrations/sqlite/020_add_chat_tap_moves.sql"
+        ))
+        .execute(pool)
+        .await;
+        let _ = sqlx::raw_sql(include_str!(
+            "../../migrations/sqlite/021_add_chat_max_games.sql"
+        ))
+        .execute(pool)
+        .await;
+        let _ = sqlx::raw_sql(include_str!(
+            "../../migrations/sqlite/022_add_game_archive.sql"
+        ))
+        .execute(pool)
+        .await;
+        let _ = sqlx::raw_sql(include_str!(
+            "../../migrations/sqlite/023_add_message_indexes.sql"
+        ))
+        .execute(pool)
+        .await;
+        let _ = sqlx::raw_sql(include_str!(
+            "../../migrations/sqlite/024_add_chat_pgn_files.sql"
+        ))
+        .execute(pool)
+        .await;
+        let _ = sqlx::raw_sql(include_str!(
+            "../../migrations/sqlite/025_add_chat_champions.sql"
+        ))
+        .execute(pool)
+        .await;
+        let _ = sqlx::raw_sql(include_str!(
+            "../../migrations/sqlite/026_add_chat_standings_thresholds.sql"
+        ))
+        .execute(pool)
+        .await;
+        let _ = sqlx::raw_sql(include_str!(
+            "../../migrations/sqlite/027_add_move_comments.sql"
+        ))
+        .execute(pool)
+        .await;
+        let _ = sqlx::raw_sql(include_str!(
+            "../../migrations/sqlite/028_add_draw_offer_gap.sql"
+        ))
+        .execute(pool)
+        .await;
+        let _ = sqlx::raw_sql(include_str!(
+            "../../migrations/sqlite/029_add_game_pause.sql"
+        ))
+        .execute(pool)
+        .await;
     }
     Ok(())
 }
@@ -65,6 +318,17 @@ pub async fn upsert_user(pool: &Pool<Any>, user: &User) -> Result<DbUser> {
             return get_user_by_telegram_id(pool, user.id).await;
         }
 
+        // Whoever held the username before has since renamed; remember it as their alias
+        sqlx::query(
+            "INSERT INTO user_aliases (user_id, username, changed_at)
+             SELECT id, username, $3 FROM users WHERE username = $1 AND telegram_id != $2",
+        )
+        .bind(username)
+        .bind(user.id)
+        .bind(Utc::now().to_rfc3339())
+        .execute(pool)
+        .await?;
+
         sqlx::query("UPDATE users SET username = NULL WHERE username = $1 AND telegram_id != $2")
             .bind(username)
             .bind(user.id)
@@ -72,6 +336,13 @@ pub async fn upsert_user(pool: &Pool<Any>, user: &User) -> Result<DbUser> {
             .await?;
     }
 
+    let previous_username: Option<String> =
+        sqlx::query("SELECT username FROM users WHERE telegram_id = $1")
+            .bind(user.id)
+            .fetch_optional(pool)
+            .await?
+            .and_then(|row| row.get("username"));
+
     sqlx::query(
         "INSERT INTO users (telegram_id, username, first_name, last_name)
          VALUES ($1, $2, $3, $4)
@@ -87,10 +358,32 @@ pub async fn upsert_user(pool: &Pool<Any>, user: &User) -> Result<DbUser> {
     .execute(pool)
     .await?;
 
-    get_user_by_telegram_id(pool, user.id).await
+    let db_user = get_user_by_telegram_id(pool, user.id).await?;
+    if let Some(previous) = previous_username {
+        if user.username.as_deref() != Some(previous.as_str()) {
+            record_alias(pool, db_user.id, &previous).await?;
+        }
+    }
+    Ok(db_user)
+}
+
+async fn record_alias(pool: &Pool<Any>, user_id: i64, username: &str) -> Result<()> {
+    sqlx::query("INSERT INTO user_aliases (user_id, username, changed_at) VALUES ($1, $2, $3)")
+        .bind(user_id)
+        .bind(username)
+        .bind(Utc::now().to_rfc3339())
+        .execute(pool)
+        .await?;
+    Ok(())
 }
 
+/// Finds the user currently holding `username`, creating a placeholder if nobody does.
+/// Previous usernames are not followed: the name may have passed to someone the bot
+/// hasn't seen yet, and a game must not go to its former owner.
 pub async fn upsert_user_by_username(pool: &Pool<Any>, username: &str) -> Result<DbUser> {
+    if let Some(user) = get_current_user_by_username(pool, username).await? {
+        return Ok(user);
+    }
     sqlx::query(
         "INSERT INTO users (username) VALUES ($1)
          ON CONFLICT(username) DO NOTHING",
@@ -98,444 +391,1892 @@ pub async fn upsert_user_by_username(pool: &Pool<Any>, username: &str) -> Result
     .bind(username)
     .execute(pool)
     .await?;
-    get_user_by_username(pool, username).await
+    get_current_user_by_username(pool, username)
+        .await?
+        .ok_or(KamaError::Db(sqlx::Error::RowNotFound))
 }
 
-fn row_to_db_user(row: &sqlx::any::AnyRow) -> DbUser {
-    DbUser {
-        id: row.get("id"),
-        telegram_id: row.get("telegram_id"),
-        username: row.get("username"),
-        first_name: row.get("first_name"),
-        last_name: row.get("last_name"),
-        wins: row.get("wins"),
-        losses: row.get("losses"),
-        draws: row.get("draws"),
+/// Like `upsert_user_by_username`, but follows previous usernames first. For lookups
+/// such as /history, where finding the former owner is what the user asked for.
+pub async fn find_user_by_username(pool: &Pool<Any>, username: &str) -> Result<DbUser> {
+    match get_user_by_username(pool, username).await {
+        Ok(user) => Ok(user),
+        Err(KamaError::Db(sqlx::Error::RowNotFound)) => upsert_user_by_username(pool, username).await,
+        Err(e) => Err(e),
     }
 }
 
 pub async fn get_user_by_telegram_id(pool: &Pool<Any>, telegram_id: i64) -> Result<DbUser> {
-    let row = sqlx::query(
+    Ok(sqlx::query_as(
         "SELECT id, telegram_id, username, first_name, last_name, wins, losses, draws
          FROM users WHERE telegram_id = $1",
     )
     .bind(telegram_id)
     .fetch_one(pool)
-    .await?;
-
-    Ok(row_to_db_user(&row))
+    .await?)
 }
 
+/// The current holder of `username`, or failing that the user who most recently went
+/// by it.
 pub async fn get_user_by_username(pool: &Pool<Any>, username: &str) -> Result<DbUser> {
-    let row = sqlx::query(
-        "SELECT id, telegram_id, username, first_name, last_name, wins, losses, draws
-         FROM users WHERE username = $1",
+    if let Some(user) = get_current_user_by_username(pool, username).await? {
+        return Ok(user);
+    }
+
+    Ok(sqlx::query_as(
+        "SELECT u.id, u.telegram_id, u.username, u.first_name, u.last_name, u.wins, u.losses, u.draws
+         FROM user_aliases a
+         JOIN users u ON u.id = a.user_id
+         WHERE a.username = $1
+         ORDER BY a.changed_at DESC, a.id DESC
+         LIMIT 1",
     )
     .bind(username)
     .fetch_one(pool)
-    .await?;
+    .await?)
+}
 
-    Ok(row_to_db_user(&row))
+async fn get_current_user_by_username(pool: &Pool<Any>, username: &str) -> Result<Option<DbUser>> {
+    Ok(sqlx::query_as(
+        "SELECT id, telegram_id, username, first_name, last_name, wins, losses, draws
+         FROM users WHERE username = $1",
+    )
+    .bind(username)
+    .fetch_optional(pool)
+    .await?)
 }
 
 pub async fn get_user_by_id(pool: &Pool<Any>, id: i64) -> Result<DbUser> {
-    let row = sqlx::query(
+    Ok(sqlx::query_as(
         "SELECT id, telegram_id, username, first_name, last_name, wins, losses, draws
          FROM users WHERE id = $1",
     )
     .bind(id)
     .fetch_one(pool)
-    .await?;
-
-    Ok(row_to_db_user(&row))
+    .await?)
 }
 
-pub async fn create_game(
+/// Folds `from_id` into `into_id`: games, moves, stats and predictions move over, the
+/// Telegram id and names fill any gaps on the kept row, and `from_id` is deleted.
+/// All or nothing.
+pub async fn merge_users(pool: &Pool<Any>, from_id: i64, into_id: i64) -> Result<DbUser> {
+    if from_id == into_id {
+        return Err(KamaError::Invalid("Cannot merge a user into itself".to_string()));
+    }
+    let from = get_user_by_id(pool, from_id).await?;
+    let into = get_user_by_id(pool, into_id).await?;
+
+    let mut tx = pool.begin().await?;
+
+    let shared: i64 = sqlx::query(&format!(
+        "SELECT COUNT(*) AS count FROM {ALL_GAMES} AS g
+         WHERE (g.white_user_id = $1 AND g.black_user_id = $2)
+            OR (g.white_user_id = $2 AND g.black_user_id = $1)"
+    ))
+    .bind(from_id)
+    .bind(into_id)
+    .fetch_one(&mut *tx)
+    .await?
+    .get("count");
+    if shared > 0 {
+        return Err(KamaError::Invalid(
+            "These users have played each other, so they are different people".to_string(),
+        ));
+    }
+
+    for statement in [
+        "UPDATE games SET white_user_id = $1 WHERE white_user_id = $2",
+        "UPDATE games SET black_user_id = $1 WHERE black_user_id = $2",
+        "UPDATE games SET draw_proposed_by = $1 WHERE draw_proposed_by = $2",
+        "UPDATE games SET left_player_id = $1 WHERE left_player_id = $2",
+        "UPDATE games SET pause_requested_by = $1 WHERE pause_requested_by = $2",
+        "UPDATE moves SET played_by = $1 WHERE played_by = $2",
+        "UPDATE games_archive SET white_user_id = $1 WHERE white_user_id = $2",
+        "UPDATE games_archive SET black_user_id = $1 WHERE black_user_id = $2",
+        "UPDATE games_archive SET draw_proposed_by = $1 WHERE draw_proposed_by = $2",
+        "UPDATE games_archive SET left_player_id = $1 WHERE left_player_id = $2",
+        "UPDATE games_archive SET pause_requested_by = $1 WHERE pause_requested_by = $2",
+        "UPDATE moves_archive SET played_by = $1 WHERE played_by = $2",
+        "UPDATE user_aliases SET user_id = $1 WHERE user_id = $2",
+        "UPDATE chat_champions SET user_id = $1 WHERE user_id = $2",
+        "UPDATE seeks SET user_id = $1 WHERE user_id = $2",
+        // One queue entry per user: when both are queued, `from_id`'s goes with it
+        "UPDATE match_queue SET user_id = $1 WHERE user_id = $2
+            AND NOT EXISTS (SELECT 1 FROM match_queue WHERE user_id = $1)",
+        // A vote in a poll both users voted in is dropped with `from_id`; the kept row's stands
+        "UPDATE predictions SET user_id = $1 WHERE user_id = $2
+            AND poll_id NOT IN (SELECT poll_id FROM predictions WHERE user_id = $1)",
+        "INSERT INTO prediction_stats (chat_id, user_id, correct, total)
+         SELECT chat_id, $1, correct, total FROM prediction_stats WHERE user_id = $2
+         ON CONFLICT (chat_id, user_id) DO UPDATE SET
+            correct = prediction_stats.correct + excluded.correct,
+            total = prediction_stats.total + excluded.total",
+    ] {
+        sqlx::query(statement)
+            .bind(into_id)
+            .bind(from_id)
+            .execute(&mut *tx)
+            .await?;
+    }
+
+    // The audit log records Telegram ids; when the kept row has none it takes over `from_id`'s
+    if let (Some(from_telegram), Some(into_telegram)) = (from.telegram_id, into.telegram_id) {
+        sqlx::query("UPDATE audit_log SET telegram_id = $1 WHERE telegram_id = $2")
+            .bind(into_telegram)
+            .bind(from_telegram)
+            .execute(&mut *tx)
+            .await?;
+    }
+
+    // Delete first so the unique telegram_id and username can move to the kept row
+    sqlx::query("DELETE FROM users WHERE id = $1")
+        .bind(from_id)
+        .execute(&mut *tx)
+        .await?;
+
+    sqlx::query(
+        "UPDATE users SET
+            telegram_id = COALESCE(telegram_id, $1),
+            username = COALESCE(username, $2),
+            first_name = COALESCE(first_name, $3),
+            last_name = COALESCE(last_name, $4),
+            wins = wins + $5,
+            losses = losses + $6,
+            draws = draws + $7
+         WHERE id = $8",
+    )
+    .bind(from.telegram_id)
+    .bind(&from.username)
+    .bind(&from.first_name)
+    .bind(&from.last_name)
+    .bind(from.wins)
+    .bind(from.losses)
+    .bind(from.draws)
+    .bind(into_id)
+    .execute(&mut *tx)
+    .await?;
+
+    // The kept row keeps its own username; the other one stays findable as an alias
+    if let (Some(username), Some(_)) = (&from.username, &into.username) {
+        sqlx::query("INSERT INTO user_aliases (user_id, username, changed_at) VALUES ($1, $2, $3)")
+            .bind(into_id)
+            .bind(username)
+            .bind(Utc::now().to_rfc3339())
+            .execute(&mut *tx)
+            .await?;
+    }
+
+    tx.commit().await?;
+
+    get_user_by_id(pool, into_id).await
+}
+
+pub async fn create_game(
+    pool: &Pool<Any>,
+    chat_id: i64,
+    white_user_id: i64,
+    black_user_id: i64,
+    fen: &str,
+    turn: Turn,
+) -> Result<i64> {
+    let now = Utc::now().to_rfc3339();
+    let row = sqlx::query(
+        "INSERT INTO games (chat_id, white_user_id, black_user_id, current_fen, turn, started_at)
+         VALUES ($1, $2, $3, $4, $5, $6)
+         RETURNING id",
+    )
+    .bind(chat_id)
+    .bind(white_user_id)
+    .bind(black_user_id)
+    .bind(fen)
+    .bind(turn.as_str())
+    .bind(now)
+    .fetch_one(pool)
+    .await?;
+
+    Ok(row.get("id"))
+}
+
+pub async fn update_game_message(pool: &Pool<Any>, game_id: i64, message_id: i64) -> Result<()> {
+    sqlx::query("UPDATE games SET last_message_id = $1 WHERE id = $2")
+        .bind(message_id)
+        .bind(game_id)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+pub async fn update_game_fen(pool: &Pool<Any>, game_id: i64, fen: &str, turn: Turn) -> Result<()> {
+    sqlx::query("UPDATE games SET current_fen = $1, turn = $2 WHERE id = $3")
+        .bind(fen)
+        .bind(turn.as_str())
+        .bind(game_id)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+/// Marks the game finished with `result`.
+pub async fn update_game_result(pool: &Pool<Any>, game_id: i64, result: GameResult) -> Result<()> {
+    let ended = Utc::now().to_rfc3339();
+    sqlx::query(
+        "UPDATE games SET result = $1, status = $2, ended_at = $3, draw_proposed_by = NULL WHERE id = $4",
+    )
+    .bind(result.as_str())
+    .bind(GameStatus::Finished.as_str())
+    .bind(ended)
+    .bind(game_id)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// Stores the offer, and the ply it was made at as the player's latest, for
+/// `last_draw_offer_ply`.
+pub async fn propose_draw(pool: &Pool<Any>, game_id: i64, player_id: i64, message_id: i64) -> Result<()> {
+    sqlx::query(
+        "UPDATE games SET draw_proposed_by = $1, draw_proposal_message_id = $2,
+            white_draw_offer_ply = CASE WHEN white_user_id = $1
+                THEN (SELECT COUNT(*) FROM moves WHERE game_id = $3) ELSE white_draw_offer_ply END,
+            black_draw_offer_ply = CASE WHEN black_user_id = $1
+                THEN (SELECT COUNT(*) FROM moves WHERE game_id = $3) ELSE black_draw_offer_ply END
+         WHERE id = $3",
+    )
+    .bind(player_id)
+    .bind(message_id)
+    .bind(game_id)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// Moves played in the game when the player last offered a draw, if they have.
+pub async fn last_draw_offer_ply(pool: &Pool<Any>, game_id: i64, player_id: i64) -> Result<Option<i64>> {
+    let row: Option<(Option<i64>,)> = sqlx::query_as(
+        "SELECT CASE WHEN white_user_id = $1 THEN white_draw_offer_ply ELSE black_draw_offer_ply END
+         FROM games WHERE id = $2",
+    )
+    .bind(player_id)
+    .bind(game_id)
+    .fetch_optional(pool)
+    .await?;
+    Ok(row.and_then(|(ply,)| ply))
+}
+
+pub async fn clear_draw_proposal(pool: &Pool<Any>, game_id: i64) -> Result<()> {
+    sqlx::query("UPDATE games SET draw_proposed_by = NULL, draw_proposal_message_id = NULL WHERE id = $1")
+        .bind(game_id)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+pub async fn set_confirm_moves(pool: &Pool<Any>, game_id: i64, enabled: bool) -> Result<()> {
+    sqlx::query("UPDATE games SET confirm_moves = $1 WHERE id = $2")
+        .bind(enabled as i64)
+        .bind(game_id)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+pub async fn set_pending_move(
+    pool: &Pool<Any>,
+    game_id: i64,
+    uci: &str,
+    message_id: i64,
+) -> Result<()> {
+    sqlx::query("UPDATE games SET pending_move = $1, pending_move_message_id = $2 WHERE id = $3")
+        .bind(uci)
+        .bind(message_id)
+        .bind(game_id)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+pub async fn clear_pending_move(pool: &Pool<Any>, game_id: i64) -> Result<()> {
+    sqlx::query("UPDATE games SET pending_move = NULL, pending_move_message_id = NULL WHERE id = $1")
+        .bind(game_id)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+/// Adds a finished game's result to both players' records in one statement.
+const UPDATE_PLAYER_STATS: &str = "UPDATE users SET
+        wins = wins + CASE WHEN id = $1 THEN $3 ELSE $4 END,
+        losses = losses + CASE WHEN id = $1 THEN $4 ELSE $3 END,
+        draws = draws + $5
+     WHERE id = $1 OR id = $2";
+
+/// (white win, black win, draw) increments for a result.
+fn stats_deltas(result: GameResult) -> (i64, i64, i64) {
+    match result {
+        GameResult::WhiteWins => (1, 0, 0),
+        GameResult::BlackWins => (0, 1, 0),
+        GameResult::Draw => (0, 0, 1),
+    }
+}
+
+pub async fn update_player_stats(
+    pool: &Pool<Any>,
+    white_id: i64,
+    black_id: i64,
+    result: GameResult,
+) -> Result<()> {
+    let (white_win, black_win, draw) = stats_deltas(result);
+    sqlx::query(UPDATE_PLAYER_STATS)
+        .bind(white_id)
+        .bind(black_id)
+        .bind(white_win)
+        .bind(black_win)
+        .bind(draw)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+/// Finishes an ongoing game with `result` and adds it to both players' records, in one
+/// transaction. Returns false, changing nothing, when the game already ended, e.g. by a
+/// move or a second tap processed at the same time.
+pub async fn settle_game(pool: &Pool<Any>, game: &GameRow, result: GameResult) -> Result<bool> {
+    let mut tx = pool.begin().await?;
+    let updated = sqlx::query(
+        "UPDATE games SET result = $1, status = $2, ended_at = $3, draw_proposed_by = NULL
+         WHERE id = $4 AND status = 'ongoing'",
+    )
+    .bind(result.as_str())
+    .bind(GameStatus::Finished.as_str())
+    .bind(Utc::now().to_rfc3339())
+    .bind(game.id)
+    .execute(&mut *tx)
+    .await?;
+    if updated.rows_affected() != 1 {
+        tx.rollback().await?;
+        return Ok(false);
+    }
+
+    let (white_win, black_win, draw) = stats_deltas(result);
+    sqlx::query(UPDATE_PLAYER_STATS)
+        .bind(game.white_user_id)
+        .bind(game.black_user_id)
+        .bind(white_win)
+        .bind(black_win)
+        .bind(draw)
+        .execute(&mut *tx)
+        .await?;
+
+    tx.commit().await?;
+    Ok(true)
+}
+
+/// Stores a played move together with the game's new state: the move row (numbered in the
+/// same statement), the FEN and turn, a cleared draw offer and, once the game is finished,
+/// the result and both players' stats. One transaction, so SQLite syncs to disk
+/// once per move instead of once per statement.
+/// Nothing is stored and false is returned when the game is no longer ongoing at
+/// `previous_fen`, i.e. another move (often a double tap) got there first, or when it
+/// was paused in the meantime.
+pub async fn record_move(
+    pool: &Pool<Any>,
+    game: &GameRow,
+    previous_fen: &str,
+    player_id: i64,
+    uci: &str,
+    san: &str,
+    comment: Option<&str>,
+) -> Result<bool> {
+    let now = Utc::now().to_rfc3339();
+    let finished = game.status == GameStatus::Finished;
+    let mut tx = pool.begin().await?;
+
+    // Updated first, so a concurrent move for the same position waits on the row and
+    // then finds it changed
+    let updated = sqlx::query(
+        "UPDATE games SET current_fen = $1, turn = $2, status = $3, result = $4,
+            ended_at = CASE WHEN $5 = 1 THEN $6 ELSE ended_at END,
+            draw_proposed_by = NULL, draw_proposal_message_id = NULL, pause_requested_by = NULL
+         WHERE id = $7 AND current_fen = $8 AND status = 'ongoing' AND paused = 0",
+    )
+    .bind(&game.current_fen)
+    .bind(game.turn.as_str())
+    .bind(game.status.as_str())
+    .bind(game.result.map(|result| result.as_str()))
+    .bind(finished as i64)
+    .bind(&now)
+    .bind(game.id)
+    .bind(previous_fen)
+    .execute(&mut *tx)
+    .await?;
+    if updated.rows_affected() != 1 {
+        tx.rollback().await?;
+        return Ok(false);
+    }
+
+    sqlx::query(
+        "INSERT INTO moves (game_id, move_number, uci, san, played_by, played_at, comment)
+         SELECT $1, COALESCE(MAX(move_number), 0) + 1, $2, $3, $4, $5, $6 FROM moves WHERE game_id = $1",
+    )
+    .bind(game.id)
+    .bind(uci)
+    .bind(san)
+    .bind(player_id)
+    .bind(&now)
+    .bind(comment)
+    .execute(&mut *tx)
+    .await?;
+
+    if let Some((white_win, black_win, draw)) = game.result.filter(|_| finished).map(stats_deltas) {
+        sqlx::query(UPDATE_PLAYER_STATS)
+            .bind(game.white_user_id)
+            .bind(game.black_user_id)
+            .bind(white_win)
+            .bind(black_win)
+            .bind(draw)
+            .execute(&mut *tx)
+            .await?;
+    }
+
+    tx.commit().await?;
+    Ok(true)
+}
+
+pub async fn insert_move(
+    pool: &Pool<Any>,
+    game_id: i64,
+    player_id: i64,
+    move_number: i64,
+    uci: &str,
+    san: Option<&str>,
+) -> Result<()> {
+    let now = Utc::now().to_rfc3339();
+    sqlx::query(
+        "INSERT INTO moves (game_id, move_number, uci, san, played_by, played_at)
+         VALUES ($1, $2, $3, $4, $5, $6)",
+    )
+    .bind(game_id)
+    .bind(move_number)
+    .bind(uci)
+    .bind(san)
+    .bind(player_id)
+    .bind(now)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+pub async fn next_move_number(pool: &Pool<Any>, game_id: i64) -> Result<i64> {
+    let row = sqlx::query(
+        "SELECT COALESCE(MAX(move_number), 0) + 1 as next FROM moves WHERE game_id = $1",
+    )
+    .bind(game_id)
+    .fetch_one(pool)
+    .await?;
+    Ok(row.get("next"))
+}
+
+/// Think time per player (keyed by user id) in one game.
+pub async fn get_game_think_times(pool: &Pool<Any>, game_id: i64) -> Result<HashMap<i64, ThinkTime>> {
+    let rows = sqlx::query(
+        "SELECT m.game_id, m.played_by, m.played_at, g.started_at
+         FROM moves m
+         JOIN games g ON g.id = m.game_id
+         WHERE m.game_id = $1
+         ORDER BY m.move_number ASC",
+    )
+    .bind(game_id)
+    .fetch_all(pool)
+    .await?;
+    Ok(accumulate_think_times(&rows))
+}
+
+/// Think time of a user over all their games in a chat.
+pub async fn get_user_think_time(pool: &Pool<Any>, user_id: i64, chat_id: i64) -> Result<ThinkTime> {
+    let rows = sqlx::query(&format!(
+        "SELECT m.game_id, m.played_by, m.played_at, g.started_at
+         FROM {ALL_MOVES} AS m
+         JOIN {ALL_GAMES} AS g ON g.id = m.game_id
+         WHERE g.chat_id = $1
+           AND (g.white_user_id = $2 OR g.black_user_id = $2)
+         ORDER BY m.game_id, m.move_number ASC"
+    ))
+    .bind(chat_id)
+    .bind(user_id)
+    .fetch_all(pool)
+    .await?;
+    Ok(accumulate_think_times(&rows)
+        .remove(&user_id)
+        .unwrap_or_default())
+}
+
+/// Each move's think time runs from the previous move, or from the start of the game
+/// for the first one. Rows must be ordered by game and move number.
+fn accumulate_think_times(rows: &[sqlx::any::AnyRow]) -> HashMap<i64, ThinkTime> {
+    let parse = |text: &str| DateTime::parse_from_rfc3339(text).ok();
+    let mut result: HashMap<i64, ThinkTime> = HashMap::new();
+    let mut previous: Option<(i64, DateTime<chrono::FixedOffset>)> = None;
+
+    for row in rows {
+        let game_id: i64 = row.get("game_id");
+        let played_by: i64 = row.get("played_by");
+        let Some(played_at) = parse(&row.get::<String, _>("played_at")) else {
+            continue;
+        };
+        let since = match previous {
+            Some((game, at)) if game == game_id => Some(at),
+            _ => parse(&row.get::<String, _>("started_at")),
+        };
+        if let Some(since) = since {
+            let secs = (played_at - since).num_seconds().max(0);
+            result.entry(played_by).or_default().record(secs);
+        }
+        previous = Some((game_id, played_at));
+    }
+    result
+}
+
+async fn get_games_san_moves(pool: &Pool<Any>, game_ids: &[i64]) -> HashMap<i64, Vec<String>> {
+    if game_ids.is_empty() {
+        return HashMap::new();
+    }
+
+    let placeholders: String = game_ids
+        .iter()
+        .enumerate()
+        .map(|(i, _)| format!("${}", i + 1))
+        .collect::<Vec<_>>()
+        .join(",");
+    let sql = format!(
+        "SELECT m.game_id, m.san, m.uci FROM {ALL_MOVES} AS m WHERE m.game_id IN ({}) ORDER BY m.game_id, m.move_number ASC",
+        placeholders
+    );
+
+    let mut query = sqlx::query(&sql);
+    for id in game_ids {
+        query = query.bind(id);
+    }
+
+    let rows = match query.fetch_all(pool).await {
+        Ok(r) => r,
+        Err(_) => return HashMap::new(),
+    };
+
+    let mut result: HashMap<i64, Vec<String>> = HashMap::new();
+    for row in rows {
+        let game_id: i64 = row.get("game_id");
+        let san: Option<String> = row.get("san");
+        let uci: String = row.get("uci");
+        result.entry(game_id).or_default().push(san.unwrap_or(uci));
+    }
+    result
+}
+
+/// Moves of one game in order, as SAN where it was recorded and UCI otherwise.
+pub async fn get_game_san_moves(pool: &Pool<Any>, game_id: i64) -> Result<Vec<String>> {
+    let rows = sqlx::query(&format!(
+        "SELECT m.san, m.uci FROM {ALL_MOVES} AS m WHERE m.game_id = $1 ORDER BY m.move_number ASC"
+    ))
+        .bind(game_id)
+        .fetch_all(pool)
+        .await?;
+    Ok(rows
+        .into_iter()
+        .map(|row| {
+            let san: Option<String> = row.get("san");
+            san.unwrap_or_else(|| row.get("uci"))
+        })
+        .collect())
+}
+
+/// Ids of the user's finished games in the chat, archived ones included, oldest first.
+/// Paged by id: pass the last id of the previous page as `after_id`, 0 for the first.
+pub async fn get_user_finished_game_ids(
+    pool: &Pool<Any>,
+    chat_id: i64,
+    user_id: i64,
+    after_id: i64,
+    limit: i64,
+) -> Result<Vec<i64>> {
+    let rows: Vec<(i64,)> = sqlx::query_as(&format!(
+        "SELECT g.id FROM {ALL_GAMES} AS g
+         WHERE g.chat_id = $1 AND (g.white_user_id = $2 OR g.black_user_id = $2)
+           AND g.status = 'finished' AND g.id > $3
+         ORDER BY g.id ASC
+         LIMIT $4"
+    ))
+    .bind(chat_id)
+    .bind(user_id)
+    .bind(after_id)
+    .bind(limit)
+    .fetch_all(pool)
+    .await?;
+    Ok(rows.into_iter().map(|(id,)| id).collect())
+}
+
+/// The players' comments on a game's moves, one entry per move in order.
+pub async fn get_game_move_comments(pool: &Pool<Any>, game_id: i64) -> Result<Vec<Option<String>>> {
+    let rows = sqlx::query(&format!(
+        "SELECT m.comment FROM {ALL_MOVES} AS m WHERE m.game_id = $1 ORDER BY m.move_number ASC"
+    ))
+    .bind(game_id)
+    .fetch_all(pool)
+    .await?;
+    Ok(rows.into_iter().map(|row| row.get("comment")).collect())
+}
+
+fn format_history_lines(
+    history_rows: &[HistoryRow],
+    all_moves: &HashMap<i64, Vec<String>>,
+) -> Vec<String> {
+    let mut lines = Vec::new();
+    for row in history_rows {
+        let result = row.result.clone().unwrap_or_else(|| "ongoing".to_string());
+        let white_name = crate::utils::format_username(&row.white_username);
+        let black_name = crate::utils::format_username(&row.black_username);
+        let moves = all_moves.get(&row.id).map(|v| v.as_slice()).unwrap_or(&[]);
+        let lichess_url = crate::utils::lichess_analysis_url(moves);
+        let times = crate::utils::format_game_times(&row.started_at, row.ended_at.as_deref())
+            .map(|times| format!(" · {}", times))
+            .unwrap_or_default();
+        lines.push(format!(
+            "#{}: {} vs {} ({}){} - <a href=\"{}\">analysis</a>",
+            row.local_num, white_name, black_name, result, times, lichess_url
+        ));
+    }
+    lines
+}
+
+fn format_history_output(lines: &[String]) -> String {
+    let mut output = lines.join("\n");
+    if lines.is_empty() {
+        output.push_str("No games yet.");
+    }
+    output.push_str("\nUse /history &lt;page&gt; for more.");
+    output
+}
+
+/// Mapped by hand: `confirm_moves` is an INTEGER column in both backends, which `FromRow`
+/// can't decode into a `bool` through the Any driver.
+fn row_to_game_row(row: &sqlx::any::AnyRow) -> GameRow {
+    GameRow {
+        id: row.get("id"),
+        chat_id: row.get("chat_id"),
+        white_user_id: row.get("white_user_id"),
+        black_user_id: row.get("black_user_id"),
+        current_fen: row.get("current_fen"),
+        // Unknown values never come from this code; treat such rows as unplayable
+        turn: Turn::parse(row.get("turn")).unwrap_or(Turn::White),
+        status: GameStatus::parse(row.get("status")).unwrap_or(GameStatus::Finished),
+        result: row
+            .get::<Option<String>, _>("result")
+            .as_deref()
+            .and_then(GameResult::parse),
+        last_message_id: row.get("last_message_id"),
+        draw_proposed_by: row.get("draw_proposed_by"),
+        draw_proposal_message_id: row.get("draw_proposal_message_id"),
+        confirm_moves: row.get::<i64, _>("confirm_moves") != 0,
+        pending_move: row.get("pending_move"),
+        pending_move_message_id: row.get("pending_move_message_id"),
+        peer_chat_id: row.get("peer_chat_id"),
+    }
+}
+
+pub async fn get_game_by_id(pool: &Pool<Any>, game_id: i64) -> Result<Option<GameRow>> {
+    let row = sqlx::query(
+        "SELECT id, chat_id, white_user_id, black_user_id, current_fen, turn, status, result, last_message_id, draw_proposed_by, draw_proposal_message_id, confirm_moves, pending_move, pending_move_message_id, peer_chat_id
+         FROM games
+         WHERE id = $1",
+    )
+    .bind(game_id)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(row.map(|r| row_to_game_row(&r)))
+}
+
+/// The game, archived or not. Gameplay only deals with live games and uses `get_game_by_id`.
+pub async fn find_game_with_archive(pool: &Pool<Any>, game_id: i64) -> Result<Option<GameRow>> {
+    let row = sqlx::query(&format!(
+        "SELECT g.id, g.chat_id, g.white_user_id, g.black_user_id, g.current_fen, g.turn, g.status, g.result, g.last_message_id, g.draw_proposed_by, g.draw_proposal_message_id, g.confirm_moves, g.pending_move, g.pending_move_message_id, g.peer_chat_id
+         FROM {ALL_GAMES} AS g
+         WHERE g.id = $1"
+    ))
+    .bind(game_id)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(row.map(|r| row_to_game_row(&r)))
+}
+
+/// The game's number in its chat: 1 for the chat's first game, and so on. Archived games
+/// keep their place.
+pub async fn get_game_number(pool: &Pool<Any>, game: &GameRow) -> Result<i64> {
+    let (number,): (i64,) = sqlx::query_as(&format!("SELECT COUNT(*) FROM {ALL_GAMES} AS g WHERE g.chat_id = $1 AND g.id <= $2"))
+        .bind(game.chat_id)
+        .bind(game.id)
+        .fetch_one(pool)
+        .await?;
+    Ok(number)
+}
+
+/// The chat's `number`th game, counting from 1.
+pub async fn find_game_by_number(pool: &Pool<Any>, chat_id: i64, number: i64) -> Result<Option<GameRow>> {
+    if number < 1 {
+        return Ok(None);
+    }
+    let row = sqlx::query(&format!(
+        "SELECT g.id, g.chat_id, g.white_user_id, g.black_user_id, g.current_fen, g.turn, g.status, g.result, g.last_message_id, g.draw_proposed_by, g.draw_proposal_message_id, g.confirm_moves, g.pending_move, g.pending_move_message_id, g.peer_chat_id
+         FROM {ALL_GAMES} AS g
+         WHERE g.chat_id = $1
+         ORDER BY g.id ASC
+         LIMIT 1 OFFSET $2"
+    ))
+    .bind(chat_id)
+    .bind(number - 1)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(row.map(|r| row_to_game_row(&r)))
+}
+
+/// When the game started and, once over, ended (RFC 3339).
+pub async fn get_game_times(pool: &Pool<Any>, game_id: i64) -> Result<Option<(String, Option<String>)>> {
+    Ok(sqlx::query_as(&format!("SELECT g.started_at, g.ended_at FROM {ALL_GAMES} AS g WHERE g.id = $1"))
+        .bind(game_id)
+        .fetch_optional(pool)
+        .await?)
+}
+
+/// Moves and duration of a game, for its end-of-game summary.
+pub async fn get_game_summary(pool: &Pool<Any>, game_id: i64) -> Result<GameSummary> {
+    let moves = get_game_san_moves(pool, game_id).await?;
+    let duration_secs = get_game_times(pool, game_id)
+        .await?
+        .and_then(|(started_at, ended_at)| crate::utils::elapsed_secs(&started_at, ended_at.as_deref()?));
+    Ok(GameSummary { moves, duration_secs })
+}
+
+pub async fn find_ongoing_game(
+    pool: &Pool<Any>,
+    chat_id: i64,
+    white_id: i64,
+    black_id: i64,
+) -> Result<Option<GameRow>> {
+    let row = sqlx::query(
+        "SELECT id, chat_id, white_user_id, black_user_id, current_fen, turn, status, result, last_message_id, draw_proposed_by, draw_proposal_message_id, confirm_moves, pending_move, pending_move_message_id, peer_chat_id
+         FROM games
+         WHERE chat_id = $1 AND status = 'ongoing'
+           AND ((white_user_id = $2 AND black_user_id = $3)
+             OR (white_user_id = $3 AND black_user_id = $2))
+         LIMIT 1",
+    )
+    .bind(chat_id)
+    .bind(white_id)
+    .bind(black_id)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(row.map(|r| row_to_game_row(&r)))
+}
+
+pub async fn find_game_by_message(
+    pool: &Pool<Any>,
+    chat_id: i64,
+    message_id: i64,
+) -> Result<Option<GameRow>> {
+    let row = sqlx::query(
+        "SELECT g.id, g.chat_id, g.white_user_id, g.black_user_id, g.current_fen, g.turn, g.status, g.result, g.last_message_id, g.draw_proposed_by, g.draw_proposal_message_id, g.confirm_moves, g.pending_move, g.pending_move_message_id, g.peer_chat_id
+         FROM games g
+         WHERE (g.chat_id = $1 OR g.peer_chat_id = $1)
+           AND ((g.chat_id = $1 AND (g.last_message_id = $2 OR g.draw_proposal_message_id = $2))
+                OR EXISTS (
+                    SELECT 1 FROM game_messages gm
+                    WHERE gm.game_id = g.id AND gm.message_id = $2
+                      AND COALESCE(gm.chat_id, g.chat_id) = $1
+                ))
+         LIMIT 1",
+    )
+    .bind(chat_id)
+    .bind(message_id)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(row.map(|r| row_to_game_row(&r)))
+}
+
+/// Returns the move number and SAN (or UCI when SAN is missing) of the game's latest move.
+pub async fn get_last_move(pool: &Pool<Any>, game_id: i64) -> Result<Option<(i64, String)>> {
+    let row = sqlx::query(
+        "SELECT move_number, uci, san FROM moves WHERE game_id = $1
+         ORDER BY move_number DESC LIMIT 1",
+    )
+    .bind(game_id)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(row.map(|row| {
+        let san: Option<String> = row.get("san");
+        let uci: String = row.get("uci");
+        (row.get("move_number"), san.unwrap_or(uci))
+    }))
+}
+
+pub async fn insert_game_message(
+    pool: &Pool<Any>,
+    game_id: i64,
+    chat_id: i64,
+    message_id: i64,
+) -> Result<()> {
+    let now = Utc::now().to_rfc3339();
+    sqlx::query(
+        "INSERT INTO game_messages (game_id, chat_id, message_id, created_at)
+         VALUES ($1, $2, $3, $4)",
+    )
+    .bind(game_id)
+    .bind(chat_id)
+    .bind(message_id)
+    .bind(now)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// Messages tracked for the game in one of its chats. Rows from before messages recorded
+/// their chat belong to the game's own chat.
+pub async fn get_game_message_ids(pool: &Pool<Any>, game_id: i64, chat_id: i64) -> Result<Vec<i64>> {
+    let rows = sqlx::query(
+        "SELECT gm.message_id FROM game_messages gm
+         JOIN games g ON g.id = gm.game_id
+         WHERE gm.game_id = $1 AND COALESCE(gm.chat_id, g.chat_id) = $2
+         ORDER BY gm.created_at ASC",
+    )
+    .bind(game_id)
+    .bind(chat_id)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows.into_iter().map(|row| row.get("message_id")).collect())
+}
+
+pub async fn delete_game_messages(pool: &Pool<Any>, game_id: i64, chat_id: i64) -> Result<()> {
+    sqlx::query(
+        "DELETE FROM game_messages
+         WHERE game_id = $1
+           AND COALESCE(chat_id, (SELECT g.chat_id FROM games g WHERE g.id = $1)) = $2",
+    )
+    .bind(game_id)
+    .bind(chat_id)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// Every column of `games`, which `games_archive` mirrors.
+macro_rules! game_columns {
+    () => {
+        "id, chat_id, white_user_id, black_user_id, current_fen, turn, status, result, started_at, ended_at, last_message_id, draw_proposed_by, draw_proposal_message_id, confirm_moves, pending_move, pending_move_message_id, peer_chat_id, left_player_id, left_at, white_draw_offer_ply, black_draw_offer_ply, paused, pause_requested_by"
+    };
+}
+
+/// Every column of `moves`, which `moves_archive` mirrors.
+macro_rules! move_columns {
+    () => {
+        "id, game_id, move_number, uci, san, played_by, played_at, comment"
+    };
+}
+
+/// Live and archived games together, for reads that cover a chat's whole past. Needs an
+/// alias where it is used.
+const ALL_GAMES: &str = concat!(
+    "(SELECT ", game_columns!(), " FROM games UNION ALL SELECT ", game_columns!(), " FROM games_archive)"
+);
+
+/// Live and archived moves together; see `ALL_GAMES`.
+const ALL_MOVES: &str = concat!(
+    "(SELECT ", move_columns!(), " FROM moves UNION ALL SELECT ", move_columns!(), " FROM moves_archive)"
+);
+
+/// Each decided game in chat `$1` once per player, from their side: `player_id`, `won`,
+/// `lost`, `drawn` (each 0 or 1) and `ended_at`. Aborted games don't count.
+fn player_results() -> String {
+    let side = |player: &str, win: &str, loss: &str| {
+        format!(
+            "SELECT {player} AS player_id,
+                    CASE WHEN result = '{win}' THEN 1 ELSE 0 END AS won,
+                    CASE WHEN result = '{loss}' THEN 1 ELSE 0 END AS lost,
+                    CASE WHEN result = '1/2-1/2' THEN 1 ELSE 0 END AS drawn,
+                    ended_at
+             FROM {ALL_GAMES} AS g
+             WHERE chat_id = $1 AND result IN ('1-0', '0-1', '1/2-1/2')"
+        )
+    };
+    format!(
+        "({} UNION ALL {})",
+        side("white_user_id", "1-0", "0-1"),
+        side("black_user_id", "0-1", "1-0")
+    )
+}
+
+const CHAT_SETTINGS_COLUMNS: &str =
+    "chat_id, coordinates, orientation, send_as_document, strict_notation, win_template, draw_template, prediction_polls, announcements, tap_moves, max_games, pgn_files, standings_min_games, standings_inactive_days, draw_offer_gap";
+
+/// Links a second chat to a game played over private chats; its boards are mirrored there.
+pub async fn set_game_peer_chat(pool: &Pool<Any>, game_id: i64, peer_chat_id: i64) -> Result<()> {
+    sqlx::query("UPDATE games SET peer_chat_id = $1 WHERE id = $2")
+        .bind(peer_chat_id)
+        .bind(game_id)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+/// Running games in the chat, and how many of them are between `user_a` and `user_b`.
+pub async fn count_ongoing_games(pool: &Pool<Any>, chat_id: i64, user_a: i64, user_b: i64) -> Result<(i64, i64)> {
+    let row = sqlx::query(
+        "SELECT COUNT(*) AS total,
+                COALESCE(SUM(CASE WHEN (white_user_id = $2 AND black_user_id = $3)
+                                    OR (white_user_id = $3 AND black_user_id = $2) THEN 1 ELSE 0 END), 0) AS pair
+         FROM games
+         WHERE chat_id = $1 AND status = 'ongoing'",
+    )
+    .bind(chat_id)
+    .bind(user_a)
+    .bind(user_b)
+    .fetch_one(pool)
+    .await?;
+    Ok((row.get("total"), row.get("pair")))
+}
+
+/// Whether the two users already play each other over private chats.
+pub async fn has_ongoing_peer_game(pool: &Pool<Any>, user_a: i64, user_b: i64) -> Result<bool> {
+    let row = sqlx::query(
+        "SELECT id FROM games
+         WHERE status = 'ongoing' AND peer_chat_id IS NOT NULL
+           AND ((white_user_id = $1 AND black_user_id = $2)
+                OR (white_user_id = $2 AND black_user_id = $1))
+         LIMIT 1",
+    )
+    .bind(user_a)
+    .bind(user_b)
+    .fetch_optional(pool)
+    .await?;
+    Ok(row.is_some())
+}
+
+/// Ends every ongoing game played in the chat, as either of a peer game's chats, with the
+/// PGN "unknown result" `*`. Returns the games as they were.
+pub async fn abandon_chat_games(pool: &Pool<Any>, chat_id: i64) -> Result<Vec<GameRow>> {
+    let rows = sqlx::query(
+        "SELECT id, chat_id, white_user_id, black_user_id, current_fen, turn, status, result, last_message_id, draw_proposed_by, draw_proposal_message_id, confirm_moves, pending_move, pending_move_message_id, peer_chat_id
+         FROM games
+         WHERE status = 'ongoing' AND (chat_id = $1 OR peer_chat_id = $1)",
+    )
+    .bind(chat_id)
+    .fetch_all(pool)
+    .await?;
+    let games: Vec<GameRow> = rows.iter().map(row_to_game_row).collect();
+
+    for game in &games {
+        abandon_game(pool, game.id).await?;
+    }
+    Ok(games)
+}
+
+/// Ends an ongoing game without a result or rating changes. Returns false when the game
+/// was already over.
+pub async fn abandon_game(pool: &Pool<Any>, game_id: i64) -> Result<bool> {
+    let updated = sqlx::query(
+        "UPDATE games SET status = $1, result = '*', ended_at = $2, draw_proposed_by = NULL
+         WHERE id = $3 AND status = 'ongoing'",
+    )
+    .bind(GameStatus::Abandoned.as_str())
+    .bind(Utc::now().to_rfc3339())
+    .bind(game_id)
+    .execute(pool)
+    .await?;
+    Ok(updated.rows_affected() == 1)
+}
+
+/// The user's ongoing games hosted in `chat_id`.
+pub async fn find_player_games_in_chat(pool: &Pool<Any>, chat_id: i64, user_id: i64) -> Result<Vec<GameRow>> {
+    let rows = sqlx::query(
+        "SELECT id, chat_id, white_user_id, black_user_id, current_fen, turn, status, result, last_message_id, draw_proposed_by, draw_proposal_message_id, confirm_moves, pending_move, pending_move_message_id, peer_chat_id
+         FROM games
+         WHERE chat_id = $1 AND status = 'ongoing' AND (white_user_id = $2 OR black_user_id = $2)",
+    )
+    .bind(chat_id)
+    .bind(user_id)
+    .fetch_all(pool)
+    .await?;
+    Ok(rows.iter().map(row_to_game_row).collect())
+}
+
+/// Moves finished games that ended before `before` and their moves into the archive
+/// tables, returning how many games were archived. Their board messages and prediction
+/// polls are dropped, as nothing can reply to them any more.
+/// The newest game and the game with the newest move always stay: SQLite hands out the
+/// highest remaining id plus one, so archiving them would let new rows reuse archived ids.
+pub async fn archive_finished_games(pool: &Pool<Any>, before: DateTime<Utc>) -> Result<u64> {
+    // Stored times carry a varying number of fractional digits, so only whole seconds are compared
+    const OLD_GAMES: &str = "SELECT id FROM games
+        WHERE status <> 'ongoing' AND ended_at IS NOT NULL AND SUBSTR(ended_at, 1, 19) < $1
+            AND id < $2 AND id <> $3";
+    let before = before.format("%Y-%m-%dT%H:%M:%S").to_string();
+    let mut tx = pool.begin().await?;
+
+    // Read once, so a game created meanwhile can't make the newest one archivable halfway
+    let newest_game: i64 = sqlx::query("SELECT COALESCE(MAX(id), 0) AS id FROM games")
+        .fetch_one(&mut *tx)
+        .await?
+        .get("id");
+    let newest_move_game: i64 = sqlx::query(
+        "SELECT COALESCE(MAX(game_id), 0) AS game_id FROM moves WHERE id = (SELECT MAX(id) FROM moves)",
+    )
+    .fetch_one(&mut *tx)
+    .await?
+    .get("game_id");
+
+    for statement in [
+        format!("INSERT INTO games_archive ({cols}) SELECT {cols} FROM games WHERE id IN ({OLD_GAMES})", cols = game_columns!()),
+        format!("INSERT INTO moves_archive ({cols}) SELECT {cols} FROM moves WHERE game_id IN ({OLD_GAMES})", cols = move_columns!()),
+        format!("DELETE FROM moves WHERE game_id IN ({OLD_GAMES})"),
+        format!("DELETE FROM game_messages WHERE game_id IN ({OLD_GAMES})"),
+        format!("DELETE FROM predictions WHERE poll_id IN (SELECT poll_id FROM prediction_polls WHERE game_id IN ({OLD_GAMES}))"),
+        format!("DELETE FROM prediction_polls WHERE game_id IN ({OLD_GAMES})"),
+    ] {
+        sqlx::query(&statement)
+            .bind(&before)
+            .bind(newest_game)
+            .bind(newest_move_game)
+            .execute(&mut *tx)
+            .await?;
+    }
+    let archived = sqlx::query(&format!("DELETE FROM games WHERE id IN ({OLD_GAMES})"))
+        .bind(&before)
+        .bind(newest_game)
+        .bind(newest_move_game)
+        .execute(&mut *tx)
+        .await?
+        .rows_affected();
+
+    tx.commit().await?;
+    Ok(archived)
+}
+
+/// The user's running games in every chat, oldest first.
+pub async fn find_user_ongoing_games(pool: &Pool<Any>, user_id: i64) -> Result<Vec<GameRow>> {
+    let rows = sqlx::query(
+        "SELECT id, chat_id, white_user_id, black_user_id, current_fen, turn, status, result, last_message_id, draw_proposed_by, draw_proposal_message_id, confirm_moves, pending_move, pending_move_message_id, peer_chat_id
+         FROM games
+         WHERE status = 'ongoing' AND (white_user_id = $1 OR black_user_id = $1)
+         ORDER BY id ASC",
+    )
+    .bind(user_id)
+    .fetch_all(pool)
+    .await?;
+    Ok(rows.iter().map(row_to_game_row).collect())
+}
+
+/// Whether the game is paused, and who asked to pause it if the opponent hasn't agreed yet.
+pub async fn get_game_pause(pool: &Pool<Any>, game_id: i64) -> Result<(bool, Option<i64>)> {
+    let row = sqlx::query("SELECT paused, pause_requested_by FROM games WHERE id = $1")
+        .bind(game_id)
+        .fetch_optional(pool)
+        .await?;
+    Ok(row.map_or((false, None), |r| {
+        (r.get::<i64, _>("paused") != 0, r.get("pause_requested_by"))
+    }))
+}
+
+/// Stores a player's request to pause a running game, for the opponent to agree to.
+pub async fn request_pause(pool: &Pool<Any>, game_id: i64, player_id: i64) -> Result<()> {
+    sqlx::query(
+        "UPDATE games SET pause_requested_by = $1 WHERE id = $2 AND status = 'ongoing' AND paused = 0",
+    )
+    .bind(player_id)
+    .bind(game_id)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// Pauses or resumes a running game and drops any pending request. Returns false when
+/// the game was already in that state or has ended.
+pub async fn set_game_paused(pool: &Pool<Any>, game_id: i64, paused: bool) -> Result<bool> {
+    let updated = sqlx::query(
+        "UPDATE games SET paused = $1, pause_requested_by = NULL
+         WHERE id = $2 AND status = 'ongoing' AND paused <> $1",
+    )
+    .bind(paused as i64)
+    .bind(game_id)
+    .execute(pool)
+    .await?;
+    Ok(updated.rows_affected() == 1)
+}
+
+/// Records that a player left the game's chat just now, or clears it with `None` when
+/// they came back.
+pub async fn set_player_left(pool: &Pool<Any>, game_id: i64, user_id: Option<i64>) -> Result<()> {
+    let left_at = user_id.map(|_| Utc::now().to_rfc3339());
+    sqlx::query("UPDATE games SET left_player_id = $1, left_at = $2 WHERE id = $3")
+        .bind(user_id)
+        .bind(left_at)
+        .bind(game_id)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+/// The player who left the game's chat and when, unless they have rejoined since.
+pub async fn get_player_left(pool: &Pool<Any>, game_id: i64) -> Result<Option<(i64, DateTime<Utc>)>> {
+    let row: Option<(Option<i64>, Option<String>)> =
+        sqlx::query_as("SELECT left_player_id, left_at FROM games WHERE id = $1")
+            .bind(game_id)
+            .fetch_optional(pool)
+            .await?;
+    Ok(row.and_then(|(user_id, left_at)| {
+        let left_at = DateTime::parse_from_rfc3339(&left_at?).ok()?.with_timezone(&Utc);
+        Some((user_id?, left_at))
+    }))
+}
+
+/// Drops the chat's open seeks and, for a private chat, its place in the queue.
+pub async fn close_chat_invitations(pool: &Pool<Any>, chat_id: i64) -> Result<()> {
+    sqlx::query("DELETE FROM seeks WHERE chat_id = $1")
+        .bind(chat_id)
+        .execute(pool)
+        .await?;
+    sqlx::query("DELETE FROM match_queue WHERE chat_id = $1")
+        .bind(chat_id)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+/// Adds the user to the matchmaking queue; `false` when they were already waiting.
+pub async fn enqueue_player(pool: &Pool<Any>, user_id: i64, chat_id: i64) -> Result<bool> {
+    let inserted = sqlx::query(
+        "INSERT INTO match_queue (user_id, chat_id, queued_at) VALUES ($1, $2, $3)
+         ON CONFLICT (user_id) DO NOTHING",
+    )
+    .bind(user_id)
+    .bind(chat_id)
+    .bind(Utc::now().to_rfc3339())
+    .execute(pool)
+    .await?;
+    Ok(inserted.rows_affected() == 1)
+}
+
+/// Removes the user from the queue and reports whether this call did, so a waiting player
+/// is only ever matched once.
+pub async fn dequeue_player(pool: &Pool<Any>, user_id: i64) -> Result<bool> {
+    let deleted = sqlx::query("DELETE FROM match_queue WHERE user_id = $1")
+        .bind(user_id)
+        .execute(pool)
+        .await?;
+    Ok(deleted.rows_affected() == 1)
+}
+
+/// Everyone waiting for a match with the private chat to reach them in, longest wait first.
+pub async fn get_queued_players(pool: &Pool<Any>) -> Result<Vec<(DbUser, i64)>> {
+    let rows = sqlx::query(
+        "SELECT u.id, u.telegram_id, u.username, u.first_name, u.last_name, u.wins, u.losses, u.draws, q.chat_id
+         FROM match_queue q
+         JOIN users u ON u.id = q.user_id
+         ORDER BY q.queued_at ASC",
+    )
+    .fetch_all(pool)
+    .await?;
+    rows.iter()
+        .map(|row| Ok((DbUser::from_row(row)?, row.get("chat_id"))))
+        .collect()
+}
+
+pub async fn create_prediction_poll(
+    pool: &Pool<Any>,
+    poll_id: &str,
+    game_id: i64,
+    chat_id: i64,
+    message_id: i64,
+) -> Result<()> {
+    sqlx::query(
+        "INSERT INTO prediction_polls (poll_id, game_id, chat_id, message_id) VALUES ($1, $2, $3, $4)",
+    )
+    .bind(poll_id)
+    .bind(game_id)
+    .bind(chat_id)
+    .bind(message_id)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// The game an open prediction poll belongs to.
+pub async fn get_prediction_poll_game(pool: &Pool<Any>, poll_id: &str) -> Result<Option<i64>> {
+    let row = sqlx::query("SELECT game_id FROM prediction_polls WHERE poll_id = $1")
+        .bind(poll_id)
+        .fetch_optional(pool)
+        .await?;
+    Ok(row.map(|r| r.get("game_id")))
+}
+
+/// Open polls of a game as `(poll_id, chat_id, message_id)`.
+pub async fn get_game_prediction_polls(pool: &Pool<Any>, game_id: i64) -> Result<Vec<(String, i64, i64)>> {
+    let rows = sqlx::query("SELECT poll_id, chat_id, message_id FROM prediction_polls WHERE game_id = $1")
+        .bind(game_id)
+        .fetch_all(pool)
+        .await?;
+    Ok(rows
+        .iter()
+        .map(|r| (r.get("poll_id"), r.get("chat_id"), r.get("message_id")))
+        .collect())
+}
+
+/// Stores a vote; `None` is a retracted one.
+pub async fn record_prediction(
+    pool: &Pool<Any>,
+    poll_id: &str,
+    user_id: i64,
+    choice: Option<i64>,
+) -> Result<()> {
+    let mut tx = pool.begin().await?;
+    sqlx::query("DELETE FROM predictions WHERE poll_id = $1 AND user_id = $2")
+        .bind(poll_id)
+        .bind(user_id)
+        .execute(&mut *tx)
+        .await?;
+    if let Some(choice) = choice {
+        sqlx::query("INSERT INTO predictions (poll_id, user_id, choice) VALUES ($1, $2, $3)")
+            .bind(poll_id)
+            .bind(user_id)
+            .bind(choice)
+            .execute(&mut *tx)
+            .await?;
+    }
+    tx.commit().await?;
+    Ok(())
+}
+
+/// Scores every vote of a closed poll against `outcome` in the chat's standings and
+/// forgets the poll.
+pub async fn settle_prediction_poll(
+    pool: &Pool<Any>,
+    poll_id: &str,
+    chat_id: i64,
+    outcome: i64,
+) -> Result<()> {
+    let mut tx = pool.begin().await?;
+    sqlx::query(
+        "INSERT INTO prediction_stats (chat_id, user_id, correct, total)
+         SELECT $1, user_id, CASE WHEN choice = $2 THEN 1 ELSE 0 END, 1
+         FROM predictions WHERE poll_id = $3
+         ON CONFLICT (chat_id, user_id) DO UPDATE SET
+            correct = prediction_stats.correct + excluded.correct,
+            total = prediction_stats.total + 1",
+    )
+    .bind(chat_id)
+    .bind(outcome)
+    .bind(poll_id)
+    .execute(&mut *tx)
+    .await?;
+    sqlx::query("DELETE FROM predictions WHERE poll_id = $1")
+        .bind(poll_id)
+        .execute(&mut *tx)
+        .await?;
+    sqlx::query("DELETE FROM prediction_polls WHERE poll_id = $1")
+        .bind(poll_id)
+        .execute(&mut *tx)
+        .await?;
+    tx.commit().await?;
+    Ok(())
+}
+
+/// The chat's best predictors as `(user, correct, total)`, most correct first.
+pub async fn get_prediction_leaderboard(
+    pool: &Pool<Any>,
+    chat_id: i64,
+    limit: i64,
+) -> Result<Vec<(DbUser, i64, i64)>> {
+    let rows = sqlx::query(
+        "SELECT u.id, u.telegram_id, u.username, u.first_name, u.last_name, u.wins, u.losses, u.draws,
+                s.correct, s.total
+         FROM prediction_stats s
+         JOIN users u ON u.id = s.user_id
+         WHERE s.chat_id = $1
+         ORDER BY s.correct DESC, s.total ASC, u.id ASC
+         LIMIT $2",
+    )
+    .bind(chat_id)
+    .bind(limit)
+    .fetch_all(pool)
+    .await?;
+    rows.iter()
+        .map(|row| Ok((DbUser::from_row(row)?, row.get("correct"), row.get("total"))))
+        .collect()
+}
+
+/// Everyone with at least `min_games` decided games in the chat and one ending after
+/// `active_since`, best first: most points, then fewest games.
+pub async fn get_chat_standings(
+    pool: &Pool<Any>,
+    chat_id: i64,
+    min_games: i64,
+    active_since: Option<DateTime<Utc>>,
+) -> Result<Vec<ChatStanding>> {
+    let rows = sqlx::query(&format!(
+        "SELECT u.id, u.telegram_id, u.username, u.first_name, u.last_name, u.wins, u.losses, u.draws,
+                r.chat_wins, r.chat_losses, r.chat_draws, r.last_played
+         FROM (
+             SELECT player_id, SUM(won) AS chat_wins, SUM(lost) AS chat_losses, SUM(drawn) AS chat_draws,
+                    MAX(ended_at) AS last_played
+             FROM {} AS pr
+             GROUP BY player_id
+             HAVING COUNT(*) >= $2 AND COALESCE(MAX(ended_at), '') >= $3
+         ) AS r
+         JOIN users u ON u.id = r.player_id
+         ORDER BY 2 * r.chat_wins + r.chat_draws DESC, r.chat_wins + r.chat_losses + r.chat_draws ASC, u.id ASC",
+        player_results()
+    ))
+    .bind(chat_id)
+    .bind(min_games)
+    .bind(active_since.map_or_else(String::new, |since| since.to_rfc3339()))
+    .fetch_all(pool)
+    .await?;
+    rows.iter()
+        .map(|row| {
+            Ok(ChatStanding {
+                user: DbUser::from_row(row)?,
+                wins: row.get("chat_wins"),
+                losses: row.get("chat_losses"),
+                draws: row.get("chat_draws"),
+                last_played: row.get("last_played"),
+            })
+        })
+        .collect()
+}
+
+/// The player's last `limit` decided games in the chat as `(wins, losses, draws)`.
+pub async fn get_recent_record(pool: &Pool<Any>, chat_id: i64, user_id: i64, limit: i64) -> Result<(i64, i64, i64)> {
+    let rows: Vec<(i64, i64, i64)> = sqlx::query_as(&format!(
+        "SELECT won, lost, drawn FROM {} AS pr
+         WHERE player_id = $2
+         ORDER BY ended_at DESC
+         LIMIT $3",
+        player_results()
+    ))
+    .bind(chat_id)
+    .bind(user_id)
+    .bind(limit)
+    .fetch_all(pool)
+    .await?;
+    Ok(rows
+        .iter()
+        .fold((0, 0, 0), |(w, l, d), (won, lost, drawn)| (w + won, l + lost, d + drawn)))
+}
+
+/// When each game in the chat started, for games started after `since`, archived ones
+/// included.
+pub async fn get_chat_game_starts(pool: &Pool<Any>, chat_id: i64, since: DateTime<Utc>) -> Result<Vec<DateTime<Utc>>> {
+    let rows: Vec<(String,)> = sqlx::query_as(&format!(
+        "SELECT started_at FROM {ALL_GAMES} AS g WHERE chat_id = $1 AND started_at >= $2"
+    ))
+    .bind(chat_id)
+    .bind(since.to_rfc3339())
+    .fetch_all(pool)
+    .await?;
+    Ok(rows
+        .iter()
+        .filter_map(|(text,)| DateTime::parse_from_rfc3339(text).ok())
+        .map(|at| at.with_timezone(&Utc))
+        .collect())
+}
+
+/// The user holding the chat's champion title, if anyone has been given it.
+pub async fn get_chat_champion(pool: &Pool<Any>, chat_id: i64) -> Result<Option<i64>> {
+    let row: Option<(i64,)> = sqlx::query_as("SELECT user_id FROM chat_champions WHERE chat_id = $1")
+        .bind(chat_id)
+        .fetch_optional(pool)
+        .await?;
+    Ok(row.map(|(user_id,)| user_id))
+}
+
+/// Gives the chat's champion title to the user from now on.
+pub async fn set_chat_champion(pool: &Pool<Any>, chat_id: i64, user_id: i64) -> Result<()> {
+    sqlx::query(
+        "INSERT INTO chat_champions (chat_id, user_id, since) VALUES ($1, $2, $3)
+         ON CONFLICT (chat_id) DO UPDATE SET user_id = excluded.user_id, since = excluded.since",
+    )
+    .bind(chat_id)
+    .bind(user_id)
+    .bind(Utc::now().to_rfc3339())
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// `feature_flags.chat_id` of flags that apply to every chat. Telegram never uses 0.
+const GLOBAL_FLAG_SCOPE: i64 = 0;
+
+/// The features `/feature` can switch, with whether each is on where no flag is set.
+pub const FEATURES: &[(&str, bool)] = &[
+    // /eval, wherever an engine is configured
+    ("engine", true),
+];
+
+/// Whether an experimental feature is on in the chat. A chat's own flag wins over the
+/// global one; without either the feature's default from `FEATURES` applies, and
+/// unknown features are off.
+pub async fn is_feature_enabled(pool: &Pool<Any>, name: &str, chat_id: i64) -> Result<bool> {
+    let default = FEATURES
+        .iter()
+        .find(|(feature, _)| *feature == name)
+        .is_some_and(|(_, enabled)| *enabled);
+    let row = sqlx::query(
+        "SELECT enabled FROM feature_flags
+         WHERE name = $1 AND (chat_id = $2 OR chat_id = $3)
+         ORDER BY CASE WHEN chat_id = $3 THEN 1 ELSE 0 END
+         LIMIT 1",
+    )
+    .bind(name)
+    .bind(chat_id)
+    .bind(GLOBAL_FLAG_SCOPE)
+    .fetch_optional(pool)
+    .await?;
+    Ok(row.map_or(default, |r| r.get::<i64, _>("enabled") != 0))
+}
+
+/// Sets a flag for one chat, or for all with `chat_id` `None`. `enabled` `None` removes
+/// the flag, so the chat falls back to the global setting.
+pub async fn set_feature_flag(
+    pool: &Pool<Any>,
+    name: &str,
+    chat_id: Option<i64>,
+    enabled: Option<bool>,
+) -> Result<()> {
+    let scope = chat_id.unwrap_or(GLOBAL_FLAG_SCOPE);
+    let Some(enabled) = enabled else {
+        sqlx::query("DELETE FROM feature_flags WHERE name = $1 AND chat_id = $2")
+            .bind(name)
+            .bind(scope)
+            .execute(pool)
+            .await?;
+        return Ok(());
+    };
+    sqlx::query(
+        "INSERT INTO feature_flags (name, chat_id, enabled, updated_at) VALUES ($1, $2, $3, $4)
+         ON CONFLICT (name, chat_id) DO UPDATE SET enabled = excluded.enabled, updated_at = excluded.updated_at",
+    )
+    .bind(name)
+    .bind(scope)
+    .bind(enabled as i64)
+    .bind(Utc::now().to_rfc3339())
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// Flags that affect the chat as `(name, global, enabled)`, by name with the global one first.
+pub async fn get_feature_flags(pool: &Pool<Any>, chat_id: i64) -> Result<Vec<(String, bool, bool)>> {
+    let rows = sqlx::query(
+        "SELECT name, chat_id, enabled FROM feature_flags
+         WHERE chat_id = $1 OR chat_id = $2
+         ORDER BY name, CASE WHEN chat_id = $2 THEN 0 ELSE 1 END",
+    )
+    .bind(chat_id)
+    .bind(GLOBAL_FLAG_SCOPE)
+    .fetch_all(pool)
+    .await?;
+    Ok(rows
+        .iter()
+        .map(|r| {
+            (
+                r.get("name"),
+                r.get::<i64, _>("chat_id") == GLOBAL_FLAG_SCOPE,
+                r.get::<i64, _>("enabled") != 0,
+            )
+        })
+        .collect())
+}
+
+pub async fn insert_audit_entry(
+    pool: &Pool<Any>,
+    telegram_id: Option<i64>,
+    chat_id: i64,
+    game_id: Option<i64>,
+    command: &str,
+    outcome: &str,
+) -> Result<()> {
+    sqlx::query(
+        "INSERT INTO audit_log (telegram_id, chat_id, game_id, command, outcome, created_at)
+         VALUES ($1, $2, $3, $4, $5, $6)",
+    )
+    .bind(telegram_id)
+    .bind(chat_id)
+    .bind(game_id)
+    .bind(command)
+    .bind(outcome)
+    .bind(Utc::now().to_rfc3339())
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// Which audit entries to look at.
+#[derive(Debug, Clone, Copy)]
+pub enum AuditFilter {
+    Chat(i64),
+    Game(i64),
+    /// By the sender's Telegram id.
+    User(i64),
+}
+
+/// The latest `limit` matching audit entries, newest first.
+pub async fn get_audit_log(pool: &Pool<Any>, filter: AuditFilter, limit: i64) -> Result<Vec<AuditEntry>> {
+    let (column, value) = match filter {
+        AuditFilter::Chat(id) => ("chat_id", id),
+        AuditFilter::Game(id) => ("game_id", id),
+        AuditFilter::User(id) => ("telegram_id", id),
+    };
+    let rows = sqlx::query(&format!(
+        "SELECT a.id, a.telegram_id, u.username, a.chat_id, a.game_id, a.command, a.outcome, a.created_at
+         FROM audit_log a
+         LEFT JOIN users u ON u.telegram_id = a.telegram_id
+         WHERE a.{} = $1
+         ORDER BY a.id DESC
+         LIMIT $2",
+        column
+    ))
+    .bind(value)
+    .bind(limit)
+    .fetch_all(pool)
+    .await?;
+    Ok(rows
+        .iter()
+        .map(|r| AuditEntry {
+            id: r.get("id"),
+            telegram_id: r.get("telegram_id"),
+            username: r.get("username"),
+            chat_id: r.get("chat_id"),
+            game_id: r.get("game_id"),
+            command: r.get("command"),
+            outcome: r.get("outcome"),
+            created_at: r.get("created_at"),
+        })
+        .collect())
+}
+
+/// Expiry timestamps use a fixed-width format so they compare correctly as text.
+fn seek_timestamp(at: DateTime<Utc>) -> String {
+    at.to_rfc3339_opts(chrono::SecondsFormat::Secs, true)
+}
+
+pub async fn create_seek(
     pool: &Pool<Any>,
     chat_id: i64,
-    white_user_id: i64,
-    black_user_id: i64,
-    fen: &str,
-    turn: &str,
+    user_id: i64,
+    confirm_moves: bool,
+    expires_at: DateTime<Utc>,
 ) -> Result<i64> {
-    let now = Utc::now().to_rfc3339();
     let row = sqlx::query(
-        "INSERT INTO games (chat_id, white_user_id, black_user_id, current_fen, turn, started_at)
-         VALUES ($1, $2, $3, $4, $5, $6)
+        "INSERT INTO seeks (chat_id, user_id, confirm_moves, created_at, expires_at)
+         VALUES ($1, $2, $3, $4, $5)
          RETURNING id",
     )
     .bind(chat_id)
-    .bind(white_user_id)
-    .bind(black_user_id)
-    .bind(fen)
-    .bind(turn)
-    .bind(now)
+    .bind(user_id)
+    .bind(confirm_moves as i64)
+    .bind(Utc::now().to_rfc3339())
+    .bind(seek_timestamp(expires_at))
     .fetch_one(pool)
     .await?;
-
     Ok(row.get("id"))
 }
 
-pub async fn update_game_message(pool: &Pool<Any>, game_id: i64, message_id: i64) -> Result<()> {
-    sqlx::query("UPDATE games SET last_message_id = $1 WHERE id = $2")
+pub async fn set_seek_message(pool: &Pool<Any>, seek_id: i64, message_id: i64) -> Result<()> {
+    sqlx::query("UPDATE seeks SET message_id = $1 WHERE id = $2")
         .bind(message_id)
-        .bind(game_id)
+        .bind(seek_id)
         .execute(pool)
         .await?;
     Ok(())
 }
 
-pub async fn update_game_fen(pool: &Pool<Any>, game_id: i64, fen: &str, turn: &str) -> Result<()> {
-    sqlx::query("UPDATE games SET current_fen = $1, turn = $2 WHERE id = $3")
-        .bind(fen)
-        .bind(turn)
-        .bind(game_id)
-        .execute(pool)
-        .await?;
-    Ok(())
+const SEEK_COLUMNS: &str = "id, chat_id, user_id, message_id, confirm_moves, expires_at";
+
+fn row_to_seek(row: &sqlx::any::AnyRow) -> SeekRow {
+    SeekRow {
+        id: row.get("id"),
+        chat_id: row.get("chat_id"),
+        user_id: row.get("user_id"),
+        message_id: row.get("message_id"),
+        confirm_moves: row.get::<i64, _>("confirm_moves") != 0,
+        expires_at: row.get("expires_at"),
+    }
 }
 
-pub async fn update_game_result(
-    pool: &Pool<Any>,
-    game_id: i64,
-    result: &Option<String>,
-    status: &str,
-) -> Result<()> {
-    let ended = Utc::now().to_rfc3339();
-    sqlx::query(
-        "UPDATE games SET result = $1, status = $2, ended_at = $3, draw_proposed_by = NULL WHERE id = $4",
-    )
-    .bind(result)
-    .bind(status)
-    .bind(ended)
-    .bind(game_id)
-    .execute(pool)
+/// A seek that can still be joined: not taken and not expired.
+pub async fn get_open_seek(pool: &Pool<Any>, seek_id: i64) -> Result<Option<SeekRow>> {
+    let row = sqlx::query(&format!(
+        "SELECT {} FROM seeks WHERE id = $1 AND expires_at > $2",
+        SEEK_COLUMNS
+    ))
+    .bind(seek_id)
+    .bind(seek_timestamp(Utc::now()))
+    .fetch_optional(pool)
     .await?;
-    Ok(())
+    Ok(row.map(|r| row_to_seek(&r)))
 }
 
-pub async fn propose_draw(pool: &Pool<Any>, game_id: i64, player_id: i64, message_id: i64) -> Result<()> {
-    sqlx::query("UPDATE games SET draw_proposed_by = $1, draw_proposal_message_id = $2 WHERE id = $3")
-        .bind(player_id)
-        .bind(message_id)
-        .bind(game_id)
-        .execute(pool)
-        .await?;
-    Ok(())
+/// The user's seek in this chat that hasn't expired yet.
+pub async fn find_open_seek(pool: &Pool<Any>, chat_id: i64, user_id: i64) -> Result<Option<SeekRow>> {
+    let row = sqlx::query(&format!(
+        "SELECT {} FROM seeks WHERE chat_id = $1 AND user_id = $2 AND expires_at > $3 LIMIT 1",
+        SEEK_COLUMNS
+    ))
+    .bind(chat_id)
+    .bind(user_id)
+    .bind(seek_timestamp(Utc::now()))
+    .fetch_optional(pool)
+    .await?;
+    Ok(row.map(|r| row_to_seek(&r)))
 }
 
-pub async fn clear_draw_proposal(pool: &Pool<Any>, game_id: i64) -> Result<()> {
-    sqlx::query("UPDATE games SET draw_proposed_by = NULL, draw_proposal_message_id = NULL WHERE id = $1")
-        .bind(game_id)
-        .execute(pool)
+pub async fn get_expired_seeks(pool: &Pool<Any>) -> Result<Vec<SeekRow>> {
+    let rows = sqlx::query(&format!("SELECT {} FROM seeks WHERE expires_at <= $1", SEEK_COLUMNS))
+        .bind(seek_timestamp(Utc::now()))
+        .fetch_all(pool)
         .await?;
-    Ok(())
+    Ok(rows.iter().map(row_to_seek).collect())
 }
 
-pub async fn update_player_stats(
-    pool: &Pool<Any>,
-    white_id: i64,
-    black_id: i64,
-    result: &str,
-) -> Result<()> {
-    match result {
-        "1-0" => {
-            sqlx::query("UPDATE users SET wins = wins + 1 WHERE id = $1")
-                .bind(white_id)
-                .execute(pool)
-                .await?;
-            sqlx::query("UPDATE users SET losses = losses + 1 WHERE id = $1")
-                .bind(black_id)
-                .execute(pool)
-                .await?;
-        }
-        "0-1" => {
-            sqlx::query("UPDATE users SET wins = wins + 1 WHERE id = $1")
-                .bind(black_id)
-                .execute(pool)
-                .await?;
-            sqlx::query("UPDATE users SET losses = losses + 1 WHERE id = $1")
-                .bind(white_id)
-                .execute(pool)
-                .await?;
-        }
-        "1/2-1/2" => {
-            sqlx::query("UPDATE users SET draws = draws + 1 WHERE id = $1")
-                .bind(white_id)
-                .execute(pool)
-                .await?;
-            sqlx::query("UPDATE users SET draws = draws + 1 WHERE id = $1")
-                .bind(black_id)
-                .execute(pool)
-                .await?;
-        }
-        _ => {}
-    }
-    Ok(())
+/// Removes a seek and reports whether this call was the one that removed it, so two
+/// players tapping Join at once (or a join racing the expiry sweep) can't both win.
+pub async fn take_seek(pool: &Pool<Any>, seek_id: i64) -> Result<bool> {
+    let deleted = sqlx::query("DELETE FROM seeks WHERE id = $1")
+        .bind(seek_id)
+        .execute(pool)
+        .await?;
+    Ok(deleted.rows_affected() == 1)
 }
 
-pub async fn insert_move(
-    pool: &Pool<Any>,
-    game_id: i64,
-    player_id: i64,
-    move_number: i64,
-    uci: &str,
-    san: Option<&str>,
-) -> Result<()> {
-    let now = Utc::now().to_rfc3339();
-    sqlx::query(
-        "INSERT INTO moves (game_id, move_number, uci, san, played_by, played_at)
-         VALUES ($1, $2, $3, $4, $5, $6)",
-    )
-    .bind(game_id)
-    .bind(move_number)
-    .bind(uci)
-    .bind(san)
-    .bind(player_id)
-    .bind(now)
-    .execute(pool)
+/// Returns the chat's settings, falling back to defaults for chats that never changed any.
+pub async fn get_chat_settings(pool: &Pool<Any>, chat_id: i64) -> Result<ChatSettings> {
+    let row = sqlx::query(&format!(
+        "SELECT {CHAT_SETTINGS_COLUMNS} FROM chat_settings WHERE chat_id = $1"
+    ))
+    .bind(chat_id)
+    .fetch_optional(pool)
     .await?;
-    Ok(())
+
+    Ok(match row {
+        Some(row) => row_to_chat_settings(&row),
+        None => ChatSettings::defaults(chat_id),
+    })
 }
 
-pub async fn next_move_number(pool: &Pool<Any>, game_id: i64) -> Result<i64> {
-    let row = sqlx::query(
-        "SELECT COALESCE(MAX(move_number), 0) + 1 as next FROM moves WHERE game_id = $1",
-    )
-    .bind(game_id)
-    .fetch_one(pool)
+/// Chats that replaced the game-end announcement, loaded into the templates at startup.
+pub async fn get_chats_with_end_templates(pool: &Pool<Any>) -> Result<Vec<ChatSettings>> {
+    let rows = sqlx::query(&format!(
+        "SELECT {CHAT_SETTINGS_COLUMNS} FROM chat_settings
+         WHERE win_template IS NOT NULL OR draw_template IS NOT NULL"
+    ))
+    .fetch_all(pool)
     .await?;
-    Ok(row.get("next"))
+
+    Ok(rows.iter().map(row_to_chat_settings).collect())
 }
 
-async fn get_games_san_moves(pool: &Pool<Any>, game_ids: &[i64]) -> HashMap<i64, Vec<String>> {
-    if game_ids.is_empty() {
-        return HashMap::new();
+fn row_to_chat_settings(row: &sqlx::any::AnyRow) -> ChatSettings {
+    ChatSettings {
+        chat_id: row.get("chat_id"),
+        coordinates: row.get("coordinates"),
+        orientation: row.get("orientation"),
+        send_as_document: row.get::<i64, _>("send_as_document") != 0,
+        strict_notation: row.get::<i64, _>("strict_notation") != 0,
+        win_template: row.get("win_template"),
+        draw_template: row.get("draw_template"),
+        prediction_polls: row.get::<i64, _>("prediction_polls") != 0,
+        announcements: row.get::<i64, _>("announcements") != 0,
+        tap_moves: row.get::<i64, _>("tap_moves") != 0,
+        pgn_files: row.get::<i64, _>("pgn_files") != 0,
+        max_games: row.get("max_games"),
+        standings_min_games: row.get("standings_min_games"),
+        standings_inactive_days: row.get("standings_inactive_days"),
+        draw_offer_gap: row.get("draw_offer_gap"),
     }
+}
 
-    let placeholders: String = game_ids
-        .iter()
-        .enumerate()
-        .map(|(i, _)| format!("${}", i + 1))
-        .collect::<Vec<_>>()
-        .join(",");
-    let sql = format!(
-        "SELECT game_id, san, uci FROM moves WHERE game_id IN ({}) ORDER BY game_id, move_number ASC",
-        placeholders
-    );
-
-    let mut query = sqlx::query(&sql);
-    for id in game_ids {
-        query = query.bind(id);
-    }
+pub async fn set_chat_coordinates(pool: &Pool<Any>, chat_id: i64, coordinates: &str) -> Result<()> {
+    set_chat_setting(pool, chat_id, "coordinates", SettingValue::Text(coordinates)).await
+}
 
-    let rows = match query.fetch_all(pool).await {
-        Ok(r) => r,
-        Err(_) => return HashMap::new(),
-    };
+pub async fn set_chat_orientation(pool: &Pool<Any>, chat_id: i64, orientation: &str) -> Result<()> {
+    set_chat_setting(pool, chat_id, "orientation", SettingValue::Text(orientation)).await
+}
 
-    let mut result: HashMap<i64, Vec<String>> = HashMap::new();
-    for row in rows {
-        let game_id: i64 = row.get("game_id");
-        let san: Option<String> = row.get("san");
-        let uci: String = row.get("uci");
-        result.entry(game_id).or_default().push(san.unwrap_or(uci));
-    }
-    result
+pub async fn set_chat_send_as_document(pool: &Pool<Any>, chat_id: i64, enabled: bool) -> Result<()> {
+    set_chat_setting(pool, chat_id, "send_as_document", SettingValue::Flag(enabled)).await
 }
 
-fn format_history_lines(
-    history_rows: &[HistoryRow],
-    all_moves: &HashMap<i64, Vec<String>>,
-) -> Vec<String> {
-    let mut lines = Vec::new();
-    for row in history_rows {
-        let result = row.result.clone().unwrap_or_else(|| "ongoing".to_string());
-        let white_name = crate::utils::format_username(&row.white_username);
-        let black_name = crate::utils::format_username(&row.black_username);
-        let moves = all_moves.get(&row.id).map(|v| v.as_slice()).unwrap_or(&[]);
-        let lichess_url = build_lichess_url_from_moves(moves);
-        lines.push(format!(
-            "#{}: {} vs {} ({}) - <a href=\"{}\">analysis</a>",
-            row.local_num, white_name, black_name, result, lichess_url
-        ));
-    }
-    lines
+pub async fn set_chat_strict_notation(pool: &Pool<Any>, chat_id: i64, enabled: bool) -> Result<()> {
+    set_chat_setting(pool, chat_id, "strict_notation", SettingValue::Flag(enabled)).await
 }
 
-fn format_history_output(lines: &[String]) -> String {
-    let mut output = lines.join("\n");
-    if lines.is_empty() {
-        output.push_str("No games yet.");
-    }
-    output.push_str("\nUse /history &lt;page&gt; for more.");
-    output
+pub async fn set_chat_prediction_polls(pool: &Pool<Any>, chat_id: i64, enabled: bool) -> Result<()> {
+    set_chat_setting(pool, chat_id, "prediction_polls", SettingValue::Flag(enabled)).await
 }
 
-fn build_lichess_url_from_moves(moves: &[String]) -> String {
-    if moves.is_empty() {
-        return "https://lichess.org/analysis".to_string();
-    }
+pub async fn set_chat_announcements(pool: &Pool<Any>, chat_id: i64, enabled: bool) -> Result<()> {
+    set_chat_setting(pool, chat_id, "announcements", SettingValue::Flag(enabled)).await
+}
 
-    let mut pgn = String::new();
-    for (i, mv) in moves.iter().enumerate() {
-        if i % 2 == 0 {
-            if !pgn.is_empty() {
-                pgn.push(' ');
-            }
-            pgn.push_str(&format!("{}.", i / 2 + 1));
-        }
-        pgn.push(' ');
-        pgn.push_str(mv);
-    }
+pub async fn set_chat_tap_moves(pool: &Pool<Any>, chat_id: i64, enabled: bool) -> Result<()> {
+    set_chat_setting(pool, chat_id, "tap_moves", SettingValue::Flag(enabled)).await
+}
 
-    let encoded: String = pgn
-        .chars()
-        .map(|c| match c {
-            ' ' => "%20".to_string(),
-            '.' => ".".to_string(),
-            '#' => "%23".to_string(),
-            '+' => "%2B".to_string(),
-            '=' => "%3D".to_string(),
-            _ if c.is_ascii_alphanumeric() || c == '-' => c.to_string(),
-            _ => format!("%{:02X}", c as u8),
-        })
-        .collect();
+pub async fn set_chat_pgn_files(pool: &Pool<Any>, chat_id: i64, enabled: bool) -> Result<()> {
+    set_chat_setting(pool, chat_id, "pgn_files", SettingValue::Flag(enabled)).await
+}
 
-    format!("https://lichess.org/analysis/pgn/{}", encoded)
+/// The chat's own cap on running games; `None` goes back to the bot's default.
+pub async fn set_chat_max_games(pool: &Pool<Any>, chat_id: i64, max_games: Option<i64>) -> Result<()> {
+    set_chat_setting(pool, chat_id, "max_games", SettingValue::OptionalNumber(max_games)).await
 }
 
-fn row_to_game_row(row: &sqlx::any::AnyRow) -> GameRow {
-    GameRow {
-        id: row.get("id"),
-        chat_id: row.get("chat_id"),
-        white_user_id: row.get("white_user_id"),
-        black_user_id: row.get("black_user_id"),
-        current_fen: row.get("current_fen"),
-        turn: row.get("turn"),
-        status: row.get("status"),
-        result: row.get("result"),
-        last_message_id: row.get("last_message_id"),
-        draw_proposed_by: row.get("draw_proposed_by"),
-        draw_proposal_message_id: row.get("draw_proposal_message_id"),
-    }
+/// Moves a player must make between draw offers in the chat's games; `None` goes back to
+/// the bot's default.
+pub async fn set_chat_draw_offer_gap(pool: &Pool<Any>, chat_id: i64, moves: Option<i64>) -> Result<()> {
+    set_chat_setting(pool, chat_id, "draw_offer_gap", SettingValue::OptionalNumber(moves)).await
 }
 
-pub async fn find_ongoing_game(
-    pool: &Pool<Any>,
-    chat_id: i64,
-    white_id: i64,
-    black_id: i64,
-) -> Result<Option<GameRow>> {
-    let row = sqlx::query(
-        "SELECT id, chat_id, white_user_id, black_user_id, current_fen, turn, status, result, last_message_id, draw_proposed_by, draw_proposal_message_id
-         FROM games
-         WHERE chat_id = $1 AND status = 'ongoing'
-           AND ((white_user_id = $2 AND black_user_id = $3)
-             OR (white_user_id = $3 AND black_user_id = $2))
-         LIMIT 1",
-    )
-    .bind(chat_id)
-    .bind(white_id)
-    .bind(black_id)
-    .fetch_optional(pool)
-    .await?;
+/// Finished games a player needs in the chat to be listed in its standings; `None` lists everyone.
+pub async fn set_chat_standings_min_games(pool: &Pool<Any>, chat_id: i64, games: Option<i64>) -> Result<()> {
+    set_chat_setting(pool, chat_id, "standings_min_games", SettingValue::OptionalNumber(games)).await
+}
 
-    Ok(row.map(|r| row_to_game_row(&r)))
+/// Days without a finished game after which a player leaves the standings; `None` keeps them.
+pub async fn set_chat_standings_inactive_days(pool: &Pool<Any>, chat_id: i64, days: Option<i64>) -> Result<()> {
+    set_chat_setting(pool, chat_id, "standings_inactive_days", SettingValue::OptionalNumber(days)).await
 }
 
-pub async fn find_game_by_message(
-    pool: &Pool<Any>,
-    chat_id: i64,
-    message_id: i64,
-) -> Result<Option<GameRow>> {
-    let row = sqlx::query(
-        "SELECT g.id, g.chat_id, g.white_user_id, g.black_user_id, g.current_fen, g.turn, g.status, g.result, g.last_message_id, g.draw_proposed_by, g.draw_proposal_message_id
-         FROM games g
-         WHERE g.chat_id = $1 
-           AND (g.last_message_id = $2 
-                OR g.draw_proposal_message_id = $2
-                OR EXISTS (
-                    SELECT 1 FROM game_messages gm 
-                    WHERE gm.game_id = g.id AND gm.message_id = $2
-                ))
-         LIMIT 1",
+/// Chats with a game started or a move played since `since`, minus those that opted out
+/// of announcements or removed the bot. Both chats of a game played over private chats count.
+pub async fn get_active_chats(pool: &Pool<Any>, since: DateTime<Utc>) -> Result<Vec<i64>> {
+    let rows = sqlx::query(
+        "SELECT DISTINCT active.chat_id FROM (
+            SELECT g.chat_id FROM games g
+            WHERE g.started_at >= $1
+               OR EXISTS (SELECT 1 FROM moves m WHERE m.game_id = g.id AND m.played_at >= $1)
+            UNION
+            SELECT g.peer_chat_id FROM games g
+            WHERE g.peer_chat_id IS NOT NULL
+              AND (g.started_at >= $1
+                   OR EXISTS (SELECT 1 FROM moves m WHERE m.game_id = g.id AND m.played_at >= $1))
+         ) active
+         LEFT JOIN chat_settings cs ON cs.chat_id = active.chat_id
+         WHERE COALESCE(cs.announcements, 1) = 1 AND COALESCE(cs.bot_removed, 0) = 0
+         ORDER BY active.chat_id",
     )
-    .bind(chat_id)
-    .bind(message_id)
-    .fetch_optional(pool)
+    .bind(since.to_rfc3339())
+    .fetch_all(pool)
     .await?;
+    Ok(rows.iter().map(|row| row.get("chat_id")).collect())
+}
 
-    Ok(row.map(|r| row_to_game_row(&r)))
+/// Remembers that the bot was removed from (or blocked in) the chat, or added back.
+pub async fn set_chat_bot_removed(pool: &Pool<Any>, chat_id: i64, removed: bool) -> Result<()> {
+    set_chat_setting(pool, chat_id, "bot_removed", SettingValue::Flag(removed)).await
 }
 
-pub async fn insert_game_message(pool: &Pool<Any>, game_id: i64, message_id: i64) -> Result<()> {
-    let now = Utc::now().to_rfc3339();
-    sqlx::query(
-        "INSERT INTO game_messages (game_id, message_id, created_at)
-         VALUES ($1, $2, $3)",
-    )
-    .bind(game_id)
-    .bind(message_id)
-    .bind(now)
-    .execute(pool)
-    .await?;
-    Ok(())
+/// `None` goes back to the default announcement.
+pub async fn set_chat_win_template(pool: &Pool<Any>, chat_id: i64, template: Option<&str>) -> Result<()> {
+    set_chat_setting(pool, chat_id, "win_template", SettingValue::OptionalText(template)).await
 }
 
-pub async fn get_game_message_ids(pool: &Pool<Any>, game_id: i64) -> Result<Vec<i64>> {
-    let rows = sqlx::query(
-        "SELECT message_id FROM game_messages WHERE game_id = $1 ORDER BY created_at ASC",
-    )
-    .bind(game_id)
-    .fetch_all(pool)
-    .await?;
+pub async fn set_chat_draw_template(pool: &Pool<Any>, chat_id: i64, template: Option<&str>) -> Result<()> {
+    set_chat_setting(pool, chat_id, "draw_template", SettingValue::OptionalText(template)).await
+}
 
-    Ok(rows.into_iter().map(|row| row.get("message_id")).collect())
+enum SettingValue<'a> {
+    Text(&'a str),
+    OptionalText(Option<&'a str>),
+    Flag(bool),
+    OptionalNumber(Option<i64>),
 }
 
-pub async fn delete_game_messages(pool: &Pool<Any>, game_id: i64) -> Result<()> {
-    sqlx::query("DELETE FROM game_messages WHERE game_id = $1")
-        .bind(game_id)
-        .execute(pool)
-        .await?;
+/// `column` must be a fixed column name, never user input.
+async fn set_chat_setting(
+    pool: &Pool<Any>,
+    chat_id: i64,
+    column: &'static str,
+    value: SettingValue<'_>,
+) -> Result<()> {
+    let query = format!(
+        "INSERT INTO chat_settings (chat_id, {column}) VALUES ($1, $2)
+         ON CONFLICT (chat_id) DO UPDATE SET {column} = excluded.{column}"
+    );
+    let query = sqlx::query(&query).bind(chat_id);
+    let query = match value {
+        SettingValue::Text(text) => query.bind(text),
+        SettingValue::OptionalText(text) => query.bind(text),
+        SettingValue::Flag(flag) => query.bind(flag as i64),
+        SettingValue::OptionalNumber(number) => query.bind(number),
+    };
+    query.execute(pool).await?;
     Ok(())
 }
 
+/// Results with one color, shown as "+3 -1 =2".
+struct ColorRecord {
+    wins: i64,
+    losses: i64,
+    draws: i64,
+}
+
+impl std::fmt::Display for ColorRecord {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "+{} -{} ={}", self.wins, self.losses, self.draws)
+    }
+}
+
 pub async fn format_user_history(
     pool: &Pool<Any>,
     user: &DbUser,
     chat_id: i64,
     page: u32,
 ) -> Result<String> {
-    let stats_row = sqlx::query(
+    let stats_row = sqlx::query(&format!(
         "SELECT
-            SUM(CASE
-                WHEN result = '1-0' AND white_user_id = $1 THEN 1
-                WHEN result = '0-1' AND black_user_id = $1 THEN 1
-                ELSE 0
-            END) AS wins,
-            SUM(CASE
-                WHEN result = '0-1' AND white_user_id = $1 THEN 1
-                WHEN result = '1-0' AND black_user_id = $1 THEN 1
-                ELSE 0
-            END) AS losses,
-            SUM(CASE
-                WHEN result = '1/2-1/2' THEN 1
-                ELSE 0
-            END) AS draws
-         FROM games
+            SUM(CASE WHEN white_user_id = $1 AND result = '1-0' THEN 1 ELSE 0 END) AS white_wins,
+            SUM(CASE WHEN white_user_id = $1 AND result = '0-1' THEN 1 ELSE 0 END) AS white_losses,
+            SUM(CASE WHEN white_user_id = $1 AND result = '1/2-1/2' THEN 1 ELSE 0 END) AS white_draws,
+            SUM(CASE WHEN black_user_id = $1 AND result = '0-1' THEN 1 ELSE 0 END) AS black_wins,
+            SUM(CASE WHEN black_user_id = $1 AND result = '1-0' THEN 1 ELSE 0 END) AS black_losses,
+            SUM(CASE WHEN black_user_id = $1 AND result = '1/2-1/2' THEN 1 ELSE 0 END) AS black_draws
+         FROM {ALL_GAMES} AS g
          WHERE chat_id = $2
-           AND (white_user_id = $1 OR black_user_id = $1)",
-    )
+           AND (white_user_id = $1 OR black_user_id = $1)"
+    ))
     .bind(user.id)
     .bind(chat_id)
     .fetch_one(pool)
     .await?;
 
-    let wins: i64 = stats_row.try_get::<i64, _>("wins").unwrap_or(0);
-    let losses: i64 = stats_row.try_get::<i64, _>("losses").unwrap_or(0);
-    let draws: i64 = stats_row.try_get::<i64, _>("draws").unwrap_or(0);
+    let count = |column: &str| stats_row.try_get::<i64, _>(column).unwrap_or(0);
+    let as_white = ColorRecord {
+        wins: count("white_wins"),
+        losses: count("white_losses"),
+        draws: count("white_draws"),
+    };
+    let as_black = ColorRecord {
+        wins: count("black_wins"),
+        losses: count("black_losses"),
+        draws: count("black_draws"),
+    };
+    let wins = as_white.wins + as_black.wins;
+    let losses = as_white.losses + as_black.losses;
+    let draws = as_white.draws + as_black.draws;
 
     let total = wins + losses + draws;
     let win_pct = if total == 0 {
@@ -546,21 +2287,21 @@ pub async fn format_user_history(
 
     let limit: i64 = 10;
     let offset = ((page - 1) as i64) * limit;
-    let history_rows: Vec<HistoryRow> = sqlx::query_as(
+    let history_rows: Vec<HistoryRow> = sqlx::query_as(&format!(
         "WITH numbered AS (
-            SELECT g.id, g.started_at, g.result, u1.username AS white_username, u2.username AS black_username,
+            SELECT g.id, g.started_at, g.ended_at, g.result, u1.username AS white_username, u2.username AS black_username,
                    ROW_NUMBER() OVER (ORDER BY g.started_at ASC) AS local_num
-            FROM games g
+            FROM {ALL_GAMES} AS g
             JOIN users u1 ON g.white_user_id = u1.id
             JOIN users u2 ON g.black_user_id = u2.id
             WHERE g.chat_id = $1
               AND (g.white_user_id = $2 OR g.black_user_id = $2)
         )
-        SELECT id, local_num, started_at, result, white_username, black_username
+        SELECT id, local_num, started_at, ended_at, result, white_username, black_username
         FROM numbered
         ORDER BY started_at DESC
-        LIMIT $3 OFFSET $4",
-    )
+        LIMIT $3 OFFSET $4"
+    ))
     .bind(chat_id)
     .bind(user.id)
     .bind(limit)
@@ -573,17 +2314,75 @@ pub async fn format_user_history(
     let lines = format_history_lines(&history_rows, &all_moves);
 
     let mut output = format!(
-        "History for {} in this chat.\nWins: {}, Losses: {}, Draws: {}, Win%: {:.1}\n\n",
+        "History for {} in this chat.\nWins: {}, Losses: {}, Draws: {}, Win%: {:.1}\nAs White: {}\nAs Black: {}\n",
         crate::utils::escape_html(&user.display_name()),
         wins,
         losses,
         draws,
-        win_pct
+        win_pct,
+        as_white,
+        as_black
     );
+    let think = get_user_think_time(pool, user.id, chat_id).await?;
+    if think.moves > 0 {
+        output.push_str(&format!(
+            "Think time: avg {}, longest {}\n",
+            crate::utils::format_duration(think.average_secs()),
+            crate::utils::format_duration(think.longest_secs)
+        ));
+    }
+    output.push('\n');
     output.push_str(&format_history_output(&lines));
     Ok(output)
 }
 
+/// The two players' finished games against each other in the chat, archived ones included.
+pub async fn get_pair_record(pool: &Pool<Any>, chat_id: i64, player_a: i64, player_b: i64) -> Result<PairRecord> {
+    let pair = "g.chat_id = $3 AND g.status = 'finished'
+           AND ((g.white_user_id = $1 AND g.black_user_id = $2)
+             OR (g.white_user_id = $2 AND g.black_user_id = $1))";
+    let row = sqlx::query(&format!(
+        "SELECT
+            SUM(CASE WHEN (g.white_user_id = $1 AND g.result = '1-0') OR (g.black_user_id = $1 AND g.result = '0-1') THEN 1 ELSE 0 END) AS a_wins,
+            SUM(CASE WHEN (g.white_user_id = $2 AND g.result = '1-0') OR (g.black_user_id = $2 AND g.result = '0-1') THEN 1 ELSE 0 END) AS b_wins,
+            SUM(CASE WHEN g.result = '1/2-1/2' THEN 1 ELSE 0 END) AS draws
+         FROM {ALL_GAMES} AS g
+         WHERE {pair}"
+    ))
+    .bind(player_a)
+    .bind(player_b)
+    .bind(chat_id)
+    .fetch_one(pool)
+    .await?;
+    let count = |column: &str| row.try_get::<i64, _>(column).unwrap_or(0);
+
+    let last: Option<(i64, i64, Option<String>)> = sqlx::query_as(&format!(
+        "SELECT g.white_user_id, g.black_user_id, g.result FROM {ALL_GAMES} AS g
+         WHERE {pair}
+         ORDER BY g.id DESC
+         LIMIT 1"
+    ))
+    .bind(player_a)
+    .bind(player_b)
+    .bind(chat_id)
+    .fetch_optional(pool)
+    .await?;
+    let last_winner = last.and_then(|(white, black, result)| match result.as_deref().and_then(GameResult::parse)? {
+        GameResult::WhiteWins => Some(white),
+        GameResult::BlackWins => Some(black),
+        GameResult::Draw => None,
+    });
+
+    Ok(PairRecord {
+        player_a,
+        player_b,
+        a_wins: count("a_wins"),
+        b_wins: count("b_wins"),
+        draws: count("draws"),
+        last_winner,
+    })
+}
+
 pub async fn format_head_to_head(
     pool: &Pool<Any>,
     user_a: &DbUser,
@@ -591,12 +2390,12 @@ pub async fn format_head_to_head(
     chat_id: i64,
     page: u32,
 ) -> Result<String> {
-    let count_row = sqlx::query(
-        "SELECT COUNT(*) as total FROM games
-         WHERE chat_id = $3
-           AND ((white_user_id = $1 AND black_user_id = $2)
-             OR (white_user_id = $2 AND black_user_id = $1))",
-    )
+    let count_row = sqlx::query(&format!(
+        "SELECT COUNT(*) as total FROM {ALL_GAMES} AS g
+         WHERE g.chat_id = $3
+           AND ((g.white_user_id = $1 AND g.black_user_id = $2)
+             OR (g.white_user_id = $2 AND g.black_user_id = $1))"
+    ))
     .bind(user_a.id)
     .bind(user_b.id)
     .bind(chat_id)
@@ -606,22 +2405,22 @@ pub async fn format_head_to_head(
 
     let limit: i64 = 10;
     let offset = ((page - 1) as i64) * limit;
-    let history_rows: Vec<HistoryRow> = sqlx::query_as(
+    let history_rows: Vec<HistoryRow> = sqlx::query_as(&format!(
         "WITH numbered AS (
-            SELECT g.id, g.started_at, g.result, u1.username AS white_username, u2.username AS black_username,
+            SELECT g.id, g.started_at, g.ended_at, g.result, u1.username AS white_username, u2.username AS black_username,
                    ROW_NUMBER() OVER (ORDER BY g.started_at ASC) AS local_num
-            FROM games g
+            FROM {ALL_GAMES} AS g
             JOIN users u1 ON g.white_user_id = u1.id
             JOIN users u2 ON g.black_user_id = u2.id
             WHERE g.chat_id = $3
               AND ((g.white_user_id = $1 AND g.black_user_id = $2)
                 OR (g.white_user_id = $2 AND g.black_user_id = $1))
         )
-        SELECT id, local_num, started_at, result, white_username, black_username
+        SELECT id, local_num, started_at, ended_at, result, white_username, black_username
         FROM numbered
         ORDER BY started_at DESC
-        LIMIT $4 OFFSET $5",
-    )
+        LIMIT $4 OFFSET $5"
+    ))
     .bind(user_a.id)
     .bind(user_b.id)
     .bind(chat_id)
@@ -1,3 +1,7 @@
 pub mod database;
+mod pool;
+mod user_cache;
 
 pub use database::*;
+pub use pool::PoolConfig;
+pub use user_cache::{UserCache, DEFAULT_USER_CACHE_TTL};
@@ -0,0 +1,121 @@
+use crate::error::Result;
+use sqlx::any::{AnyConnectOptions, AnyPoolOptions};
+use sqlx::{Any, ConnectOptions, Pool};
+use std::env;
+use std::str::FromStr;
+use std::time::Duration;
+use tracing::warn;
+
+const DEFAULT_MAX_CONNECTIONS: u32 = 5;
+const DEFAULT_ACQUIRE_TIMEOUT_SECS: u64 = 30;
+const DEFAULT_IDLE_TIMEOUT_SECS: u64 = 600;
+const DEFAULT_SLOW_QUERY_MS: u64 = 500;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PoolConfig {
+    pub max_connections: u32,
+    /// How long a query waits for a free connection before failing.
+    pub acquire_timeout: Duration,
+    /// Idle connections are closed after this long; `None` keeps them open.
+    pub idle_timeout: Option<Duration>,
+    /// Prepared statements kept per connection. Only PostgreSQL honours it; `None` keeps
+    /// the driver default.
+    pub statement_cache_capacity: Option<usize>,
+    /// Statements taking longer are logged as warnings with their duration; `None` doesn't.
+    pub slow_query: Option<Duration>,
+}
+
+impl Default for PoolConfig {
+    fn default() -> Self {
+        Self {
+            max_connections: DEFAULT_MAX_CONNECTIONS,
+            acquire_timeout: Duration::from_secs(DEFAULT_ACQUIRE_TIMEOUT_SECS),
+            idle_timeout: Some(Duration::from_secs(DEFAULT_IDLE_TIMEOUT_SECS)),
+            statement_cache_capacity: None,
+            slow_query: Some(Duration::from_millis(DEFAULT_SLOW_QUERY_MS)),
+        }
+    }
+}
+
+impl PoolConfig {
+    /// Reads `DB_MAX_CONNECTIONS`, `DB_ACQUIRE_TIMEOUT_SECS`, `DB_IDLE_TIMEOUT_SECS`
+    /// (0 disables the idle timeout), `DB_STATEMENT_CACHE_SIZE` and `SLOW_QUERY_MS` (0 turns
+    /// slow query warnings off).
+    pub fn from_env() -> Self {
+        let defaults = Self::default();
+        let number = |name: &str| env::var(name).ok().and_then(|v| v.trim().parse::<u64>().ok());
+
+        Self {
+            max_connections: number("DB_MAX_CONNECTIONS")
+                .filter(|n| *n > 0)
+                .map_or(defaults.max_connections, |n| n as u32),
+            acquire_timeout: number("DB_ACQUIRE_TIMEOUT_SECS")
+                .filter(|secs| *secs > 0)
+                .map_or(defaults.acquire_timeout, Duration::from_secs),
+            idle_timeout: match number("DB_IDLE_TIMEOUT_SECS") {
+                Some(0) => None,
+                Some(secs) => Some(Duration::from_secs(secs)),
+                None => defaults.idle_timeout,
+            },
+            statement_cache_capacity: number("DB_STATEMENT_CACHE_SIZE").map(|n| n as usize),
+            slow_query: match number("SLOW_QUERY_MS") {
+                Some(0) => None,
+                Some(ms) => Some(Duration::from_millis(ms)),
+                None => defaults.slow_query,
+            },
+        }
+    }
+
+    pub async fn connect(&self, database_url: &str) -> Result<Pool<Any>> {
+        let url = match self.statement_cache_capacity {
+            Some(capacity) if is_postgres(database_url) => {
+                with_statement_cache_capacity(database_url, capacity)
+            }
+            Some(_) => {
+                warn!("DB_STATEMENT_CACHE_SIZE only applies to PostgreSQL and is ignored");
+                database_url.to_string()
+            }
+            None => database_url.to_string(),
+        };
+
+        let mut options = AnyConnectOptions::from_str(&url)?;
+        options = match self.slow_query {
+            Some(threshold) => options.log_slow_statements(log::LevelFilter::Warn, threshold),
+            None => options.log_slow_statements(log::LevelFilter::Off, Duration::MAX),
+        };
+
+        Ok(AnyPoolOptions::new()
+            .max_connections(self.max_connections)
+            .acquire_timeout(self.acquire_timeout)
+            .idle_timeout(self.idle_timeout)
+            .connect_with(options)
+            .await?)
+    }
+}
+
+fn is_postgres(database_url: &str) -> bool {
+    database_url.starts_with("postgres")
+}
+
+/// Adds the `statement-cache-capacity` connection parameter to a PostgreSQL URL.
+fn with_statement_cache_capacity(database_url: &str, capacity: usize) -> String {
+    let separator = if database_url.contains('?') { '&' } else { '?' };
+    format!("{}{}statement-cache-capacity={}", database_url, separator, capacity)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_with_statement_cache_capacity() {
+        assert_eq!(
+            with_statement_cache_capacity("postgres://u:p@db/kamachess", 50),
+            "postgres://u:p@db/kamachess?statement-cache-capacity=50"
+        );
+        assert_eq!(
+            with_statement_cache_capacity("postgres://db/k?sslmode=require", 0),
+            "postgres://db/k?sslmode=require&statement-cache-capacity=0"
+        );
+    }
+}
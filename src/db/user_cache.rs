@@ -0,0 +1,169 @@
+//! Short-lived cache of `DbUser` rows. Every command upserts its sender and a move looks up
+//! both players, so in a busy group the same few rows are read over and over. Entries expire
+//! after the TTL and are refreshed whenever something changes a user.
+
+use super::database;
+use crate::error::Result;
+use crate::models::{DbUser, User};
+use sqlx::{Any, Pool};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+pub const DEFAULT_USER_CACHE_TTL: Duration = Duration::from_secs(60);
+
+#[derive(Default)]
+struct Entries {
+    by_id: HashMap<i64, (DbUser, Instant)>,
+    telegram_ids: HashMap<i64, i64>,
+}
+
+#[derive(Clone)]
+pub struct UserCache {
+    ttl: Duration,
+    entries: Arc<Mutex<Entries>>,
+}
+
+impl Default for UserCache {
+    fn default() -> Self {
+        Self::new(DEFAULT_USER_CACHE_TTL)
+    }
+}
+
+impl UserCache {
+    /// A zero `ttl` disables caching.
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            entries: Arc::new(Mutex::new(Entries::default())),
+        }
+    }
+
+    /// `database::upsert_user`, skipped when the cached row already has the same names.
+    pub async fn upsert(&self, pool: &Pool<Any>, user: &User) -> Result<DbUser> {
+        if let Some(cached) = self.by_telegram_id(user.id) {
+            if cached.username == user.username
+                && cached.first_name == user.first_name
+                && cached.last_name == user.last_name
+            {
+                return Ok(cached);
+            }
+        }
+
+        let db_user = database::upsert_user(pool, user).await?;
+        // The upsert may have taken the username from another row
+        if let Some(username) = &db_user.username {
+            self.forget_username(username);
+        }
+        self.store(&db_user);
+        Ok(db_user)
+    }
+
+    /// `database::upsert_user_by_username`, answered from the cache when a fresh row
+    /// currently holds the username.
+    pub async fn upsert_by_username(&self, pool: &Pool<Any>, username: &str) -> Result<DbUser> {
+        if let Some(cached) = self.fresh_by_username(username) {
+            return Ok(cached);
+        }
+
+        let db_user = database::upsert_user_by_username(pool, username).await?;
+        self.store(&db_user);
+        Ok(db_user)
+    }
+
+    /// `database::find_user_by_username`: like `upsert_by_username`, but also finds users
+    /// by a previous username. Only for lookups, never for picking an opponent.
+    pub async fn find_by_username(&self, pool: &Pool<Any>, username: &str) -> Result<DbUser> {
+        if let Some(cached) = self.fresh_by_username(username) {
+            return Ok(cached);
+        }
+
+        let db_user = database::find_user_by_username(pool, username).await?;
+        self.store(&db_user);
+        Ok(db_user)
+    }
+
+    fn fresh_by_username(&self, username: &str) -> Option<DbUser> {
+        self.entries
+            .lock()
+            .unwrap()
+            .by_id
+            .values()
+            .find(|(user, at)| user.username.as_deref() == Some(username) && at.elapsed() < self.ttl)
+            .map(|(user, _)| user.clone())
+    }
+
+    pub async fn get_by_id(&self, pool: &Pool<Any>, id: i64) -> Result<DbUser> {
+        if let Some(cached) = self.fresh(id) {
+            return Ok(cached);
+        }
+        let db_user = database::get_user_by_id(pool, id).await?;
+        self.store(&db_user);
+        Ok(db_user)
+    }
+
+    /// Drops a row whose stats or identity changed behind the cache's back.
+    pub fn invalidate(&self, id: i64) {
+        let mut entries = self.entries.lock().unwrap();
+        if let Some((user, _)) = entries.by_id.remove(&id) {
+            if let Some(telegram_id) = user.telegram_id {
+                entries.telegram_ids.remove(&telegram_id);
+            }
+        }
+    }
+
+    pub fn clear(&self) {
+        *self.entries.lock().unwrap() = Entries::default();
+    }
+
+    fn by_telegram_id(&self, telegram_id: i64) -> Option<DbUser> {
+        let id = *self.entries.lock().unwrap().telegram_ids.get(&telegram_id)?;
+        self.fresh(id)
+    }
+
+    fn fresh(&self, id: i64) -> Option<DbUser> {
+        let entries = self.entries.lock().unwrap();
+        match entries.by_id.get(&id) {
+            Some((user, at)) if at.elapsed() < self.ttl => Some(user.clone()),
+            Some(_) => {
+                drop(entries);
+                self.invalidate(id);
+                None
+            }
+            None => None,
+        }
+    }
+
+    fn store(&self, user: &DbUser) {
+        if self.ttl.is_zero() {
+            return;
+        }
+        let mut entries = self.entries.lock().unwrap();
+        // Keep the map from growing without bound in long-running processes
+        let ttl = self.ttl;
+        if entries.by_id.len() > 10_000 {
+            entries.by_id.retain(|_, (_, at)| at.elapsed() < ttl);
+            let Entries { by_id, telegram_ids } = &mut *entries;
+            telegram_ids.retain(|_, id| by_id.contains_key(id));
+        }
+        entries.by_id.insert(user.id, (user.clone(), Instant::now()));
+        if let Some(telegram_id) = user.telegram_id {
+            entries.telegram_ids.insert(telegram_id, user.id);
+        }
+    }
+
+    fn forget_username(&self, username: &str) {
+        let stale: Vec<i64> = self
+            .entries
+            .lock()
+            .unwrap()
+            .by_id
+            .values()
+            .filter(|(user, _)| user.username.as_deref() == Some(username))
+            .map(|(user, _)| user.id)
+            .collect();
+        for id in stale {
+            self.invalidate(id);
+        }
+    }
+}
@@ -0,0 +1,147 @@
+//! External UCI engine (Stockfish or anything speaking UCI), configured from the
+//! environment. Every search runs in its own engine process; a semaphore caps how many
+//! run at once so a burst of requests queues up instead of starving the bot of CPU.
+
+mod uci;
+
+use std::env;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::error::{KamaError, Result};
+use tokio::sync::Semaphore;
+
+pub use uci::{Analysis, Score};
+
+const DEFAULT_HASH_MB: u32 = 16;
+const DEFAULT_THREADS: u32 = 1;
+const DEFAULT_MOVETIME_MS: u64 = 1000;
+const DEFAULT_MAX_WORKERS: usize = 2;
+/// How long a request waits for a free worker before giving up.
+const QUEUE_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EngineConfig {
+    pub path: PathBuf,
+    /// Extra `setoption` pairs, e.g. Skill Level.
+    pub options: Vec<(String, String)>,
+    pub hash_mb: u32,
+    pub threads: u32,
+    pub movetime: Duration,
+    /// Engine processes allowed to run at the same time.
+    pub max_workers: usize,
+}
+
+impl EngineConfig {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            options: Vec::new(),
+            hash_mb: DEFAULT_HASH_MB,
+            threads: DEFAULT_THREADS,
+            movetime: Duration::from_millis(DEFAULT_MOVETIME_MS),
+            max_workers: DEFAULT_MAX_WORKERS,
+        }
+    }
+
+    /// Reads `ENGINE_PATH`, `ENGINE_OPTIONS`, `ENGINE_HASH_MB`, `ENGINE_THREADS`,
+    /// `ENGINE_MOVETIME_MS` and `ENGINE_MAX_WORKERS`. Returns `None` when no engine
+    /// path is set, which disables engine features.
+    pub fn from_env() -> Option<Self> {
+        let path = env::var("ENGINE_PATH").ok().filter(|p| !p.trim().is_empty())?;
+        let defaults = Self::new(path.trim());
+        let number = |name: &str| env::var(name).ok().and_then(|v| v.trim().parse::<u64>().ok());
+
+        Some(Self {
+            options: env::var("ENGINE_OPTIONS")
+                .map(|v| parse_options(&v))
+                .unwrap_or_default(),
+            hash_mb: number("ENGINE_HASH_MB")
+                .filter(|mb| *mb > 0)
+                .map_or(defaults.hash_mb, |mb| mb as u32),
+            threads: number("ENGINE_THREADS")
+                .filter(|t| *t > 0)
+                .map_or(defaults.threads, |t| t as u32),
+            movetime: number("ENGINE_MOVETIME_MS")
+                .filter(|ms| *ms > 0)
+                .map_or(defaults.movetime, Duration::from_millis),
+            max_workers: number("ENGINE_MAX_WORKERS")
+                .filter(|n| *n > 0)
+                .map_or(defaults.max_workers, |n| n as usize),
+            ..defaults
+        })
+    }
+}
+
+/// Parses `Name=Value` pairs separated by `;`, e.g. "Skill Level=10;UCI_ShowWDL=true".
+fn parse_options(value: &str) -> Vec<(String, String)> {
+    value
+        .split(';')
+        .filter_map(|pair| {
+            let (name, value) = pair.split_once('=')?;
+            let name = name.trim();
+            (!name.is_empty()).then(|| (name.to_string(), value.trim().to_string()))
+        })
+        .collect()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SearchLimit {
+    /// Use the configured move time.
+    Default,
+    MoveTime(Duration),
+    Depth(u32),
+}
+
+#[derive(Clone)]
+pub struct Engine {
+    config: Arc<EngineConfig>,
+    workers: Arc<Semaphore>,
+}
+
+impl Engine {
+    pub fn new(config: EngineConfig) -> Self {
+        let workers = Arc::new(Semaphore::new(config.max_workers));
+        Self {
+            config: Arc::new(config),
+            workers,
+        }
+    }
+
+    pub fn config(&self) -> &EngineConfig {
+        &self.config
+    }
+
+    /// Searches the position and returns the final evaluation and principal variation.
+    /// Waits for a free worker first; errors if none frees up in time.
+    pub async fn analyse(&self, fen: &str, limit: SearchLimit) -> Result<Analysis> {
+        let _permit = tokio::time::timeout(QUEUE_TIMEOUT, self.workers.acquire())
+            .await
+            .map_err(|_| KamaError::Engine("The engine is busy, try again in a moment.".to_string()))?
+            .map_err(|e| KamaError::Engine(e.to_string()))?;
+
+        let go = match limit {
+            SearchLimit::Default => format!("go movetime {}", self.config.movetime.as_millis()),
+            SearchLimit::MoveTime(time) => format!("go movetime {}", time.as_millis()),
+            SearchLimit::Depth(depth) => format!("go depth {}", depth),
+        };
+        uci::run_search(&self.config, fen, &go).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_options() {
+        assert_eq!(
+            parse_options("Skill Level=10; UCI_ShowWDL = true;;broken"),
+            vec![
+                ("Skill Level".to_string(), "10".to_string()),
+                ("UCI_ShowWDL".to_string(), "true".to_string()),
+            ]
+        );
+    }
+}
@@ -0,0 +1,190 @@
+use std::fmt;
+use std::process::Stdio;
+use std::time::Duration;
+
+use crate::error::{KamaError, Result};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader, Lines};
+use tokio::process::{ChildStdout, Command};
+use tracing::debug;
+
+use super::EngineConfig;
+use crate::game::Color;
+
+/// Extra time on top of the search itself for startup and `isready` round trips.
+const STARTUP_TIMEOUT: Duration = Duration::from_secs(10);
+/// Depth-limited searches have no clock, so they are cut off here.
+const MAX_SEARCH_TIME: Duration = Duration::from_secs(60);
+
+/// Evaluation from the side to move's point of view, as engines report it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Score {
+    Centipawns(i32),
+    /// Moves to mate; negative when the side to move is getting mated.
+    Mate(i32),
+}
+
+impl Score {
+    /// The same score from White's point of view.
+    pub fn for_white(self, side_to_move: Color) -> Score {
+        if side_to_move == Color::White {
+            return self;
+        }
+        match self {
+            Score::Centipawns(cp) => Score::Centipawns(-cp),
+            Score::Mate(moves) => Score::Mate(-moves),
+        }
+    }
+}
+
+impl fmt::Display for Score {
+    /// "+0.34", "-1.20", "#3" or "#-2".
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Score::Centipawns(cp) => write!(f, "{:+.2}", *cp as f64 / 100.0),
+            Score::Mate(moves) => write!(f, "#{}", moves),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Analysis {
+    pub depth: u32,
+    pub score: Option<Score>,
+    /// Principal variation in UCI notation.
+    pub pv: Vec<String>,
+    pub best_move: Option<String>,
+}
+
+impl Analysis {
+    /// Folds one `info` line into the analysis. Lines without a score (currmove,
+    /// strings) and secondary MultiPV lines are ignored.
+    fn apply_info(&mut self, line: &str) {
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        let value = |key: &str| {
+            tokens
+                .iter()
+                .position(|t| *t == key)
+                .and_then(|i| tokens.get(i + 1))
+                .copied()
+        };
+
+        if value("multipv").is_some_and(|n| n != "1") {
+            return;
+        }
+        let Some(at) = tokens.iter().position(|t| *t == "score") else {
+            return;
+        };
+        let amount = tokens.get(at + 2).and_then(|v| v.parse().ok());
+        let score = match (tokens.get(at + 1), amount) {
+            (Some(&"cp"), Some(cp)) => Score::Centipawns(cp),
+            (Some(&"mate"), Some(moves)) => Score::Mate(moves),
+            _ => return,
+        };
+
+        self.score = Some(score);
+        if let Some(depth) = value("depth").and_then(|d| d.parse().ok()) {
+            self.depth = depth;
+        }
+        if let Some(start) = tokens.iter().position(|t| *t == "pv") {
+            self.pv = tokens[start + 1..].iter().map(|m| m.to_string()).collect();
+        }
+    }
+}
+
+fn engine_error(message: impl Into<String>) -> KamaError {
+    KamaError::Engine(message.into())
+}
+
+pub(super) async fn run_search(config: &EngineConfig, fen: &str, go: &str) -> Result<Analysis> {
+    let mut child = Command::new(&config.path)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(|e| engine_error(format!("Failed to start engine {}: {}", config.path.display(), e)))?;
+    let mut stdin = child.stdin.take().ok_or_else(|| engine_error("Engine stdin unavailable"))?;
+    let stdout = child.stdout.take().ok_or_else(|| engine_error("Engine stdout unavailable"))?;
+    let mut lines = BufReader::new(stdout).lines();
+
+    let mut setup = String::from("uci\n");
+    setup.push_str(&format!("setoption name Hash value {}\n", config.hash_mb));
+    setup.push_str(&format!("setoption name Threads value {}\n", config.threads));
+    for (name, value) in &config.options {
+        setup.push_str(&format!("setoption name {} value {}\n", name, value));
+    }
+    setup.push_str("isready\n");
+    stdin.write_all(setup.as_bytes()).await?;
+    tokio::time::timeout(STARTUP_TIMEOUT, wait_for(&mut lines, "readyok"))
+        .await
+        .map_err(|_| engine_error("Engine did not become ready"))??;
+
+    stdin
+        .write_all(format!("position fen {}\n{}\n", fen, go).as_bytes())
+        .await?;
+    let search_limit = config.movetime.max(MAX_SEARCH_TIME) + STARTUP_TIMEOUT;
+    let analysis = tokio::time::timeout(search_limit, read_search(&mut lines))
+        .await
+        .map_err(|_| engine_error("Engine search timed out"))??;
+
+    // Best effort; kill_on_drop cleans up engines that ignore it
+    let _ = stdin.write_all(b"quit\n").await;
+    let _ = tokio::time::timeout(Duration::from_secs(1), child.wait()).await;
+
+    Ok(analysis)
+}
+
+async fn wait_for(lines: &mut Lines<BufReader<ChildStdout>>, expected: &str) -> Result<()> {
+    while let Some(line) = lines.next_line().await? {
+        if line.trim() == expected {
+            return Ok(());
+        }
+    }
+    Err(engine_error(format!("Engine exited before sending {}", expected)))
+}
+
+async fn read_search(lines: &mut Lines<BufReader<ChildStdout>>) -> Result<Analysis> {
+    let mut analysis = Analysis::default();
+    while let Some(line) = lines.next_line().await? {
+        let line = line.trim();
+        if line.starts_with("info ") {
+            analysis.apply_info(line);
+        } else if let Some(rest) = line.strip_prefix("bestmove") {
+            analysis.best_move = rest
+                .split_whitespace()
+                .next()
+                .filter(|m| *m != "(none)")
+                .map(str::to_string);
+            debug!(depth = analysis.depth, score = ?analysis.score, "Engine search finished");
+            return Ok(analysis);
+        }
+    }
+    Err(engine_error("Engine exited during the search"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_apply_info() {
+        let mut analysis = Analysis::default();
+        analysis.apply_info("info depth 12 seldepth 18 multipv 1 score cp 34 nodes 1000 pv e2e4 e7e5 g1f3");
+        analysis.apply_info("info depth 13 currmove d2d4 currmovenumber 2");
+        analysis.apply_info("info depth 12 multipv 2 score cp -10 pv d2d4");
+        assert_eq!(analysis.depth, 12);
+        assert_eq!(analysis.score, Some(Score::Centipawns(34)));
+        assert_eq!(analysis.pv, vec!["e2e4", "e7e5", "g1f3"]);
+
+        analysis.apply_info("info depth 20 score mate -3 pv h7h8");
+        assert_eq!(analysis.score, Some(Score::Mate(-3)));
+    }
+
+    #[test]
+    fn test_score_display_and_perspective() {
+        assert_eq!(Score::Centipawns(34).to_string(), "+0.34");
+        assert_eq!(Score::Centipawns(-120).to_string(), "-1.20");
+        assert_eq!(Score::Mate(3).for_white(Color::Black).to_string(), "#-3");
+        assert_eq!(Score::Centipawns(50).for_white(Color::White), Score::Centipawns(50));
+    }
+}
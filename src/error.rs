@@ -0,0 +1,96 @@
+//! Error type for the library modules (database, Telegram client, chess rules, rendering,
+//! engine). Handlers match on the kind to choose what to tell the user; the binary and the
+//! handlers themselves still use `anyhow` for glue code.
+
+use axum::http::StatusCode;
+
+#[derive(Debug, thiserror::Error)]
+pub enum KamaError {
+    #[error("Database error: {0}")]
+    Db(#[from] sqlx::Error),
+    #[error("Telegram API error: {0}")]
+    Telegram(String),
+    /// A move or position the rules don't allow. The message is meant for players.
+    #[error("{0}")]
+    ChessRule(String),
+    #[error("It is not your turn.")]
+    NotYourTurn,
+    #[error("Game not found.")]
+    GameNotFound,
+    #[error("Render error: {0}")]
+    Render(String),
+    #[error("Engine error: {0}")]
+    Engine(String),
+    /// A request that can't be carried out as asked, e.g. merging a user into itself.
+    #[error("{0}")]
+    Invalid(String),
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+pub type Result<T, E = KamaError> = std::result::Result<T, E>;
+
+impl From<reqwest::Error> for KamaError {
+    fn from(err: reqwest::Error) -> Self {
+        KamaError::Telegram(err.to_string())
+    }
+}
+
+impl From<image::ImageError> for KamaError {
+    fn from(err: image::ImageError) -> Self {
+        KamaError::Render(err.to_string())
+    }
+}
+
+impl From<png::EncodingError> for KamaError {
+    fn from(err: png::EncodingError) -> Self {
+        KamaError::Render(err.to_string())
+    }
+}
+
+impl KamaError {
+    /// Text safe to show in a chat. Internal failures get a generic line; the details
+    /// belong in the logs.
+    pub fn user_message(&self) -> String {
+        match self {
+            KamaError::ChessRule(_)
+            | KamaError::NotYourTurn
+            | KamaError::GameNotFound
+            | KamaError::Invalid(_) => self.to_string(),
+            KamaError::Engine(_) => "The engine could not analyse this position.".to_string(),
+            KamaError::Db(_) | KamaError::Telegram(_) | KamaError::Render(_) | KamaError::Io(_) => {
+                "Something went wrong, please try again.".to_string()
+            }
+        }
+    }
+
+    pub fn status_code(&self) -> StatusCode {
+        match self {
+            KamaError::ChessRule(_) | KamaError::Invalid(_) => StatusCode::BAD_REQUEST,
+            KamaError::NotYourTurn => StatusCode::CONFLICT,
+            KamaError::GameNotFound => StatusCode::NOT_FOUND,
+            KamaError::Telegram(_) => StatusCode::BAD_GATEWAY,
+            KamaError::Engine(_) => StatusCode::SERVICE_UNAVAILABLE,
+            KamaError::Db(_) | KamaError::Render(_) | KamaError::Io(_) => {
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_user_message_hides_internal_errors() {
+        let rule = KamaError::ChessRule("Illegal move.".to_string());
+        assert_eq!(rule.user_message(), "Illegal move.");
+        assert_eq!(rule.status_code(), StatusCode::BAD_REQUEST);
+
+        let db = KamaError::Db(sqlx::Error::RowNotFound);
+        assert!(!db.user_message().contains("row"));
+        assert_eq!(db.status_code(), StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(KamaError::GameNotFound.status_code(), StatusCode::NOT_FOUND);
+    }
+}
@@ -1,74 +1,346 @@
-use anyhow::{Context, Result};
-use chess::Board;
+use crate::error::{KamaError, Result};
+use super::position::Position;
+use fs2::FileExt;
 use std::fs;
-use std::io::Read;
 use std::path::{Path, PathBuf};
-use std::time::SystemTime;
-use tracing::{debug, warn};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, SystemTime};
+use tokio::sync::Notify;
+use tracing::{debug, info, warn};
 
-const CACHE_DIR: &str = "images_cache";
+const DEFAULT_CACHE_DIR: &str = "images_cache";
 const DEFAULT_CACHE_SIZE_MB: u64 = 100;
 const EVICTION_TARGET_PERCENT: u64 = 80; // Evict to 80% of limit
+/// Locked while a sweep runs, so instances sharing the directory take turns.
+const LOCK_FILE: &str = ".lock";
+const TEMP_EXTENSION: &str = "tmp";
+/// Temp files older than this were left by a crashed write and are removed.
+const STALE_TEMP_AGE: Duration = Duration::from_secs(600);
+/// Part of every cache file name. Bump it whenever drawing changes the pixels of existing
+/// boards (pieces, fonts, colours), so older renders are dropped at startup.
+const RENDER_VERSION: u32 = 2;
+
+/// Size of the cache as this process knows it: recounted by every sweep and increased by
+/// every write in between, so cache misses never have to list the directory.
+static CACHED_BYTES: AtomicU64 = AtomicU64::new(0);
+/// Wakes the cleanup task before its next tick once a write takes the cache over the limit.
+static OVER_LIMIT: Notify = Notify::const_new();
+/// Makes temp file names unique within the process; the pid separates processes.
+static TEMP_COUNTER: AtomicU64 = AtomicU64::new(0);
 
 /// Get cached image or create it using the provided render function.
-/// Handles cache size management with LRU eviction.
-pub fn get_or_create<F>(board: &Board, flip_board: bool, render_fn: F) -> Result<Vec<u8>>
+/// `variant_key` distinguishes renders of the same position (e.g. overlays) and may be empty;
+/// `extension` is the output format's file extension.
+/// File I/O goes through `tokio::fs` and rendering runs on the blocking pool, so a slow
+/// disk or a large render doesn't stall other updates.
+/// Size limits are enforced by the background cleanup task, not here; a write only adds
+/// to the in-memory total.
+pub async fn get_or_create<F>(
+    board: &Position,
+    flip_board: bool,
+    variant_key: &str,
+    extension: &str,
+    render_fn: F,
+) -> Result<Vec<u8>>
 where
-    F: FnOnce() -> Result<Vec<u8>>,
+    F: FnOnce() -> Result<Vec<u8>> + Send + 'static,
 {
-    let cache_dir = PathBuf::from(CACHE_DIR);
+    let cache_dir = cache_dir();
 
-    if !cache_dir.exists() {
-        fs::create_dir_all(&cache_dir).context("Failed to create cache directory")?;
-    }
+    tokio::fs::create_dir_all(&cache_dir).await?;
 
-    let file_path = get_cache_path(board, flip_board);
+    let file_path = get_cache_path(&cache_dir, board, flip_board, variant_key, extension);
 
-    if file_path.exists() {
-        match read_cached_image(&file_path) {
-            Ok(bytes) => {
-                debug!("Cache hit: {}", file_path.display());
-                return Ok(bytes);
-            }
-            Err(e) => {
-                warn!("Failed to read cached image: {}", e);
-            }
+    match tokio::fs::read(&file_path).await {
+        Ok(bytes) => {
+            debug!("Cache hit: {}", file_path.display());
+            return Ok(bytes);
+        }
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+        Err(e) => {
+            warn!("Failed to read cached image: {}", e);
         }
     }
 
     debug!("Cache miss: {}", file_path.display());
-    let bytes = render_fn()?;
+    let bytes = tokio::task::spawn_blocking(render_fn)
+        .await
+        .map_err(|e| KamaError::Render(format!("Render task failed: {}", e)))??;
 
-    if let Err(e) = check_and_evict_if_needed(&cache_dir) {
-        warn!("Cache eviction failed: {}. Continuing anyway.", e);
-    }
-
-    if let Err(e) = fs::write(&file_path, &bytes) {
+    if let Err(e) = write_atomically(&file_path, &bytes).await {
         warn!("Failed to cache image: {}", e);
     } else {
         debug!("Cached image: {}", file_path.display());
+        record_write(bytes.len() as u64);
     }
 
     Ok(bytes)
 }
 
-fn get_cache_path(board: &Board, flip_board: bool) -> PathBuf {
-    let fen = board.to_string();
+/// Writes to a temp file next to `path` and renames it into place, so another instance
+/// reading the same directory sees either the whole image or none of it.
+async fn write_atomically(path: &Path, bytes: &[u8]) -> std::io::Result<()> {
+    let file_name = path.file_name().and_then(|name| name.to_str()).unwrap_or_default();
+    let temp_path = path.with_file_name(format!(
+        "{}.{}.{}.{}",
+        file_name,
+        std::process::id(),
+        TEMP_COUNTER.fetch_add(1, Ordering::Relaxed),
+        TEMP_EXTENSION
+    ));
+    let result = match tokio::fs::write(&temp_path, bytes).await {
+        Ok(()) => tokio::fs::rename(&temp_path, path).await,
+        Err(e) => Err(e),
+    };
+    if result.is_err() {
+        let _ = tokio::fs::remove_file(&temp_path).await;
+    }
+    result
+}
+
+/// Takes the directory's sweep lock, or returns `None` when another instance holds it.
+/// The lock is released when the returned file is dropped.
+fn try_lock_dir(cache_dir: &Path) -> Result<Option<fs::File>> {
+    let file = fs::OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(cache_dir.join(LOCK_FILE))?;
+    match file.try_lock_exclusive() {
+        Ok(()) => Ok(Some(file)),
+        Err(e) if e.raw_os_error() == fs2::lock_contended_error().raw_os_error() => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+fn is_temp_file(path: &Path) -> bool {
+    path.extension().and_then(|s| s.to_str()) == Some(TEMP_EXTENSION)
+}
+
+/// Directory of the image cache, `IMAGE_CACHE_DIR` or `images_cache` by default.
+pub fn cache_dir() -> PathBuf {
+    std::env::var("IMAGE_CACHE_DIR")
+        .ok()
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from(DEFAULT_CACHE_DIR))
+}
+
+fn get_cache_path(
+    cache_dir: &Path,
+    board: &Position,
+    flip_board: bool,
+    variant_key: &str,
+    extension: &str,
+) -> PathBuf {
+    // Move counters don't change the picture, so they are fixed to keep one entry per position
+    let fen = format!("{} 0 1", board.epd());
     let flip_suffix = if flip_board { "_flipped" } else { "" };
     let safe_fen = fen.replace(['/', ' '], "_");
-    PathBuf::from(CACHE_DIR).join(format!("{}{}.png", safe_fen, flip_suffix))
+    let variant_suffix = if variant_key.is_empty() {
+        String::new()
+    } else {
+        format!("_{}", variant_key)
+    };
+    cache_dir.join(format!(
+        "{}_v{}{}{}.{}",
+        safe_fen, RENDER_VERSION, flip_suffix, variant_suffix, extension
+    ))
 }
 
-fn read_cached_image(path: &Path) -> Result<Vec<u8>> {
-    let mut file = fs::File::open(path).context("Failed to open cached image")?;
-    let mut bytes = Vec::new();
-    file.read_to_end(&mut bytes)?;
-    Ok(bytes)
+fn is_cached_image(path: &Path) -> bool {
+    matches!(
+        path.extension().and_then(|s| s.to_str()),
+        Some("png" | "jpg" | "webp")
+    )
 }
 
-fn check_and_evict_if_needed(cache_dir: &Path) -> Result<()> {
-    let max_size_mb = get_cache_size_limit_mb();
-    let max_size_bytes = max_size_mb * 1024 * 1024;
+/// Removes cache entries that can't be served safely: files that fail to decode
+/// (disk errors, writes from before they were atomic), files whose name doesn't follow
+/// the current naming scheme, i.e. renders made with options that no longer exist, and
+/// temp files abandoned by a crashed write. Skipped while another instance holds the
+/// sweep lock. Returns the number of removed files.
+pub fn verify_cache(cache_dir: &Path) -> Result<usize> {
+    if !cache_dir.exists() {
+        return Ok(0);
+    }
+    let Some(_lock) = try_lock_dir(cache_dir)? else {
+        debug!("Cache is being swept by another instance, skipping verification");
+        return Ok(0);
+    };
+
+    let now = SystemTime::now();
+    let mut removed = 0;
+    for entry in fs::read_dir(cache_dir)? {
+        let entry = entry?;
+        let path = entry.path();
+        if !path.is_file() || path.file_name().is_some_and(|name| name == LOCK_FILE) {
+            continue;
+        }
+
+        let reason = if is_temp_file(&path) {
+            // A write in progress, possibly in another instance
+            let age = entry
+                .metadata()
+                .and_then(|m| m.modified())
+                .map(|mtime| now.duration_since(mtime).unwrap_or_default())
+                .unwrap_or_default();
+            (age > STALE_TEMP_AGE).then_some("abandoned temp file")
+        } else if !is_current_cache_name(&path) {
+            Some("outdated name")
+        } else if fs::read(&path)
+            .ok()
+            .and_then(|bytes| image::load_from_memory(&bytes).ok())
+            .is_none()
+        {
+            Some("corrupt image")
+        } else {
+            None
+        };
+
+        if let Some(reason) = reason {
+            match fs::remove_file(&path) {
+                Ok(_) => {
+                    removed += 1;
+                    debug!("Removed {} ({})", path.display(), reason);
+                }
+                Err(e) => warn!("Failed to remove {}: {}", path.display(), e),
+            }
+        }
+    }
+
+    if removed > 0 {
+        info!(removed = removed, "Removed invalid cache entries");
+    }
+    Ok(removed)
+}
+
+/// Checks a file name against the layout produced by `get_cache_path`:
+/// the FEN with `/` and spaces as `_`, the current render version, then `flipped` and
+/// variant key parts.
+fn is_current_cache_name(path: &Path) -> bool {
+    if !is_cached_image(path) {
+        return false;
+    }
+    let Some(stem) = path.file_stem().and_then(|s| s.to_str()) else {
+        return false;
+    };
+
+    let parts: Vec<&str> = stem.split('_').collect();
+    if parts.len() < 13 {
+        return false;
+    }
+    let fen = format!("{} {}", parts[..8].join("/"), parts[8..13].join(" "));
+    if Position::from_str(&fen).is_err() {
+        return false;
+    }
+
+    if parts.get(13) != Some(&format!("v{}", RENDER_VERSION).as_str()) {
+        return false;
+    }
+
+    parts[14..].iter().all(|part| is_known_variant_part(part))
+}
+
+fn is_known_variant_part(part: &str) -> bool {
+    match part {
+        "flipped" | "inside" | "hidden" => true,
+        // JPEG quality
+        _ if part.starts_with('q') => part[1..].parse::<u8>().is_ok(),
+        // Hash of the info strip contents
+        _ if part.starts_with("info") => {
+            part.len() == 20 && part[4..].bytes().all(|b| b.is_ascii_hexdigit())
+        }
+        // Overlay arrows and circles, e.g. ae2e4-cf7
+        _ => part.split('-').all(|item| {
+            let squares = match item.strip_prefix('a') {
+                Some(rest) => rest.len() == 4,
+                None => item.strip_prefix('c').is_some_and(|rest| rest.len() == 2),
+            };
+            squares
+                && item.as_bytes()[1..]
+                    .chunks(2)
+                    .all(|sq| matches!(sq, [b'a'..=b'h', b'1'..=b'8']))
+        }),
+    }
+}
+
+/// Periodically removes entries older than `ttl` (if set) and evicts the oldest
+/// entries once the cache grows over `IMAGE_CACHE_SIZE_MB`. A write that takes the cache
+/// over the limit triggers an eviction right away instead of waiting for the next tick.
+pub async fn run_cleanup_task(interval: Duration, ttl: Option<Duration>) {
+    let mut ticker = tokio::time::interval(interval);
+    loop {
+        let ttl = tokio::select! {
+            _ = ticker.tick() => ttl,
+            _ = OVER_LIMIT.notified() => None,
+        };
+        let result = tokio::task::spawn_blocking(move || cleanup(&cache_dir(), ttl)).await;
+        match result {
+            Ok(Ok(())) => {}
+            Ok(Err(e)) => warn!("Cache cleanup failed: {}", e),
+            Err(e) => warn!("Cache cleanup task panicked: {}", e),
+        }
+    }
+}
+
+fn record_write(size: u64) {
+    let total = CACHED_BYTES.fetch_add(size, Ordering::Relaxed) + size;
+    if total > cache_size_limit_bytes() {
+        OVER_LIMIT.notify_one();
+    }
+}
+
+fn cleanup(cache_dir: &Path, ttl: Option<Duration>) -> Result<()> {
+    if !cache_dir.exists() {
+        return Ok(());
+    }
+    let Some(_lock) = try_lock_dir(cache_dir)? else {
+        debug!("Cache is being swept by another instance, skipping cleanup");
+        return Ok(());
+    };
+    if let Some(ttl) = ttl {
+        let removed = remove_expired(cache_dir, ttl)?;
+        if removed > 0 {
+            info!(removed = removed, "Removed expired cache entries");
+        }
+    }
+    let size = check_and_evict_if_needed(cache_dir)?;
+    CACHED_BYTES.store(size, Ordering::Relaxed);
+    Ok(())
+}
+
+fn remove_expired(cache_dir: &Path, ttl: Duration) -> Result<usize> {
+    let now = SystemTime::now();
+    let mut removed = 0;
+
+    for entry in fs::read_dir(cache_dir)? {
+        let entry = entry?;
+        let path = entry.path();
+        if !is_cached_image(&path) {
+            continue;
+        }
+
+        let expired = entry
+            .metadata()
+            .and_then(|m| m.modified())
+            .map(|mtime| now.duration_since(mtime).unwrap_or_default() > ttl)
+            .unwrap_or(false);
+        if expired {
+            match fs::remove_file(&path) {
+                Ok(_) => removed += 1,
+                Err(e) => warn!("Failed to remove expired {}: {}", path.display(), e),
+            }
+        }
+    }
+
+    Ok(removed)
+}
+
+/// Evicts down to the target when the cache is over its limit. Returns the size left.
+fn check_and_evict_if_needed(cache_dir: &Path) -> Result<u64> {
+    let max_size_bytes = cache_size_limit_bytes();
 
     let current_size = calculate_cache_size(cache_dir)?;
 
@@ -77,23 +349,24 @@ fn check_and_evict_if_needed(cache_dir: &Path) -> Result<()> {
         debug!(
             "Cache size {}MB exceeds limit {}MB. Evicting to {}MB",
             current_size / 1024 / 1024,
-            max_size_mb,
+            max_size_bytes / 1024 / 1024,
             target_size / 1024 / 1024
         );
-        evict_lru_files(cache_dir, current_size, target_size)?;
+        let freed = evict_lru_files(cache_dir, current_size, target_size)?;
+        return Ok(current_size - freed);
     }
 
-    Ok(())
+    Ok(current_size)
 }
 
 fn calculate_cache_size(cache_dir: &Path) -> Result<u64> {
     let mut total_size = 0u64;
 
-    for entry in fs::read_dir(cache_dir).context("Failed to read cache directory")? {
+    for entry in fs::read_dir(cache_dir)? {
         let entry = entry?;
         let path = entry.path();
 
-        if path.extension().and_then(|s| s.to_str()) == Some("png") {
+        if is_cached_image(&path) {
             if let Ok(metadata) = entry.metadata() {
                 total_size += metadata.len();
             }
@@ -103,14 +376,16 @@ fn calculate_cache_size(cache_dir: &Path) -> Result<u64> {
     Ok(total_size)
 }
 
-fn evict_lru_files(cache_dir: &Path, current_size: u64, target_size: u64) -> Result<()> {
+/// Removes the least recently written files until the cache is at `target_size`.
+/// Returns the number of bytes freed.
+fn evict_lru_files(cache_dir: &Path, current_size: u64, target_size: u64) -> Result<u64> {
     let mut files: Vec<(PathBuf, u64, SystemTime)> = Vec::new();
 
-    for entry in fs::read_dir(cache_dir).context("Failed to read cache directory")? {
+    for entry in fs::read_dir(cache_dir)? {
         let entry = entry?;
         let path = entry.path();
 
-        if path.extension().and_then(|s| s.to_str()) == Some("png") {
+        if is_cached_image(&path) {
             if let Ok(metadata) = entry.metadata() {
                 if let Ok(mtime) = metadata.modified() {
                     files.push((path, metadata.len(), mtime));
@@ -147,7 +422,11 @@ fn evict_lru_files(cache_dir: &Path, current_size: u64, target_size: u64) -> Res
         freed_size / 1024 / 1024
     );
 
-    Ok(())
+    Ok(freed_size)
+}
+
+fn cache_size_limit_bytes() -> u64 {
+    get_cache_size_limit_mb() * 1024 * 1024
 }
 
 fn get_cache_size_limit_mb() -> u64 {
@@ -161,6 +440,108 @@ fn get_cache_size_limit_mb() -> u64 {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_remove_expired_keeps_fresh_entries() {
+        let dir = std::env::temp_dir().join(format!("kamachess_cache_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("board.png"), b"png").unwrap();
+        fs::write(dir.join("notes.txt"), b"txt").unwrap();
+
+        assert_eq!(remove_expired(&dir, Duration::from_secs(3600)).unwrap(), 0);
+        std::thread::sleep(Duration::from_millis(20));
+        assert_eq!(remove_expired(&dir, Duration::from_millis(1)).unwrap(), 1);
+        assert!(!dir.join("board.png").exists());
+        assert!(dir.join("notes.txt").exists());
+
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn test_evict_returns_remaining_size() {
+        let dir = std::env::temp_dir().join(format!("kamachess_evict_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        for name in ["a.png", "b.png", "c.png"] {
+            fs::write(dir.join(name), [0u8; 100]).unwrap();
+            std::thread::sleep(Duration::from_millis(20));
+        }
+
+        assert_eq!(evict_lru_files(&dir, 300, 150).unwrap(), 200);
+        assert!(!dir.join("a.png").exists());
+        assert!(dir.join("c.png").exists());
+        assert_eq!(calculate_cache_size(&dir).unwrap(), 100);
+
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn test_cache_name_validation() {
+        let board = Position::default();
+        let dir = Path::new("cache");
+        let current = get_cache_path(dir, &board, true, "inside_ae2e4-cf7_q85", "jpg");
+        assert!(is_current_cache_name(&current));
+        assert!(is_current_cache_name(&get_cache_path(dir, &board, false, "", "png")));
+
+        let with_info = get_cache_path(dir, &board, false, "info0123456789abcdef", "png");
+        assert!(is_current_cache_name(&with_info));
+        let outdated = get_cache_path(dir, &board, false, "info0123abcd", "png");
+        assert!(!is_current_cache_name(&outdated));
+        assert!(!is_current_cache_name(Path::new("cache/not_a_fen.png")));
+        // Renders from before the current version
+        let name = current.to_str().unwrap();
+        let version = format!("_v{}", RENDER_VERSION);
+        assert!(!is_current_cache_name(Path::new(&name.replace(&version, ""))));
+        assert!(!is_current_cache_name(Path::new(&name.replace(&version, "_v1"))));
+        assert!(!is_current_cache_name(&get_cache_path(dir, &board, false, "", "gif")));
+    }
+
+    #[test]
+    fn test_verify_cache_removes_corrupt_files() {
+        let dir = std::env::temp_dir().join(format!("kamachess_verify_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let board = Position::default();
+        let truncated = get_cache_path(&dir, &board, false, "", "png");
+        fs::write(&truncated, b"\x89PNG\r\n").unwrap();
+
+        assert_eq!(verify_cache(&dir).unwrap(), 1);
+        assert!(!truncated.exists());
+
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn test_sweep_lock_is_exclusive() {
+        let dir = std::env::temp_dir().join(format!("kamachess_lock_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let fresh_temp = dir.join("board.png.1.0.tmp");
+        fs::write(&fresh_temp, b"partial").unwrap();
+
+        let lock = try_lock_dir(&dir).unwrap();
+        assert!(lock.is_some());
+        assert!(try_lock_dir(&dir).unwrap().is_none());
+        drop(lock);
+
+        // An in-progress write is left alone, and so is the lock file
+        assert_eq!(verify_cache(&dir).unwrap(), 0);
+        assert!(fresh_temp.exists());
+        assert!(dir.join(LOCK_FILE).exists());
+
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[tokio::test]
+    async fn test_write_atomically_leaves_no_temp_file() {
+        let dir = std::env::temp_dir().join(format!("kamachess_atomic_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("board.png");
+
+        write_atomically(&path, b"first").await.unwrap();
+        write_atomically(&path, b"second").await.unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"second");
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+
+        let _ = fs::remove_dir_all(dir);
+    }
+
     #[test]
     fn test_get_cache_size_limit_default() {
         std::env::remove_var("IMAGE_CACHE_SIZE_MB");
@@ -0,0 +1,91 @@
+//! Bar charts for chat statistics, drawn in the colours of the board's info bars.
+
+use image::{ImageBuffer, Rgba};
+
+use super::{text, ImageEncoding};
+use crate::error::{KamaError, Result};
+
+const WIDTH: u32 = 640;
+const HEIGHT: u32 = 360;
+const PADDING: u32 = 16;
+const TITLE_SIZE: f32 = 18.0;
+const LABEL_SIZE: f32 = 11.0;
+const BAR_GAP: u32 = 6;
+
+const BACKGROUND: Rgba<u8> = Rgba([49, 46, 43, 255]);
+const BAR_COLOR: Rgba<u8> = Rgba([118, 150, 86, 255]);
+const AXIS_COLOR: Rgba<u8> = Rgba([110, 106, 102, 255]);
+const TEXT_COLOR: Rgba<u8> = Rgba([235, 235, 235, 255]);
+
+/// Renders `bars` as labelled columns under `title`, each with its value on top. Heights
+/// are relative to the largest value.
+pub async fn render_bar_chart(title: &str, bars: &[(String, i64)], encoding: ImageEncoding) -> Result<Vec<u8>> {
+    let title = title.to_string();
+    let bars = bars.to_vec();
+    tokio::task::spawn_blocking(move || encoding.encode(&draw_bar_chart(&title, &bars)))
+        .await
+        .map_err(|e| KamaError::Render(format!("Chart task failed: {}", e)))?
+}
+
+fn draw_bar_chart(title: &str, bars: &[(String, i64)]) -> ImageBuffer<Rgba<u8>, Vec<u8>> {
+    let mut img = ImageBuffer::from_pixel(WIDTH, HEIGHT, BACKGROUND);
+    text::draw_text(&mut img, PADDING as i32, PADDING as i32, title, TITLE_SIZE, TEXT_COLOR);
+    if bars.is_empty() {
+        return img;
+    }
+
+    let label_height = text::cap_height(LABEL_SIZE);
+    // Room for the title above and the value over the tallest bar
+    let top = PADDING * 2 + text::cap_height(TITLE_SIZE) + label_height + 4;
+    let axis_y = HEIGHT - PADDING - label_height - 6;
+    let slot = (WIDTH - PADDING * 2) / bars.len() as u32;
+    let max = bars.iter().map(|(_, value)| *value).max().unwrap_or(0).max(1);
+
+    fill(&mut img, PADDING, axis_y, WIDTH - PADDING * 2, 1, AXIS_COLOR);
+    for (i, (label, value)) in bars.iter().enumerate() {
+        let x = PADDING + slot * i as u32;
+        let height = ((axis_y - top) as i64 * value.max(&0) / max) as u32;
+        fill(&mut img, x + BAR_GAP / 2, axis_y - height, slot.saturating_sub(BAR_GAP), height, BAR_COLOR);
+
+        let centered = |text: &str| (x + slot / 2).saturating_sub(text::text_width(text, LABEL_SIZE) / 2) as i32;
+        let value_text = value.to_string();
+        let value_y = axis_y - height - label_height - 4;
+        text::draw_text(&mut img, centered(&value_text), value_y as i32, &value_text, LABEL_SIZE, TEXT_COLOR);
+        text::draw_text(&mut img, centered(label), (axis_y + 6) as i32, label, LABEL_SIZE, TEXT_COLOR);
+    }
+    img
+}
+
+fn fill(img: &mut ImageBuffer<Rgba<u8>, Vec<u8>>, x: u32, y: u32, width: u32, height: u32, color: Rgba<u8>) {
+    for py in y..(y + height).min(img.height()) {
+        for px in x..(x + width).min(img.width()) {
+            img.put_pixel(px, py, color);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bar_heights() {
+        let bars = vec![("a".to_string(), 0), ("b".to_string(), 5), ("c".to_string(), 10)];
+        let img = draw_bar_chart("Games", &bars);
+        assert_eq!(img.dimensions(), (WIDTH, HEIGHT));
+
+        // Column middles just above the axis: only bars with games are filled
+        let slot = (WIDTH - PADDING * 2) / 3;
+        let axis_y = HEIGHT - PADDING - text::cap_height(LABEL_SIZE) - 6;
+        let column = |i: u32| *img.get_pixel(PADDING + slot * i + slot / 2, axis_y - 2);
+        assert_eq!(column(0), BACKGROUND);
+        assert_eq!(column(1), BAR_COLOR);
+        assert_eq!(column(2), BAR_COLOR);
+    }
+
+    #[test]
+    fn test_empty_chart() {
+        let img = draw_bar_chart("Games", &[]);
+        assert_eq!(img.dimensions(), (WIDTH, HEIGHT));
+    }
+}
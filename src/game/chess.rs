@@ -1,10 +1,21 @@
 use crate::models::DbUser;
-use anyhow::{anyhow, Result};
-use chess::{Board, ChessMove, Color, File, MoveGen, Piece, Rank, Square};
+use crate::error::{KamaError, Result};
+use super::position::{Color, Move, Position, Role, Square};
 use std::str::FromStr;
 
-pub fn parse_move(board: &Board, input: &str) -> Result<ChessMove> {
-    let trimmed = input.trim();
+/// A `KamaError::ChessRule` with a formatted, player-facing message.
+macro_rules! rule {
+    ($($arg:tt)*) => {
+        KamaError::ChessRule(format!($($arg)*))
+    };
+}
+
+pub fn parse_move(board: &Position, input: &str) -> Result<Move> {
+    let trimmed = strip_en_passant_suffix(input.trim());
+
+    if let Some(mv) = board.parse_san(trimmed) {
+        return Ok(mv);
+    }
 
     if let Ok(mv) = parse_san(board, trimmed) {
         return Ok(mv);
@@ -13,51 +24,102 @@ pub fn parse_move(board: &Board, input: &str) -> Result<ChessMove> {
     let mv = trimmed.to_lowercase();
 
     if mv.len() == 2 {
-        let dest = Square::from_str(&mv).map_err(|e| anyhow!("Invalid square: {}", e))?;
+        let dest = Square::from_str(&mv).map_err(|e| rule!("Invalid square: {}", e))?;
 
-        let mut matches: Vec<ChessMove> = MoveGen::new_legal(board)
-            .filter(|m| m.get_dest() == dest && board.piece_on(m.get_source()) == Some(Piece::Pawn))
+        let mut matches: Vec<Move> = board
+            .legal_moves()
+            .into_iter()
+            .filter(|m| m.to() == dest && m.role() == Role::Pawn)
             .collect();
 
         if matches.len() == 1 {
             return Ok(matches.remove(0));
         }
 
-        return Err(anyhow!(
+        return Err(rule!(
             "Illegal or ambiguous pawn move to {}. Use SAN like e4 or coordinate like e2e4.",
             mv
         ));
     }
 
     if mv.len() == 4 || mv.len() == 5 {
-        let from =
-            Square::from_str(&mv[0..2]).map_err(|e| anyhow!("Invalid source square: {}", e))?;
-        let to = Square::from_str(&mv[2..4])
-            .map_err(|e| anyhow!("Invalid destination square: {}", e))?;
-        let promo = if mv.len() == 5 {
-            Some(parse_promotion(&mv[4..5])?)
-        } else {
-            None
-        };
+        Square::from_str(&mv[0..2]).map_err(|e| rule!("Invalid source square: {}", e))?;
+        Square::from_str(&mv[2..4]).map_err(|e| rule!("Invalid destination square: {}", e))?;
+        if mv.len() == 5 {
+            parse_promotion(&mv[4..5])?;
+        }
 
-        let candidate = ChessMove::new(from, to, promo);
-        if MoveGen::new_legal(board).any(|m| m == candidate) {
+        if let Some(candidate) = board.parse_uci(&mv) {
             return Ok(candidate);
         }
     }
 
-    Err(anyhow!("Illegal move. Try e4, e2e4, or Nf6."))
+    Err(rule!("Illegal move. Try e4, e2e4, or Nf6."))
 }
 
-fn parse_san(board: &Board, input: &str) -> Result<ChessMove> {
+/// Like `parse_move`, but SAN input must be exactly the standard notation of the move:
+/// correct piece letter, `x` on captures and only the disambiguation that is needed.
+/// Check marks are optional. Coordinate input (e2e4) is unambiguous and accepted as is.
+pub fn parse_move_strict(board: &Position, input: &str) -> Result<Move> {
+    let trimmed = strip_en_passant_suffix(input.trim());
+    let mv = parse_move(board, trimmed)?;
+
+    if is_coordinate_notation(trimmed) {
+        return Ok(mv);
+    }
+
+    let expected = move_to_san(board, mv);
+    let without_check = |s: &str| s.trim_end_matches(['+', '#']).to_string();
+    if without_check(trimmed) != without_check(&expected) {
+        return Err(rule!(
+            "Non-standard notation: {}. Strict notation is on, write {}.",
+            trimmed,
+            expected
+        ));
+    }
+
+    Ok(mv)
+}
+
+/// Drops an optional "e.p." marker (also "ep", with or without a space) from an
+/// en passant capture like "exd6 e.p.".
+pub fn strip_en_passant_suffix(input: &str) -> &str {
+    for suffix in ["e.p.", "e.p", "ep"] {
+        let Some(split) = input.len().checked_sub(suffix.len()) else {
+            continue;
+        };
+        if !input.is_char_boundary(split) || !input[split..].eq_ignore_ascii_case(suffix) {
+            continue;
+        }
+        let rest = input[..split].trim_end();
+        // Only after a destination square, so "Rep" style input isn't mangled
+        if rest.ends_with(|c: char| c.is_ascii_digit()) {
+            return rest;
+        }
+    }
+    input
+}
+
+fn is_coordinate_notation(input: &str) -> bool {
+    let bytes = input.as_bytes();
+    let square = |file: u8, rank: u8| (b'a'..=b'h').contains(&file) && (b'1'..=b'8').contains(&rank);
+    match bytes {
+        [f1, r1, f2, r2] => square(*f1, *r1) && square(*f2, *r2),
+        [f1, r1, f2, r2, promo] => {
+            square(*f1, *r1) && square(*f2, *r2) && matches!(promo, b'q' | b'r' | b'b' | b'n')
+        }
+        _ => false,
+    }
+}
+
+fn parse_san(board: &Position, input: &str) -> Result<Move> {
     let s = input.trim();
-    let side = board.side_to_move();
 
     if s == "O-O" || s == "o-o" || s == "0-0" || s == "00" || s.eq_ignore_ascii_case("oo") {
-        return parse_castling(board, side, false);
+        return parse_castling(board, false);
     }
     if s == "O-O-O" || s == "o-o-o" || s == "0-0-0" || s == "000" || s.eq_ignore_ascii_case("ooo") {
-        return parse_castling(board, side, true);
+        return parse_castling(board, true);
     }
 
     let s = s.trim_end_matches('+').trim_end_matches('#');
@@ -85,19 +147,21 @@ fn parse_san(board: &Board, input: &str) -> Result<ChessMove> {
     let move_part = move_part.replace(['x', 'X'], "");
 
     if move_part.len() < 2 {
-        return Err(anyhow!("Invalid SAN move: too short"));
+        return Err(rule!("Invalid SAN move: too short"));
     }
 
     let dest_str = &move_part[move_part.len() - 2..];
     let dest = Square::from_str(&dest_str.to_lowercase())
-        .map_err(|_| anyhow!("Invalid destination square in SAN: {}", dest_str))?;
+        .map_err(|_| rule!("Invalid destination square in SAN: {}", dest_str))?;
 
-    let candidates: Vec<ChessMove> = MoveGen::new_legal(board)
-        .filter(|m| m.get_dest() == dest && promo.is_none_or(|p| m.get_promotion() == Some(p)))
+    let candidates: Vec<Move> = board
+        .legal_moves()
+        .into_iter()
+        .filter(|m| m.to() == dest && promo.is_none_or(|p| m.promotion() == Some(p)))
         .collect();
 
     if candidates.is_empty() {
-        return Err(anyhow!("No legal moves to {}", dest_str));
+        return Err(rule!("No legal moves to {}", dest_str));
     }
 
     let piece_type = if move_part.len() > 2 {
@@ -106,15 +170,15 @@ fn parse_san(board: &Board, input: &str) -> Result<ChessMove> {
         None
     };
 
-    let matches: Vec<ChessMove> = candidates
+    let matches: Vec<Move> = candidates
         .iter()
         .filter(|&m| {
-            let piece = board.piece_on(m.get_source()).unwrap_or(Piece::Pawn);
-            let expected_piece = piece_type.unwrap_or(Piece::Pawn);
-
-            if piece != expected_piece {
+            if m.role() != piece_type.unwrap_or(Role::Pawn) {
                 return false;
             }
+            let Some(source) = m.from() else {
+                return false;
+            };
 
             if move_part.len() > 3 {
                 let disambig = &move_part[1..move_part.len() - 2];
@@ -122,17 +186,17 @@ fn parse_san(board: &Board, input: &str) -> Result<ChessMove> {
                     let ch = disambig.chars().next().unwrap();
                     if ch.is_ascii_lowercase() {
                         let file_idx = (ch as u8 - b'a') as usize;
-                        return m.get_source().get_file().to_index() == file_idx;
+                        return source.file().to_usize() == file_idx;
                     }
                     if ch.is_ascii_digit() {
                         if let Ok(rank_num) = disambig.parse::<u8>() {
                             let rank_idx = (rank_num - 1) as usize;
-                            return m.get_source().get_rank().to_index() == rank_idx;
+                            return source.rank().to_usize() == rank_idx;
                         }
                     }
                 } else if disambig.len() == 2 {
                     if let Ok(sq) = Square::from_str(disambig) {
-                        return m.get_source() == sq;
+                        return source == sq;
                     }
                 }
                 return false;
@@ -149,257 +213,228 @@ fn parse_san(board: &Board, input: &str) -> Result<ChessMove> {
         let piece_info = piece_type
             .map(|p| format!("{:?}", p))
             .unwrap_or_else(|| "pawn".to_string());
-        Err(anyhow!(
+        Err(rule!(
             "No legal {:?} move to {} for SAN: {}. Try a different move or use coordinate notation like e2e4.",
             piece_info,
             dest_str,
             input
         ))
     } else {
-        Err(anyhow!(
+        Err(rule!(
             "Ambiguous SAN move: {}. Use disambiguation like Nbd7 or R1e2.",
             input
         ))
     }
 }
 
-fn parse_castling(board: &Board, side: Color, queenside: bool) -> Result<ChessMove> {
-    let rank = if side == Color::White {
-        Rank::First
-    } else {
-        Rank::Eighth
-    };
-    let king_from = Square::make_square(rank, File::E);
-    let king_to = Square::make_square(rank, if queenside { File::C } else { File::G });
-
-    let candidate = ChessMove::new(king_from, king_to, None);
-
-    if MoveGen::new_legal(board).any(|m| m == candidate) {
-        Ok(candidate)
-    } else {
-        Err(anyhow!("Castling not legal"))
-    }
+fn parse_castling(board: &Position, queenside: bool) -> Result<Move> {
+    board
+        .castling_move(queenside)
+        .ok_or_else(|| rule!("Castling not legal"))
 }
 
-fn parse_piece_char(c: char) -> Option<Piece> {
+fn parse_piece_char(c: char) -> Option<Role> {
     match c.to_ascii_uppercase() {
-        'K' => Some(Piece::King),
-        'Q' => Some(Piece::Queen),
-        'R' => Some(Piece::Rook),
-        'B' => Some(Piece::Bishop),
-        'N' => Some(Piece::Knight),
+        'K' => Some(Role::King),
+        'Q' => Some(Role::Queen),
+        'R' => Some(Role::Rook),
+        'B' => Some(Role::Bishop),
+        'N' => Some(Role::Knight),
         _ => None,
     }
 }
 
-fn parse_promotion_char(s: &str) -> Result<Piece> {
+fn parse_promotion_char(s: &str) -> Result<Role> {
     match s.to_ascii_uppercase().as_str() {
-        "Q" => Ok(Piece::Queen),
-        "R" => Ok(Piece::Rook),
-        "B" => Ok(Piece::Bishop),
-        "N" => Ok(Piece::Knight),
-        _ => Err(anyhow!("Invalid promotion piece")),
+        "Q" => Ok(Role::Queen),
+        "R" => Ok(Role::Rook),
+        "B" => Ok(Role::Bishop),
+        "N" => Ok(Role::Knight),
+        _ => Err(rule!("Invalid promotion piece")),
     }
 }
 
-fn parse_promotion(token: &str) -> Result<Piece> {
+fn parse_promotion(token: &str) -> Result<Role> {
     match token {
-        "q" => Ok(Piece::Queen),
-        "r" => Ok(Piece::Rook),
-        "b" => Ok(Piece::Bishop),
-        "n" => Ok(Piece::Knight),
-        _ => Err(anyhow!("Unknown promotion piece. Use q, r, b, or n.")),
+        "q" => Ok(Role::Queen),
+        "r" => Ok(Role::Rook),
+        "b" => Ok(Role::Bishop),
+        "n" => Ok(Role::Knight),
+        _ => Err(rule!("Unknown promotion piece. Use q, r, b, or n.")),
     }
 }
 
-pub fn color_to_turn(color: Color) -> &'static str {
-    if color == Color::White {
-        "w"
-    } else {
-        "b"
-    }
+pub fn move_to_san(board: &Position, mv: Move) -> String {
+    board.san(mv)
 }
 
-pub fn uci_string(mv: ChessMove) -> String {
-    let mut uci = format!("{}{}", mv.get_source(), mv.get_dest());
-    if let Some(promo) = mv.get_promotion() {
-        let suffix = match promo {
-            Piece::Queen => "q",
-            Piece::Rook => "r",
-            Piece::Bishop => "b",
-            Piece::Knight => "n",
-            Piece::Pawn | Piece::King => "",
-        };
-        uci.push_str(suffix);
+/// SAN for showing to players, with en passant captures marked "e.p.".
+/// Stored SAN stays plain so PGN exports remain standard.
+pub fn move_to_display_san(board: &Position, mv: Move) -> String {
+    let san = move_to_san(board, mv);
+    if mv.is_en_passant() {
+        format!("{} e.p.", san)
+    } else {
+        san
     }
-    uci
 }
 
-pub fn move_to_san(board: &Board, mv: ChessMove) -> String {
-    let piece = board.piece_on(mv.get_source()).unwrap_or(Piece::Pawn);
-    let dest = mv.get_dest();
-    let is_capture = board.piece_on(dest).is_some();
-
-    if piece == Piece::King {
-        let source_file = mv.get_source().get_file();
-        let dest_file = dest.get_file();
-        if source_file == File::E {
-            if dest_file == File::G {
-                return "O-O".to_string();
-            } else if dest_file == File::C {
-                return "O-O-O".to_string();
-            }
-        }
-    }
-
-    let mut san = String::new();
-
-    if piece != Piece::Pawn {
-        san.push(match piece {
-            Piece::King => 'K',
-            Piece::Queen => 'Q',
-            Piece::Rook => 'R',
-            Piece::Bishop => 'B',
-            Piece::Knight => 'N',
-            Piece::Pawn => unreachable!(),
-        });
-
-        let legal_moves: Vec<ChessMove> = MoveGen::new_legal(board)
-            .filter(|m| {
-                m.get_dest() == dest && board.piece_on(m.get_source()) == Some(piece) && *m != mv
-            })
-            .collect();
-
-        if !legal_moves.is_empty() {
-            let source_file = mv.get_source().get_file();
-            let source_rank = mv.get_source().get_rank();
-
-            let same_file = legal_moves
-                .iter()
-                .any(|m| m.get_source().get_file() == source_file);
-            let same_rank = legal_moves
-                .iter()
-                .any(|m| m.get_source().get_rank() == source_rank);
-
-            if !same_file {
-                san.push((b'a' + source_file.to_index() as u8) as char);
-            } else if !same_rank {
-                san.push_str(&(source_rank.to_index() + 1).to_string());
-            } else {
-                san.push((b'a' + source_file.to_index() as u8) as char);
-                san.push_str(&(source_rank.to_index() + 1).to_string());
-            }
-        }
-    } else if is_capture {
-        san.push((b'a' + mv.get_source().get_file().to_index() as u8) as char);
-    }
-
-    if is_capture {
-        san.push('x');
-    }
-
-    san.push_str(&dest.to_string());
-
-    if let Some(promo) = mv.get_promotion() {
-        san.push('=');
-        san.push(match promo {
-            Piece::Queen => 'Q',
-            Piece::Rook => 'R',
-            Piece::Bishop => 'B',
-            Piece::Knight => 'N',
-            Piece::Pawn | Piece::King => unreachable!(),
-        });
-    }
-
-    let next_board = board.make_move_new(mv);
-    if *next_board.checkers() != chess::EMPTY {
-        if next_board.status() == chess::BoardStatus::Checkmate {
-            san.push('#');
-        } else {
-            san.push('+');
+/// Legal moves in display SAN, grouped by the square they start from in a1..h8 order.
+/// Drops (crazyhouse) come last under `None`.
+pub fn legal_moves_by_square(board: &Position) -> Vec<(Option<Square>, Vec<String>)> {
+    let mut moves = board.legal_moves();
+    moves.sort_by_key(|mv| mv.from().map_or(u32::MAX, u32::from));
+
+    let mut groups: Vec<(Option<Square>, Vec<String>)> = Vec::new();
+    for mv in moves {
+        let san = move_to_display_san(board, mv);
+        match groups.last_mut() {
+            Some((from, list)) if *from == mv.from() => list.push(san),
+            _ => groups.push((mv.from(), vec![san])),
         }
     }
+    groups
+}
 
-    san
+/// A board caption cut to Telegram's limit.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Caption {
+    pub text: String,
+    /// Trailing lines that did not fit, to be sent as a follow-up message.
+    pub overflow: Option<String>,
 }
 
+/// Lines are ordered by importance: players and side to move first, so anything pushed
+/// past the caption limit is the least essential. The chat's `champion` gets a crown.
 pub fn build_caption(
     header: &str,
-    board: &Board,
+    board: &Position,
     white: &DbUser,
     black: &DbUser,
     to_move: Color,
     result_line: Option<String>,
-) -> String {
-    let white_name = white.mention_html();
-    let black_name = black.mention_html();
+    champion: Option<i64>,
+) -> Caption {
     let side = if to_move == Color::White {
         white.mention_html()
     } else {
         black.mention_html()
     };
 
-    let mut caption = format!(
-        "{}.
-White: {}
-Black: {}
-To move: {}",
-        crate::utils::escape_html(header),
-        white_name,
-        black_name,
-        side
-    );
-
-    if *board.checkers() != chess::EMPTY {
-        caption.push_str(
-            "Check!",
-        );
-    }
+    let crown = |player: &DbUser| {
+        if champion == Some(player.id) {
+            format!(" {}", crate::utils::CROWN)
+        } else {
+            String::new()
+        }
+    };
 
-    if let Some(advantage) = material_advantage(board, white, black) {
-        caption.push_str(&format!(
-            "
-{}",
-            advantage
-        ));
+    let mut lines = vec![
+        format!("{}.", crate::utils::escape_html(header)),
+        format!("White: {}{}", white.mention_html(), crown(white)),
+        format!("Black: {}{}", black.mention_html(), crown(black)),
+        format!("To move: {}", side),
+        format!("Move {} · {}", board.fullmoves(), game_phase(board)),
+    ];
+    if board.is_check() {
+        lines.push("Check!".to_string());
     }
-
-    if let Some(result) = result_line {
-        caption.push_str(&format!(
-            "
-{}",
-            result
-        ));
+    lines.extend(material_advantage(board, white, black));
+    lines.extend(result_line);
+
+    // Once a line doesn't fit, it and everything after it overflow, keeping the order
+    let limit = crate::utils::MAX_CAPTION_LEN;
+    let mut text = String::new();
+    let mut overflow = Vec::new();
+    for line in lines {
+        let joined = if text.is_empty() {
+            line.clone()
+        } else {
+            format!("{}\n{}", text, line)
+        };
+        if overflow.is_empty() && crate::utils::visible_len(&joined) <= limit {
+            text = joined;
+        } else if text.is_empty() {
+            text = crate::utils::split_message(&line, limit).swap_remove(0);
+        } else {
+            overflow.push(line);
+        }
     }
 
-    caption
+    Caption {
+        text,
+        overflow: (!overflow.is_empty()).then(|| overflow.join("\n")),
+    }
 }
 
-pub fn material_advantage(board: &Board, white: &DbUser, black: &DbUser) -> Option<String> {
+/// Piece values for the material line, most valuable first, with the white and black icons.
+const PIECE_VALUES: [(Role, i32, char, char); 5] = [
+    (Role::Queen, 9, '♕', '♛'),
+    (Role::Rook, 5, '♖', '♜'),
+    (Role::Bishop, 3, '♗', '♝'),
+    (Role::Knight, 3, '♘', '♞'),
+    (Role::Pawn, 1, '♙', '♟'),
+];
+
+/// The side ahead in material with the pieces it has more of and the points they are
+/// worth in total, e.g. `@ann ♘♙ +4`.
+pub fn material_advantage(board: &Position, white: &DbUser, black: &DbUser) -> Option<String> {
     let score = material_score(board);
     if score == 0 {
         return None;
     }
 
-    if score > 0 {
-        Some(format!("{} +{}", white.mention_html(), score))
+    let (leader, color) = if score > 0 {
+        (white, Color::White)
     } else {
-        Some(format!("{} +{}", black.mention_html(), score.abs()))
-    }
+        (black, Color::Black)
+    };
+    Some(format!("{} {} +{}", leader.mention_html(), surplus_pieces(board, color), score.abs()))
+}
+
+/// Icons of the pieces `color` has more of than the opponent, most valuable first.
+fn surplus_pieces(board: &Position, color: Color) -> String {
+    PIECE_VALUES
+        .iter()
+        .flat_map(|&(role, _, white_icon, black_icon)| {
+            let extra = board.count(color, role).saturating_sub(board.count(!color, role));
+            let icon = if color == Color::White { white_icon } else { black_icon };
+            std::iter::repeat_n(icon, extra as usize)
+        })
+        .collect()
 }
 
-fn material_score(board: &Board) -> i32 {
-    let mut score = 0;
-    for (piece, value) in [
-        (Piece::Pawn, 1),
-        (Piece::Knight, 3),
-        (Piece::Bishop, 3),
-        (Piece::Rook, 5),
-        (Piece::Queen, 9),
-    ] {
-        let white = (board.pieces(piece) & board.color_combined(Color::White)).popcnt();
-        let black = (board.pieces(piece) & board.color_combined(Color::Black)).popcnt();
-        score += (white as i32 - black as i32) * value;
+/// Pieces other than pawns, in points for both sides together, up to which a position
+/// counts as an endgame: a rook and two minor pieces each, or less.
+const ENDGAME_MATERIAL: i32 = 22;
+
+/// Moves that count as the opening, as long as the position isn't an endgame already.
+const OPENING_MOVES: u32 = 10;
+
+/// A rough stage of the game for the caption, from the move number and what's left on
+/// the board.
+fn game_phase(board: &Position) -> &'static str {
+    let pieces: i32 = PIECE_VALUES
+        .iter()
+        .filter(|(role, ..)| *role != Role::Pawn)
+        .map(|&(role, value, _, _)| (board.count(Color::White, role) + board.count(Color::Black, role)) as i32 * value)
+        .sum();
+    if pieces <= ENDGAME_MATERIAL {
+        "Endgame"
+    } else if board.fullmoves() <= OPENING_MOVES {
+        "Opening"
+    } else {
+        "Middlegame"
     }
-    score
+}
+
+fn material_score(board: &Position) -> i32 {
+    PIECE_VALUES
+        .iter()
+        .map(|&(role, value, _, _)| {
+            let white = board.count(Color::White, role);
+            let black = board.count(Color::Black, role);
+            (white as i32 - black as i32) * value
+        })
+        .sum()
 }
@@ -0,0 +1,272 @@
+//! Output encoding for rendered boards. PNG stays the default; JPEG and WebP trade
+//! sharpness or encoder speed for smaller uploads on slow connections.
+
+use crate::error::Result;
+use image::codecs::jpeg::JpegEncoder;
+use image::codecs::png::{CompressionType, FilterType, PngEncoder};
+use image::codecs::webp::WebPEncoder;
+use image::{DynamicImage, ImageBuffer, Rgba};
+use std::collections::{HashMap, HashSet};
+
+const DEFAULT_JPEG_QUALITY: u8 = 85;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ImageFormat {
+    #[default]
+    Png,
+    Jpeg,
+    /// Lossless WebP; the image crate has no lossy encoder.
+    Webp,
+}
+
+impl ImageFormat {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.to_ascii_lowercase().as_str() {
+            "png" => Some(ImageFormat::Png),
+            "jpeg" | "jpg" => Some(ImageFormat::Jpeg),
+            "webp" => Some(ImageFormat::Webp),
+            _ => None,
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ImageFormat::Png => "png",
+            ImageFormat::Jpeg => "jpg",
+            ImageFormat::Webp => "webp",
+        }
+    }
+
+    pub fn mime_type(&self) -> &'static str {
+        match self {
+            ImageFormat::Png => "image/png",
+            ImageFormat::Jpeg => "image/jpeg",
+            ImageFormat::Webp => "image/webp",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PngCompression {
+    Fast,
+    #[default]
+    Default,
+    Best,
+}
+
+impl PngCompression {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.to_ascii_lowercase().as_str() {
+            "fast" => Some(PngCompression::Fast),
+            "default" => Some(PngCompression::Default),
+            "best" => Some(PngCompression::Best),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ImageEncoding {
+    pub format: ImageFormat,
+    pub png_compression: PngCompression,
+    /// 1-100, only used for JPEG.
+    pub jpeg_quality: u8,
+}
+
+impl Default for ImageEncoding {
+    fn default() -> Self {
+        Self {
+            format: ImageFormat::Png,
+            png_compression: PngCompression::Default,
+            jpeg_quality: DEFAULT_JPEG_QUALITY,
+        }
+    }
+}
+
+impl ImageEncoding {
+    /// Reads `BOARD_IMAGE_FORMAT`, `PNG_COMPRESSION` and `JPEG_QUALITY`,
+    /// keeping the default for anything missing or invalid.
+    pub fn from_env() -> Self {
+        let defaults = Self::default();
+        let var = |name: &str| std::env::var(name).ok();
+        Self {
+            format: var("BOARD_IMAGE_FORMAT")
+                .and_then(|v| ImageFormat::parse(&v))
+                .unwrap_or(defaults.format),
+            png_compression: var("PNG_COMPRESSION")
+                .and_then(|v| PngCompression::parse(&v))
+                .unwrap_or(defaults.png_compression),
+            jpeg_quality: var("JPEG_QUALITY")
+                .and_then(|v| v.parse::<u8>().ok())
+                .filter(|q| (1..=100).contains(q))
+                .unwrap_or(defaults.jpeg_quality),
+        }
+    }
+
+    /// Suffix for cache file names. JPEG quality changes the pixels, so it is part of the
+    /// key; PNG compression and WebP are lossless and share the plain entry.
+    pub(super) fn cache_key(&self) -> String {
+        match self.format {
+            ImageFormat::Jpeg => format!("q{}", self.jpeg_quality),
+            ImageFormat::Png | ImageFormat::Webp => String::new(),
+        }
+    }
+
+    pub(super) fn encode(&self, img: &ImageBuffer<Rgba<u8>, Vec<u8>>) -> Result<Vec<u8>> {
+        let mut bytes = Vec::new();
+        match self.format {
+            ImageFormat::Png => {
+                if let Some(indexed) = Indexed::from_image(img) {
+                    self.encode_indexed_png(&mut bytes, img.width(), img.height(), &indexed)?;
+                    return Ok(bytes);
+                }
+                let compression = match self.png_compression {
+                    PngCompression::Fast => CompressionType::Fast,
+                    PngCompression::Default => CompressionType::Default,
+                    PngCompression::Best => CompressionType::Best,
+                };
+                let encoder =
+                    PngEncoder::new_with_quality(&mut bytes, compression, FilterType::Adaptive);
+                img.write_with_encoder(encoder)?;
+            }
+            ImageFormat::Jpeg => {
+                // JPEG has no alpha channel
+                let rgb = DynamicImage::ImageRgba8(img.clone()).to_rgb8();
+                let encoder = JpegEncoder::new_with_quality(&mut bytes, self.jpeg_quality);
+                rgb.write_with_encoder(encoder)?;
+            }
+            ImageFormat::Webp => {
+                let encoder = WebPEncoder::new_lossless(&mut bytes);
+                img.write_with_encoder(encoder)?;
+            }
+        }
+        Ok(bytes)
+    }
+
+    fn encode_indexed_png(&self, out: &mut Vec<u8>, width: u32, height: u32, indexed: &Indexed) -> Result<()> {
+        let mut encoder = png::Encoder::new(out, width, height);
+        encoder.set_color(png::ColorType::Indexed);
+        encoder.set_depth(indexed.depth);
+        encoder.set_palette(indexed.palette.as_slice());
+        if !indexed.alpha.is_empty() {
+            encoder.set_trns(indexed.alpha.as_slice());
+        }
+        encoder.set_compression(match self.png_compression {
+            PngCompression::Fast => png::Compression::Fast,
+            PngCompression::Default => png::Compression::Balanced,
+            PngCompression::Best => png::Compression::High,
+        });
+        encoder.set_filter(png::Filter::Adaptive);
+        let mut writer = encoder.write_header()?;
+        writer.write_image_data(&indexed.data)?;
+        writer.finish()?;
+        Ok(())
+    }
+}
+
+/// An image reduced to a palette. Boards are flat colors apart from antialiased text and
+/// blended overlays, so most fit in 256 entries and come out about a third smaller than
+/// as RGBA.
+struct Indexed {
+    /// RGB triples.
+    palette: Vec<u8>,
+    /// Alpha per palette entry; translucent entries come first so it can stop at the last
+    /// one. Empty when the image is opaque.
+    alpha: Vec<u8>,
+    depth: png::BitDepth,
+    /// Indices packed `depth` bits per pixel, each row starting on a byte.
+    data: Vec<u8>,
+}
+
+impl Indexed {
+    /// `None` when the image has more than 256 colors.
+    fn from_image(img: &ImageBuffer<Rgba<u8>, Vec<u8>>) -> Option<Self> {
+        let mut colors: Vec<[u8; 4]> = Vec::new();
+        let mut seen = HashSet::new();
+        for pixel in img.pixels() {
+            if seen.insert(pixel.0) {
+                if colors.len() == 256 {
+                    return None;
+                }
+                colors.push(pixel.0);
+            }
+        }
+        colors.sort_by_key(|color| color[3] == 255);
+
+        let index: HashMap<[u8; 4], u8> = colors
+            .iter()
+            .enumerate()
+            .map(|(i, color)| (*color, i as u8))
+            .collect();
+        let translucent = colors.iter().take_while(|color| color[3] != 255).count();
+
+        let bits = match colors.len() {
+            0..=2 => 1,
+            3..=4 => 2,
+            5..=16 => 4,
+            _ => 8,
+        };
+        let per_byte = 8 / bits;
+        let row_bytes = (img.width() as usize).div_ceil(per_byte);
+        let mut data = vec![0u8; row_bytes * img.height() as usize];
+        for (y, row) in img.rows().enumerate() {
+            for (x, pixel) in row.enumerate() {
+                let shift = 8 - bits * (x % per_byte + 1);
+                data[y * row_bytes + x / per_byte] |= index[&pixel.0] << shift;
+            }
+        }
+
+        Some(Self {
+            palette: colors.iter().flat_map(|color| [color[0], color[1], color[2]]).collect(),
+            alpha: colors[..translucent].iter().map(|color| color[3]).collect(),
+            depth: match bits {
+                1 => png::BitDepth::One,
+                2 => png::BitDepth::Two,
+                4 => png::BitDepth::Four,
+                _ => png::BitDepth::Eight,
+            },
+            data,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_image_format_parse() {
+        assert_eq!(ImageFormat::parse("PNG"), Some(ImageFormat::Png));
+        assert_eq!(ImageFormat::parse("jpg"), Some(ImageFormat::Jpeg));
+        assert_eq!(ImageFormat::parse("webp"), Some(ImageFormat::Webp));
+        assert_eq!(ImageFormat::parse("gif"), None);
+    }
+
+    #[test]
+    fn test_encode_formats() {
+        let img = ImageBuffer::from_pixel(16, 16, Rgba([10, 20, 30, 255]));
+        for format in [ImageFormat::Png, ImageFormat::Jpeg, ImageFormat::Webp] {
+            let encoding = ImageEncoding {
+                format,
+                ..ImageEncoding::default()
+            };
+            let bytes = encoding.encode(&img).unwrap();
+            let guessed = image::guess_format(&bytes).unwrap();
+            assert_eq!(guessed.extensions_str()[0], format.extension());
+        }
+    }
+
+    #[test]
+    fn test_indexed_png_round_trip() {
+        let mut img = ImageBuffer::from_pixel(13, 5, Rgba([240, 217, 181, 255]));
+        img.put_pixel(0, 0, Rgba([60, 60, 60, 200]));
+        img.put_pixel(12, 4, Rgba([40, 40, 40, 255]));
+        let bytes = ImageEncoding::default().encode(&img).unwrap();
+        assert_eq!(image::load_from_memory(&bytes).unwrap().to_rgba8(), img);
+
+        let many_colors = ImageBuffer::from_fn(32, 32, |x, y| Rgba([x as u8, y as u8, 0, 255]));
+        assert!(Indexed::from_image(&many_colors).is_none());
+        let bytes = ImageEncoding::default().encode(&many_colors).unwrap();
+        assert_eq!(image::load_from_memory(&bytes).unwrap().to_rgba8(), many_colors);
+    }
+}
@@ -1,78 +1,12 @@
-//! Bitmap glyph patterns for board rendering
-//!
-//! Contains coordinate labels (letters a-h, numbers 1-8) and chess piece patterns.
+//! Bitmap patterns for the chess pieces. Text is drawn from a TrueType font, see `text.rs`.
 
-use chess::Piece;
-
-/// 5x9 bitmap patterns for file labels (a-h)
-/// 9 rows with top/bottom padding for vertical centering
-pub fn glyph_for_file(letter: char) -> [u8; 9] {
-    match letter.to_ascii_uppercase() {
-        'A' => [
-            0b00000, 0b01110, 0b10001, 0b10001, 0b11111, 0b10001, 0b10001, 0b10001, 0b00000,
-        ],
-        'B' => [
-            0b00000, 0b11110, 0b10001, 0b10001, 0b11110, 0b10001, 0b10001, 0b11110, 0b00000,
-        ],
-        'C' => [
-            0b00000, 0b01110, 0b10001, 0b10000, 0b10000, 0b10000, 0b10001, 0b01110, 0b00000,
-        ],
-        'D' => [
-            0b00000, 0b11110, 0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b11110, 0b00000,
-        ],
-        'E' => [
-            0b00000, 0b11111, 0b10000, 0b10000, 0b11110, 0b10000, 0b10000, 0b11111, 0b00000,
-        ],
-        'F' => [
-            0b00000, 0b11111, 0b10000, 0b10000, 0b11110, 0b10000, 0b10000, 0b10000, 0b00000,
-        ],
-        'G' => [
-            0b00000, 0b01110, 0b10001, 0b10000, 0b10111, 0b10001, 0b10001, 0b01110, 0b00000,
-        ],
-        'H' => [
-            0b00000, 0b10001, 0b10001, 0b10001, 0b11111, 0b10001, 0b10001, 0b10001, 0b00000,
-        ],
-        _ => [0; 9],
-    }
-}
-
-/// 7x7 bitmap patterns for rank labels (1-8)
-/// 7 bits wide with 1 bit left padding
-pub fn glyph_for_rank(rank: u8) -> [u8; 7] {
-    match rank {
-        1 => [
-            0b0001000, 0b0011000, 0b0001000, 0b0001000, 0b0001000, 0b0001000, 0b0011100,
-        ],
-        2 => [
-            0b0011100, 0b0100010, 0b0000010, 0b0000100, 0b0001000, 0b0010000, 0b0111110,
-        ],
-        3 => [
-            0b0111100, 0b0000010, 0b0000010, 0b0011100, 0b0000010, 0b0000010, 0b0111100,
-        ],
-        4 => [
-            0b0000100, 0b0001100, 0b0010100, 0b0100100, 0b0111110, 0b0000100, 0b0000100,
-        ],
-        5 => [
-            0b0111110, 0b0100000, 0b0100000, 0b0111100, 0b0000010, 0b0000010, 0b0111100,
-        ],
-        6 => [
-            0b0011100, 0b0100000, 0b0100000, 0b0111100, 0b0100010, 0b0100010, 0b0011100,
-        ],
-        7 => [
-            0b0111110, 0b0000010, 0b0000100, 0b0001000, 0b0010000, 0b0010000, 0b0010000,
-        ],
-        8 => [
-            0b0011100, 0b0100010, 0b0100010, 0b0011100, 0b0100010, 0b0100010, 0b0011100,
-        ],
-        _ => [0; 7],
-    }
-}
+use super::position::Role;
 
 /// 16x16 bitmap patterns for chess pieces
-pub fn piece_pattern(piece: Piece) -> [u16; 16] {
+pub fn piece_pattern(piece: Role) -> [u16; 16] {
     match piece {
         // King - crown shape with cross on top
-        Piece::King => [
+        Role::King => [
             0b0000000000000000,
             0b0000001111000000,
             0b0000001111000000,
@@ -91,7 +25,7 @@ pub fn piece_pattern(piece: Piece) -> [u16; 16] {
             0b0000000000000000,
         ],
         // Queen - crown with ball on top
-        Piece::Queen => [
+        Role::Queen => [
             0b0000000000000000,
             0b0000001111000000,
             0b0000001111000000,
@@ -110,7 +44,7 @@ pub fn piece_pattern(piece: Piece) -> [u16; 16] {
             0b0000000000000000,
         ],
         // Rook - castle tower
-        Piece::Rook => [
+        Role::Rook => [
             0b0000000000000000,
             0b0011110111111100,
             0b0011111111111100,
@@ -129,7 +63,7 @@ pub fn piece_pattern(piece: Piece) -> [u16; 16] {
             0b0000000000000000,
         ],
         // Bishop - mitre shape
-        Piece::Bishop => [
+        Role::Bishop => [
             0b0000000000000000,
             0b0000001111000000,
             0b0000001111000000,
@@ -148,7 +82,7 @@ pub fn piece_pattern(piece: Piece) -> [u16; 16] {
             0b0000000000000000,
         ],
         // Knight - horse head silhouette
-        Piece::Knight => [
+        Role::Knight => [
             0b0000000000000000,
             0b0001111111100000,
             0b0000011111111000,
@@ -167,7 +101,7 @@ pub fn piece_pattern(piece: Piece) -> [u16; 16] {
             0b0000000000000000,
         ],
         // Pawn - simple round top
-        Piece::Pawn => [
+        Role::Pawn => [
             0b0000000000000000,
             0b0000000000000000,
             0b0000001111000000,
@@ -0,0 +1,220 @@
+//! Header and footer strips with player names, clocks and the last move,
+//! so the image alone carries the context of the caption. Finished games also get a
+//! result banner on top.
+
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+
+use image::{imageops, ImageBuffer, Rgba};
+
+use super::{qr, text};
+
+const BAR_HEIGHT: u32 = 28;
+const TEXT_SIZE: f32 = 15.0;
+const BANNER_HEIGHT: u32 = 40;
+const BANNER_TEXT_SIZE: f32 = 22.0;
+const PADDING: u32 = 8;
+const SWATCH_SIZE: u32 = 12;
+const MAX_NAME_CHARS: usize = 20;
+const ANALYSIS_LABEL: &str = "Scan for analysis";
+
+const BAR_BACKGROUND: Rgba<u8> = Rgba([49, 46, 43, 255]);
+const BANNER_BACKGROUND: Rgba<u8> = Rgba([118, 150, 86, 255]);
+const TEXT_COLOR: Rgba<u8> = Rgba([235, 235, 235, 255]);
+const WHITE_SWATCH: Rgba<u8> = Rgba([245, 245, 245, 255]);
+const BLACK_SWATCH: Rgba<u8> = Rgba([20, 20, 20, 255]);
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct BoardInfo {
+    pub white_name: String,
+    pub black_name: String,
+    /// Remaining time, already formatted. Only set for games with a time control.
+    pub white_clock: Option<String>,
+    pub black_clock: Option<String>,
+    /// Last move with its move number, e.g. "12... Nf6".
+    pub last_move: Option<String>,
+    /// Link shown as a QR code in a strip under the footer, for finished games.
+    pub analysis_url: Option<String>,
+    /// Result and how the game ended, e.g. "1-0 · Checkmate", shown above the header.
+    pub banner: Option<String>,
+}
+
+impl BoardInfo {
+    pub fn new(white_name: impl Into<String>, black_name: impl Into<String>) -> Self {
+        Self {
+            white_name: white_name.into(),
+            black_name: black_name.into(),
+            ..Self::default()
+        }
+    }
+
+    pub fn with_last_move(mut self, ply: i64, san: &str) -> Self {
+        self.last_move = Some(format_last_move(ply, san));
+        self
+    }
+
+    pub fn with_analysis_url(mut self, url: impl Into<String>) -> Self {
+        self.analysis_url = Some(url.into());
+        self
+    }
+
+    pub fn with_result(mut self, result: &str, reason: &str) -> Self {
+        self.banner = Some(format!("{} · {}", result, reason));
+        self
+    }
+
+    /// Names and clocks are free text, so the cache key is a hash of the contents.
+    pub fn cache_key(&self) -> String {
+        let mut hasher = DefaultHasher::new();
+        self.hash(&mut hasher);
+        format!("info{:016x}", hasher.finish())
+    }
+}
+
+/// `ply` is the 1-based half-move index of the move, as stored in the moves table.
+fn format_last_move(ply: i64, san: &str) -> String {
+    let number = (ply + 1) / 2;
+    if ply % 2 == 1 {
+        format!("{}. {}", number, san)
+    } else {
+        format!("{}... {}", number, san)
+    }
+}
+
+/// Returns a copy of `board_img` with a bar above and below it. The player whose pieces
+/// are at the top of the board goes in the header, the other one in the footer.
+/// An analysis link adds a strip under the footer with its QR code in the right corner;
+/// links too long for a small code are left out. A result banner goes above the header.
+pub(super) fn add_info_bars(
+    board_img: &ImageBuffer<Rgba<u8>, Vec<u8>>,
+    info: &BoardInfo,
+    flip_board: bool,
+) -> ImageBuffer<Rgba<u8>, Vec<u8>> {
+    let width = board_img.width();
+    let qr_code = info.analysis_url.as_deref().and_then(qr::qr_image);
+    let qr_strip = qr_code.as_ref().map_or(0, |code| code.height() + PADDING * 2);
+    let banner = info.banner.as_ref().map_or(0, |_| BANNER_HEIGHT);
+    let mut img = ImageBuffer::from_pixel(
+        width,
+        banner + board_img.height() + BAR_HEIGHT * 2 + qr_strip,
+        BAR_BACKGROUND,
+    );
+    if let Some(label) = &info.banner {
+        for pixel in img.rows_mut().take(BANNER_HEIGHT as usize).flatten() {
+            *pixel = BANNER_BACKGROUND;
+        }
+        let x = width.saturating_sub(text::text_width(label, BANNER_TEXT_SIZE)) / 2;
+        let y = (BANNER_HEIGHT - text::cap_height(BANNER_TEXT_SIZE)) / 2;
+        text::draw_text(&mut img, x as i32, y as i32, label, BANNER_TEXT_SIZE, TEXT_COLOR);
+    }
+    let header_y = banner;
+    imageops::replace(&mut img, board_img, 0, (header_y + BAR_HEIGHT) as i64);
+
+    let white = (WHITE_SWATCH, info.white_name.as_str(), info.white_clock.as_deref());
+    let black = (BLACK_SWATCH, info.black_name.as_str(), info.black_clock.as_deref());
+    let (top, bottom) = if flip_board { (white, black) } else { (black, white) };
+
+    let footer_y = header_y + BAR_HEIGHT + board_img.height();
+    draw_player(&mut img, header_y, top);
+    draw_player(&mut img, footer_y, bottom);
+
+    if let Some(last_move) = &info.last_move {
+        let x = width.saturating_sub(PADDING + text::text_width(last_move, TEXT_SIZE));
+        draw_text(&mut img, x, footer_y + text_top_offset(), last_move);
+    }
+
+    if let Some(code) = qr_code {
+        let strip_y = footer_y + BAR_HEIGHT;
+        let x = width.saturating_sub(PADDING + code.width());
+        imageops::replace(&mut img, &code, x as i64, (strip_y + PADDING) as i64);
+        let label_y = strip_y + (qr_strip - text::cap_height(TEXT_SIZE)) / 2;
+        draw_text(&mut img, PADDING, label_y, ANALYSIS_LABEL);
+    }
+
+    img
+}
+
+fn draw_player(
+    img: &mut ImageBuffer<Rgba<u8>, Vec<u8>>,
+    bar_y: u32,
+    (swatch, name, clock): (Rgba<u8>, &str, Option<&str>),
+) {
+    let swatch_y = bar_y + (BAR_HEIGHT - SWATCH_SIZE) / 2;
+    for y in swatch_y..swatch_y + SWATCH_SIZE {
+        for x in PADDING..PADDING + SWATCH_SIZE {
+            // Outline keeps the black swatch visible against the dark bar
+            let edge = y == swatch_y
+                || y == swatch_y + SWATCH_SIZE - 1
+                || x == PADDING
+                || x == PADDING + SWATCH_SIZE - 1;
+            img.put_pixel(x, y, if edge { TEXT_COLOR } else { swatch });
+        }
+    }
+
+    let mut label: String = name.chars().take(MAX_NAME_CHARS).collect();
+    if let Some(clock) = clock {
+        label.push_str("  ");
+        label.push_str(clock);
+    }
+    let x = PADDING * 2 + SWATCH_SIZE;
+    draw_text(img, x, bar_y + text_top_offset(), &label);
+}
+
+fn text_top_offset() -> u32 {
+    (BAR_HEIGHT - text::cap_height(TEXT_SIZE)) / 2
+}
+
+fn draw_text(img: &mut ImageBuffer<Rgba<u8>, Vec<u8>>, x: u32, y: u32, label: &str) {
+    text::draw_text(img, x as i32, y as i32, label, TEXT_SIZE, TEXT_COLOR);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_last_move() {
+        assert_eq!(format_last_move(1, "e4"), "1. e4");
+        assert_eq!(format_last_move(2, "e5"), "1... e5");
+        assert_eq!(format_last_move(23, "Nf3"), "12. Nf3");
+    }
+
+    #[test]
+    fn test_info_bars_extend_image() {
+        let board_img = ImageBuffer::from_pixel(100, 100, Rgba([0, 0, 0, 255]));
+        let info = BoardInfo::new("@alice", "@bob").with_last_move(1, "e4");
+        let img = add_info_bars(&board_img, &info, false);
+        assert_eq!(img.width(), 100);
+        assert_eq!(img.height(), 100 + BAR_HEIGHT * 2);
+    }
+
+    #[test]
+    fn test_analysis_url_adds_qr_strip() {
+        let board_img = ImageBuffer::from_pixel(200, 200, Rgba([0, 0, 0, 255]));
+        let info = BoardInfo::new("@alice", "@bob").with_analysis_url("https://lichess.org/analysis");
+        let img = add_info_bars(&board_img, &info, false);
+        let code = qr::qr_image("https://lichess.org/analysis").unwrap();
+        assert_eq!(img.height(), 200 + BAR_HEIGHT * 2 + code.height() + PADDING * 2);
+
+        let too_long = BoardInfo::new("@alice", "@bob").with_analysis_url("x".repeat(2000));
+        assert_eq!(add_info_bars(&board_img, &too_long, false).height(), 200 + BAR_HEIGHT * 2);
+    }
+
+    #[test]
+    fn test_result_banner_on_top() {
+        let board_img = ImageBuffer::from_pixel(200, 200, Rgba([0, 0, 0, 255]));
+        let info = BoardInfo::new("@alice", "@bob").with_result("1-0", "Checkmate");
+        assert_eq!(info.banner.as_deref(), Some("1-0 · Checkmate"));
+        let img = add_info_bars(&board_img, &info, false);
+        assert_eq!(img.height(), 200 + BAR_HEIGHT * 2 + BANNER_HEIGHT);
+        assert_eq!(*img.get_pixel(0, 0), BANNER_BACKGROUND);
+        assert_eq!(*img.get_pixel(0, BANNER_HEIGHT + BAR_HEIGHT), Rgba([0, 0, 0, 255]));
+    }
+
+    #[test]
+    fn test_cache_key_changes_with_contents() {
+        let info = BoardInfo::new("@alice", "@bob");
+        assert_eq!(info.cache_key(), info.clone().cache_key());
+        assert_ne!(info.cache_key(), info.with_last_move(1, "e4").cache_key());
+    }
+}
@@ -1,7 +1,28 @@
 mod cache;
+mod chart;
 pub mod chess;
+mod encode;
 mod glyphs;
+mod info_bar;
+mod openings;
+mod overlay;
+mod position;
+mod qr;
 mod render;
+mod sprites;
+mod text;
+mod warmup;
 
-pub use chess::{build_caption, color_to_turn, move_to_san, parse_move, uci_string};
-pub use render::render_board_png;
+pub use chess::{
+    build_caption, legal_moves_by_square, Caption, move_to_display_san, move_to_san, parse_move, parse_move_strict,
+    strip_en_passant_suffix,
+};
+pub use chart::render_bar_chart;
+pub use encode::{ImageEncoding, ImageFormat, PngCompression};
+pub use info_bar::BoardInfo;
+pub use openings::opening_name;
+pub use overlay::BoardOverlay;
+pub use position::{Color, File, GameStatus, Move, Position, Rank, Role, Square, Variant};
+pub use render::{draw_board_image, render_board, render_board_png, BoardOrientation, CoordinateStyle, RenderOptions};
+pub use warmup::{opening_positions, warm_cache};
+pub use cache::{cache_dir, run_cleanup_task, verify_cache};
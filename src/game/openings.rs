@@ -0,0 +1,100 @@
+//! Names of common openings, recognised from a game's first moves. Only move orders listed
+//! here are recognised; a transposition into a known opening keeps the shorter name.
+
+/// Opening names with the moves that define them. When several match, the longest wins.
+const OPENINGS: &[(&str, &str)] = &[
+    ("King's Pawn Opening", "e4"),
+    ("Open Game", "e4 e5"),
+    ("King's Knight Opening", "e4 e5 Nf3"),
+    ("Ruy Lopez", "e4 e5 Nf3 Nc6 Bb5"),
+    ("Ruy Lopez: Morphy Defense", "e4 e5 Nf3 Nc6 Bb5 a6"),
+    ("Ruy Lopez: Berlin Defense", "e4 e5 Nf3 Nc6 Bb5 Nf6"),
+    ("Italian Game", "e4 e5 Nf3 Nc6 Bc4"),
+    ("Italian Game: Giuoco Piano", "e4 e5 Nf3 Nc6 Bc4 Bc5"),
+    ("Italian Game: Two Knights Defense", "e4 e5 Nf3 Nc6 Bc4 Nf6"),
+    ("Evans Gambit", "e4 e5 Nf3 Nc6 Bc4 Bc5 b4"),
+    ("Scotch Game", "e4 e5 Nf3 Nc6 d4"),
+    ("Four Knights Game", "e4 e5 Nf3 Nc6 Nc3 Nf6"),
+    ("Petrov's Defense", "e4 e5 Nf3 Nf6"),
+    ("Philidor Defense", "e4 e5 Nf3 d6"),
+    ("King's Gambit", "e4 e5 f4"),
+    ("Vienna Game", "e4 e5 Nc3"),
+    ("Center Game", "e4 e5 d4 exd4"),
+    ("Bishop's Opening", "e4 e5 Bc4"),
+    ("Sicilian Defense", "e4 c5"),
+    ("Sicilian Defense: Open", "e4 c5 Nf3 d6 d4"),
+    ("Sicilian Defense: Najdorf Variation", "e4 c5 Nf3 d6 d4 cxd4 Nxd4 Nf6 Nc3 a6"),
+    ("Sicilian Defense: Dragon Variation", "e4 c5 Nf3 d6 d4 cxd4 Nxd4 Nf6 Nc3 g6"),
+    ("Sicilian Defense: Closed", "e4 c5 Nc3"),
+    ("Sicilian Defense: Alapin Variation", "e4 c5 c3"),
+    ("French Defense", "e4 e6"),
+    ("French Defense: Advance Variation", "e4 e6 d4 d5 e5"),
+    ("French Defense: Exchange Variation", "e4 e6 d4 d5 exd5"),
+    ("Caro-Kann Defense", "e4 c6"),
+    ("Caro-Kann Defense: Advance Variation", "e4 c6 d4 d5 e5"),
+    ("Scandinavian Defense", "e4 d5"),
+    ("Pirc Defense", "e4 d6 d4 Nf6 Nc3 g6"),
+    ("Modern Defense", "e4 g6"),
+    ("Alekhine's Defense", "e4 Nf6"),
+    ("Queen's Pawn Opening", "d4"),
+    ("Queen's Pawn Game", "d4 d5"),
+    ("Queen's Gambit", "d4 d5 c4"),
+    ("Queen's Gambit Accepted", "d4 d5 c4 dxc4"),
+    ("Queen's Gambit Declined", "d4 d5 c4 e6"),
+    ("Slav Defense", "d4 d5 c4 c6"),
+    ("London System", "d4 d5 Nf3 Nf6 Bf4"),
+    ("London System", "d4 d5 Bf4"),
+    ("London System", "d4 Nf6 Bf4"),
+    ("Indian Defense", "d4 Nf6"),
+    ("King's Indian Defense", "d4 Nf6 c4 g6 Nc3 Bg7"),
+    ("Grünfeld Defense", "d4 Nf6 c4 g6 Nc3 d5"),
+    ("Nimzo-Indian Defense", "d4 Nf6 c4 e6 Nc3 Bb4"),
+    ("Queen's Indian Defense", "d4 Nf6 c4 e6 Nf3 b6"),
+    ("Benoni Defense", "d4 Nf6 c4 c5"),
+    ("Dutch Defense", "d4 f5"),
+    ("English Opening", "c4"),
+    ("Réti Opening", "Nf3 d5"),
+    ("Zukertort Opening", "Nf3"),
+    ("Bird's Opening", "f4"),
+    ("King's Fianchetto Opening", "g3"),
+    ("Nimzo-Larsen Attack", "b3"),
+];
+
+/// The name of the longest listed opening the game starts with. Check marks are ignored.
+pub fn opening_name(moves: &[String]) -> Option<&'static str> {
+    let played: Vec<&str> = moves.iter().map(|san| san.trim_end_matches(['+', '#'])).collect();
+    OPENINGS
+        .iter()
+        .map(|(name, line)| (name, line.split_whitespace().collect::<Vec<_>>()))
+        .filter(|(_, line)| played.starts_with(line))
+        .max_by_key(|(_, line)| line.len())
+        .map(|(name, _)| *name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn moves(line: &str) -> Vec<String> {
+        line.split_whitespace().map(str::to_string).collect()
+    }
+
+    #[test]
+    fn test_longest_match_wins() {
+        assert_eq!(opening_name(&moves("e4 e5 Nf3 Nc6 Bb5 a6 Ba4")), Some("Ruy Lopez: Morphy Defense"));
+        assert_eq!(opening_name(&moves("e4 c5 Nf3 d6 d4 cxd4 Nxd4 Nf6 Nc3 a6")), Some("Sicilian Defense: Najdorf Variation"));
+        assert_eq!(opening_name(&moves("e4 e5 Qh5")), Some("Open Game"));
+    }
+
+    #[test]
+    fn test_unknown_or_empty() {
+        assert_eq!(opening_name(&moves("a3 e5")), None);
+        assert_eq!(opening_name(&[]), None);
+    }
+
+    #[test]
+    fn test_checks_ignored() {
+        assert_eq!(opening_name(&moves("f3 e5 g4 Qh4#")), None);
+        assert_eq!(opening_name(&moves("e4 e5 Bc4 Nc6 Qh5 Nf6 Qxf7#")), Some("Bishop's Opening"));
+    }
+}
@@ -0,0 +1,187 @@
+//! Annotation layer drawn on top of the board: arrows between squares and circled squares.
+//!
+//! Used to point at moves (hints, analysis lines, puzzle solutions) without changing the position.
+
+use super::position::Square;
+use image::{ImageBuffer, Rgba};
+
+const ARROW_COLOR: Rgba<u8> = Rgba([21, 120, 27, 170]);
+const CIRCLE_COLOR: Rgba<u8> = Rgba([200, 40, 40, 190]);
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BoardOverlay {
+    pub arrows: Vec<(Square, Square)>,
+    pub circles: Vec<Square>,
+}
+
+impl BoardOverlay {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_arrow(mut self, from: Square, to: Square) -> Self {
+        self.arrows.push((from, to));
+        self
+    }
+
+    pub fn with_circle(mut self, square: Square) -> Self {
+        self.circles.push(square);
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.arrows.is_empty() && self.circles.is_empty()
+    }
+
+    /// Stable identifier of the overlay contents, used as part of image cache file names.
+    /// Empty overlays produce an empty key so plain boards keep their existing cache entries.
+    pub fn cache_key(&self) -> String {
+        let mut parts: Vec<String> = self
+            .arrows
+            .iter()
+            .map(|(from, to)| format!("a{}{}", from, to))
+            .collect();
+        parts.extend(self.circles.iter().map(|sq| format!("c{}", sq)));
+        parts.join("-")
+    }
+}
+
+pub(super) fn draw_overlay(
+    img: &mut ImageBuffer<Rgba<u8>, Vec<u8>>,
+    overlay: &BoardOverlay,
+    flip_board: bool,
+    origin: u32,
+    square_size: u32,
+) {
+    let center = |sq: Square| -> (f32, f32) {
+        let file = sq.file().to_u32();
+        let rank = sq.rank().to_u32();
+        let col = if flip_board { 7 - file } else { file };
+        let row = if flip_board { rank } else { 7 - rank };
+        (
+            (origin + col * square_size + square_size / 2) as f32,
+            (origin + row * square_size + square_size / 2) as f32,
+        )
+    };
+
+    for &square in &overlay.circles {
+        let (cx, cy) = center(square);
+        draw_ring(img, cx, cy, square_size as f32 * 0.46, square_size as f32 * 0.07);
+    }
+
+    for &(from, to) in &overlay.arrows {
+        if from == to {
+            continue;
+        }
+        draw_arrow(img, center(from), center(to), square_size as f32);
+    }
+}
+
+fn draw_ring(img: &mut ImageBuffer<Rgba<u8>, Vec<u8>>, cx: f32, cy: f32, radius: f32, width: f32) {
+    let (x0, y0, x1, y1) = bounds(img, cx - radius, cy - radius, cx + radius, cy + radius);
+    for y in y0..y1 {
+        for x in x0..x1 {
+            let dx = x as f32 + 0.5 - cx;
+            let dy = y as f32 + 0.5 - cy;
+            let dist = (dx * dx + dy * dy).sqrt();
+            if dist <= radius && dist >= radius - width {
+                blend_pixel(img, x, y, CIRCLE_COLOR);
+            }
+        }
+    }
+}
+
+fn draw_arrow(
+    img: &mut ImageBuffer<Rgba<u8>, Vec<u8>>,
+    (sx, sy): (f32, f32),
+    (ex, ey): (f32, f32),
+    square_size: f32,
+) {
+    let shaft_half_width = square_size * 0.09;
+    let head_length = square_size * 0.42;
+    let head_half_width = square_size * 0.26;
+
+    let (dx, dy) = (ex - sx, ey - sy);
+    let length = (dx * dx + dy * dy).sqrt();
+    let (ux, uy) = (dx / length, dy / length);
+    let shaft_length = (length - head_length).max(0.0);
+
+    let pad = head_half_width;
+    let (x0, y0, x1, y1) = bounds(
+        img,
+        sx.min(ex) - pad,
+        sy.min(ey) - pad,
+        sx.max(ex) + pad,
+        sy.max(ey) + pad,
+    );
+
+    for y in y0..y1 {
+        for x in x0..x1 {
+            let px = x as f32 + 0.5 - sx;
+            let py = y as f32 + 0.5 - sy;
+            // Position along the arrow and distance from its axis
+            let along = px * ux + py * uy;
+            let across = (px * uy - py * ux).abs();
+
+            let in_shaft = along >= 0.0 && along <= shaft_length && across <= shaft_half_width;
+            let in_head = along > shaft_length
+                && along <= length
+                && across <= head_half_width * (length - along) / head_length;
+
+            if in_shaft || in_head {
+                blend_pixel(img, x, y, ARROW_COLOR);
+            }
+        }
+    }
+}
+
+fn bounds(
+    img: &ImageBuffer<Rgba<u8>, Vec<u8>>,
+    min_x: f32,
+    min_y: f32,
+    max_x: f32,
+    max_y: f32,
+) -> (u32, u32, u32, u32) {
+    let clamp = |v: f32, max: u32| v.max(0.0).min(max as f32) as u32;
+    (
+        clamp(min_x.floor(), img.width()),
+        clamp(min_y.floor(), img.height()),
+        clamp(max_x.ceil() + 1.0, img.width()),
+        clamp(max_y.ceil() + 1.0, img.height()),
+    )
+}
+
+fn blend_pixel(img: &mut ImageBuffer<Rgba<u8>, Vec<u8>>, x: u32, y: u32, color: Rgba<u8>) {
+    let base = img.get_pixel(x, y);
+    let alpha = color[3] as u32;
+    let mix = |top: u8, bottom: u8| ((top as u32 * alpha + bottom as u32 * (255 - alpha)) / 255) as u8;
+    let blended = Rgba([
+        mix(color[0], base[0]),
+        mix(color[1], base[1]),
+        mix(color[2], base[2]),
+        255,
+    ]);
+    img.put_pixel(x, y, blended);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    #[test]
+    fn test_empty_overlay_has_empty_cache_key() {
+        let overlay = BoardOverlay::new();
+        assert!(overlay.is_empty());
+        assert_eq!(overlay.cache_key(), "");
+    }
+
+    #[test]
+    fn test_overlay_cache_key() {
+        let overlay = BoardOverlay::new()
+            .with_arrow(Square::from_str("e2").unwrap(), Square::from_str("e4").unwrap())
+            .with_circle(Square::from_str("f7").unwrap());
+        assert!(!overlay.is_empty());
+        assert_eq!(overlay.cache_key(), "ae2e4-cf7");
+    }
+}
@@ -0,0 +1,306 @@
+//! Chess rules behind a small API, backed by shakmaty. The rest of the crate only sees
+//! `Position` and `Move`, so the rules library can change without touching handlers.
+//!
+//! Standard chess is the default. Chess960 castling rights are picked up from the FEN,
+//! and the lichess variants are available through `Position::new`/`Position::from_fen`.
+
+use std::fmt;
+use std::str::FromStr;
+
+use crate::error::{KamaError, Result};
+use shakmaty::fen::{Epd, Fen};
+use shakmaty::san::SanPlus;
+use shakmaty::uci::UciMove;
+use shakmaty::variant::VariantPosition;
+use shakmaty::zobrist::Zobrist64;
+use shakmaty::{CastlingMode, CastlingSide, EnPassantMode, Position as _};
+
+pub use shakmaty::variant::Variant;
+pub use shakmaty::{Color, File, Rank, Role, Square};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GameStatus {
+    Ongoing,
+    Checkmate,
+    Stalemate,
+    InsufficientMaterial,
+    /// Ended by a variant rule (exploded king, king of the hill, ...). `None` is a draw.
+    VariantEnd { winner: Option<Color> },
+}
+
+/// A legal move in some position. Castling moves report the king's destination from
+/// `to()` (g1/c1 style) in every castling mode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Move(shakmaty::Move);
+
+impl Move {
+    /// Source square, `None` for crazyhouse drops.
+    pub fn from(&self) -> Option<Square> {
+        self.0.from()
+    }
+
+    pub fn to(&self) -> Square {
+        match self.0 {
+            shakmaty::Move::Castle { king, rook } => {
+                let side = if rook.file() < king.file() {
+                    CastlingSide::QueenSide
+                } else {
+                    CastlingSide::KingSide
+                };
+                Square::from_coords(side.king_to_file(), king.rank())
+            }
+            m => m.to(),
+        }
+    }
+
+    pub fn role(&self) -> Role {
+        self.0.role()
+    }
+
+    pub fn promotion(&self) -> Option<Role> {
+        self.0.promotion()
+    }
+
+    pub fn is_capture(&self) -> bool {
+        self.0.is_capture()
+    }
+
+    pub fn is_en_passant(&self) -> bool {
+        self.0.is_en_passant()
+    }
+
+    pub fn is_castle(&self) -> bool {
+        self.0.is_castle()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Position {
+    pos: VariantPosition,
+    castling_mode: CastlingMode,
+}
+
+impl Default for Position {
+    fn default() -> Self {
+        Self::new(Variant::Chess)
+    }
+}
+
+impl Position {
+    /// Starting position of `variant`.
+    pub fn new(variant: Variant) -> Self {
+        Self {
+            pos: VariantPosition::new(variant),
+            castling_mode: CastlingMode::Standard,
+        }
+    }
+
+    /// Parses a FEN for `variant`. Castling rights that only make sense in Chess960
+    /// (rooks off the corners, Shredder-FEN letters) switch the position to Chess960 castling.
+    pub fn from_fen(fen: &str, variant: Variant) -> Result<Self> {
+        let setup = Fen::from_str(fen.trim())
+            .map_err(|e| KamaError::ChessRule(format!("Invalid FEN: {}", e)))?
+            .into_setup();
+
+        match VariantPosition::from_setup(variant, setup.clone(), CastlingMode::Standard) {
+            Ok(pos) => Ok(Self {
+                pos,
+                castling_mode: CastlingMode::Standard,
+            }),
+            Err(_) => VariantPosition::from_setup(variant, setup, CastlingMode::Chess960)
+                .map(|pos| Self {
+                    pos,
+                    castling_mode: CastlingMode::Chess960,
+                })
+                .map_err(|e| KamaError::ChessRule(format!("Illegal position: {}", e))),
+        }
+    }
+
+    pub fn variant(&self) -> Variant {
+        self.pos.variant()
+    }
+
+    pub fn castling_mode(&self) -> CastlingMode {
+        self.castling_mode
+    }
+
+    pub fn side_to_move(&self) -> Color {
+        self.pos.turn()
+    }
+
+    pub fn piece_on(&self, square: Square) -> Option<Role> {
+        self.pos.board().role_at(square)
+    }
+
+    pub fn color_on(&self, square: Square) -> Option<Color> {
+        self.pos.board().color_at(square)
+    }
+
+    /// Number of `role` pieces `color` has on the board.
+    pub fn count(&self, color: Color, role: Role) -> u32 {
+        self.pos.board().by_piece(role.of(color)).count() as u32
+    }
+
+    /// The full-move number: 1 for the first move of each side, then up by one after Black's.
+    pub fn fullmoves(&self) -> u32 {
+        self.pos.fullmoves().get()
+    }
+
+    pub fn is_check(&self) -> bool {
+        self.pos.is_check()
+    }
+
+    pub fn status(&self) -> GameStatus {
+        if self.pos.is_variant_end() {
+            let winner = self.pos.variant_outcome().winner();
+            return GameStatus::VariantEnd { winner };
+        }
+        if self.pos.is_checkmate() {
+            GameStatus::Checkmate
+        } else if self.pos.is_stalemate() {
+            GameStatus::Stalemate
+        } else if self.pos.is_insufficient_material() {
+            GameStatus::InsufficientMaterial
+        } else {
+            GameStatus::Ongoing
+        }
+    }
+
+    /// Zobrist hash of the position. Equal for repeated positions, regardless of move
+    /// counters, so it can be used to detect repetitions.
+    pub fn zobrist_hash(&self) -> u64 {
+        self.pos.zobrist_hash::<Zobrist64>(EnPassantMode::Legal).0
+    }
+
+    /// FEN without the move counters.
+    pub fn epd(&self) -> String {
+        Epd::from_position(&self.pos, EnPassantMode::Legal).to_string()
+    }
+
+    pub fn legal_moves(&self) -> Vec<Move> {
+        self.pos.legal_moves().into_iter().map(Move).collect()
+    }
+
+    pub fn is_legal(&self, mv: Move) -> bool {
+        self.pos.is_legal(mv.0)
+    }
+
+    /// Legal castling move towards the queenside or kingside, if there is one.
+    pub fn castling_move(&self, queenside: bool) -> Option<Move> {
+        self.pos
+            .castling_moves(CastlingSide::from_queen_side(queenside))
+            .first()
+            .copied()
+            .map(Move)
+    }
+
+    /// Position after `mv`, which must be legal here.
+    pub fn play(&self, mv: Move) -> Position {
+        let mut next = self.clone();
+        next.pos.play_unchecked(mv.0);
+        next
+    }
+
+    /// Standard SAN including the check or mate suffix.
+    pub fn san(&self, mv: Move) -> String {
+        SanPlus::from_move(self.pos.clone(), mv.0).to_string()
+    }
+
+    /// Parses strict SAN, e.g. "Nbd7" or "exd6". Check marks are optional.
+    pub fn parse_san(&self, input: &str) -> Option<Move> {
+        let san = SanPlus::from_str(input).ok()?;
+        san.san.to_move(&self.pos).ok().map(Move)
+    }
+
+    /// UCI notation. Chess960 castling is written king-takes-rook, standard as e1g1.
+    pub fn uci(&self, mv: Move) -> String {
+        mv.0.to_uci(self.castling_mode).to_string()
+    }
+
+    pub fn parse_uci(&self, input: &str) -> Option<Move> {
+        let uci = UciMove::from_str(input).ok()?;
+        uci.to_move(&self.pos).ok().map(Move)
+    }
+}
+
+impl FromStr for Position {
+    type Err = KamaError;
+
+    /// Standard chess (or Chess960) from a FEN.
+    fn from_str(fen: &str) -> Result<Self> {
+        Self::from_fen(fen, Variant::Chess)
+    }
+}
+
+impl fmt::Display for Position {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", Fen::from_position(&self.pos, EnPassantMode::Legal))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn play_uci(pos: &Position, uci: &str) -> Position {
+        pos.play(pos.parse_uci(uci).unwrap())
+    }
+
+    #[test]
+    fn test_fen_round_trip() {
+        let fen = "rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3";
+        assert_eq!(Position::from_str(fen).unwrap().to_string(), fen);
+        assert_eq!(
+            Position::default().to_string(),
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1"
+        );
+    }
+
+    #[test]
+    fn test_repetition_has_same_hash() {
+        let start = Position::default();
+        let mut pos = start.clone();
+        for uci in ["g1f3", "g8f6", "f3g1", "f6g8"] {
+            pos = play_uci(&pos, uci);
+        }
+        assert_eq!(pos.zobrist_hash(), start.zobrist_hash());
+        assert_ne!(pos.to_string(), start.to_string());
+        assert_ne!(play_uci(&start, "e2e4").zobrist_hash(), start.zobrist_hash());
+    }
+
+    #[test]
+    fn test_chess960_castling() {
+        // King on b1, rooks on a1 and h1
+        let pos = Position::from_str("4k3/8/8/8/8/8/8/RK5R w HA - 0 1").unwrap();
+        assert_eq!(pos.castling_mode(), CastlingMode::Chess960);
+
+        let castle = pos.castling_move(false).unwrap();
+        assert_eq!(castle.to(), Square::G1);
+        assert_eq!(pos.san(castle), "O-O");
+        assert_eq!(pos.uci(castle), "b1h1");
+
+        let next = pos.play(castle);
+        assert_eq!(next.piece_on(Square::G1), Some(Role::King));
+        assert_eq!(next.piece_on(Square::F1), Some(Role::Rook));
+    }
+
+    #[test]
+    fn test_status() {
+        let mate = Position::from_str("7k/6Q1/6K1/8/8/8/8/8 b - - 0 1").unwrap();
+        assert_eq!(mate.status(), GameStatus::Checkmate);
+        let bare_kings = Position::from_str("8/8/4k3/8/8/4K3/8/8 w - - 0 1").unwrap();
+        assert_eq!(bare_kings.status(), GameStatus::InsufficientMaterial);
+        assert_eq!(Position::default().status(), GameStatus::Ongoing);
+    }
+
+    #[test]
+    fn test_variant_end() {
+        let hill = Position::from_fen("8/8/8/3K4/8/8/8/7k b - - 0 1", Variant::KingOfTheHill).unwrap();
+        assert_eq!(
+            hill.status(),
+            GameStatus::VariantEnd {
+                winner: Some(Color::White)
+            }
+        );
+    }
+}
@@ -0,0 +1,60 @@
+//! QR codes drawn into board images, so a phone camera can open a link from the picture.
+
+use image::{ImageBuffer, Rgba};
+use qrcode::{Color as Module, EcLevel, QrCode};
+
+/// Pixels per module; smaller codes don't scan reliably from a screen.
+const MODULE_SIZE: u32 = 2;
+/// Light border around the code, in modules. The spec asks for 4, scanners cope with 2.
+const QUIET_ZONE: u32 = 2;
+/// Largest code drawn (version 16). Longer links would make the code dominate the image.
+const MAX_MODULES: usize = 81;
+
+const DARK: Rgba<u8> = Rgba([0, 0, 0, 255]);
+const LIGHT: Rgba<u8> = Rgba([255, 255, 255, 255]);
+
+/// Renders `data` as a black-on-white code with its quiet zone, or `None` when it doesn't
+/// fit in `MAX_MODULES`.
+pub(super) fn qr_image(data: &str) -> Option<ImageBuffer<Rgba<u8>, Vec<u8>>> {
+    let code = QrCode::with_error_correction_level(data, EcLevel::L).ok()?;
+    let modules = code.width();
+    if modules > MAX_MODULES {
+        return None;
+    }
+
+    let colors = code.to_colors();
+    let side = (modules as u32 + QUIET_ZONE * 2) * MODULE_SIZE;
+    Some(ImageBuffer::from_fn(side, side, |x, y| {
+        let col = (x / MODULE_SIZE).checked_sub(QUIET_ZONE);
+        let row = (y / MODULE_SIZE).checked_sub(QUIET_ZONE);
+        match (col, row) {
+            (Some(col), Some(row)) if (col as usize) < modules && (row as usize) < modules => {
+                match colors[row as usize * modules + col as usize] {
+                    Module::Dark => DARK,
+                    Module::Light => LIGHT,
+                }
+            }
+            _ => LIGHT,
+        }
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_qr_image_size() {
+        // Version 1 is 21 modules wide
+        let img = qr_image("kamachess").unwrap();
+        assert_eq!(img.width(), (21 + QUIET_ZONE * 2) * MODULE_SIZE);
+        assert_eq!(img.width(), img.height());
+        assert_eq!(*img.get_pixel(0, 0), LIGHT);
+        assert_eq!(*img.get_pixel(QUIET_ZONE * MODULE_SIZE, QUIET_ZONE * MODULE_SIZE), DARK);
+    }
+
+    #[test]
+    fn test_qr_image_too_long() {
+        assert!(qr_image(&"x".repeat(2000)).is_none());
+    }
+}
@@ -1,219 +1,318 @@
-use anyhow::Result;
-use chess::{Board, Color, File, Piece, Rank, Square};
+use crate::error::Result;
+use super::position::{Color, File, Position, Rank, Square};
 use image::{ImageBuffer, Rgba};
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
+use tracing::warn;
 
 use super::cache;
-use super::glyphs::{glyph_for_file, glyph_for_rank, piece_pattern};
+use super::encode::ImageEncoding;
+use super::info_bar::{self, BoardInfo};
+use super::overlay::{self, BoardOverlay};
+use super::sprites;
+use super::text;
 
 const SQUARE_SIZE: u32 = 64;
 const COORD_MARGIN: u32 = 20;
-const BOARD_SIZE: u32 = SQUARE_SIZE * 8 + COORD_MARGIN * 2;
+const LABEL_SIZE: f32 = 14.0;
+const INNER_LABEL_SIZE: f32 = 11.0;
+const DEFAULT_SLOW_RENDER_MS: u64 = 500;
 
 const LIGHT_SQUARE: Rgba<u8> = Rgba([240, 217, 181, 255]);
 const DARK_SQUARE: Rgba<u8> = Rgba([181, 136, 99, 255]);
 const COORD_BORDER: Rgba<u8> = Rgba([101, 76, 59, 255]);
 
-pub fn render_board_png(board: &Board, flip_board: bool) -> Result<Vec<u8>> {
-    cache::get_or_create(board, flip_board, || {
-        let mut img: ImageBuffer<Rgba<u8>, Vec<u8>> =
-            ImageBuffer::from_pixel(BOARD_SIZE, BOARD_SIZE, COORD_BORDER);
+/// Where rank and file labels are drawn.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CoordinateStyle {
+    /// Labels in a border around the board.
+    #[default]
+    Outside,
+    /// Small labels in the corner of the edge squares, no border.
+    Inside,
+    /// No labels and no border.
+    Hidden,
+}
 
-        draw_board_squares(&mut img);
-        draw_coordinates(&mut img, flip_board);
-        draw_pieces(board, &mut img, flip_board);
+impl CoordinateStyle {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CoordinateStyle::Outside => "outside",
+            CoordinateStyle::Inside => "inside",
+            CoordinateStyle::Hidden => "hidden",
+        }
+    }
 
-        let mut bytes = Vec::new();
-        img.write_to(
-            &mut std::io::Cursor::new(&mut bytes),
-            image::ImageFormat::Png,
-        )?;
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.to_ascii_lowercase().as_str() {
+            "outside" => Some(CoordinateStyle::Outside),
+            "inside" => Some(CoordinateStyle::Inside),
+            "hidden" | "off" | "none" => Some(CoordinateStyle::Hidden),
+            _ => None,
+        }
+    }
 
-        Ok(bytes)
-    })
+    fn margin(&self) -> u32 {
+        match self {
+            CoordinateStyle::Outside => COORD_MARGIN,
+            CoordinateStyle::Inside | CoordinateStyle::Hidden => 0,
+        }
+    }
 }
 
-fn draw_board_squares(img: &mut ImageBuffer<Rgba<u8>, Vec<u8>>) {
-    let origin_x = COORD_MARGIN;
-    let origin_y = COORD_MARGIN;
-    for rank in 0..8 {
-        for file in 0..8 {
-            let x0 = origin_x + file * SQUARE_SIZE;
-            let y0 = origin_y + rank * SQUARE_SIZE;
-            let is_light = (rank + file) % 2 == 0;
-            let color = if is_light { LIGHT_SQUARE } else { DARK_SQUARE };
-
-            for y in y0..(y0 + SQUARE_SIZE) {
-                for x in x0..(x0 + SQUARE_SIZE) {
-                    img.put_pixel(x, y, color);
-                }
+/// Which side of the board is drawn at the bottom.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum BoardOrientation {
+    /// Follow the side to move.
+    #[default]
+    Auto,
+    /// Always from White's side.
+    White,
+    /// From the viewer's own side; only meaningful in private chats, falls back to `Auto`.
+    Own,
+}
+
+impl BoardOrientation {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            BoardOrientation::Auto => "auto",
+            BoardOrientation::White => "white",
+            BoardOrientation::Own => "own",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.to_ascii_lowercase().as_str() {
+            "auto" => Some(BoardOrientation::Auto),
+            "white" => Some(BoardOrientation::White),
+            "own" => Some(BoardOrientation::Own),
+            _ => None,
+        }
+    }
+
+    /// Whether the board should be drawn from Black's side. `viewer` is the colour of the
+    /// player the image is meant for, when there is a single one.
+    pub fn flip_board(&self, side_to_move: Color, viewer: Option<Color>) -> bool {
+        match (self, viewer) {
+            (BoardOrientation::White, _) => false,
+            (BoardOrientation::Own, Some(color)) => color == Color::Black,
+            (BoardOrientation::Own, None) | (BoardOrientation::Auto, _) => {
+                side_to_move == Color::Black
             }
         }
     }
 }
 
-fn draw_coordinates(img: &mut ImageBuffer<Rgba<u8>, Vec<u8>>, flip_board: bool) {
-    let scale: i32 = 2;
-    let file_glyph_w: i32 = 5 * scale;
-    let file_glyph_h: i32 = 9 * scale;
-    let rank_glyph_w: i32 = 7 * scale;
-    let rank_glyph_h: i32 = 7 * scale;
-    let pad: i32 = 1;
-    let origin_x = COORD_MARGIN as i32;
-    let origin_y = COORD_MARGIN as i32;
-    let margin = COORD_MARGIN as i32;
-    let board_span = (SQUARE_SIZE * 8) as i32;
-    let label_color = Rgba([220, 200, 180, 255]);
+#[derive(Debug, Clone, Default)]
+pub struct RenderOptions {
+    pub flip_board: bool,
+    pub coordinates: CoordinateStyle,
+    pub overlay: BoardOverlay,
+    /// Player names and last move drawn above and below the board.
+    pub info: Option<BoardInfo>,
+    pub encoding: ImageEncoding,
+}
 
-    let (side1_y, side2_y) =
-        calculate_vertical_side_positions(margin, file_glyph_h, origin_y, board_span, pad);
-    let (side1_x, side2_x) =
-        calculate_horizontal_side_positions(margin, rank_glyph_w, origin_x, board_span, pad);
-
-    draw_labels_on_two_sides(
-        img,
-        0..8,
-        |file| {
-            let file_idx = if flip_board { 7 - file } else { file };
-            let letter = (b'a' + file_idx as u8) as char;
-            glyph_for_file(letter)
-        },
-        |file| {
-            let x =
-                origin_x + (file * SQUARE_SIZE) as i32 + (SQUARE_SIZE as i32 - file_glyph_w) / 2;
-            ((x, side1_y), (x, side2_y))
-        },
-        label_color,
-        scale,
-        |img,
-                 x,
-                 y,
-                 glyph,
-                 color,
-                 scale| {
-            draw_glyph_file(img, x, y, color, glyph, scale);
-        },
-    );
-
-    draw_labels_on_two_sides(
-        img,
-        0..8,
-        |rank| {
-            let rank_num = if flip_board { rank + 1 } else { 8 - rank };
-            let number = rank_num as u8;
-            glyph_for_rank(number)
-        },
-        |rank| {
-            let y = origin_y + (rank * SQUARE_SIZE) as i32 + (SQUARE_SIZE as i32 - rank_glyph_h) / 2;
-            ((side1_x, y), (side2_x, y))
-        },
-        label_color,
-        scale,
-        |img, x, y, glyph, color, scale| {
-            draw_glyph_rank(img, x, y, color, glyph, scale);
+impl RenderOptions {
+    /// Cache key suffix for everything except orientation, which the cache tracks itself.
+    fn variant_key(&self) -> String {
+        let coords = match self.coordinates {
+            CoordinateStyle::Outside => "",
+            style => style.as_str(),
+        };
+        let info = self.info.as_ref().map(BoardInfo::cache_key).unwrap_or_default();
+        [coords.to_string(), self.overlay.cache_key(), info, self.encoding.cache_key()]
+            .into_iter()
+            .filter(|part| !part.is_empty())
+            .collect::<Vec<_>>()
+            .join("_")
+    }
+}
+
+pub async fn render_board_png(board: &Position, flip_board: bool) -> Result<Vec<u8>> {
+    render_board(
+        board,
+        &RenderOptions {
+            flip_board,
+            ..RenderOptions::default()
         },
-    );
+    )
+    .await
 }
 
-fn calculate_vertical_side_positions(margin: i32, glyph_h: i32, origin_y: i32, board_span: i32, pad: i32) -> (i32, i32) {
-    let side1 = (margin - glyph_h) / 2 + pad;
-    let side2 = origin_y + board_span + (margin - glyph_h) / 2 - pad;
-    (side1, side2)
+/// Renders the board, reusing the image cache. Boards with info strips are cached as the
+/// finished image, keyed on the strip contents, so they are encoded once. Renders slower
+/// than `SLOW_RENDER_MS` are logged.
+pub async fn render_board(board: &Position, options: &RenderOptions) -> Result<Vec<u8>> {
+    let started = Instant::now();
+    let result = render_cached(board, options).await;
+    let elapsed = started.elapsed();
+    if slow_render_threshold().is_some_and(|threshold| elapsed > threshold) {
+        warn!(
+            elapsed_ms = elapsed.as_millis() as u64,
+            format = options.encoding.format.extension(),
+            info_bars = options.info.is_some(),
+            "Slow board render"
+        );
+    }
+    result
 }
 
-fn calculate_horizontal_side_positions(margin: i32, glyph_w: i32, origin_x: i32, board_span: i32, pad: i32) -> (i32, i32) {
-    let side1 = (margin - glyph_w) / 2 + pad;
-    let side2 = origin_x + board_span + (margin - glyph_w) / 2 - pad;
-    (side1, side2)
+/// `SLOW_RENDER_MS` (500), or `None` when it is 0.
+fn slow_render_threshold() -> Option<Duration> {
+    static THRESHOLD: OnceLock<Option<Duration>> = OnceLock::new();
+    *THRESHOLD.get_or_init(|| {
+        let ms = std::env::var("SLOW_RENDER_MS")
+            .ok()
+            .and_then(|v| v.trim().parse::<u64>().ok())
+            .unwrap_or(DEFAULT_SLOW_RENDER_MS);
+        (ms > 0).then(|| Duration::from_millis(ms))
+    })
 }
 
-fn draw_labels_on_two_sides<G, F1, F4, const N: usize>(
-    img: &mut ImageBuffer<Rgba<u8>, Vec<u8>>,
-    indices: impl Iterator<Item = u32>,
-    get_glyph: G,
-    get_coords: F1,
-    color: Rgba<u8>,
-    scale: i32,
-    draw_fn: F4,
-) where
-    G: Fn(u32) -> [u8; N],
-    F1: Fn(u32) -> ((i32, i32), (i32, i32)),
-    F4: Fn(&mut ImageBuffer<Rgba<u8>, Vec<u8>>, i32, i32, &[u8; N], Rgba<u8>, i32),
-{
-    for idx in indices {
-        let glyph = get_glyph(idx);
-        let ((x1, y1), (x2, y2)) = get_coords(idx);
-        
-        draw_fn(img, x1, y1, &glyph, color, scale);
-        draw_fn(img, x2, y2, &glyph, color, scale);
+async fn render_cached(board: &Position, options: &RenderOptions) -> Result<Vec<u8>> {
+    let extension = options.encoding.format.extension();
+    let owned_board = board.clone();
+    let render_options = options.clone();
+    cache::get_or_create(board, options.flip_board, &options.variant_key(), extension, move || {
+        draw_board(&owned_board, &render_options)
+    })
+    .await
+}
+
+fn draw_board(board: &Position, options: &RenderOptions) -> Result<Vec<u8>> {
+    let img = draw_board_image(board, options);
+    match &options.info {
+        Some(info) => options
+            .encoding
+            .encode(&info_bar::add_info_bars(&img, info, options.flip_board)),
+        None => options.encoding.encode(&img),
     }
 }
 
-fn draw_scaled_pixel(
-    img: &mut ImageBuffer<Rgba<u8>, Vec<u8>>,
-    base_x: i32,
-    base_y: i32,
-    col: usize,
-    row: usize,
-    scale: i32,
-    color: Rgba<u8>,
-) {
-    for dy in 0..scale {
-        for dx in 0..scale {
-            let px = base_x + (col as i32 * scale) + dx;
-            let py = base_y + (row as i32 * scale) + dy;
-            if px >= 0 && py >= 0 && px < img.width() as i32 && py < img.height() as i32 {
-                img.put_pixel(px as u32, py as u32, color);
-            }
+/// Draws the board without touching the cache or encoding it. Info strips are not
+/// included; `render_board` adds them before encoding.
+pub fn draw_board_image(board: &Position, options: &RenderOptions) -> ImageBuffer<Rgba<u8>, Vec<u8>> {
+    let flip_board = options.flip_board;
+    let margin = options.coordinates.margin();
+    let mut img = empty_board(options.coordinates, flip_board).clone();
+
+    draw_pieces(board, &mut img, flip_board, margin);
+    overlay::draw_overlay(&mut img, &options.overlay, flip_board, margin, SQUARE_SIZE);
+
+    img
+}
+
+/// Squares and coordinate labels depend only on the label style and orientation, so each
+/// combination is drawn once and copied for every render.
+fn empty_board(coordinates: CoordinateStyle, flip_board: bool) -> &'static ImageBuffer<Rgba<u8>, Vec<u8>> {
+    static BOARDS: [OnceLock<ImageBuffer<Rgba<u8>, Vec<u8>>>; 6] = [const { OnceLock::new() }; 6];
+    let style_index = match coordinates {
+        CoordinateStyle::Outside => 0,
+        CoordinateStyle::Inside => 1,
+        CoordinateStyle::Hidden => 2,
+    };
+    BOARDS[style_index * 2 + flip_board as usize].get_or_init(|| {
+        let margin = coordinates.margin();
+        let board_size = SQUARE_SIZE * 8 + margin * 2;
+        let mut img = ImageBuffer::from_pixel(board_size, board_size, COORD_BORDER);
+        draw_board_squares(&mut img, margin);
+        match coordinates {
+            CoordinateStyle::Outside => draw_coordinates(&mut img, flip_board),
+            CoordinateStyle::Inside => draw_inner_coordinates(&mut img, flip_board),
+            CoordinateStyle::Hidden => {}
+        }
+        img
+    })
+}
+
+fn draw_board_squares(img: &mut ImageBuffer<Rgba<u8>, Vec<u8>>, margin: u32) {
+    let width = img.width() as usize;
+    let buffer: &mut [u8] = img;
+    for rank in 0..8 {
+        // One pixel row of this rank, copied into each of its lines
+        let line: Vec<u8> = (0..8)
+            .flat_map(|file| {
+                let color = if (rank + file) % 2 == 0 { LIGHT_SQUARE } else { DARK_SQUARE };
+                std::iter::repeat_n(color.0, SQUARE_SIZE as usize).flatten()
+            })
+            .collect();
+        for y in margin + rank * SQUARE_SIZE..margin + (rank + 1) * SQUARE_SIZE {
+            let start = (y as usize * width + margin as usize) * 4;
+            buffer[start..start + line.len()].copy_from_slice(&line);
         }
     }
 }
 
-struct GlyphParams {
-    width: usize,
-    bit_shift: usize,
+/// Draws file letters in the bottom-right corner of the bottom row and rank numbers
+/// in the top-left corner of the left column, colored to contrast with each square.
+fn draw_inner_coordinates(img: &mut ImageBuffer<Rgba<u8>, Vec<u8>>, flip_board: bool) {
+    let inset: i32 = 3;
+    let square = SQUARE_SIZE as i32;
+    let label_color = |col: u32, row: u32| {
+        if (row + col).is_multiple_of(2) {
+            DARK_SQUARE
+        } else {
+            LIGHT_SQUARE
+        }
+    };
+
+    for col in 0..8u32 {
+        let label = file_label(col, flip_board);
+        let x = (col as i32 + 1) * square - text::text_width(&label, INNER_LABEL_SIZE) as i32 - inset;
+        let y = 8 * square - text::cap_height(INNER_LABEL_SIZE) as i32 - inset;
+        text::draw_text(img, x, y, &label, INNER_LABEL_SIZE, label_color(col, 7));
+    }
+
+    for row in 0..8u32 {
+        let label = rank_label(row, flip_board);
+        let y = row as i32 * square + inset;
+        text::draw_text(img, inset, y, &label, INNER_LABEL_SIZE, label_color(0, row));
+    }
 }
 
-fn draw_glyph(
-    img: &mut ImageBuffer<Rgba<u8>, Vec<u8>>,
-    x: i32,
-    y: i32,
-    color: Rgba<u8>,
-    glyph: &[u8],
-    params: GlyphParams,
-    scale: i32,
-) {
-    for (row, bits) in glyph.iter().enumerate() {
-        for col in 0..params.width {
-            if (bits >> (params.bit_shift - col)) & 1 == 1 {
-                draw_scaled_pixel(img, x, y, col, row, scale, color);
-            }
+/// Labels in the border: files above and below the board, ranks on both sides.
+fn draw_coordinates(img: &mut ImageBuffer<Rgba<u8>, Vec<u8>>, flip_board: bool) {
+    let margin = COORD_MARGIN as i32;
+    let square = SQUARE_SIZE as i32;
+    let far_side = margin + square * 8;
+    let label_height = text::cap_height(LABEL_SIZE) as i32;
+    let label_color = Rgba([220, 200, 180, 255]);
+
+    for i in 0..8u32 {
+        let label = file_label(i, flip_board);
+        let x = margin + i as i32 * square + (square - text::text_width(&label, LABEL_SIZE) as i32) / 2;
+        for y in [(margin - label_height) / 2, far_side + (margin - label_height) / 2] {
+            text::draw_text(img, x, y, &label, LABEL_SIZE, label_color);
+        }
+
+        let label = rank_label(i, flip_board);
+        let y = margin + i as i32 * square + (square - label_height) / 2;
+        let width = text::text_width(&label, LABEL_SIZE) as i32;
+        for x in [(margin - width) / 2, far_side + (margin - width) / 2] {
+            text::draw_text(img, x, y, &label, LABEL_SIZE, label_color);
         }
     }
 }
 
-fn draw_glyph_file(
-    img: &mut ImageBuffer<Rgba<u8>, Vec<u8>>,
-    x: i32,
-    y: i32,
-    color: Rgba<u8>,
-    glyph: &[u8; 9],
-    scale: i32,
-) {
-    draw_glyph(img, x, y, color, glyph, GlyphParams { width: 5, bit_shift: 4 }, scale);
+/// File letter for the `col`-th column from the left.
+fn file_label(col: u32, flip_board: bool) -> String {
+    let file = if flip_board { 7 - col } else { col };
+    ((b'a' + file as u8) as char).to_string()
 }
 
-fn draw_glyph_rank(
-    img: &mut ImageBuffer<Rgba<u8>, Vec<u8>>,
-    x: i32,
-    y: i32,
-    color: Rgba<u8>,
-    glyph: &[u8; 7],
-    scale: i32,
-) {
-    draw_glyph(img, x, y, color, glyph, GlyphParams { width: 7, bit_shift: 6 }, scale);
+/// Rank number for the `row`-th row from the top.
+fn rank_label(row: u32, flip_board: bool) -> String {
+    let rank = if flip_board { row + 1 } else { 8 - row };
+    rank.to_string()
 }
 
-fn draw_pieces(board: &Board, img: &mut ImageBuffer<Rgba<u8>, Vec<u8>>, flip_board: bool) {
+fn draw_pieces(
+    board: &Position,
+    img: &mut ImageBuffer<Rgba<u8>, Vec<u8>>,
+    flip_board: bool,
+    margin: u32,
+) {
     for rank in 0..8 {
         for file in 0..8 {
             let board_rank = if flip_board { rank } else { 7 - rank };
@@ -221,83 +320,29 @@ fn draw_pieces(board: &Board, img: &mut ImageBuffer<Rgba<u8>, Vec<u8>>, flip_boa
             let square = square_from_coords(board_file, board_rank);
             if let Some(piece) = board.piece_on(square) {
                 let color = board.color_on(square).unwrap_or(Color::White);
-
-                let x = (COORD_MARGIN + file * SQUARE_SIZE + 8) as i32;
-                let y = (COORD_MARGIN + rank * SQUARE_SIZE + 8) as i32;
-
-                draw_piece(img, piece, x + 2, y + 2, Rgba([60, 60, 60, 200]));
-
-                let piece_color = if color == Color::White {
-                    Rgba([255, 255, 255, 255])
-                } else {
-                    Rgba([40, 40, 40, 255])
-                };
-                draw_piece(img, piece, x, y, piece_color);
-
-                if color == Color::White {
-                    draw_piece_outline(img, piece, x, y, Rgba([60, 60, 60, 255]));
-                }
+                let x = margin + file * SQUARE_SIZE + 8;
+                let y = margin + rank * SQUARE_SIZE + 8;
+                sprites::draw_piece(img, piece, color, x, y);
             }
         }
     }
 }
 
 fn square_from_coords(file: u32, rank: u32) -> Square {
-    let f = File::from_index(file as usize);
-    let r = Rank::from_index(rank as usize);
-    Square::make_square(r, f)
+    Square::from_coords(File::new(file), Rank::new(rank))
 }
 
-fn draw_piece_pattern_pixels(
-    img: &mut ImageBuffer<Rgba<u8>, Vec<u8>>,
-    pattern: &[u16; 16],
-    x: i32,
-    y: i32,
-    color: Rgba<u8>,
-    scale: i32,
-    should_draw: impl Fn(usize, usize, &[u16; 16]) -> bool,
-) {
-    for row in 0..16 {
-        for col in 0..16 {
-            if should_draw(row, col, pattern) {
-                draw_scaled_pixel(img, x, y, col, row, scale, color);
-            }
-        }
-    }
-}
-
-fn draw_piece(
-    img: &mut ImageBuffer<Rgba<u8>, Vec<u8>>,
-    piece: Piece,
-    x: i32,
-    y: i32,
-    color: Rgba<u8>,
-) {
-    let pattern = piece_pattern(piece);
-    let scale = 3;
-    draw_piece_pattern_pixels(img, &pattern, x, y, color, scale, |_row, col, pattern| {
-        (pattern[_row] >> (15 - col)) & 1 == 1
-    });
-}
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-fn draw_piece_outline(
-    img: &mut ImageBuffer<Rgba<u8>, Vec<u8>>,
-    piece: Piece,
-    x: i32,
-    y: i32,
-    color: Rgba<u8>,
-) {
-    let pattern = piece_pattern(piece);
-    let scale = 3;
-    draw_piece_pattern_pixels(img, &pattern, x, y, color, scale, |row, col, pattern| {
-        let is_filled = (pattern[row] >> (15 - col)) & 1 == 1;
-        if !is_filled {
-            return false;
-        }
-        let left = col > 0 && (pattern[row] >> (15 - col + 1)) & 1 == 0;
-        let right = col < 15 && (pattern[row] >> (15 - col - 1)) & 1 == 0;
-        let up = row > 0 && (pattern[row - 1] >> (15 - col)) & 1 == 0;
-        let down = row < 15 && (pattern[row + 1] >> (15 - col)) & 1 == 0;
-        left || right || up || down
-    });
+    #[test]
+    fn test_board_orientation_flip() {
+        assert!(BoardOrientation::Auto.flip_board(Color::Black, None));
+        assert!(!BoardOrientation::Auto.flip_board(Color::White, Some(Color::Black)));
+        assert!(!BoardOrientation::White.flip_board(Color::Black, Some(Color::Black)));
+        assert!(BoardOrientation::Own.flip_board(Color::White, Some(Color::Black)));
+        assert!(!BoardOrientation::Own.flip_board(Color::Black, Some(Color::White)));
+        assert!(BoardOrientation::Own.flip_board(Color::Black, None));
+    }
 }
@@ -0,0 +1,141 @@
+//! Piece sprites composed once from the bitmap patterns: shadow, body and, for white
+//! pieces, an outline. Drawing a piece is then a handful of row copies instead of
+//! thousands of single-pixel writes.
+
+use image::{ImageBuffer, Rgba};
+use std::sync::OnceLock;
+
+use super::glyphs::piece_pattern;
+use super::position::{Color, Role};
+
+/// Each pattern bit becomes a `SCALE`x`SCALE` block.
+const SCALE: u32 = 3;
+const SHADOW_OFFSET: u32 = 2;
+const SIDE: u32 = 16 * SCALE + SHADOW_OFFSET;
+
+const SHADOW_COLOR: Rgba<u8> = Rgba([60, 60, 60, 200]);
+const WHITE_PIECE: Rgba<u8> = Rgba([255, 255, 255, 255]);
+const BLACK_PIECE: Rgba<u8> = Rgba([40, 40, 40, 255]);
+const OUTLINE_COLOR: Rgba<u8> = Rgba([60, 60, 60, 255]);
+
+const ROLES: [Role; 6] = [Role::Pawn, Role::Knight, Role::Bishop, Role::Rook, Role::Queen, Role::King];
+
+/// A horizontal run of opaque sprite pixels, stored as raw RGBA bytes.
+struct Span {
+    x: u32,
+    y: u32,
+    bytes: Vec<u8>,
+}
+
+struct Sprite {
+    spans: Vec<Span>,
+}
+
+/// Draws a piece with the top-left of its body at (`x`, `y`); the shadow extends a few
+/// pixels right and down. Parts outside the image are clipped.
+pub(super) fn draw_piece(
+    img: &mut ImageBuffer<Rgba<u8>, Vec<u8>>,
+    role: Role,
+    color: Color,
+    x: u32,
+    y: u32,
+) {
+    let width = img.width();
+    let height = img.height();
+    let buffer: &mut [u8] = img;
+    for span in &sprite(role, color).spans {
+        let py = y + span.y;
+        let px = x + span.x;
+        if py >= height || px >= width {
+            continue;
+        }
+        let len = (span.bytes.len() as u32 / 4).min(width - px) as usize * 4;
+        let start = (py as usize * width as usize + px as usize) * 4;
+        buffer[start..start + len].copy_from_slice(&span.bytes[..len]);
+    }
+}
+
+fn sprite(role: Role, color: Color) -> &'static Sprite {
+    static SPRITES: OnceLock<Vec<Sprite>> = OnceLock::new();
+    let sprites = SPRITES.get_or_init(|| {
+        [Color::White, Color::Black]
+            .into_iter()
+            .flat_map(|color| ROLES.into_iter().map(move |role| compose(role, color)))
+            .collect()
+    });
+    let color_index = if color == Color::White { 0 } else { 1 };
+    let role_index = ROLES.iter().position(|r| *r == role).unwrap_or(0);
+    &sprites[color_index * ROLES.len() + role_index]
+}
+
+fn compose(role: Role, color: Color) -> Sprite {
+    let pattern = piece_pattern(role);
+    let filled = |row: usize, col: usize| (pattern[row] >> (15 - col)) & 1 == 1;
+    let is_edge = |row: usize, col: usize| {
+        filled(row, col)
+            && ((col > 0 && !filled(row, col - 1))
+                || (col < 15 && !filled(row, col + 1))
+                || (row > 0 && !filled(row - 1, col))
+                || (row < 15 && !filled(row + 1, col)))
+    };
+
+    let mut canvas: Vec<Option<Rgba<u8>>> = vec![None; (SIDE * SIDE) as usize];
+    let mut paint = |offset: u32, color: Rgba<u8>, covered: &dyn Fn(usize, usize) -> bool| {
+        for row in 0..16 {
+            for col in 0..16 {
+                if !covered(row, col) {
+                    continue;
+                }
+                for dy in 0..SCALE {
+                    let y = offset + row as u32 * SCALE + dy;
+                    let x = offset + col as u32 * SCALE;
+                    let start = (y * SIDE + x) as usize;
+                    canvas[start..start + SCALE as usize].fill(Some(color));
+                }
+            }
+        }
+    };
+
+    paint(SHADOW_OFFSET, SHADOW_COLOR, &filled);
+    if color == Color::White {
+        paint(0, WHITE_PIECE, &filled);
+        paint(0, OUTLINE_COLOR, &is_edge);
+    } else {
+        paint(0, BLACK_PIECE, &filled);
+    }
+
+    let mut spans = Vec::new();
+    for (y, row) in canvas.chunks_exact(SIDE as usize).enumerate() {
+        let mut x = 0;
+        while x < row.len() {
+            let run = row[x..].iter().take_while(|pixel| pixel.is_some()).count();
+            if run == 0 {
+                x += 1;
+                continue;
+            }
+            let bytes = row[x..x + run].iter().flat_map(|pixel| pixel.unwrap_or(SHADOW_COLOR).0).collect();
+            spans.push(Span { x: x as u32, y: y as u32, bytes });
+            x += run;
+        }
+    }
+    Sprite { spans }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_draw_piece_clips_at_image_edge() {
+        let background = Rgba([0, 0, 0, 255]);
+        let mut img = ImageBuffer::from_pixel(30, 30, background);
+        draw_piece(&mut img, Role::Queen, Color::White, 0, 0);
+        assert!(img.pixels().any(|p| *p == WHITE_PIECE));
+        assert!(img.pixels().any(|p| *p == OUTLINE_COLOR));
+
+        let mut img = ImageBuffer::from_pixel(30, 30, background);
+        draw_piece(&mut img, Role::Rook, Color::Black, 0, 0);
+        assert!(img.pixels().any(|p| *p == BLACK_PIECE));
+        assert!(!img.pixels().any(|p| *p == WHITE_PIECE));
+    }
+}
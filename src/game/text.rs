@@ -0,0 +1,102 @@
+//! Antialiased text from the embedded DejaVu Sans Bold, used for coordinate labels and
+//! the info bars. Sizes are in pixels, so labels stay sharp whatever the board size.
+
+use ab_glyph::{point, Font, FontRef, PxScale, ScaleFont};
+use image::{ImageBuffer, Rgba};
+use std::sync::OnceLock;
+
+static FONT_DATA: &[u8] = include_bytes!("../../assets/fonts/DejaVuSans-Bold.ttf");
+
+fn font() -> &'static FontRef<'static> {
+    static FONT: OnceLock<FontRef<'static>> = OnceLock::new();
+    FONT.get_or_init(|| FontRef::try_from_slice(FONT_DATA).expect("embedded font is valid"))
+}
+
+/// Advance width of `text` at `size`, kerning included.
+pub(super) fn text_width(text: &str, size: f32) -> u32 {
+    layout(text, size).last().map_or(0, |&(_, x, advance)| (x + advance).ceil() as u32)
+}
+
+/// Height of the glyphs that sit on the baseline (digits and capitals), for centering.
+pub(super) fn cap_height(size: f32) -> u32 {
+    font()
+        .outline_glyph(font().glyph_id('H').with_scale(size))
+        .map_or(size * 0.7, |outline| outline.px_bounds().height())
+        .round() as u32
+}
+
+/// Draws `text` with the top of its capitals at `y`. Pixels outside the image are skipped.
+pub(super) fn draw_text(
+    img: &mut ImageBuffer<Rgba<u8>, Vec<u8>>,
+    x: i32,
+    y: i32,
+    text: &str,
+    size: f32,
+    color: Rgba<u8>,
+) {
+    let baseline = y as f32 + cap_height(size) as f32;
+    for (id, caret, _) in layout(text, size) {
+        let glyph = id.with_scale_and_position(size, point(x as f32 + caret, baseline));
+        let Some(outline) = font().outline_glyph(glyph) else {
+            continue;
+        };
+        let bounds = outline.px_bounds();
+        outline.draw(|gx, gy, coverage| {
+            let px = bounds.min.x as i32 + gx as i32;
+            let py = bounds.min.y as i32 + gy as i32;
+            if px >= 0 && py >= 0 && (px as u32) < img.width() && (py as u32) < img.height() {
+                blend(img.get_pixel_mut(px as u32, py as u32), color, coverage);
+            }
+        });
+    }
+}
+
+/// Glyph ids with their caret position and advance.
+fn layout(text: &str, size: f32) -> Vec<(ab_glyph::GlyphId, f32, f32)> {
+    let scaled = font().as_scaled(PxScale::from(size));
+    let mut caret = 0.0;
+    let mut previous = None;
+    let mut glyphs = Vec::new();
+    for c in text.chars() {
+        let id = scaled.glyph_id(c);
+        if let Some(previous) = previous {
+            caret += scaled.kern(previous, id);
+        }
+        let advance = scaled.h_advance(id);
+        glyphs.push((id, caret, advance));
+        caret += advance;
+        previous = Some(id);
+    }
+    glyphs
+}
+
+fn blend(pixel: &mut Rgba<u8>, color: Rgba<u8>, coverage: f32) {
+    let alpha = coverage.clamp(0.0, 1.0) * color[3] as f32 / 255.0;
+    for channel in 0..3 {
+        let mixed = pixel[channel] as f32 * (1.0 - alpha) + color[channel] as f32 * alpha;
+        pixel[channel] = mixed.round() as u8;
+    }
+    pixel[3] = pixel[3].max((alpha * 255.0).round() as u8);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_text_width_grows_with_size() {
+        assert_eq!(text_width("", 12.0), 0);
+        let small = text_width("Nf3", 12.0);
+        assert!(small > 0);
+        assert!(text_width("Nf3", 24.0) > small);
+    }
+
+    #[test]
+    fn test_draw_text_marks_pixels() {
+        let mut img = ImageBuffer::from_pixel(40, 20, Rgba([0, 0, 0, 255]));
+        draw_text(&mut img, 2, 4, "e4", 14.0, Rgba([255, 255, 255, 255]));
+        assert!(img.pixels().any(|p| p[0] > 200));
+        // Partly off the image is fine
+        draw_text(&mut img, -5, -5, "abc", 14.0, Rgba([255, 255, 255, 255]));
+    }
+}
@@ -0,0 +1,105 @@
+//! Pre-renders early-game positions of popular openings so plain boards (without player
+//! strips, e.g. the tutorial) are served from the image cache even right after a deploy.
+//! Game boards carry per-game strips and are cached on first send instead.
+
+use std::collections::HashSet;
+
+use crate::error::Result;
+use tracing::{info, warn};
+
+use super::chess::parse_move;
+use super::position::{Color, Position};
+use super::encode::ImageEncoding;
+use super::render::{render_board, RenderOptions};
+
+const POPULAR_LINES: &[&str] = &[
+    "e4 e5 Nf3 Nc6 Bb5 a6 Ba4 Nf6",
+    "e4 e5 Nf3 Nc6 Bc4 Bc5 c3 Nf6",
+    "e4 e5 Nf3 Nc6 d4 exd4 Nxd4 Nf6",
+    "e4 e5 Nf3 Nf6 Nxe5 d6 Nf3 Nxe4",
+    "e4 c5 Nf3 d6 d4 cxd4 Nxd4 Nf6",
+    "e4 c5 Nf3 Nc6 d4 cxd4 Nxd4 Nf6",
+    "e4 c5 Nf3 e6 d4 cxd4 Nxd4 Nc6",
+    "e4 c5 Nc3 Nc6 g3 g6 Bg2 Bg7",
+    "e4 e6 d4 d5 Nc3 Nf6 Bg5 Be7",
+    "e4 c6 d4 d5 Nc3 dxe4 Nxe4 Bf5",
+    "e4 d5 exd5 Qxd5 Nc3 Qa5 d4 Nf6",
+    "e4 d6 d4 Nf6 Nc3 g6 Nf3 Bg7",
+    "d4 d5 c4 e6 Nc3 Nf6 Bg5 Be7",
+    "d4 d5 c4 dxc4 Nf3 Nf6 e3 e6",
+    "d4 d5 c4 c6 Nf3 Nf6 Nc3 dxc4",
+    "d4 Nf6 c4 g6 Nc3 Bg7 e4 d6",
+    "d4 Nf6 c4 e6 Nc3 Bb4 e3 O-O",
+    "d4 Nf6 c4 e6 Nf3 b6 g3 Bb7",
+    "d4 d5 Nf3 Nf6 Bf4 e6 e3 c5",
+    "c4 e5 Nc3 Nf6 Nf3 Nc6 g3 d5",
+    "Nf3 d5 g3 Nf6 Bg2 c6 O-O Bg4",
+];
+
+/// Positions reached within the first `max_plies` moves of the popular lines,
+/// including the starting position, without duplicates.
+pub fn opening_positions(max_plies: usize) -> Vec<Position> {
+    let mut seen = HashSet::new();
+    let mut positions = Vec::new();
+
+    for line in POPULAR_LINES {
+        let mut board = Position::default();
+        if seen.insert(board.zobrist_hash()) {
+            positions.push(board.clone());
+        }
+        for san in line.split_whitespace().take(max_plies) {
+            match parse_move(&board, san) {
+                Ok(mv) => board = board.play(mv),
+                Err(e) => {
+                    warn!(line = line, san = san, error = %e, "Invalid move in warmup line");
+                    break;
+                }
+            }
+            if seen.insert(board.zobrist_hash()) {
+                positions.push(board.clone());
+            }
+        }
+    }
+
+    positions
+}
+
+/// Renders the opening positions with default chat settings, oriented the way a game
+/// shows them by default. Returns how many positions were rendered.
+pub async fn warm_cache(max_plies: usize, encoding: ImageEncoding) -> Result<usize> {
+    let positions = opening_positions(max_plies);
+    for board in &positions {
+        let options = RenderOptions {
+            flip_board: board.side_to_move() == Color::Black,
+            encoding,
+            ..RenderOptions::default()
+        };
+        render_board(board, &options).await?;
+    }
+    info!(count = positions.len(), max_plies = max_plies, "Image cache warmed");
+    Ok(positions.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_all_lines_are_legal() {
+        for line in POPULAR_LINES {
+            let mut board = Position::default();
+            for san in line.split_whitespace() {
+                let mv = parse_move(&board, san).unwrap_or_else(|_| panic!("{san} in {line}"));
+                board = board.play(mv);
+            }
+        }
+    }
+
+    #[test]
+    fn test_opening_positions_are_unique() {
+        let positions = opening_positions(2);
+        let unique: HashSet<u64> = positions.iter().map(|b| b.zobrist_hash()).collect();
+        assert_eq!(unique.len(), positions.len());
+        assert_eq!(positions[0], Position::default());
+    }
+}
@@ -0,0 +1,84 @@
+use crate::models::Message;
+use crate::responder::locale;
+use crate::{db, game, AppState};
+use anyhow::Result;
+use chrono::{DateTime, Datelike, Duration, NaiveDate, Utc};
+use std::sync::Arc;
+
+/// Weeks shown on `/activity`, the current one included.
+const WEEKS: i64 = 12;
+
+/// Monday of the first of the `weeks` weeks that end with the one containing `today`.
+fn first_monday(today: NaiveDate, weeks: i64) -> NaiveDate {
+    today - Duration::days(today.weekday().num_days_from_monday() as i64) - Duration::weeks(weeks - 1)
+}
+
+/// Games started in each of the `weeks` weeks up to the one containing `today`, oldest
+/// first, keyed by the Monday the week starts on.
+fn weekly_counts(starts: &[DateTime<Utc>], today: NaiveDate, weeks: i64) -> Vec<(NaiveDate, i64)> {
+    let first = first_monday(today, weeks);
+    let mut counts: Vec<(NaiveDate, i64)> = (0..weeks).map(|week| (first + Duration::weeks(week), 0)).collect();
+    for start in starts {
+        let week = (start.date_naive() - first).num_days().div_euclid(7);
+        if let Some((_, count)) = usize::try_from(week).ok().and_then(|week| counts.get_mut(week)) {
+            *count += 1;
+        }
+    }
+    counts
+}
+
+/// `/activity`: a bar chart of the games started in the chat each week over the last
+/// few months.
+pub async fn handle_activity(state: Arc<AppState>, message: &Message) -> Result<()> {
+    let chat_id = message.chat.id;
+    let locale = locale(message);
+    let responder = &state.responder;
+    let weeks = WEEKS.to_string();
+    let today = Utc::now().date_naive();
+    let since = first_monday(today, WEEKS).and_hms_opt(0, 0, 0).unwrap_or_default().and_utc();
+    let starts = db::get_chat_game_starts(&state.db, chat_id, since).await?;
+    if starts.is_empty() {
+        responder.reply(message, "activity.none", &[("weeks", &weeks)]).await?;
+        return Ok(());
+    }
+
+    let bars: Vec<(String, i64)> = weekly_counts(&starts, today, WEEKS)
+        .into_iter()
+        .map(|(monday, count)| (monday.format("%d.%m").to_string(), count))
+        .collect();
+    let title = responder.text(chat_id, locale, "activity.title", &[]);
+    let image = game::render_bar_chart(&title, &bars, state.image_encoding).await?;
+    let caption = responder.text(
+        chat_id,
+        locale,
+        "activity.caption",
+        &[("total", &starts.len().to_string()), ("weeks", &weeks)],
+    );
+    state
+        .telegram
+        .send_photo(chat_id, Some(message.message_id), &caption, image, state.image_encoding.format, None)
+        .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(date: &str) -> DateTime<Utc> {
+        format!("{date}T12:00:00Z").parse().unwrap()
+    }
+
+    #[test]
+    fn test_weekly_counts() {
+        // 2024-05-15 is a Wednesday; its week starts on Monday the 13th
+        let today = NaiveDate::from_ymd_opt(2024, 5, 15).unwrap();
+        let starts = [at("2024-05-13"), at("2024-05-15"), at("2024-05-12"), at("2024-04-29"), at("2024-04-01")];
+        let date = |month: u32, day: u32| NaiveDate::from_ymd_opt(2024, month, day).unwrap();
+        assert_eq!(
+            weekly_counts(&starts, today, 3),
+            vec![(date(4, 29), 1), (date(5, 6), 1), (date(5, 13), 2)]
+        );
+        assert_eq!(first_monday(today, WEEKS), date(2, 26));
+    }
+}
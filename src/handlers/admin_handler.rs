@@ -0,0 +1,334 @@
+use crate::models::{DbUser, Message, User};
+use crate::responder::locale;
+use crate::utils::escape_html;
+use crate::{db, AppState};
+use anyhow::Result;
+use chrono::{Duration as ChronoDuration, Utc};
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{info, warn};
+
+/// Chats listed by /botstats.
+const BOTSTATS_CHATS: usize = 10;
+
+/// Pause between broadcast messages, keeping well under Telegram's ~30 messages per second.
+const BROADCAST_DELAY: Duration = Duration::from_millis(50);
+
+/// Telegram ids allowed to run bot-wide maintenance commands, from `BOT_ADMINS`
+/// (comma-separated).
+pub(crate) fn is_admin(user_id: i64) -> bool {
+    std::env::var("BOT_ADMINS")
+        .map(|ids| parse_admin_ids(&ids).contains(&user_id))
+        .unwrap_or(false)
+}
+
+fn parse_admin_ids(value: &str) -> Vec<i64> {
+    value
+        .split(',')
+        .filter_map(|id| id.trim().parse().ok())
+        .collect()
+}
+
+/// `/merge <from> <into>`: folds a duplicate user row into another. Each side is an
+/// @username or a numeric Telegram id.
+pub async fn handle_merge(state: Arc<AppState>, message: &Message, from: &User, text: &str) -> Result<()> {
+    let chat_id = message.chat.id;
+    if !is_admin(from.id) {
+        return Ok(());
+    }
+
+    let args: Vec<&str> = text.split_whitespace().skip(1).collect();
+    let responder = &state.responder;
+    let locale = locale(message);
+    let not_found = |arg: &str| {
+        responder.text(chat_id, locale, "merge.not_found", &[("user", &escape_html(arg))])
+    };
+    let response = match args.as_slice() {
+        [source, target] => match (find_user(&state, source).await, find_user(&state, target).await) {
+            (Some(source), Some(target)) => {
+                match db::merge_users(&state.db, source.id, target.id).await {
+                    Ok(merged) => {
+                        state.users.clear();
+                        info!(from_id = source.id, into_id = merged.id, "Merged users");
+                        responder.text(
+                            chat_id,
+                            locale,
+                            "merge.done",
+                            &[
+                                ("from", &source.mention_html()),
+                                ("into", &merged.mention_html()),
+                                ("wins", &merged.wins.to_string()),
+                                ("losses", &merged.losses.to_string()),
+                                ("draws", &merged.draws.to_string()),
+                            ],
+                        )
+                    }
+                    Err(e) => responder.text(
+                        chat_id,
+                        locale,
+                        "merge.failed",
+                        &[("error", &escape_html(&e.to_string()))],
+                    ),
+                }
+            }
+            (None, _) => not_found(source),
+            (_, None) => not_found(target),
+        },
+        _ => responder.text(chat_id, locale, "merge.usage", &[]),
+    };
+
+    responder.send_text(chat_id, Some(message.message_id), &response).await?;
+
+    Ok(())
+}
+
+/// `/broadcast <text>`: sends an announcement to every chat with a game in the last
+/// `BROADCAST_ACTIVE_DAYS` days (default 30), except chats that turned announcements off.
+/// Messages go out in the background; the admin gets a summary when they are done.
+pub async fn handle_broadcast(state: Arc<AppState>, message: &Message, from: &User, text: &str) -> Result<()> {
+    if !is_admin(from.id) {
+        return Ok(());
+    }
+
+    let announcement = text
+        .trim()
+        .split_once(char::is_whitespace)
+        .map_or("", |(_, rest)| rest.trim());
+    if announcement.is_empty() {
+        state.responder.reply(message, "broadcast.usage", &[]).await?;
+        return Ok(());
+    }
+
+    let days = std::env::var("BROADCAST_ACTIVE_DAYS")
+        .ok()
+        .and_then(|v| v.parse::<i64>().ok())
+        .filter(|n| *n > 0)
+        .unwrap_or(30);
+    let chats = db::get_active_chats(&state.db, Utc::now() - ChronoDuration::days(days)).await?;
+    state
+        .responder
+        .reply(message, "broadcast.started", &[("count", &chats.len().to_string())])
+        .await?;
+    info!(admin_id = from.id, chats = chats.len(), "Broadcast started");
+
+    let announcement = escape_html(announcement);
+    let (admin_chat, reply_to) = (message.chat.id, message.message_id);
+    let locale = locale(message).map(String::from);
+    tokio::spawn(async move {
+        let mut sent = 0;
+        for chat_id in &chats {
+            let text = state
+                .responder
+                .text(*chat_id, None, "broadcast.message", &[("text", &announcement)]);
+            match state.responder.send_text(*chat_id, None, &text).await {
+                Ok(_) => sent += 1,
+                Err(e) => warn!(chat_id = chat_id, error = %e, "Failed to deliver broadcast"),
+            }
+            tokio::time::sleep(BROADCAST_DELAY).await;
+        }
+        let failed = chats.len() - sent;
+        info!(sent = sent, failed = failed, "Broadcast finished");
+
+        let (sent, failed) = (sent.to_string(), failed.to_string());
+        if let Err(e) = state
+            .responder
+            .send(
+                admin_chat,
+                reply_to,
+                locale.as_deref(),
+                "broadcast.done",
+                &[("sent", &sent), ("failed", &failed)],
+            )
+            .await
+        {
+            warn!(error = %e, "Failed to report broadcast result");
+        }
+    });
+
+    Ok(())
+}
+
+/// `/maintenance [on|off]`: pauses or resumes gameplay, e.g. around a deploy with
+/// migrations. Without an argument it reports the current state.
+pub async fn handle_maintenance(state: Arc<AppState>, message: &Message, from: &User, text: &str) -> Result<()> {
+    if !is_admin(from.id) {
+        return Ok(());
+    }
+
+    let enabled = match text.split_whitespace().nth(1).map(str::to_ascii_lowercase).as_deref() {
+        Some("on") => true,
+        Some("off") => false,
+        Some(_) => {
+            state.responder.reply(message, "maintenance.usage", &[]).await?;
+            return Ok(());
+        }
+        None => state.maintenance.load(Ordering::Relaxed),
+    };
+    if state.maintenance.swap(enabled, Ordering::Relaxed) != enabled {
+        info!(admin_id = from.id, enabled = enabled, "Maintenance mode changed");
+    }
+
+    let id = if enabled { "maintenance.on" } else { "maintenance.off" };
+    state.responder.reply(message, id, &[]).await?;
+    Ok(())
+}
+
+/// `/botstats`: command counts and invalid-move rates of the busiest chats since startup.
+pub async fn handle_botstats(state: Arc<AppState>, message: &Message, from: &User) -> Result<()> {
+    if !is_admin(from.id) {
+        return Ok(());
+    }
+
+    let chat_id = message.chat.id;
+    let responder = &state.responder;
+    let top = state.metrics.top_chats(BOTSTATS_CHATS);
+    let lines = top
+        .iter()
+        .map(|(id, usage)| {
+            let commands = usage
+                .commands
+                .iter()
+                .map(|(command, count)| format!("{} {}", command, count))
+                .collect::<Vec<_>>()
+                .join(", ");
+            responder.text(
+                chat_id,
+                locale(message),
+                "botstats.line",
+                &[
+                    ("chat", &id.to_string()),
+                    ("total", &usage.total_commands().to_string()),
+                    ("commands", &commands),
+                    ("moves", &usage.moves.to_string()),
+                    ("invalid", &usage.invalid_move_percent().to_string()),
+                ],
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    let since = state.metrics.started_at.format("%Y-%m-%d %H:%M UTC").to_string();
+    let id = if top.is_empty() { "botstats.empty" } else { "botstats.list" };
+    responder.reply(message, id, &[("since", &since), ("chats", &lines)]).await?;
+    Ok(())
+}
+
+/// `/feature [<name> on|off|reset [global]]`: switches one of `db::FEATURES` for this chat
+/// or, with `global`, for every chat. Without arguments it lists the flags in effect here.
+pub async fn handle_feature(state: Arc<AppState>, message: &Message, from: &User, text: &str) -> Result<()> {
+    if !is_admin(from.id) {
+        return Ok(());
+    }
+
+    let chat_id = message.chat.id;
+    let responder = &state.responder;
+    let locale = locale(message);
+    let args: Vec<String> = text.split_whitespace().skip(1).map(str::to_ascii_lowercase).collect();
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    let features = db::FEATURES.iter().map(|(name, _)| *name).collect::<Vec<_>>().join(", ");
+
+    let (name, value, global) = match args.as_slice() {
+        [] => {
+            let flags = db::get_feature_flags(&state.db, chat_id).await?;
+            if flags.is_empty() {
+                responder.reply(message, "feature.none", &[]).await?;
+                return Ok(());
+            }
+            let lines = flags
+                .iter()
+                .map(|(name, global, enabled)| {
+                    let id = if *enabled { "settings.on" } else { "settings.off" };
+                    let scope = if *global { "feature.scope_global" } else { "feature.scope_chat" };
+                    responder.text(
+                        chat_id,
+                        locale,
+                        "feature.line",
+                        &[
+                            ("name", name.as_str()),
+                            ("value", &responder.text(chat_id, locale, id, &[])),
+                            ("scope", &responder.text(chat_id, locale, scope, &[])),
+                        ],
+                    )
+                })
+                .collect::<Vec<_>>()
+                .join("\n");
+            responder.reply(message, "feature.list", &[("flags", &lines)]).await?;
+            return Ok(());
+        }
+        [name, value] => (*name, *value, false),
+        [name, value, "global"] => (*name, *value, true),
+        _ => {
+            responder.reply(message, "feature.usage", &[("features", &features)]).await?;
+            return Ok(());
+        }
+    };
+
+    let enabled = match value {
+        "on" => Some(true),
+        "off" => Some(false),
+        "reset" => None,
+        _ => {
+            responder.reply(message, "feature.usage", &[("features", &features)]).await?;
+            return Ok(());
+        }
+    };
+    if !is_feature(name) {
+        responder.reply(message, "feature.usage", &[("features", &features)]).await?;
+        return Ok(());
+    }
+
+    db::set_feature_flag(&state.db, name, (!global).then_some(chat_id), enabled).await?;
+    info!(admin_id = from.id, flag = name, enabled = ?enabled, global = global, "Feature flag changed");
+
+    let scope = if global { "feature.scope_global" } else { "feature.scope_chat" };
+    let value = match enabled {
+        Some(true) => "settings.on",
+        Some(false) => "settings.off",
+        None => "feature.reset",
+    };
+    responder
+        .reply(
+            message,
+            "feature.set",
+            &[
+                ("name", name),
+                ("value", &responder.text(chat_id, locale, value, &[])),
+                ("scope", &responder.text(chat_id, locale, scope, &[])),
+            ],
+        )
+        .await?;
+    Ok(())
+}
+
+fn is_feature(name: &str) -> bool {
+    db::FEATURES.iter().any(|(feature, _)| *feature == name)
+}
+
+pub(super) async fn find_user(state: &AppState, arg: &str) -> Option<DbUser> {
+    match arg.strip_prefix('@') {
+        Some(username) => db::get_user_by_username(&state.db, username).await.ok(),
+        None => {
+            let telegram_id = arg.parse().ok()?;
+            db::get_user_by_telegram_id(&state.db, telegram_id).await.ok()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_feature() {
+        assert!(is_feature("engine"));
+        assert!(!is_feature("engine_play"));
+        assert!(!is_feature(""));
+    }
+
+    #[test]
+    fn test_parse_admin_ids() {
+        assert_eq!(parse_admin_ids("1, 22 ,abc,,-5"), vec![1, 22, -5]);
+        assert!(parse_admin_ids("").is_empty());
+    }
+}
@@ -0,0 +1,118 @@
+use super::admin_handler::{find_user, is_admin};
+use crate::db::{self, AuditFilter};
+use crate::models::{AuditEntry, Message, User};
+use crate::responder::locale;
+use crate::utils::escape_html;
+use crate::AppState;
+use anyhow::Result;
+use std::sync::Arc;
+use tracing::warn;
+
+const AUDIT_PAGE_SIZE: i64 = 20;
+/// Longest command text stored; moves and commands are far shorter, pasted text isn't.
+const MAX_COMMAND_LEN: usize = 200;
+
+/// Appends an entry to the audit log. A failed write is only logged, so auditing never
+/// breaks the command it describes.
+pub(super) async fn record(
+    state: &AppState,
+    telegram_id: Option<i64>,
+    chat_id: i64,
+    game_id: Option<i64>,
+    command: &str,
+    outcome: &str,
+) {
+    let command: String = command.trim().chars().take(MAX_COMMAND_LEN).collect();
+    if let Err(e) = db::insert_audit_entry(&state.db, telegram_id, chat_id, game_id, &command, outcome).await {
+        warn!(chat_id = chat_id, error = %e, "Failed to write audit entry");
+    }
+}
+
+/// `/audit [game <id> | user <@name|id>]`: the latest audit entries of this chat, a game
+/// or a user.
+pub async fn handle_audit(state: Arc<AppState>, message: &Message, from: &User, text: &str) -> Result<()> {
+    if !is_admin(from.id) {
+        return Ok(());
+    }
+
+    let chat_id = message.chat.id;
+    let responder = &state.responder;
+    let args: Vec<&str> = text.split_whitespace().skip(1).collect();
+    let filter = match args.as_slice() {
+        [] => AuditFilter::Chat(chat_id),
+        ["game", id] => match id.parse() {
+            Ok(id) => AuditFilter::Game(id),
+            Err(_) => {
+                responder.reply(message, "audit.usage", &[]).await?;
+                return Ok(());
+            }
+        },
+        ["user", user] => match find_user(&state, user).await.and_then(|u| u.telegram_id) {
+            Some(telegram_id) => AuditFilter::User(telegram_id),
+            None => {
+                responder
+                    .reply(message, "merge.not_found", &[("user", &escape_html(user))])
+                    .await?;
+                return Ok(());
+            }
+        },
+        _ => {
+            responder.reply(message, "audit.usage", &[]).await?;
+            return Ok(());
+        }
+    };
+
+    let entries = db::get_audit_log(&state.db, filter, AUDIT_PAGE_SIZE).await?;
+    if entries.is_empty() {
+        responder.reply(message, "audit.none", &[]).await?;
+        return Ok(());
+    }
+
+    // Oldest first, so the list reads in the order things happened
+    let lines = entries
+        .iter()
+        .rev()
+        .map(|entry| {
+            responder.text(
+                chat_id,
+                locale(message),
+                "audit.line",
+                &[
+                    ("time", format_time(&entry.created_at)),
+                    ("user", &actor(entry)),
+                    ("chat", &entry.chat_id.to_string()),
+                    ("game", &entry.game_id.map(|id| format!(" #{}", id)).unwrap_or_default()),
+                    ("command", &escape_html(&entry.command)),
+                    ("outcome", &escape_html(&entry.outcome)),
+                ],
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+    responder.reply(message, "audit.list", &[("entries", &lines)]).await?;
+    Ok(())
+}
+
+fn actor(entry: &AuditEntry) -> String {
+    match (&entry.username, entry.telegram_id) {
+        (Some(username), _) => format!("@{}", escape_html(username)),
+        (None, Some(telegram_id)) => telegram_id.to_string(),
+        (None, None) => "bot".to_string(),
+    }
+}
+
+/// Drops the fractional seconds and UTC offset of a stored timestamp.
+fn format_time(created_at: &str) -> &str {
+    created_at.get(..19).unwrap_or(created_at)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_time() {
+        assert_eq!(format_time("2026-01-05T12:00:00.123456+00:00"), "2026-01-05T12:00:00");
+        assert_eq!(format_time("bad"), "bad");
+    }
+}
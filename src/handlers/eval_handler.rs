@@ -0,0 +1,141 @@
+use crate::engine::SearchLimit;
+use crate::error::KamaError;
+use crate::game::Position;
+use crate::models::{GameStatus, Message, User};
+use crate::responder::locale;
+use crate::{db, AppState};
+use anyhow::Result;
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+use tracing::warn;
+
+const DEFAULT_EVAL_DEPTH: u32 = 12;
+const DEFAULT_EVAL_COOLDOWN_SECS: u64 = 30;
+
+/// Last /eval per Telegram user id.
+static LAST_EVAL: OnceLock<Mutex<HashMap<i64, Instant>>> = OnceLock::new();
+
+/// `/eval` in reply to a board: a depth-limited engine score of the current position.
+/// Bot admins can switch it off per chat with `/feature engine off`.
+pub async fn handle_eval(state: Arc<AppState>, message: &Message, from: &User) -> Result<()> {
+    let chat_id = message.chat.id;
+
+    let Some(engine) = &state.engine else {
+        state.responder.reply(message, "eval.no_engine", &[]).await?;
+        return Ok(());
+    };
+    if !db::is_feature_enabled(&state.db, "engine", chat_id).await? {
+        state.responder.reply(message, "eval.disabled", &[]).await?;
+        return Ok(());
+    }
+
+    let Some(reply_id) = message.reply_to_message.as_ref().map(|msg| msg.message_id) else {
+        return Ok(());
+    };
+    let Some(game) = db::find_game_by_message(&state.db, chat_id, reply_id).await? else {
+        return Ok(());
+    };
+
+    let player = state.users.upsert(&state.db, from).await?;
+    if game.status == GameStatus::Ongoing && (player.id == game.white_user_id || player.id == game.black_user_id)
+    {
+        state.responder.reply(message, "eval.players_only", &[]).await?;
+        return Ok(());
+    }
+
+    let cooldown = Duration::from_secs(env_number("EVAL_COOLDOWN_SECS", DEFAULT_EVAL_COOLDOWN_SECS));
+    let wait = {
+        let mut last = LAST_EVAL.get_or_init(Default::default).lock().unwrap();
+        take_cooldown(&mut last, from.id, Instant::now(), cooldown)
+    };
+    if let Some(wait) = wait {
+        let seconds = wait.as_secs().max(1).to_string();
+        state
+            .responder
+            .reply(message, "eval.cooldown", &[("seconds", &seconds)])
+            .await?;
+        return Ok(());
+    }
+
+    let board = Position::from_str(&game.current_fen)?;
+    let depth = env_number("EVAL_DEPTH", DEFAULT_EVAL_DEPTH as u64) as u32;
+    let responder = &state.responder;
+    let locale = locale(message);
+    let text = match engine.analyse(&game.current_fen, SearchLimit::Depth(depth)).await {
+        Ok(analysis) => match analysis.score {
+            Some(score) => responder.text(
+                chat_id,
+                locale,
+                "eval.result",
+                &[
+                    ("score", &score.for_white(board.side_to_move()).to_string()),
+                    ("depth", &analysis.depth.to_string()),
+                ],
+            ),
+            None => responder.text(chat_id, locale, "eval.no_score", &[]),
+        },
+        Err(e) => {
+            warn!(chat_id = chat_id, game_id = game.id, error = %e, "Engine evaluation failed");
+            let reason = match &e {
+                KamaError::Engine(reason) => reason.clone(),
+                other => other.user_message(),
+            };
+            responder.text(
+                chat_id,
+                locale,
+                "eval.failed",
+                &[("reason", &crate::utils::escape_html(&reason))],
+            )
+        }
+    };
+
+    responder.send_text(chat_id, Some(message.message_id), &text).await?;
+
+    Ok(())
+}
+
+pub(super) fn env_number(name: &str, default: u64) -> u64 {
+    std::env::var(name)
+        .ok()
+        .and_then(|v| v.parse::<u64>().ok())
+        .filter(|n| *n > 0)
+        .unwrap_or(default)
+}
+
+/// Records a use (an eval, an export) by `user_id` unless they are still cooling down, in which case the
+/// remaining wait is returned. Expired entries are dropped on the way.
+pub(super) fn take_cooldown(
+    last: &mut HashMap<i64, Instant>,
+    user_id: i64,
+    now: Instant,
+    cooldown: Duration,
+) -> Option<Duration> {
+    last.retain(|_, at| now.duration_since(*at) < cooldown);
+    if let Some(at) = last.get(&user_id) {
+        return Some(cooldown - now.duration_since(*at));
+    }
+    last.insert(user_id, now);
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_take_cooldown() {
+        let mut last = HashMap::new();
+        let start = Instant::now();
+        let cooldown = Duration::from_secs(30);
+
+        assert_eq!(take_cooldown(&mut last, 1, start, cooldown), None);
+        assert_eq!(
+            take_cooldown(&mut last, 1, start + Duration::from_secs(10), cooldown),
+            Some(Duration::from_secs(20))
+        );
+        assert_eq!(take_cooldown(&mut last, 2, start, cooldown), None);
+        assert_eq!(take_cooldown(&mut last, 1, start + cooldown, cooldown), None);
+    }
+}
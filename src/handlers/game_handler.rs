@@ -1,11 +1,18 @@
-use crate::models::{Message, User, UserRef};
+use crate::models::{
+    CallbackQuery, ChatSettings, GameResult, GameRow, GameStatus, InlineKeyboardButton,
+    InlineKeyboardMarkup, Message, Rivalry, Turn, User, UserRef,
+};
+use crate::error::KamaError;
+use crate::game::{Color, Position};
+use crate::live::{Clocks, LiveEvent};
+use crate::responder::{locale, Responder, DRAW_TEMPLATE, WIN_TEMPLATE};
+use crate::utils::{escape_html, lichess_analysis_url, split_message, MAX_CAPTION_LEN};
 use crate::{db, game, parsing, AppState};
 use anyhow::{anyhow, Result};
-use chess::Board;
-use chess::Color;
+use super::{audit_handler, prediction_handler, rank_handler, settings_handler, tap_handler};
 use std::str::FromStr;
 use std::sync::Arc;
-use tracing::{error, info, warn};
+use tracing::{debug, error, info, warn};
 
 pub async fn handle_start_game(
     state: Arc<AppState>,
@@ -18,120 +25,228 @@ pub async fn handle_start_game(
     let opponent_ref = match determine_opponent(message, text) {
         Ok(opponent) => opponent,
         Err(_) => {
-            state
-                .telegram
-                .send_message(
-                    chat_id,
-                    message.message_id,
-                    "Reply to a user's message or use /start @username [move].",
-                )
-                .await?;
+            state.responder.reply(message, "start.usage", &[]).await?;
             return Ok(());
         }
     };
 
-    let white = db::upsert_user(&state.db, from).await?;
+    let white = state.users.upsert(&state.db, from).await?;
     let black = match opponent_ref {
-        UserRef::Telegram(user) => db::upsert_user(&state.db, &user).await?,
-        UserRef::Username(username) => db::upsert_user_by_username(&state.db, &username).await?,
+        UserRef::Telegram(user) => state.users.upsert(&state.db, &user).await?,
+        UserRef::Username(username) => match resolve_username(&state, chat_id, &username).await {
+            Some(user) => state.users.upsert(&state.db, &user).await?,
+            None => state.users.upsert_by_username(&state.db, &username).await?,
+        },
     };
 
     if white.id == black.id {
-        state
-            .telegram
-            .send_message(
-                chat_id,
-                message.message_id,
-                "You cannot play against yourself.",
-            )
-            .await?;
+        state.responder.reply(message, "start.self_play", &[]).await?;
         return Ok(());
     }
 
-    if db::find_ongoing_game(&state.db, chat_id, white.id, black.id)
-        .await?
-        .is_some()
-    {
-        state
-            .telegram
-            .send_message(
-                chat_id,
-                message.message_id,
-                "There is already an ongoing game between these players in this chat.",
-            )
-            .await?;
+    if let Some(refusal) = game_limit_reached(&state, chat_id, white.id, black.id).await? {
+        state.responder.reply(message, refusal, &[]).await?;
         return Ok(());
     }
 
-    let mut board = Board::default();
-    let mut initial_move: Option<chess::ChessMove> = None;
+    let mut board = Position::default();
+    let mut initial_move: Option<game::Move> = None;
 
     if let Some(candidate) = parsing::extract_move(text) {
         let before_fen = board.to_string();
-        let mv = game::parse_move(&board, &candidate)?;
-        board = board.make_move_new(mv);
+        let settings = db::get_chat_settings(&state.db, chat_id).await?;
+        let mv = if settings.strict_notation {
+            game::parse_move_strict(&board, &candidate)?
+        } else {
+            game::parse_move(&board, &candidate)?
+        };
+        let uci = Position::default().uci(mv);
+        board = board.play(mv);
         initial_move = Some(mv);
-        let uci = game::uci_string(mv);
         let after_fen = board.to_string();
         info!(
             chat_id = chat_id,
             player_id = white.id,
             move_text = candidate.as_str(),
             uci = uci.as_str(),
-            from = %mv.get_source(),
-            to = %mv.get_dest(),
+            from = ?mv.from(),
+            to = %mv.to(),
             fen_before = %before_fen,
             fen_after = %after_fen,
             "Initial move applied"
         );
     }
 
+    let confirm_moves = parsing::has_option(text, "confirm");
+    begin_game(
+        &state,
+        chat_id,
+        None,
+        locale(message),
+        &white,
+        &black,
+        &board,
+        initial_move,
+        confirm_moves,
+    )
+    .await
+}
+
+/// Reads a limit (running games, moves between draw offers) from the environment; 0 turns
+/// it off.
+fn game_limit_from_env(var: &str, default: i64) -> i64 {
+    std::env::var(var)
+        .ok()
+        .and_then(|v| v.parse::<i64>().ok())
+        .filter(|n| *n >= 0)
+        .unwrap_or(default)
+}
+
+/// The template refusing a new game between the two players in the chat, if it would go
+/// over the chat's cap (`/settings maxgames`, else `MAX_CHAT_GAMES`, off by default) or the
+/// cap per pair of players (`MAX_PAIR_GAMES`, default 1).
+pub(super) async fn game_limit_reached(
+    state: &AppState,
+    chat_id: i64,
+    user_a: i64,
+    user_b: i64,
+) -> Result<Option<&'static str>> {
+    let settings = db::get_chat_settings(&state.db, chat_id).await?;
+    let chat_limit = settings
+        .max_games
+        .unwrap_or_else(|| game_limit_from_env("MAX_CHAT_GAMES", 0));
+    let pair_limit = game_limit_from_env("MAX_PAIR_GAMES", 1);
+    let (total, pair) = db::count_ongoing_games(&state.db, chat_id, user_a, user_b).await?;
+    Ok(if pair_limit > 0 && pair >= pair_limit {
+        Some("start.already_ongoing")
+    } else if chat_limit > 0 && total >= chat_limit {
+        Some("start.chat_limit")
+    } else {
+        None
+    })
+}
+
+/// Creates the game and posts its first board. `initial_move`, if any, has already been
+/// played on `board`. With `peer_chat_id` the game is played over two private chats,
+/// White's being `chat_id`.
+#[allow(clippy::too_many_arguments)]
+pub(super) async fn begin_game(
+    state: &Arc<AppState>,
+    chat_id: i64,
+    peer_chat_id: Option<i64>,
+    locale: Option<&str>,
+    white: &crate::models::DbUser,
+    black: &crate::models::DbUser,
+    board: &Position,
+    initial_move: Option<game::Move>,
+    confirm_moves: bool,
+) -> Result<()> {
     let game_id = db::create_game(
         &state.db,
         chat_id,
         white.id,
         black.id,
         &board.to_string(),
-        game::color_to_turn(board.side_to_move()),
+        Turn::from(board.side_to_move()),
     )
     .await?;
 
+    if confirm_moves {
+        db::set_confirm_moves(&state.db, game_id, true).await?;
+    }
+    if let Some(peer) = peer_chat_id {
+        db::set_game_peer_chat(&state.db, game_id, peer).await?;
+    }
+    let players = format!("{} vs {}", white.display_name(), black.display_name());
+    audit_handler::record(state, None, chat_id, Some(game_id), "game.start", &players).await;
+
     if let Some(mv) = initial_move {
-        let san = game::move_to_san(&Board::default(), mv);
+        let start = Position::default();
+        let san = game::move_to_san(&start, mv);
         db::insert_move(
             &state.db,
             game_id,
             white.id,
             1,
-            &game::uci_string(mv),
+            &start.uci(mv),
             Some(&san),
         )
         .await?;
     }
 
-    let message_id = send_board_update(
-        state.clone(),
+    // Queue games are played over private chats, which keep no history of the pair
+    let note = match peer_chat_id {
+        None => rivalry_note(state, chat_id, locale, white, black).await?,
+        Some(_) => None,
+    };
+    let chats: Vec<i64> = std::iter::once(chat_id).chain(peer_chat_id).collect();
+    let message_id = post_game_board(
+        state,
+        game_id,
+        &chats,
         chat_id,
         None,
-        "Game started",
-        &board,
-        &white,
-        &black,
-        None,
-        Some(game_id),
+        locale,
+        "board.started",
+        note.as_deref(),
+        board,
+        white,
+        black,
     )
     .await?;
 
-    db::update_game_message(&state.db, game_id, message_id).await?;
+    // Private-chat games have no spectators to ask
+    if peer_chat_id.is_none() && db::get_chat_settings(&state.db, chat_id).await?.prediction_polls {
+        prediction_handler::open_prediction_poll(state, chat_id, game_id, message_id, white, black).await;
+    }
 
     Ok(())
 }
 
+/// A line for the first board when the players' record in the chat makes the game
+/// special: every tenth game, a level score, or a chance to get even for the last one.
+async fn rivalry_note(
+    state: &AppState,
+    chat_id: i64,
+    locale: Option<&str>,
+    white: &crate::models::DbUser,
+    black: &crate::models::DbUser,
+) -> Result<Option<String>> {
+    let record = db::get_pair_record(&state.db, chat_id, white.id, black.id).await?;
+    let name = |id: i64| if id == white.id { white.display_name() } else { black.display_name() };
+    let (white_name, black_name) = (white.display_name(), black.display_name());
+    let note = match record.rivalry() {
+        Some(Rivalry::Milestone(number)) => state.responder.text(
+            chat_id,
+            locale,
+            "rivalry.milestone",
+            &[("number", &number.to_string()), ("white", &white_name), ("black", &black_name)],
+        ),
+        Some(Rivalry::Tied(wins)) => state.responder.text(
+            chat_id,
+            locale,
+            "rivalry.tied",
+            &[("wins", &wins.to_string()), ("white", &white_name), ("black", &black_name)],
+        ),
+        Some(Rivalry::Revenge { player, opponent }) => state.responder.text(
+            chat_id,
+            locale,
+            "rivalry.revenge",
+            &[("player", &name(player)), ("opponent", &name(opponent))],
+        ),
+        None => return Ok(None),
+    };
+    Ok(Some(note))
+}
+
+/// A reply to the board that may hold a move. `comment` is the player's note from
+/// `/move <move> <comment>`, stored with the move.
 pub async fn handle_move(
     state: Arc<AppState>,
     message: &Message,
     from: &User,
     text: &str,
+    comment: Option<&str>,
 ) -> Result<()> {
     let chat_id = message.chat.id;
 
@@ -141,11 +256,11 @@ pub async fn handle_move(
         .map(|msg| msg.message_id)
         .ok_or_else(|| anyhow!("Move must be a reply to the bot's board message"))?;
 
-    let Some(mut game) = db::find_game_by_message(&state.db, chat_id, reply_id).await? else {
+    let Some(game) = db::find_game_by_message(&state.db, chat_id, reply_id).await? else {
         return Ok(());
     };
 
-    if game.status != "ongoing" {
+    if game.status != GameStatus::Ongoing {
         return Ok(());
     }
 
@@ -155,37 +270,30 @@ pub async fn handle_move(
     };
 
     // Only validate player and turn if they're actually trying to make a move
-    let player = db::upsert_user(&state.db, from).await?;
+    let player = state.users.upsert(&state.db, from).await?;
     if player.id != game.white_user_id && player.id != game.black_user_id {
-        state
-            .telegram
-            .send_message(
-                chat_id,
-                message.message_id,
-                "This game belongs to other players.",
-            )
-            .await?;
+        state.responder.reply(message, "move.other_players", &[]).await?;
         return Ok(());
     }
-
-    let board = Board::from_str(&game.current_fen).map_err(|e| anyhow!("Invalid FEN: {}", e))?;
-    let side_to_move = board.side_to_move();
-    let expected_id = if side_to_move == Color::White {
-        game.white_user_id
-    } else {
-        game.black_user_id
-    };
-
-    if player.id != expected_id {
-        state
-            .telegram
-            .send_message(chat_id, message.message_id, "It is not your turn.")
-            .await?;
+    if db::get_game_pause(&state.db, game.id).await?.0 {
+        state.responder.reply(message, "game.paused", &[]).await?;
         return Ok(());
     }
 
+    let board = Position::from_str(&game.current_fen)?;
+    if player.id != expected_player_id(&game, &board) {
+        return Err(KamaError::NotYourTurn.into());
+    }
+
     let before_fen = board.to_string();
-    let mv = match game::parse_move(&board, &candidate) {
+    let settings = db::get_chat_settings(&state.db, chat_id).await?;
+    let parsed = if settings.strict_notation {
+        game::parse_move_strict(&board, &candidate)
+    } else {
+        game::parse_move(&board, &candidate)
+    };
+    state.metrics.record_move(chat_id, parsed.is_ok());
+    let mv = match parsed {
         Ok(mv) => mv,
         Err(err) => {
             warn!(
@@ -197,102 +305,395 @@ pub async fn handle_move(
                 "Move parse failed: {err:?}"
             );
             state
-                .telegram
-                .send_message(chat_id, message.message_id, &format!("Invalid move: {err}"))
+                .responder
+                .reply(message, "move.invalid", &[("error", &escape_html(&err.to_string()))])
                 .await?;
             return Ok(());
         }
     };
-    let next_board = board.make_move_new(mv);
-    let uci = game::uci_string(mv);
+
+    if game.confirm_moves {
+        return request_move_confirmation(state, message, &game, &board, mv).await;
+    }
+
+    commit_move(
+        state,
+        chat_id,
+        message.message_id,
+        locale(message),
+        game,
+        &player,
+        &board,
+        mv,
+        &candidate,
+        comment,
+    )
+    .await?;
+    Ok(())
+}
+
+pub(super) fn expected_player_id(game: &GameRow, board: &Position) -> i64 {
+    if board.side_to_move() == Color::White {
+        game.white_user_id
+    } else {
+        game.black_user_id
+    }
+}
+
+/// Applies a validated move to the game, records it with the player's comment, and posts
+/// the resulting board (or the game-end message when the move finishes the game).
+/// Returns false, posting nothing, when another move for the same position was recorded
+/// first.
+#[allow(clippy::too_many_arguments)]
+pub(super) async fn commit_move(
+    state: Arc<AppState>,
+    chat_id: i64,
+    reply_to: i64,
+    locale: Option<&str>,
+    mut game: GameRow,
+    player: &crate::models::DbUser,
+    board: &Position,
+    mv: game::Move,
+    move_text: &str,
+    comment: Option<&str>,
+) -> Result<bool> {
+    let side_to_move = board.side_to_move();
+    let before_fen = board.to_string();
+    let next_board = board.play(mv);
+    let uci = board.uci(mv);
     let after_fen = next_board.to_string();
-    let from_sq = mv.get_source();
-    let to_sq = mv.get_dest();
     info!(
         chat_id = chat_id,
         game_id = game.id,
         player_id = player.id,
-        move_text = candidate.as_str(),
+        move_text = move_text,
         uci = uci.as_str(),
-        from = %from_sq,
-        to = %to_sq,
+        from = ?mv.from(),
+        to = %mv.to(),
         fen_before = %before_fen,
         fen_after = %after_fen,
         "Move applied"
     );
 
-    if game.draw_proposed_by.is_some() {
-        db::clear_draw_proposal(&state.db, game.id).await?;
-    }
-
-    let san = game::move_to_san(&board, mv);
-    let move_number = db::next_move_number(&state.db, game.id).await?;
-    db::insert_move(
-        &state.db,
-        game.id,
-        player.id,
-        move_number,
-        &game::uci_string(mv),
-        Some(&san),
-    )
-    .await?;
+    let san = game::move_to_san(board, mv);
+    let en_passant = mv.is_en_passant();
 
-    game.current_fen = next_board.to_string();
-    game.turn = game::color_to_turn(next_board.side_to_move()).to_string();
+    let previous_fen = std::mem::replace(&mut game.current_fen, next_board.to_string());
+    game.turn = Turn::from(next_board.side_to_move());
 
-    let white = db::get_user_by_id(&state.db, game.white_user_id).await?;
-    let black = db::get_user_by_id(&state.db, game.black_user_id).await?;
+    let white = state.users.get_by_id(&state.db, game.white_user_id).await?;
+    let black = state.users.get_by_id(&state.db, game.black_user_id).await?;
 
     let status = next_board.status();
-    let mut result_line = None;
-    let mut game_result: Option<&str> = None;
-
-    if status != chess::BoardStatus::Ongoing {
-        let (status_text, result) = determine_game_result(&status, side_to_move, &white, &black);
-        result_line = Some(status_text);
-        game_result = Some(result);
-        game.status = "finished".to_string();
-        game.result = Some(result.to_string());
-        db::update_game_result(&state.db, game.id, &game.result, &game.status).await?;
-        db::update_player_stats(&state.db, game.white_user_id, game.black_user_id, result).await?;
+    let outcome = determine_game_result(
+        &state.responder,
+        chat_id,
+        locale,
+        &status,
+        side_to_move,
+        &white,
+        &black,
+    );
+    if let Some((_, result, _)) = &outcome {
+        game.status = GameStatus::Finished;
+        game.result = Some(*result);
     }
 
-    db::update_game_fen(&state.db, game.id, &game.current_fen, &game.turn).await?;
+    if !db::record_move(&state.db, &game, &previous_fen, player.id, &uci, &san, comment).await? {
+        info!(chat_id = chat_id, game_id = game.id, uci = uci.as_str(), "Move arrived after the position changed");
+        return Ok(false);
+    }
+    react_to_move(&state, chat_id, reply_to, &status, next_board.is_check()).await;
+    publish_move(&state, &game, &uci, &san).await;
+    if outcome.is_some() {
+        state.users.invalidate(game.white_user_id);
+        state.users.invalidate(game.black_user_id);
+    }
 
     // If game ended, don't send board update - we'll cleanup and send final message instead
-    if status != chess::BoardStatus::Ongoing {
-        cleanup_game_messages(state.clone(), chat_id, game.id).await?;
-        let result_text = result_line.unwrap_or_else(|| "Game ended.".to_string());
-        send_game_end_message(
-            state,
+    if let Some((result_text, result, reason)) = outcome {
+        end_game_in_chats(
+            &state,
+            &game,
             chat_id,
-            message.message_id,
+            reply_to,
+            locale,
             &white,
             &black,
-            game_result.unwrap_or(""),
+            result,
             &result_text,
+            reason,
         )
         .await?;
     } else {
-        let message_id = send_board_update(
-            state.clone(),
+        let header_id = if en_passant {
+            "board.move_played_en_passant"
+        } else {
+            "board.move_played"
+        };
+        // Plain text: the caption escapes its header
+        let note = comment.map(|text| format!("💬 {}: {}", player.display_name(), text));
+        post_game_board(
+            &state,
+            game.id,
+            &game.chats(),
             chat_id,
-            Some(message.message_id),
-            "Move played",
+            Some(reply_to),
+            locale,
+            header_id,
+            note.as_deref(),
             &next_board,
             &white,
             &black,
-            result_line,
-            Some(game.id),
         )
         .await?;
+    }
 
-        db::update_game_message(&state.db, game.id, message_id).await?;
+    Ok(true)
+}
+
+/// Sends a stored move to the live feed. Its ply and the think times are read back from
+/// the database, and failing to do so only costs stream viewers this update.
+async fn publish_move(state: &AppState, game: &GameRow, uci: &str, san: &str) {
+    let stored = async {
+        let ply = db::get_last_move(&state.db, game.id).await?.map_or(0, |(ply, _)| ply);
+        let times = db::get_game_think_times(&state.db, game.id).await?;
+        Ok::<_, KamaError>((ply, times))
+    };
+    let (ply, times) = match stored.await {
+        Ok(stored) => stored,
+        Err(e) => {
+            warn!(game_id = game.id, error = %e, "Failed to publish move to the live feed");
+            return;
+        }
+    };
+    let spent = |user_id| times.get(&user_id).map_or(0, |time| time.total_secs);
+    state.live.publish(LiveEvent::Move {
+        game_id: game.id,
+        ply,
+        san: san.to_string(),
+        uci: uci.to_string(),
+        fen: game.current_fen.clone(),
+        turn: match game.turn {
+            Turn::White => "white",
+            Turn::Black => "black",
+        },
+        clocks: Clocks {
+            white_secs: spent(game.white_user_id),
+            black_secs: spent(game.black_user_id),
+        },
+    });
+}
+
+/// Marks the player's move message with 👍, ⚡ on check or 🏆 on checkmate. Chats can turn
+/// reactions off, so a failure is only logged.
+async fn react_to_move(
+    state: &AppState,
+    chat_id: i64,
+    message_id: i64,
+    status: &game::GameStatus,
+    is_check: bool,
+) {
+    let emoji = match status {
+        game::GameStatus::Checkmate => "🏆",
+        _ if is_check => "⚡",
+        _ => "👍",
+    };
+    if let Err(e) = state
+        .telegram
+        .set_message_reaction(chat_id, message_id, emoji)
+        .await
+    {
+        debug!(chat_id = chat_id, message_id = message_id, error = %e, "Failed to react to move");
     }
+}
+
+/// Echoes a parsed move back with Confirm/Cancel buttons instead of playing it right away.
+/// A newer move from the same player replaces any confirmation still pending.
+async fn request_move_confirmation(
+    state: Arc<AppState>,
+    message: &Message,
+    game: &GameRow,
+    board: &Position,
+    mv: game::Move,
+) -> Result<()> {
+    let chat_id = message.chat.id;
+
+    if let Some(previous_prompt) = game.pending_move_message_id {
+        if let Err(e) = state.telegram.delete_message(chat_id, previous_prompt).await {
+            warn!(
+                chat_id = chat_id,
+                game_id = game.id,
+                message_id = previous_prompt,
+                error = %e,
+                "Failed to delete superseded move confirmation"
+            );
+        }
+    }
+
+    let san = game::move_to_display_san(board, mv);
+    let button = |id: &str| state.responder.text(chat_id, locale(message), id, &[]);
+    let keyboard = InlineKeyboardMarkup {
+        inline_keyboard: vec![vec![
+            InlineKeyboardButton::callback(&button("confirm.button_confirm"), format!("confirm:{}", game.id)),
+            InlineKeyboardButton::callback(&button("confirm.button_cancel"), format!("cancel:{}", game.id)),
+        ]],
+    };
+
+    let prompt_id = state
+        .responder
+        .reply_with_keyboard(message, "confirm.prompt", &[("move", &escape_html(&san))], keyboard)
+        .await?;
+
+    db::set_pending_move(&state.db, game.id, &board.uci(mv), prompt_id).await?;
 
     Ok(())
 }
 
+pub async fn handle_move_confirmation(
+    state: Arc<AppState>,
+    query: &CallbackQuery,
+    game_id: i64,
+    confirmed: bool,
+) -> Result<()> {
+    let Some(prompt) = &query.message else {
+        return Ok(());
+    };
+    let chat_id = prompt.chat.id;
+
+    let pending = match db::get_game_by_id(&state.db, game_id).await? {
+        Some(game) if game.status == GameStatus::Ongoing && game.pending_move_message_id == Some(prompt.message_id) => {
+            game.pending_move.clone().map(|uci| (game, uci))
+        }
+        _ => None,
+    };
+
+    let Some((game, uci)) = pending else {
+        state
+            .responder
+            .answer_callback(query, Some("confirm.not_pending"))
+            .await?;
+        return Ok(());
+    };
+
+    let player = state.users.upsert(&state.db, &query.from).await?;
+    let board = Position::from_str(&game.current_fen)?;
+    if player.id != expected_player_id(&game, &board) {
+        state
+            .responder
+            .answer_callback(query, Some("confirm.wrong_player"))
+            .await?;
+        return Ok(());
+    }
+    if confirmed && db::get_game_pause(&state.db, game.id).await?.0 {
+        state.responder.answer_callback(query, Some("game.paused")).await?;
+        return Ok(());
+    }
+
+    db::clear_pending_move(&state.db, game.id).await?;
+
+    let locale = query.from.language_code.as_deref();
+    if !confirmed {
+        state
+            .responder
+            .edit(chat_id, prompt.message_id, locale, "confirm.cancelled", &[])
+            .await?;
+        state
+            .responder
+            .answer_callback(query, Some("confirm.cancelled"))
+            .await?;
+        return Ok(());
+    }
+
+    let mv = game::parse_move(&board, &uci)?;
+    let san = game::move_to_display_san(&board, mv);
+    state
+        .responder
+        .edit(
+            chat_id,
+            prompt.message_id,
+            locale,
+            "confirm.confirmed",
+            &[("move", &escape_html(&san))],
+        )
+        .await?;
+    state.responder.answer_callback(query, None).await?;
+
+    let reply_to = prompt
+        .reply_to_message
+        .as_ref()
+        .map(|msg| msg.message_id)
+        .unwrap_or(prompt.message_id);
+
+    commit_move(state, chat_id, reply_to, locale, game, &player, &board, mv, &uci, None).await?;
+    Ok(())
+}
+
+pub async fn handle_toggle_confirmation(
+    state: Arc<AppState>,
+    message: &Message,
+    from: &User,
+) -> Result<()> {
+    let chat_id = message.chat.id;
+
+    let reply_id = message
+        .reply_to_message
+        .as_ref()
+        .map(|msg| msg.message_id)
+        .ok_or_else(|| anyhow!("Confirm toggle must be a reply to the bot's board message"))?;
+
+    let Some(game) = db::find_game_by_message(&state.db, chat_id, reply_id).await? else {
+        return Ok(());
+    };
+
+    if game.status != GameStatus::Ongoing {
+        return Ok(());
+    }
+
+    let player = state.users.upsert(&state.db, from).await?;
+    if player.id != game.white_user_id && player.id != game.black_user_id {
+        return Ok(());
+    }
+
+    let enabled = !game.confirm_moves;
+    db::set_confirm_moves(&state.db, game.id, enabled).await?;
+    if !enabled {
+        db::clear_pending_move(&state.db, game.id).await?;
+    }
+
+    let id = if enabled {
+        "confirm.enabled"
+    } else {
+        "confirm.disabled"
+    };
+    state.responder.reply(message, id, &[]).await?;
+
+    Ok(())
+}
+
+/// Asks Telegram who currently owns `@username` and checks they are in this chat.
+/// Returns `None` when Telegram can't tell (the bot has never seen the user), in which
+/// case the caller falls back to a username placeholder.
+async fn resolve_username(state: &AppState, chat_id: i64, username: &str) -> Option<User> {
+    let chat = match state.telegram.get_chat(&format!("@{}", username)).await {
+        Ok(chat) if chat.kind == "private" => chat,
+        Ok(_) => return None,
+        Err(e) => {
+            debug!(username = %username, error = %e, "Could not resolve username");
+            return None;
+        }
+    };
+
+    match state.telegram.get_chat_member(chat_id, chat.id).await {
+        Ok(member) if member.is_present() && !member.user.is_bot => Some(member.user),
+        Ok(_) => None,
+        Err(e) => {
+            debug!(username = %username, user_id = chat.id, error = %e, "Could not check chat membership");
+            None
+        }
+    }
+}
+
 fn determine_opponent(message: &Message, text: &str) -> Result<UserRef> {
     if let Some(reply) = &message.reply_to_message {
         if let Some(opponent) = reply.from.clone() {
@@ -311,31 +712,47 @@ fn determine_opponent(message: &Message, text: &str) -> Result<UserRef> {
     ))
 }
 
+/// The announcement, result and the reason shown on the final image when the game is
+/// over, `None` while it goes on.
+#[allow(clippy::too_many_arguments)]
 fn determine_game_result(
-    status: &chess::BoardStatus,
+    responder: &Responder,
+    chat_id: i64,
+    locale: Option<&str>,
+    status: &game::GameStatus,
     side_to_move: Color,
     white: &crate::models::DbUser,
     black: &crate::models::DbUser,
-) -> (String, &'static str) {
-    match status {
-        chess::BoardStatus::Checkmate => {
-            let winner = if side_to_move == Color::White {
-                black.mention_html()
-            } else {
-                white.mention_html()
-            };
-            (
-                format!("Checkmate. {} wins.", winner),
-                if side_to_move == Color::White {
-                    "0-1"
-                } else {
-                    "1-0"
-                },
-            )
+) -> Option<(String, GameResult, &'static str)> {
+    let mention = |color: Color| match color {
+        Color::White => white.mention_html(),
+        Color::Black => black.mention_html(),
+    };
+    let text = |id: &str, winner: Option<Color>| match winner {
+        Some(color) => responder.text(chat_id, locale, id, &[("winner", &mention(color))]),
+        None => responder.text(chat_id, locale, id, &[]),
+    };
+    Some(match status {
+        game::GameStatus::Checkmate => {
+            let winner = side_to_move.other();
+            (text("result.checkmate", Some(winner)), GameResult::win_for(winner), "Checkmate")
         }
-        chess::BoardStatus::Stalemate => ("Draw by stalemate.".to_string(), "1/2-1/2"),
-        chess::BoardStatus::Ongoing => ("".to_string(), ""),
-    }
+        game::GameStatus::Stalemate => (text("result.stalemate", None), GameResult::Draw, "Stalemate"),
+        game::GameStatus::InsufficientMaterial => (
+            text("result.insufficient_material", None),
+            GameResult::Draw,
+            "Insufficient material",
+        ),
+        game::GameStatus::VariantEnd { winner } => match winner {
+            Some(color) => (
+                text("result.variant_win", Some(*color)),
+                GameResult::win_for(*color),
+                "Variant win",
+            ),
+            None => (text("result.variant_draw", None), GameResult::Draw, "Variant draw"),
+        },
+        game::GameStatus::Ongoing => return None,
+    })
 }
 
 pub async fn handle_resign(state: Arc<AppState>, message: &Message, from: &User) -> Result<()> {
@@ -351,48 +768,130 @@ pub async fn handle_resign(state: Arc<AppState>, message: &Message, from: &User)
         return Ok(());
     };
 
-    if game.status != "ongoing" {
+    if game.status != GameStatus::Ongoing {
         return Ok(());
     }
 
-    let player = db::upsert_user(&state.db, from).await?;
+    let player = state.users.upsert(&state.db, from).await?;
     if player.id != game.white_user_id && player.id != game.black_user_id {
         return Ok(());
     }
 
-    let white = db::get_user_by_id(&state.db, game.white_user_id).await?;
-    let black = db::get_user_by_id(&state.db, game.black_user_id).await?;
+    let white = state.users.get_by_id(&state.db, game.white_user_id).await?;
+    let black = state.users.get_by_id(&state.db, game.black_user_id).await?;
 
     let (winner, loser, result) = if player.id == game.white_user_id {
-        (&black, &white, "0-1")
+        (&black, &white, GameResult::BlackWins)
     } else {
-        (&white, &black, "1-0")
+        (&white, &black, GameResult::WhiteWins)
     };
 
-    db::update_game_result(&state.db, game.id, &Some(result.to_string()), "finished").await?;
-    db::update_player_stats(&state.db, game.white_user_id, game.black_user_id, result).await?;
+    if !db::settle_game(&state.db, &game, result).await? {
+        // A move, a draw or a second resignation ended it first
+        return Ok(());
+    }
+    state.users.invalidate(game.white_user_id);
+    state.users.invalidate(game.black_user_id);
 
-    let result_text = format!(
-        "{} resigned. {} wins.",
-        loser.mention_html(),
-        winner.mention_html()
+    let result_text = state.responder.text(
+        chat_id,
+        locale(message),
+        "result.resigned",
+        &[("loser", &loser.mention_html()), ("winner", &winner.mention_html())],
     );
 
-    cleanup_game_messages(state.clone(), chat_id, game.id).await?;
-    send_game_end_message(
-        state,
+    end_game_in_chats(
+        &state,
+        &game,
         chat_id,
         message.message_id,
+        locale(message),
         &white,
         &black,
         result,
         &result_text,
+        "Resignation",
     )
     .await?;
 
     Ok(())
 }
 
+/// `/forceresult <1-0|0-1|1/2-1/2>` in reply to a board: a chat admin settles a stuck or
+/// disputed game. It ends like any other game, and the audit log keeps who decided it.
+/// Not available in private chats, where the player would be their own admin.
+pub async fn handle_force_result(state: Arc<AppState>, message: &Message, from: &User, text: &str) -> Result<()> {
+    let chat_id = message.chat.id;
+    let responder = &state.responder;
+
+    let Some(reply_id) = message.reply_to_message.as_ref().map(|msg| msg.message_id) else {
+        return Ok(());
+    };
+    let Some(game) = db::find_game_by_message(&state.db, chat_id, reply_id).await? else {
+        return Ok(());
+    };
+    if game.status != GameStatus::Ongoing {
+        return Ok(());
+    }
+
+    if chat_id == from.id || !settings_handler::is_chat_admin(&state, chat_id, from.id).await {
+        responder.reply(message, "forceresult.admins_only", &[]).await?;
+        return Ok(());
+    }
+    let Some(result) = text.split_whitespace().nth(1).and_then(GameResult::parse) else {
+        responder.reply(message, "forceresult.usage", &[]).await?;
+        return Ok(());
+    };
+
+    let admin = state.users.upsert(&state.db, from).await?;
+    let white = state.users.get_by_id(&state.db, game.white_user_id).await?;
+    let black = state.users.get_by_id(&state.db, game.black_user_id).await?;
+
+    if !db::settle_game(&state.db, &game, result).await? {
+        // A move, resignation or another admin ended it first
+        return Ok(());
+    }
+    state.users.invalidate(game.white_user_id);
+    state.users.invalidate(game.black_user_id);
+    let forced_by = format!("{} by {}", result.as_str(), admin.display_name());
+    audit_handler::record(&state, Some(from.id), chat_id, Some(game.id), "game.force_result", &forced_by).await;
+
+    let result_text = responder.text(
+        chat_id,
+        locale(message),
+        "result.forced",
+        &[("admin", &admin.mention_html()), ("result", result.as_str())],
+    );
+    end_game_in_chats(
+        &state,
+        &game,
+        chat_id,
+        message.message_id,
+        locale(message),
+        &white,
+        &black,
+        result,
+        &result_text,
+        "Set by admin",
+    )
+    .await?;
+
+    Ok(())
+}
+
+/// Moves a player makes between two draw offers in the same game unless the chat says
+/// otherwise, so an opponent can't be pestered with one after every move.
+const DEFAULT_DRAW_OFFER_GAP: i64 = 5;
+
+/// How many more of their own moves a player has to make before offering a draw again:
+/// `gap` moves after the offer made at ply `last_offer`, now that `ply` moves are played.
+fn draw_offer_wait(last_offer: Option<i64>, ply: i64, gap: i64) -> Option<i64> {
+    let last_offer = last_offer.filter(|_| gap > 0)?;
+    // Each side makes one of every two plies
+    let remaining = gap - (ply - last_offer) / 2;
+    (remaining > 0).then_some(remaining)
+}
+
 pub async fn handle_draw_proposal(
     state: Arc<AppState>,
     message: &Message,
@@ -410,37 +909,51 @@ pub async fn handle_draw_proposal(
         return Ok(());
     };
 
-    if game.status != "ongoing" {
+    if game.status != GameStatus::Ongoing {
         return Ok(());
     }
 
-    let player = db::upsert_user(&state.db, from).await?;
+    let player = state.users.upsert(&state.db, from).await?;
     if player.id != game.white_user_id && player.id != game.black_user_id {
         return Ok(());
     }
 
-    let white = db::get_user_by_id(&state.db, game.white_user_id).await?;
-    let black = db::get_user_by_id(&state.db, game.black_user_id).await?;
+    let settings = db::get_chat_settings(&state.db, game.chat_id).await?;
+    let gap = settings
+        .draw_offer_gap
+        .unwrap_or_else(|| game_limit_from_env("DRAW_OFFER_GAP", DEFAULT_DRAW_OFFER_GAP));
+    let ply = db::get_last_move(&state.db, game.id).await?.map_or(0, |(ply, _)| ply);
+    let last_offer = db::last_draw_offer_ply(&state.db, game.id, player.id).await?;
+    if let Some(moves) = draw_offer_wait(last_offer, ply, gap) {
+        state
+            .responder
+            .reply(message, "draw.too_soon", &[("moves", &moves.to_string())])
+            .await?;
+        return Ok(());
+    }
+
+    let white = state.users.get_by_id(&state.db, game.white_user_id).await?;
+    let black = state.users.get_by_id(&state.db, game.black_user_id).await?;
     let opponent = if player.id == game.white_user_id {
         &black
     } else {
         &white
     };
 
-    let proposal_message_id = state
-        .telegram
-        .send_message(
-            chat_id,
-            message.message_id,
-            &format!(
-                "{} proposed a draw. {} can accept with /accept or continue playing.",
-                player.mention_html(),
-                opponent.mention_html()
-            ),
-        )
-        .await?;
+    let args = [("player", player.mention_html()), ("opponent", opponent.mention_html())];
+    let args: Vec<(&str, &str)> = args.iter().map(|(k, v)| (*k, v.as_str())).collect();
+    let proposal_message_id = state.responder.reply(message, "draw.proposed", &args).await?;
 
     db::propose_draw(&state.db, game.id, player.id, proposal_message_id).await?;
+    let offered_by = format!("offered by {}", player.display_name());
+    audit_handler::record(&state, None, chat_id, Some(game.id), "game.draw_offer", &offered_by).await;
+
+    // The opponent of a game over private chats can only answer in their own chat
+    for peer in game.chats().into_iter().filter(|&id| id != chat_id) {
+        let text = state.responder.text(peer, None, "draw.proposed", &args);
+        let relayed_id = state.responder.send_text(peer, None, &text).await?;
+        db::insert_game_message(&state.db, game.id, peer, relayed_id).await?;
+    }
 
     Ok(())
 }
@@ -462,70 +975,389 @@ pub async fn handle_accept_draw(
         return Ok(());
     };
 
-    if game.status != "ongoing" {
+    if game.status != GameStatus::Ongoing {
         return Ok(());
     }
 
-    let player = db::upsert_user(&state.db, from).await?;
+    let player = state.users.upsert(&state.db, from).await?;
     if player.id != game.white_user_id && player.id != game.black_user_id {
         return Ok(());
     }
 
     let Some(proposer_id) = game.draw_proposed_by else {
-        state
-            .telegram
-            .send_message(chat_id, message.message_id, "No draw proposal is pending.")
-            .await?;
+        state.responder.reply(message, "draw.none_pending", &[]).await?;
         return Ok(());
     };
 
     if proposer_id == player.id {
-        state
-            .telegram
-            .send_message(
-                chat_id,
-                message.message_id,
-                "You cannot accept your own draw proposal.",
-            )
-            .await?;
+        state.responder.reply(message, "draw.own_proposal", &[]).await?;
         return Ok(());
     }
 
-    let white = db::get_user_by_id(&state.db, game.white_user_id).await?;
-    let black = db::get_user_by_id(&state.db, game.black_user_id).await?;
+    let white = state.users.get_by_id(&state.db, game.white_user_id).await?;
+    let black = state.users.get_by_id(&state.db, game.black_user_id).await?;
 
-    db::update_game_result(&state.db, game.id, &Some("1/2-1/2".to_string()), "finished").await?;
-    db::update_player_stats(&state.db, game.white_user_id, game.black_user_id, "1/2-1/2").await?;
+    if !db::settle_game(&state.db, &game, GameResult::Draw).await? {
+        // A move or resignation ended it first
+        return Ok(());
+    }
+    state.users.invalidate(game.white_user_id);
+    state.users.invalidate(game.black_user_id);
 
-    let result_text = format!("Draw accepted by {}.", player.mention_html());
+    let result_text = state.responder.text(
+        chat_id,
+        locale(message),
+        "result.draw_accepted",
+        &[("player", &player.mention_html())],
+    );
 
-    cleanup_game_messages(state.clone(), chat_id, game.id).await?;
-    send_game_end_message(
-        state,
+    end_game_in_chats(
+        &state,
+        &game,
         chat_id,
         message.message_id,
+        locale(message),
         &white,
         &black,
-        "1/2-1/2",
+        GameResult::Draw,
         &result_text,
+        "Draw agreed",
     )
     .await?;
 
     Ok(())
 }
 
+/// The running game of the board `message` replies to, and the player sending it, when
+/// they play in it.
+async fn replied_game_and_player(
+    state: &AppState,
+    message: &Message,
+    from: &User,
+) -> Result<Option<(GameRow, crate::models::DbUser)>> {
+    let Some(reply_id) = message.reply_to_message.as_ref().map(|msg| msg.message_id) else {
+        return Ok(None);
+    };
+    let Some(game) = db::find_game_by_message(&state.db, message.chat.id, reply_id).await? else {
+        return Ok(None);
+    };
+    if game.status != GameStatus::Ongoing {
+        return Ok(None);
+    }
+    let player = state.users.upsert(&state.db, from).await?;
+    if player.id != game.white_user_id && player.id != game.black_user_id {
+        return Ok(None);
+    }
+    Ok(Some((game, player)))
+}
+
+/// `/pause` in reply to a board. The first player's /pause asks, the opponent's agrees; the
+/// game then takes no moves until either player sends /resume.
+pub async fn handle_pause(state: Arc<AppState>, message: &Message, from: &User) -> Result<()> {
+    let chat_id = message.chat.id;
+    let Some((game, player)) = replied_game_and_player(&state, message, from).await? else {
+        return Ok(());
+    };
+
+    match db::get_game_pause(&state.db, game.id).await? {
+        (true, _) => {
+            state.responder.reply(message, "pause.already_paused", &[]).await?;
+        }
+        (false, Some(requested_by)) if requested_by == player.id => {
+            state.responder.reply(message, "pause.already_requested", &[]).await?;
+        }
+        (false, Some(_)) => {
+            if db::set_game_paused(&state.db, game.id, true).await? {
+                audit_handler::record(&state, Some(from.id), chat_id, Some(game.id), "game.pause", "paused").await;
+                repost_game_board(&state, &game, message, "board.paused").await?;
+            }
+        }
+        (false, None) => {
+            let white = state.users.get_by_id(&state.db, game.white_user_id).await?;
+            let black = state.users.get_by_id(&state.db, game.black_user_id).await?;
+            let opponent = if player.id == game.white_user_id { &black } else { &white };
+            db::request_pause(&state.db, game.id, player.id).await?;
+
+            let args = [("player", player.mention_html()), ("opponent", opponent.mention_html())];
+            let args: Vec<(&str, &str)> = args.iter().map(|(k, v)| (*k, v.as_str())).collect();
+            state.responder.reply(message, "pause.requested", &args).await?;
+            // The opponent of a game over private chats can only answer in their own chat
+            for peer in game.chats().into_iter().filter(|&id| id != chat_id) {
+                let text = state.responder.text(peer, None, "pause.requested", &args);
+                let relayed_id = state.responder.send_text(peer, None, &text).await?;
+                db::insert_game_message(&state.db, game.id, peer, relayed_id).await?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// `/resume` in reply to a board: either player continues a paused game.
+pub async fn handle_resume(state: Arc<AppState>, message: &Message, from: &User) -> Result<()> {
+    let Some((game, _)) = replied_game_and_player(&state, message, from).await? else {
+        return Ok(());
+    };
+
+    if !db::set_game_paused(&state.db, game.id, false).await? {
+        state.responder.reply(message, "pause.not_paused", &[]).await?;
+        return Ok(());
+    }
+    audit_handler::record(&state, Some(from.id), message.chat.id, Some(game.id), "game.pause", "resumed").await;
+    repost_game_board(&state, &game, message, "board.resumed").await
+}
+
+/// Posts the game's current board in all its chats under `header_id`.
+async fn repost_game_board(state: &Arc<AppState>, game: &GameRow, message: &Message, header_id: &str) -> Result<()> {
+    let board = Position::from_str(&game.current_fen)?;
+    let white = state.users.get_by_id(&state.db, game.white_user_id).await?;
+    let black = state.users.get_by_id(&state.db, game.black_user_id).await?;
+    post_game_board(
+        state,
+        game.id,
+        &game.chats(),
+        message.chat.id,
+        Some(message.message_id),
+        locale(message),
+        header_id,
+        None,
+        &board,
+        &white,
+        &black,
+    )
+    .await?;
+    Ok(())
+}
+
+/// `/flip` in reply to a board: the current position again, seen from the side the
+/// chat's orientation setting doesn't show. The game itself is left as it is.
+pub async fn handle_flip(state: Arc<AppState>, message: &Message) -> Result<()> {
+    let chat_id = message.chat.id;
+    let Some(reply_id) = message.reply_to_message.as_ref().map(|msg| msg.message_id) else {
+        return Ok(());
+    };
+    let Some(game) = db::find_game_by_message(&state.db, chat_id, reply_id).await? else {
+        return Ok(());
+    };
+
+    let board = Position::from_str(&game.current_fen)?;
+    let white = state.users.get_by_id(&state.db, game.white_user_id).await?;
+    let black = state.users.get_by_id(&state.db, game.black_user_id).await?;
+    // Only a running game's boards take moves, so only those are tracked
+    let ongoing = game.status == GameStatus::Ongoing;
+
+    let header = state.responder.text(chat_id, locale(message), "board.flipped", &[]);
+    let message_id = send_board_update(
+        state.clone(),
+        chat_id,
+        Some(message.message_id),
+        &header,
+        &board,
+        &white,
+        &black,
+        None,
+        ongoing.then_some(game.id),
+        true,
+    )
+    .await?;
+
+    if ongoing {
+        db::update_game_message(&state.db, game.id, message_id).await?;
+    }
+
+    Ok(())
+}
+
+/// `/legal [square]` in reply to a board. With a square, the moves of the piece there and a
+/// board with their destinations circled; without one, every legal move by starting square.
+pub async fn handle_legal(state: Arc<AppState>, message: &Message, text: &str) -> Result<()> {
+    let chat_id = message.chat.id;
+    let Some(reply_id) = message.reply_to_message.as_ref().map(|msg| msg.message_id) else {
+        return Ok(());
+    };
+    let Some(game) = db::find_game_by_message(&state.db, chat_id, reply_id).await? else {
+        return Ok(());
+    };
+    let board = Position::from_str(&game.current_fen)?;
+    let responder = &state.responder;
+
+    let Some(arg) = text.split_whitespace().nth(1) else {
+        let groups = game::legal_moves_by_square(&board);
+        if groups.is_empty() {
+            responder.reply(message, "legal.none", &[]).await?;
+            return Ok(());
+        }
+        let count = groups.iter().map(|(_, moves)| moves.len()).sum::<usize>().to_string();
+        let lines = groups
+            .iter()
+            .map(|(from, moves)| {
+                let from = from.map_or_else(|| "@".to_string(), |sq| sq.to_string());
+                format!("<b>{}</b>: {}", from, moves.join(", "))
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+        responder
+            .reply(message, "legal.all", &[("count", &count), ("moves", &lines)])
+            .await?;
+        return Ok(());
+    };
+
+    let Ok(square) = arg.to_ascii_lowercase().parse::<game::Square>() else {
+        responder.reply(message, "legal.usage", &[]).await?;
+        return Ok(());
+    };
+    let square_name = square.to_string();
+    if board.color_on(square) != Some(board.side_to_move()) {
+        responder
+            .reply(message, "legal.no_piece", &[("square", &square_name)])
+            .await?;
+        return Ok(());
+    }
+
+    let moves: Vec<game::Move> = board
+        .legal_moves()
+        .into_iter()
+        .filter(|mv| mv.from() == Some(square))
+        .collect();
+    if moves.is_empty() {
+        responder
+            .reply(message, "legal.piece_stuck", &[("square", &square_name)])
+            .await?;
+        return Ok(());
+    }
+
+    let list = moves
+        .iter()
+        .map(|mv| game::move_to_display_san(&board, *mv))
+        .collect::<Vec<_>>()
+        .join(", ");
+    let caption = responder.text(
+        chat_id,
+        locale(message),
+        "legal.piece",
+        &[("square", &square_name), ("moves", &list)],
+    );
+
+    let white = state.users.get_by_id(&state.db, game.white_user_id).await?;
+    let black = state.users.get_by_id(&state.db, game.black_user_id).await?;
+    let settings = db::get_chat_settings(&state.db, chat_id).await?;
+    let flip_board = board_orientation_flip(&settings, chat_id, &board, &white, &black);
+    let overlay = moves
+        .iter()
+        .fold(game::BoardOverlay::new(), |overlay, mv| overlay.with_circle(mv.to()));
+    let info = game::BoardInfo::new(white.display_name(), black.display_name());
+    let image = render_game_board(&state, &settings, &board, flip_board, info, overlay).await?;
+    send_board_image(&state, &settings, chat_id, Some(message.message_id), &caption, image, None).await?;
+
+    Ok(())
+}
+
+/// Posts the position in each of `chats` and records the board in the game's own chat
+/// (the first) as the one to reply to, returning its id. Only `origin_chat`, where the
+/// action came from, gets the board as a reply; the other chats get it unprompted.
+/// `note` is a plain-text line added under the header, such as a move comment.
+#[allow(clippy::too_many_arguments)]
+pub(super) async fn post_game_board(
+    state: &Arc<AppState>,
+    game_id: i64,
+    chats: &[i64],
+    origin_chat: i64,
+    reply_to: Option<i64>,
+    locale: Option<&str>,
+    header_id: &str,
+    note: Option<&str>,
+    board: &Position,
+    white: &crate::models::DbUser,
+    black: &crate::models::DbUser,
+) -> Result<i64> {
+    let mut board_message_id = 0;
+    for (index, &chat_id) in chats.iter().enumerate() {
+        let (reply_to, locale) = if chat_id == origin_chat {
+            (reply_to, locale)
+        } else {
+            (None, None)
+        };
+        let mut header = state.responder.text(chat_id, locale, header_id, &[]);
+        if let Some(note) = note {
+            header = format!("{}\n{}", header, note);
+        }
+        let message_id = send_board_update(
+            state.clone(),
+            chat_id,
+            reply_to,
+            &header,
+            board,
+            white,
+            black,
+            None,
+            Some(game_id),
+            false,
+        )
+        .await?;
+        if index == 0 {
+            db::update_game_message(&state.db, game_id, message_id).await?;
+            board_message_id = message_id;
+        }
+    }
+    Ok(board_message_id)
+}
+
+/// Clears the running boards and posts the end message in every chat of the game,
+/// answering `reply_to` in `origin_chat`. `reason` is the short English label for how the
+/// game ended that goes on the final image's result banner.
+#[allow(clippy::too_many_arguments)]
+pub(super) async fn end_game_in_chats(
+    state: &Arc<AppState>,
+    game: &GameRow,
+    origin_chat: i64,
+    reply_to: i64,
+    locale: Option<&str>,
+    white: &crate::models::DbUser,
+    black: &crate::models::DbUser,
+    result: GameResult,
+    result_text: &str,
+    reason: &str,
+) -> Result<()> {
+    for chat_id in game.chats() {
+        let (reply_to, locale) = if chat_id == origin_chat {
+            (Some(reply_to), locale)
+        } else {
+            (None, None)
+        };
+        cleanup_game_messages(state.clone(), chat_id, game.id).await?;
+        send_game_end_message(state, chat_id, reply_to, locale, game.id, white, black, result, result_text, reason)
+            .await?;
+    }
+    state.live.publish(LiveEvent::End {
+        game_id: game.id,
+        status: GameStatus::Finished.as_str(),
+        result: result.as_str(),
+    });
+    prediction_handler::close_prediction_polls(state, game.id, result).await;
+    rank_handler::update_champion(state, game.chat_id, locale).await;
+    audit_handler::record(state, None, origin_chat, Some(game.id), "game.end", result.as_str()).await;
+    Ok(())
+}
+
 #[allow(clippy::too_many_arguments)]
 async fn send_board_update(
     state: Arc<AppState>,
     chat_id: i64,
     reply_to: Option<i64>,
     header: &str,
-    board: &Board,
+    board: &Position,
     white: &crate::models::DbUser,
     black: &crate::models::DbUser,
     result_line: Option<String>,
     game_id: Option<i64>,
+    opposite_side: bool,
 ) -> Result<i64> {
+    let paused = match game_id {
+        Some(gid) => db::get_game_pause(&state.db, gid).await?.0,
+        None => false,
+    };
+    let result_line = result_line.or_else(|| {
+        paused.then(|| state.responder.text(chat_id, None, "board.paused_line", &[]))
+    });
     let caption = game::build_caption(
         header,
         board,
@@ -533,19 +1365,31 @@ async fn send_board_update(
         black,
         board.side_to_move(),
         result_line,
+        db::get_chat_champion(&state.db, chat_id).await?,
     );
-    let flip_board = board.side_to_move() == Color::Black;
-    let image = game::render_board_png(board, flip_board)?;
-    let message_id = state
-        .telegram
-        .send_photo(chat_id, reply_to, &caption, image)
-        .await?;
+    let settings = db::get_chat_settings(&state.db, chat_id).await?;
+    let mut info = game::BoardInfo::new(white.display_name(), black.display_name());
+    let last_move = match game_id {
+        Some(gid) => db::get_last_move(&state.db, gid).await?,
+        None => None,
+    };
+    if let Some((ply, san)) = &last_move {
+        info = info.with_last_move(*ply, san);
+    }
+    // Only running games' boards are sent with a game id, so only they get move buttons
+    let keyboard = game_id
+        .filter(|_| settings.tap_moves && !paused)
+        .map(|gid| tap_handler::board_keyboard(gid, last_move.as_ref().map_or(0, |(ply, _)| *ply), board, None));
+    let flip_board = board_orientation_flip(&settings, chat_id, board, white, black) != opposite_side;
+    let image = render_game_board(&state, &settings, board, flip_board, info, game::BoardOverlay::new()).await?;
+    let message_id =
+        send_board_image(&state, &settings, chat_id, reply_to, &caption.text, image, keyboard.as_ref()).await?;
     
     if let Some(gid) = game_id {
         // If no_trash mode is enabled, delete all previous board messages for this game
         // before adding the new one, keeping only the most recent board image
         if state.no_trash {
-            let previous_message_ids = db::get_game_message_ids(&state.db, gid).await?;
+            let previous_message_ids = db::get_game_message_ids(&state.db, gid, chat_id).await?;
             for prev_id in previous_message_ids {
                 if let Err(e) = state.telegram.delete_message(chat_id, prev_id).await {
                     error!(
@@ -558,21 +1402,92 @@ async fn send_board_update(
                 }
             }
             // Delete all previous message records from database
-            db::delete_game_messages(&state.db, gid).await?;
+            db::delete_game_messages(&state.db, gid, chat_id).await?;
         }
         
-        let _ = db::insert_game_message(&state.db, gid, message_id).await;
+        let _ = db::insert_game_message(&state.db, gid, chat_id, message_id).await;
+    }
+
+    if let Some(overflow) = caption.overflow {
+        let overflow_id = state.responder.send_text(chat_id, Some(message_id), &overflow).await?;
+        // Tracked with the board so no-trash mode cleans it up too
+        if let Some(gid) = game_id {
+            let _ = db::insert_game_message(&state.db, gid, chat_id, overflow_id).await;
+        }
     }
     
     Ok(message_id)
 }
 
+/// Renders `board` with the chat's coordinate style.
+async fn render_game_board(
+    state: &AppState,
+    settings: &ChatSettings,
+    board: &Position,
+    flip_board: bool,
+    info: game::BoardInfo,
+    overlay: game::BoardOverlay,
+) -> Result<Vec<u8>> {
+    let options = game::RenderOptions {
+        flip_board,
+        coordinates: game::CoordinateStyle::parse(&settings.coordinates).unwrap_or_default(),
+        overlay,
+        info: Some(info),
+        encoding: state.image_encoding,
+    };
+    Ok(game::render_board(board, &options).await?)
+}
+
+/// Sends a board as a photo, or as a file when the chat wants uncompressed images.
+async fn send_board_image(
+    state: &AppState,
+    settings: &ChatSettings,
+    chat_id: i64,
+    reply_to: Option<i64>,
+    caption: &str,
+    image: Vec<u8>,
+    keyboard: Option<&InlineKeyboardMarkup>,
+) -> Result<i64> {
+    let format = state.image_encoding.format;
+    let message_id = if settings.send_as_document {
+        state
+            .telegram
+            .send_document(chat_id, reply_to, caption, image, format, keyboard)
+            .await?
+    } else {
+        state
+            .telegram
+            .send_photo(chat_id, reply_to, caption, image, format, keyboard)
+            .await?
+    };
+    Ok(message_id)
+}
+
+fn board_orientation_flip(
+    settings: &ChatSettings,
+    chat_id: i64,
+    board: &Position,
+    white: &crate::models::DbUser,
+    black: &crate::models::DbUser,
+) -> bool {
+    let orientation = game::BoardOrientation::parse(&settings.orientation).unwrap_or_default();
+    // In a private chat the chat id is the user's Telegram id
+    let viewer = if white.telegram_id == Some(chat_id) {
+        Some(Color::White)
+    } else if black.telegram_id == Some(chat_id) {
+        Some(Color::Black)
+    } else {
+        None
+    };
+    orientation.flip_board(board.side_to_move(), viewer)
+}
+
 async fn cleanup_game_messages(
     state: Arc<AppState>,
     chat_id: i64,
     game_id: i64,
 ) -> Result<()> {
-    let message_ids = db::get_game_message_ids(&state.db, game_id).await?;
+    let message_ids = db::get_game_message_ids(&state.db, game_id, chat_id).await?;
     
     for message_id in message_ids {
         if let Err(e) = state.telegram.delete_message(chat_id, message_id).await {
@@ -586,36 +1501,206 @@ async fn cleanup_game_messages(
         }
     }
     
-    db::delete_game_messages(&state.db, game_id).await?;
+    db::delete_game_messages(&state.db, game_id, chat_id).await?;
     Ok(())
 }
 
+#[allow(clippy::too_many_arguments)]
 async fn send_game_end_message(
-    state: Arc<AppState>,
+    state: &AppState,
     chat_id: i64,
-    reply_to: i64,
-    _white: &crate::models::DbUser,
-    _black: &crate::models::DbUser,
-    result: &str,
+    reply_to: Option<i64>,
+    locale: Option<&str>,
+    game_id: i64,
+    white: &crate::models::DbUser,
+    black: &crate::models::DbUser,
+    result: GameResult,
     result_text: &str,
+    reason: &str,
 ) -> Result<()> {
-    let result_notation = match result {
-        "1-0" => "1-0",
-        "0-1" => "0-1",
-        "1/2-1/2" => "1/2-1/2",
-        _ => result,
+    let responder = &state.responder;
+    let summary = db::get_game_summary(&state.db, game_id).await?;
+    let moves = summary.move_count().to_string();
+    let (white_name, black_name) = (white.mention_html(), black.mention_html());
+    let mut args = vec![
+        ("announcement", result_text),
+        ("result", result.as_str()),
+        ("white", white_name.as_str()),
+        ("black", black_name.as_str()),
+        ("moves", moves.as_str()),
+    ];
+    let id = match result {
+        GameResult::WhiteWins => {
+            args.extend([("winner", white_name.as_str()), ("loser", black_name.as_str())]);
+            WIN_TEMPLATE
+        }
+        GameResult::BlackWins => {
+            args.extend([("winner", black_name.as_str()), ("loser", white_name.as_str())]);
+            WIN_TEMPLATE
+        }
+        GameResult::Draw => DRAW_TEMPLATE,
     };
+    let mut message = responder.text(chat_id, locale, id, &args);
+
+    message.push('\n');
+    message.push_str(&responder.text(
+        chat_id,
+        locale,
+        "game.stats",
+        &[
+            ("moves", &moves),
+            ("captures", &summary.captures().to_string()),
+            ("checks", &summary.checks().to_string()),
+        ],
+    ));
+    if let Some(secs) = summary.duration_secs {
+        message.push('\n');
+        let duration = crate::utils::format_duration(secs);
+        message.push_str(&responder.text(chat_id, locale, "game.duration", &[("duration", &duration)]));
+    }
+    if let Some(opening) = game::opening_name(&summary.moves) {
+        message.push('\n');
+        message.push_str(&responder.text(chat_id, locale, "game.opening", &[("opening", opening)]));
+    }
+    let think_times = db::get_game_think_times(&state.db, game_id).await?;
+    for (side, player) in [("side.white", white), ("side.black", black)] {
+        if let Some(think) = think_times.get(&player.id) {
+            message.push('\n');
+            message.push_str(&responder.text(
+                chat_id,
+                locale,
+                "game.think_time",
+                &[
+                    ("side", &responder.text(chat_id, locale, side, &[])),
+                    ("average", &crate::utils::format_duration(think.average_secs())),
+                    ("longest", &crate::utils::format_duration(think.longest_secs)),
+                ],
+            ));
+        }
+    }
+    if !summary.moves.is_empty() {
+        message.push('\n');
+        let url = escape_html(&lichess_analysis_url(&summary.moves));
+        message.push_str(&responder.text(chat_id, locale, "game.analysis", &[("url", &url)]));
+    }
+    let game = db::get_game_by_id(&state.db, game_id).await?;
+    let number = match &game {
+        Some(game) => Some(db::get_game_number(&state.db, game).await?),
+        None => None,
+    };
+    if let (Some(game), Some(number)) = (&game, number) {
+        if let Some(url) = crate::utils::game_page_url(game.chat_id, number) {
+            message.push('\n');
+            message.push_str(&responder.text(chat_id, locale, "game.page", &[("url", &escape_html(&url))]));
+        }
+    }
     
-    let message = format!(
-        "Game ended.\n{}\nResult: {}",
-        result_text,
-        result_notation
-    );
+    let settings = db::get_chat_settings(&state.db, chat_id).await?;
+    let image = match render_final_board(state, &settings, chat_id, game_id, white, black, reason).await {
+        Ok(image) => image,
+        Err(e) => {
+            warn!(chat_id = chat_id, game_id = game_id, error = %e, "Failed to render final board");
+            None
+        }
+    };
+    let end_message_id = match image {
+        Some(image) => {
+            // The end message becomes the caption; whatever doesn't fit follows as a reply
+            let mut chunks = split_message(&message, MAX_CAPTION_LEN).into_iter();
+            let caption = chunks.next().unwrap_or_default();
+            let board_id = send_board_image(state, &settings, chat_id, reply_to, &caption, image, None).await?;
+            let rest: Vec<String> = chunks.collect();
+            if !rest.is_empty() {
+                responder.send_text(chat_id, Some(board_id), &rest.join("\n")).await?;
+            }
+            board_id
+        }
+        None => responder.send_text(chat_id, reply_to, &message).await?,
+    };
+
+    if let (true, Some(game), Some(number)) = (settings.pgn_files, &game, number) {
+        if let Err(e) = send_pgn_file(state, chat_id, end_message_id, game, number, white, black, &summary.moves).await {
+            warn!(chat_id = chat_id, game_id = game_id, error = %e, "Failed to send PGN file");
+        }
+    }
     
+    Ok(())
+}
+
+/// The finished game's PGN as a file replying to its end message, for chats with
+/// `/settings pgn on`.
+#[allow(clippy::too_many_arguments)]
+async fn send_pgn_file(
+    state: &AppState,
+    chat_id: i64,
+    reply_to: i64,
+    game: &crate::models::GameRow,
+    number: i64,
+    white: &crate::models::DbUser,
+    black: &crate::models::DbUser,
+    moves: &[String],
+) -> Result<()> {
+    let Some((started_at, ended_at)) = db::get_game_times(&state.db, game.id).await? else {
+        return Ok(());
+    };
+    let comments = db::get_game_move_comments(&state.db, game.id).await?;
+    let pgn = crate::utils::game_pgn(game, white, black, moves, &comments, &started_at, ended_at.as_deref());
     state
         .telegram
-        .send_message(chat_id, reply_to, &message)
+        .send_attachment(
+            chat_id,
+            Some(reply_to),
+            "",
+            &format!("kamachess-game-{}.pgn", number),
+            crate::utils::PGN_MIME_TYPE,
+            pgn.into_bytes(),
+        )
         .await?;
-    
     Ok(())
 }
+
+/// The final position under a result banner, with a QR code for the game on the lichess
+/// analysis board, so the picture can be shared on its own and people looking at the chat
+/// over someone's shoulder can open the game on their own phone.
+#[allow(clippy::too_many_arguments)]
+async fn render_final_board(
+    state: &AppState,
+    settings: &ChatSettings,
+    chat_id: i64,
+    game_id: i64,
+    white: &crate::models::DbUser,
+    black: &crate::models::DbUser,
+    reason: &str,
+) -> Result<Option<Vec<u8>>> {
+    let Some(game) = db::get_game_by_id(&state.db, game_id).await? else {
+        return Ok(None);
+    };
+    let board = Position::from_str(&game.current_fen)?;
+    let moves = db::get_game_san_moves(&state.db, game_id).await?;
+
+    let mut info = game::BoardInfo::new(white.display_name(), black.display_name())
+        .with_analysis_url(lichess_analysis_url(&moves));
+    if let Some(result) = &game.result {
+        info = info.with_result(result.as_str(), reason);
+    }
+    if let Some(san) = moves.last() {
+        info = info.with_last_move(moves.len() as i64, san);
+    }
+    let flip_board = board_orientation_flip(settings, chat_id, &board, white, black);
+    let image = render_game_board(state, settings, &board, flip_board, info, game::BoardOverlay::new()).await?;
+    Ok(Some(image))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_draw_offer_wait() {
+        assert_eq!(draw_offer_wait(None, 12, 5), None);
+        assert_eq!(draw_offer_wait(Some(12), 12, 5), Some(5));
+        assert_eq!(draw_offer_wait(Some(12), 17, 5), Some(3));
+        assert_eq!(draw_offer_wait(Some(12), 22, 5), None);
+        assert_eq!(draw_offer_wait(Some(12), 12, 0), None);
+    }
+}
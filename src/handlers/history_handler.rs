@@ -22,21 +22,21 @@ pub async fn handle_history(
     }
 
     let user_a = if let Some(username) = usernames.first() {
-        db::upsert_user_by_username(&state.db, username).await?
+        state.users.find_by_username(&state.db, username).await?
     } else {
-        db::upsert_user(&state.db, from).await?
+        state.users.upsert(&state.db, from).await?
     };
 
     let response = if let Some(username_b) = usernames.get(1) {
-        let user_b = db::upsert_user_by_username(&state.db, username_b).await?;
+        let user_b = state.users.find_by_username(&state.db, username_b).await?;
         db::format_head_to_head(&state.db, &user_a, &user_b, chat_id, page).await?
     } else {
         db::format_user_history(&state.db, &user_a, chat_id, page).await?
     };
 
     state
-        .telegram
-        .send_message(chat_id, message.message_id, &response)
+        .responder
+        .send_text(chat_id, Some(message.message_id), &response)
         .await?;
 
     Ok(())
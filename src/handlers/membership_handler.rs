@@ -0,0 +1,251 @@
+use super::{audit_handler, game_handler};
+use crate::models::{
+    CallbackQuery, ChatMemberUpdated, GameResult, GameRow, GameStatus, InlineKeyboardButton, InlineKeyboardMarkup,
+    Message, User,
+};
+use crate::live::LiveEvent;
+use crate::responder::locale;
+use crate::{db, AppState};
+use anyhow::Result;
+use chrono::{Duration, Utc};
+use std::sync::Arc;
+use tracing::{info, warn};
+
+/// How long the remaining player waits before they can claim a win from, or abort a game
+/// with, a player who left the chat.
+fn leave_grace_period() -> Duration {
+    let minutes = std::env::var("LEAVE_GRACE_MINS")
+        .ok()
+        .and_then(|v| v.parse::<i64>().ok())
+        .filter(|n| *n >= 0)
+        .unwrap_or(10);
+    Duration::minutes(minutes)
+}
+
+/// The bot's own membership changed. Being added to a group posts a short introduction;
+/// being removed or blocked abandons the chat's games and stops announcements to it.
+pub async fn handle_my_chat_member(state: Arc<AppState>, update: ChatMemberUpdated) -> Result<()> {
+    let chat_id = update.chat.id;
+    match (update.old_chat_member.is_present(), update.new_chat_member.is_present()) {
+        (false, true) => {
+            db::set_chat_bot_removed(&state.db, chat_id, false).await?;
+            audit_handler::record(&state, Some(update.from.id), chat_id, None, "bot.added", "ok").await;
+            info!(chat_id = chat_id, "Bot added to chat");
+            // Group ids are negative; a private chat is only unblocking the bot, and the
+            // user gets the usual /start reply there
+            if chat_id < 0 {
+                let locale = update.from.language_code.as_deref();
+                let text = state.responder.text(chat_id, locale, "onboarding", &[]);
+                state.responder.send_text(chat_id, None, &text).await?;
+            }
+        }
+        (true, false) => {
+            db::set_chat_bot_removed(&state.db, chat_id, true).await?;
+            db::close_chat_invitations(&state.db, chat_id).await?;
+            let games = db::abandon_chat_games(&state.db, chat_id).await?;
+            info!(chat_id = chat_id, abandoned = games.len(), "Bot removed from chat");
+            let outcome = format!("{} games abandoned", games.len());
+            audit_handler::record(&state, Some(update.from.id), chat_id, None, "bot.removed", &outcome).await;
+
+            // The other chat of a game played over private chats can still be told
+            for game in games {
+                state.live.publish(abandoned(&game));
+                audit_handler::record(&state, None, chat_id, Some(game.id), "game.end", "*").await;
+                for peer in game.chats().into_iter().filter(|&id| id != chat_id) {
+                    let white = state.users.get_by_id(&state.db, game.white_user_id).await?;
+                    let black = state.users.get_by_id(&state.db, game.black_user_id).await?;
+                    let text = state.responder.text(
+                        peer,
+                        None,
+                        "game.abandoned",
+                        &[("white", &white.mention_html()), ("black", &black.mention_html())],
+                    );
+                    if let Err(e) = state.responder.send_text(peer, None, &text).await {
+                        warn!(chat_id = peer, game_id = game.id, error = %e, "Failed to announce abandoned game");
+                    }
+                }
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+/// A user left or was removed from a group. For each of their games there, the opponent
+/// is offered to claim the win or abort the game once the grace period is over.
+pub async fn handle_left_chat_member(state: Arc<AppState>, message: &Message, left: &User) -> Result<()> {
+    if left.is_bot {
+        return Ok(());
+    }
+    let chat_id = message.chat.id;
+    let Ok(player) = db::get_user_by_telegram_id(&state.db, left.id).await else {
+        return Ok(());
+    };
+
+    let minutes = leave_grace_period().num_minutes().to_string();
+    for game in db::find_player_games_in_chat(&state.db, chat_id, player.id).await? {
+        db::set_player_left(&state.db, game.id, Some(player.id)).await?;
+        let opponent = state.users.get_by_id(&state.db, opponent_id(&game, player.id)).await?;
+
+        let button = |id| state.responder.text(chat_id, locale(message), id, &[]);
+        let keyboard = InlineKeyboardMarkup {
+            inline_keyboard: vec![vec![
+                InlineKeyboardButton::callback(&button("left.button_claim"), format!("claim:{}", game.id)),
+                InlineKeyboardButton::callback(&button("left.button_abort"), format!("abort:{}", game.id)),
+            ]],
+        };
+        state
+            .responder
+            .reply_with_keyboard(
+                message,
+                "game.player_left",
+                &[
+                    ("player", &player.mention_html()),
+                    ("opponent", &opponent.mention_html()),
+                    ("minutes", &minutes),
+                ],
+                keyboard,
+            )
+            .await?;
+        audit_handler::record(&state, Some(left.id), chat_id, Some(game.id), "game.player_left", "ok").await;
+    }
+    Ok(())
+}
+
+/// Users joined a group. A player who left during a game and came back gets to carry on.
+pub async fn handle_new_chat_members(state: Arc<AppState>, message: &Message, members: &[User]) -> Result<()> {
+    let chat_id = message.chat.id;
+    for member in members.iter().filter(|member| !member.is_bot) {
+        let Ok(player) = db::get_user_by_telegram_id(&state.db, member.id).await else {
+            continue;
+        };
+        for game in db::find_player_games_in_chat(&state.db, chat_id, player.id).await? {
+            if !matches!(db::get_player_left(&state.db, game.id).await?, Some((id, _)) if id == player.id) {
+                continue;
+            }
+            db::set_player_left(&state.db, game.id, None).await?;
+            let opponent = state.users.get_by_id(&state.db, opponent_id(&game, player.id)).await?;
+            state
+                .responder
+                .reply(
+                    message,
+                    "game.player_returned",
+                    &[("player", &player.mention_html()), ("opponent", &opponent.mention_html())],
+                )
+                .await?;
+        }
+    }
+    Ok(())
+}
+
+/// The Claim win and Abort buttons posted when a player left. Only the remaining player
+/// can use them, and only after the grace period.
+pub async fn handle_left_player_choice(
+    state: Arc<AppState>,
+    query: &CallbackQuery,
+    game_id: i64,
+    claim: bool,
+) -> Result<()> {
+    let Some(prompt) = &query.message else {
+        return Ok(());
+    };
+    let chat_id = prompt.chat.id;
+
+    let pending = match db::get_game_by_id(&state.db, game_id).await? {
+        Some(game) if game.status == GameStatus::Ongoing => {
+            db::get_player_left(&state.db, game.id).await?.map(|left| (game, left))
+        }
+        _ => None,
+    };
+    let Some((game, (left_id, left_at))) = pending else {
+        state.responder.answer_callback(query, Some("left.not_pending")).await?;
+        return Ok(());
+    };
+
+    let player = state.users.upsert(&state.db, &query.from).await?;
+    if player.id == left_id || (player.id != game.white_user_id && player.id != game.black_user_id) {
+        state.responder.answer_callback(query, Some("left.not_opponent")).await?;
+        return Ok(());
+    }
+    if Utc::now() < left_at + leave_grace_period() {
+        state.responder.answer_callback(query, Some("left.too_early")).await?;
+        return Ok(());
+    }
+
+    let white = state.users.get_by_id(&state.db, game.white_user_id).await?;
+    let black = state.users.get_by_id(&state.db, game.black_user_id).await?;
+    let leaver = if left_id == white.id { &white } else { &black };
+    let locale = query.from.language_code.as_deref();
+    state.responder.answer_callback(query, None).await?;
+
+    if !claim {
+        if db::abandon_game(&state.db, game.id).await? {
+            state.live.publish(abandoned(&game));
+            state
+                .responder
+                .edit(
+                    chat_id,
+                    prompt.message_id,
+                    locale,
+                    "left.aborted",
+                    &[
+                        ("white", &white.mention_html()),
+                        ("black", &black.mention_html()),
+                        ("player", &leaver.mention_html()),
+                    ],
+                )
+                .await?;
+            audit_handler::record(&state, Some(query.from.id), chat_id, Some(game.id), "game.end", "*").await;
+        }
+        return Ok(());
+    }
+
+    let result = if left_id == white.id {
+        GameResult::BlackWins
+    } else {
+        GameResult::WhiteWins
+    };
+    if !db::settle_game(&state.db, &game, result).await? {
+        // Claimed twice, or the game ended some other way in the meantime
+        return Ok(());
+    }
+    state.users.invalidate(game.white_user_id);
+    state.users.invalidate(game.black_user_id);
+
+    let result_text = state.responder.text(
+        chat_id,
+        locale,
+        "result.left",
+        &[("loser", &leaver.mention_html()), ("winner", &player.mention_html())],
+    );
+    game_handler::end_game_in_chats(
+        &state,
+        &game,
+        chat_id,
+        prompt.message_id,
+        locale,
+        &white,
+        &black,
+        result,
+        &result_text,
+        "Opponent left",
+    )
+    .await?;
+    Ok(())
+}
+
+fn abandoned(game: &GameRow) -> LiveEvent {
+    LiveEvent::End {
+        game_id: game.id,
+        status: GameStatus::Abandoned.as_str(),
+        result: "*",
+    }
+}
+
+fn opponent_id(game: &GameRow, player_id: i64) -> i64 {
+    if game.white_user_id == player_id {
+        game.black_user_id
+    } else {
+        game.white_user_id
+    }
+}
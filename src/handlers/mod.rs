@@ -1,6 +1,21 @@
+mod activity_handler;
+mod admin_handler;
+mod audit_handler;
+mod eval_handler;
 mod game_handler;
 mod help_handler;
 mod history_handler;
+mod membership_handler;
+mod ongoing_handler;
+mod pgn_handler;
+mod prediction_handler;
+mod queue_handler;
+mod rank_handler;
+mod seek_handler;
+mod settings_handler;
+mod tap_handler;
+mod tutorial_handler;
 mod update_router;
 
-pub use update_router::process_update;
+pub use seek_handler::run_seek_expiry_task;
+pub use update_router::{error_ref, is_background_update, process_update};
@@ -0,0 +1,140 @@
+use super::game_handler;
+use crate::game::Position;
+use crate::models::{CallbackQuery, GameRow, GameStatus, InlineKeyboardButton, InlineKeyboardMarkup, Message, Turn, User};
+use crate::responder::locale;
+use crate::utils::escape_html;
+use crate::{db, AppState};
+use anyhow::Result;
+use std::str::FromStr;
+use std::sync::Arc;
+
+/// Supergroup ids are the internal chat id with this added and the sign flipped.
+const SUPERGROUP_OFFSET: i64 = 1_000_000_000_000;
+
+/// Link to a message in a supergroup, which members can open from anywhere. Basic groups
+/// and private chats have no such links.
+fn message_link(chat_id: i64, message_id: i64) -> Option<String> {
+    let internal = -chat_id - SUPERGROUP_OFFSET;
+    (internal > 0).then(|| format!("https://t.me/c/{}/{}", internal, message_id))
+}
+
+/// `/ongoing`: the caller's running games in every chat, each with a button to the
+/// current board where Telegram can link to it and one that posts the board again.
+pub async fn handle_ongoing(state: Arc<AppState>, message: &Message, from: &User) -> Result<()> {
+    let chat_id = message.chat.id;
+    let locale = locale(message);
+    let responder = &state.responder;
+    let player = state.users.upsert(&state.db, from).await?;
+    let games = db::find_user_ongoing_games(&state.db, player.id).await?;
+    if games.is_empty() {
+        responder.reply(message, "ongoing.none", &[]).await?;
+        return Ok(());
+    }
+
+    let mut lines = vec![responder.text(chat_id, locale, "ongoing.header", &[("count", &games.len().to_string())])];
+    let mut rows = Vec::new();
+    for game in &games {
+        let number = db::get_game_number(&state.db, game).await?;
+        let opponent_id = if game.white_user_id == player.id {
+            game.black_user_id
+        } else {
+            game.white_user_id
+        };
+        let opponent = state.users.get_by_id(&state.db, opponent_id).await?;
+        let plies = db::get_last_move(&state.db, game.id).await?.map_or(0, |(ply, _)| ply);
+        let to_move = match game.turn {
+            Turn::White => game.white_user_id,
+            Turn::Black => game.black_user_id,
+        };
+        let turn = if to_move == player.id {
+            "ongoing.your_move"
+        } else {
+            "ongoing.their_move"
+        };
+        let here = if game.chats().contains(&chat_id) {
+            responder.text(chat_id, locale, "ongoing.this_chat", &[])
+        } else {
+            String::new()
+        };
+        lines.push(responder.text(
+            chat_id,
+            locale,
+            "ongoing.entry",
+            &[
+                ("number", &number.to_string()),
+                ("opponent", &escape_html(&opponent.display_name())),
+                ("moves", &((plies + 1) / 2).to_string()),
+                ("turn", &responder.text(chat_id, locale, turn, &[])),
+                ("here", &here),
+            ],
+        ));
+
+        let label = |id: &str| responder.text(chat_id, locale, id, &[("number", &number.to_string())]);
+        let mut row = Vec::new();
+        if let Some(url) = game.last_message_id.and_then(|id| message_link(game.chat_id, id)) {
+            row.push(InlineKeyboardButton::link(&label("ongoing.button_open"), url));
+        }
+        row.push(InlineKeyboardButton::callback(&label("ongoing.button_repost"), format!("repost:{}", game.id)));
+        rows.push(row);
+    }
+
+    let keyboard = InlineKeyboardMarkup { inline_keyboard: rows };
+    state
+        .telegram
+        .send_message_with_keyboard(chat_id, message.message_id, &lines.join("\n"), keyboard)
+        .await?;
+    Ok(())
+}
+
+/// The repost button under an `/ongoing` entry: posts the board again in the game's chats,
+/// so it's at the bottom of the conversation.
+pub async fn handle_repost(state: Arc<AppState>, query: &CallbackQuery, game_id: i64) -> Result<()> {
+    let responder = &state.responder;
+    let player = state.users.upsert(&state.db, &query.from).await?;
+    let game: Option<GameRow> = db::get_game_by_id(&state.db, game_id)
+        .await?
+        .filter(|game| game.white_user_id == player.id || game.black_user_id == player.id);
+    let Some(game) = game else {
+        responder.answer_callback(query, Some("ongoing.not_player")).await?;
+        return Ok(());
+    };
+    if game.status != GameStatus::Ongoing {
+        responder.answer_callback(query, Some("ongoing.finished")).await?;
+        return Ok(());
+    }
+
+    let board = Position::from_str(&game.current_fen)?;
+    let white = state.users.get_by_id(&state.db, game.white_user_id).await?;
+    let black = state.users.get_by_id(&state.db, game.black_user_id).await?;
+    game_handler::post_game_board(
+        &state,
+        game.id,
+        &game.chats(),
+        game.chat_id,
+        None,
+        None,
+        "board.reposted",
+        None,
+        &board,
+        &white,
+        &black,
+    )
+    .await?;
+    responder.answer_callback(query, Some("ongoing.reposted")).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_message_link() {
+        assert_eq!(
+            message_link(-1001234567890, 42).as_deref(),
+            Some("https://t.me/c/1234567890/42")
+        );
+        assert_eq!(message_link(-123456, 42), None);
+        assert_eq!(message_link(123456, 42), None);
+    }
+}
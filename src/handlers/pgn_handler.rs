@@ -0,0 +1,107 @@
+use super::eval_handler::{env_number, take_cooldown};
+use crate::models::{Message, User};
+use crate::responder::locale;
+use crate::utils::{escape_html, game_pgn, PGN_MIME_TYPE};
+use crate::{db, parsing, AppState};
+use anyhow::Result;
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncWriteExt, BufWriter};
+
+const DEFAULT_PGNALL_COOLDOWN_SECS: u64 = 300;
+
+/// Games read from the database at a time while the file is written.
+const PAGE_SIZE: i64 = 50;
+
+/// Last /pgnall per Telegram user id.
+static LAST_EXPORT: OnceLock<Mutex<HashMap<i64, Instant>>> = OnceLock::new();
+
+/// `/pgnall [@user]`: every finished game of the user (the caller by default) in this
+/// chat as one PGN file. The file is written to disk a page of games at a time and
+/// uploaded from there, so long careers don't have to fit in memory.
+pub async fn handle_pgn_all(state: Arc<AppState>, message: &Message, from: &User, text: &str) -> Result<()> {
+    let chat_id = message.chat.id;
+    let responder = &state.responder;
+
+    let cooldown = Duration::from_secs(env_number("PGNALL_COOLDOWN_SECS", DEFAULT_PGNALL_COOLDOWN_SECS));
+    let wait = {
+        let mut last = LAST_EXPORT.get_or_init(Default::default).lock().unwrap();
+        take_cooldown(&mut last, from.id, Instant::now(), cooldown)
+    };
+    if let Some(wait) = wait {
+        let seconds = wait.as_secs().max(1).to_string();
+        responder.reply(message, "pgnall.cooldown", &[("seconds", &seconds)]).await?;
+        return Ok(());
+    }
+
+    let username = parsing::extract_usernames(text)
+        .into_iter()
+        .find(|name| !name.eq_ignore_ascii_case(&state.bot_username));
+    let user = match username {
+        Some(username) => state.users.find_by_username(&state.db, &username).await?,
+        None => state.users.upsert(&state.db, from).await?,
+    };
+
+    let path = std::env::temp_dir().join(format!("kamachess-pgnall-{}-{}.pgn", chat_id, message.message_id));
+    let player = escape_html(&user.display_name());
+    let sent = async {
+        match write_games(&state, chat_id, user.id, &path).await? {
+            0 => {
+                responder.reply(message, "pgnall.none", &[("player", &player)]).await?;
+            }
+            count => {
+                let count = count.to_string();
+                let caption =
+                    responder.text(chat_id, locale(message), "pgnall.caption", &[("count", &count), ("player", &player)]);
+                let name = format!("kamachess-{}-games.pgn", user.username.as_deref().unwrap_or("player"));
+                state
+                    .telegram
+                    .send_attachment_file(chat_id, Some(message.message_id), &caption, &path, &name, PGN_MIME_TYPE)
+                    .await?;
+            }
+        }
+        Ok(())
+    }
+    .await;
+    if let Err(e) = tokio::fs::remove_file(&path).await {
+        tracing::debug!(path = %path.display(), error = %e, "Failed to remove PGN export");
+    }
+    sent
+}
+
+/// Writes the user's finished games in the chat to `path`, returning how many there were.
+async fn write_games(state: &AppState, chat_id: i64, user_id: i64, path: &Path) -> Result<usize> {
+    let mut file = BufWriter::new(tokio::fs::File::create(path).await?);
+    let mut count = 0;
+    let mut after_id = 0;
+    loop {
+        let ids = db::get_user_finished_game_ids(&state.db, chat_id, user_id, after_id, PAGE_SIZE).await?;
+        let Some(&last) = ids.last() else {
+            break;
+        };
+        for id in ids {
+            let Some(game) = db::find_game_with_archive(&state.db, id).await? else {
+                continue;
+            };
+            let Some((started_at, ended_at)) = db::get_game_times(&state.db, id).await? else {
+                continue;
+            };
+            let white = state.users.get_by_id(&state.db, game.white_user_id).await?;
+            let black = state.users.get_by_id(&state.db, game.black_user_id).await?;
+            let moves = db::get_game_san_moves(&state.db, id).await?;
+            let comments = db::get_game_move_comments(&state.db, id).await?;
+            // Games in a PGN database are separated by a blank line
+            if count > 0 {
+                file.write_all(b"\n").await?;
+            }
+            let pgn = game_pgn(&game, &white, &black, &moves, &comments, &started_at, ended_at.as_deref());
+            file.write_all(pgn.as_bytes()).await?;
+            count += 1;
+        }
+        after_id = last;
+    }
+    file.flush().await?;
+    Ok(count)
+}
@@ -0,0 +1,134 @@
+use crate::models::{DbUser, GameResult, Message, PollAnswer};
+use crate::responder::locale;
+use crate::utils::escape_html;
+use crate::{db, AppState};
+use anyhow::Result;
+use std::sync::Arc;
+use tracing::warn;
+
+const LEADERBOARD_SIZE: i64 = 10;
+
+/// Poll option for each outcome, in the order the options are posted.
+fn outcome_option(result: GameResult) -> i64 {
+    match result {
+        GameResult::WhiteWins => 0,
+        GameResult::Draw => 1,
+        GameResult::BlackWins => 2,
+    }
+}
+
+/// Posts the "Who wins?" poll under a new game's first board. A poll that can't be sent
+/// is only logged; the game goes on without it.
+pub(super) async fn open_prediction_poll(
+    state: &AppState,
+    chat_id: i64,
+    game_id: i64,
+    board_message_id: i64,
+    white: &DbUser,
+    black: &DbUser,
+) {
+    let text = |id: &str, args: &[(&str, &str)]| state.responder.text(chat_id, None, id, args);
+    let question = text("prediction.question", &[]);
+    // Poll texts are plain text, so names go in unescaped
+    let options = [
+        text("prediction.white", &[("player", &white.display_name())]),
+        text("prediction.draw", &[]),
+        text("prediction.black", &[("player", &black.display_name())]),
+    ];
+
+    let poll = match state
+        .telegram
+        .send_poll(chat_id, Some(board_message_id), &question, &options)
+        .await
+    {
+        Ok(poll) => poll,
+        Err(e) => {
+            warn!(chat_id = chat_id, game_id = game_id, error = %e, "Failed to send prediction poll");
+            return;
+        }
+    };
+    if let Err(e) =
+        db::create_prediction_poll(&state.db, &poll.poll.id, game_id, chat_id, poll.message_id).await
+    {
+        warn!(chat_id = chat_id, game_id = game_id, error = %e, "Failed to store prediction poll");
+    }
+}
+
+/// Stops the game's polls and scores their votes.
+pub(super) async fn close_prediction_polls(state: &AppState, game_id: i64, result: GameResult) {
+    let polls = match db::get_game_prediction_polls(&state.db, game_id).await {
+        Ok(polls) => polls,
+        Err(e) => {
+            warn!(game_id = game_id, error = %e, "Failed to load prediction polls");
+            return;
+        }
+    };
+
+    for (poll_id, chat_id, message_id) in polls {
+        if let Err(e) = state.telegram.stop_poll(chat_id, message_id).await {
+            warn!(chat_id = chat_id, game_id = game_id, error = %e, "Failed to stop prediction poll");
+        }
+        if let Err(e) =
+            db::settle_prediction_poll(&state.db, &poll_id, chat_id, outcome_option(result)).await
+        {
+            warn!(chat_id = chat_id, game_id = game_id, error = %e, "Failed to settle prediction poll");
+        }
+    }
+}
+
+/// Records a spectator's vote. The players' own votes don't count.
+pub async fn handle_poll_answer(state: Arc<AppState>, answer: PollAnswer) -> Result<()> {
+    let Some(user) = &answer.user else {
+        return Ok(());
+    };
+    let Some(game_id) = db::get_prediction_poll_game(&state.db, &answer.poll_id).await? else {
+        return Ok(());
+    };
+    let Some(game) = db::get_game_by_id(&state.db, game_id).await? else {
+        return Ok(());
+    };
+
+    let voter = state.users.upsert(&state.db, user).await?;
+    if voter.id == game.white_user_id || voter.id == game.black_user_id {
+        return Ok(());
+    }
+
+    let choice = answer.option_ids.first().copied();
+    db::record_prediction(&state.db, &answer.poll_id, voter.id, choice).await?;
+    Ok(())
+}
+
+/// `/predictions`: the chat's most accurate spectators.
+pub async fn handle_predictions(state: Arc<AppState>, message: &Message) -> Result<()> {
+    let chat_id = message.chat.id;
+    let responder = &state.responder;
+    let standings = db::get_prediction_leaderboard(&state.db, chat_id, LEADERBOARD_SIZE).await?;
+    if standings.is_empty() {
+        responder.reply(message, "prediction.none", &[]).await?;
+        return Ok(());
+    }
+
+    let lines = standings
+        .iter()
+        .enumerate()
+        .map(|(index, (user, correct, total))| {
+            responder.text(
+                chat_id,
+                locale(message),
+                "prediction.line",
+                &[
+                    ("rank", &(index + 1).to_string()),
+                    ("player", &escape_html(&user.display_name())),
+                    ("correct", &correct.to_string()),
+                    ("total", &total.to_string()),
+                    ("percent", &(correct * 100 / (*total).max(1)).to_string()),
+                ],
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+    responder
+        .reply(message, "prediction.leaderboard", &[("lines", &lines)])
+        .await?;
+    Ok(())
+}
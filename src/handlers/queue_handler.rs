@@ -0,0 +1,113 @@
+use super::game_handler;
+use crate::game::Position;
+use crate::models::{DbUser, Message, User};
+use crate::{db, AppState};
+use anyhow::Result;
+use std::sync::Arc;
+use tracing::info;
+
+/// Expected score from the player's record, pulled towards 0.5 so a newcomer with one
+/// lucky win isn't treated as unbeatable. There are no ratings, so this stands in for one.
+fn strength(user: &DbUser) -> f64 {
+    let games = (user.wins + user.losses + user.draws) as f64;
+    (user.wins as f64 + user.draws as f64 / 2.0 + 1.0) / (games + 2.0)
+}
+
+/// `/queue` in a private chat: pairs the player with the waiting player closest in
+/// strength, or puts them in the queue. The game is then played over both private chats,
+/// the player who waited taking White.
+pub async fn handle_queue(state: Arc<AppState>, message: &Message, from: &User) -> Result<()> {
+    let chat_id = message.chat.id;
+    // In a private chat the chat id is the user's Telegram id
+    if chat_id != from.id {
+        state.responder.reply(message, "queue.private_only", &[]).await?;
+        return Ok(());
+    }
+
+    let player = state.users.upsert(&state.db, from).await?;
+    let queued = db::get_queued_players(&state.db).await?;
+    if queued.iter().any(|(user, _)| user.id == player.id) {
+        state.responder.reply(message, "queue.already", &[]).await?;
+        return Ok(());
+    }
+
+    let own = strength(&player);
+    let mut candidates: Vec<(DbUser, i64)> = queued;
+    // Stable, so among equally close players the one waiting longest goes first
+    candidates.sort_by(|(a, _), (b, _)| (strength(a) - own).abs().total_cmp(&(strength(b) - own).abs()));
+
+    for (opponent, opponent_chat) in candidates {
+        if db::has_ongoing_peer_game(&state.db, player.id, opponent.id).await? {
+            continue;
+        }
+        // Someone else may have been matched with them in the meantime
+        if !db::dequeue_player(&state.db, opponent.id).await? {
+            continue;
+        }
+
+        info!(white_id = opponent.id, black_id = player.id, "Matched queued players");
+        state
+            .responder
+            .reply(message, "queue.matched", &[("opponent", &opponent.mention_html())])
+            .await?;
+        let text = state
+            .responder
+            .text(opponent_chat, None, "queue.matched", &[("opponent", &player.mention_html())]);
+        state.responder.send_text(opponent_chat, None, &text).await?;
+
+        return game_handler::begin_game(
+            &state,
+            opponent_chat,
+            Some(chat_id),
+            None,
+            &opponent,
+            &player,
+            &Position::default(),
+            None,
+            false,
+        )
+        .await;
+    }
+
+    db::enqueue_player(&state.db, player.id, chat_id).await?;
+    state.responder.reply(message, "queue.waiting", &[]).await?;
+    Ok(())
+}
+
+/// `/unqueue`: stops waiting for a match.
+pub async fn handle_unqueue(state: Arc<AppState>, message: &Message, from: &User) -> Result<()> {
+    let player = state.users.upsert(&state.db, from).await?;
+    let id = if db::dequeue_player(&state.db, player.id).await? {
+        "queue.left"
+    } else {
+        "queue.not_queued"
+    };
+    state.responder.reply(message, id, &[]).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn user(wins: i64, losses: i64, draws: i64) -> DbUser {
+        DbUser {
+            id: 1,
+            telegram_id: None,
+            username: None,
+            first_name: None,
+            last_name: None,
+            wins,
+            losses,
+            draws,
+        }
+    }
+
+    #[test]
+    fn test_strength() {
+        assert_eq!(strength(&user(0, 0, 0)), 0.5);
+        assert_eq!(strength(&user(1, 1, 2)), 0.5);
+        assert!(strength(&user(1, 0, 0)) < strength(&user(10, 0, 0)));
+        assert!(strength(&user(0, 5, 0)) < 0.5);
+    }
+}
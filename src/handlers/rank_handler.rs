@@ -0,0 +1,197 @@
+use crate::models::{ChatSettings, ChatStanding, Message, User};
+use crate::responder::locale;
+use crate::utils::{escape_html, CROWN};
+use crate::{db, AppState};
+use anyhow::Result;
+use chrono::{Duration, Utc};
+use std::sync::Arc;
+use tracing::warn;
+
+/// Games the trend on `/rank` looks back over.
+const RECENT_GAMES: i64 = 10;
+
+/// Half points as shown to players: "4", "4.5".
+fn format_points(half_points: i64) -> String {
+    if half_points % 2 == 0 {
+        (half_points / 2).to_string()
+    } else {
+        format!("{}.5", half_points / 2)
+    }
+}
+
+/// The chat's standings, leaving out players below its `/settings mingames` threshold or
+/// inactive for longer than its `/settings inactivedays`.
+async fn chat_standings(state: &AppState, settings: &ChatSettings) -> Result<Vec<ChatStanding>> {
+    let min_games = settings.standings_min_games.unwrap_or(0);
+    let active_since = settings
+        .standings_inactive_days
+        .map(|days| Utc::now() - Duration::days(days));
+    Ok(db::get_chat_standings(&state.db, settings.chat_id, min_games, active_since).await?)
+}
+
+/// ↑ when the recent games were won more often than lost, ↓ when the other way round.
+fn trend_arrow(wins: i64, losses: i64) -> &'static str {
+    match wins.cmp(&losses) {
+        std::cmp::Ordering::Greater => "↑",
+        std::cmp::Ordering::Less => "↓",
+        std::cmp::Ordering::Equal => "→",
+    }
+}
+
+/// `/rank`: the caller's place in the chat's standings, their record there and how their
+/// last few games went.
+pub async fn handle_rank(state: Arc<AppState>, message: &Message, from: &User) -> Result<()> {
+    let chat_id = message.chat.id;
+    let locale = locale(message);
+    let responder = &state.responder;
+    let player = state.users.upsert(&state.db, from).await?;
+    let settings = db::get_chat_settings(&state.db, chat_id).await?;
+    let standings = chat_standings(&state, &settings).await?;
+    let Some((index, standing)) = standings
+        .iter()
+        .enumerate()
+        .find(|(_, standing)| standing.user.id == player.id)
+    else {
+        // Tell players who have games here why they aren't listed
+        let everyone = db::get_chat_standings(&state.db, chat_id, 0, None).await?;
+        let own = everyone.iter().find(|standing| standing.user.id == player.id);
+        match (own, settings.standings_min_games, settings.standings_inactive_days) {
+            (None, _, _) => responder.reply(message, "rank.none", &[]).await?,
+            (Some(own), Some(min), _) if own.games() < min => {
+                let (games, min) = (own.games().to_string(), min.to_string());
+                responder.reply(message, "rank.too_few_games", &[("games", &games), ("min", &min)]).await?
+            }
+            (Some(_), _, days) => {
+                let days = days.unwrap_or_default().to_string();
+                responder.reply(message, "rank.inactive", &[("days", &days)]).await?
+            }
+        };
+        return Ok(());
+    };
+
+    let champion = db::get_chat_champion(&state.db, chat_id).await?;
+    let crown = if champion == Some(player.id) {
+        format!(" {}", CROWN)
+    } else {
+        String::new()
+    };
+    let (wins, losses, draws) = db::get_recent_record(&state.db, chat_id, player.id, RECENT_GAMES).await?;
+    let recent = wins + losses + draws;
+    let text = responder.text(
+        chat_id,
+        locale,
+        "rank.summary",
+        &[
+            ("player", &escape_html(&player.display_name())),
+            ("crown", &crown),
+            ("position", &(index + 1).to_string()),
+            ("players", &standings.len().to_string()),
+            ("record", &format!("+{} -{} ={}", standing.wins, standing.losses, standing.draws)),
+            ("points", &format_points(standing.half_points())),
+            ("games", &standing.games().to_string()),
+            ("recent", &recent.to_string()),
+            ("recent_record", &format!("+{} -{} ={}", wins, losses, draws)),
+            ("trend", trend_arrow(wins, losses)),
+        ],
+    );
+    responder.send_text(chat_id, Some(message.message_id), &text).await?;
+    Ok(())
+}
+
+/// Who should hold the title: the top of the standings, unless the holder is level with
+/// them, since the lead only changes hands when someone moves ahead.
+fn next_champion(standings: &[ChatStanding], holder: Option<i64>) -> Option<i64> {
+    let top = standings.first()?;
+    let level = |standing: &ChatStanding| {
+        standing.half_points() == top.half_points() && standing.games() == top.games()
+    };
+    match holder.and_then(|id| standings.iter().find(|standing| standing.user.id == id)) {
+        Some(holder) if level(holder) => Some(holder.user.id),
+        _ => Some(top.user.id),
+    }
+}
+
+/// After a game in a group, hands the champion title to the new #1 of the standings and
+/// announces it. The first title in a chat is given quietly. Errors are logged, as the
+/// game is over either way.
+pub(super) async fn update_champion(state: &AppState, chat_id: i64, locale: Option<&str>) {
+    // Private chats have no standings to lead
+    if chat_id > 0 {
+        return;
+    }
+    if let Err(e) = try_update_champion(state, chat_id, locale).await {
+        warn!(chat_id = chat_id, error = %e, "Failed to update the chat champion");
+    }
+}
+
+async fn try_update_champion(state: &AppState, chat_id: i64, locale: Option<&str>) -> Result<()> {
+    let holder = db::get_chat_champion(&state.db, chat_id).await?;
+    let settings = db::get_chat_settings(&state.db, chat_id).await?;
+    let standings = chat_standings(state, &settings).await?;
+    let Some(champion) = next_champion(&standings, holder).filter(|id| Some(*id) != holder) else {
+        return Ok(());
+    };
+    db::set_chat_champion(&state.db, chat_id, champion).await?;
+    let Some(previous) = holder else {
+        return Ok(());
+    };
+
+    let champion = state.users.get_by_id(&state.db, champion).await?;
+    let previous = state.users.get_by_id(&state.db, previous).await?;
+    let text = state.responder.text(
+        chat_id,
+        locale,
+        "rank.new_champion",
+        &[("player", &champion.mention_html()), ("previous", &previous.mention_html())],
+    );
+    state.responder.send_text(chat_id, None, &text).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn standing(id: i64, wins: i64, losses: i64, draws: i64) -> ChatStanding {
+        ChatStanding {
+            user: crate::models::DbUser {
+                id,
+                telegram_id: Some(id),
+                username: None,
+                first_name: None,
+                last_name: None,
+                wins: 0,
+                losses: 0,
+                draws: 0,
+            },
+            wins,
+            losses,
+            draws,
+            last_played: None,
+        }
+    }
+
+    #[test]
+    fn test_next_champion() {
+        let standings = [standing(1, 2, 0, 1), standing(2, 2, 0, 1), standing(3, 1, 2, 0)];
+        assert_eq!(next_champion(&standings, None), Some(1));
+        // Level with the top keeps the title
+        assert_eq!(next_champion(&standings, Some(2)), Some(2));
+        assert_eq!(next_champion(&standings, Some(3)), Some(1));
+        assert_eq!(next_champion(&[], Some(3)), None);
+    }
+
+    #[test]
+    fn test_format_points() {
+        assert_eq!(format_points(0), "0");
+        assert_eq!(format_points(9), "4.5");
+        assert_eq!(format_points(10), "5");
+    }
+
+    #[test]
+    fn test_trend_arrow() {
+        assert_eq!(trend_arrow(6, 3), "↑");
+        assert_eq!(trend_arrow(2, 5), "↓");
+        assert_eq!(trend_arrow(4, 4), "→");
+    }
+}
@@ -0,0 +1,151 @@
+use super::game_handler;
+use crate::models::{CallbackQuery, InlineKeyboardButton, InlineKeyboardMarkup, Message, User};
+use crate::game::Position;
+use crate::responder::locale;
+use crate::{db, parsing, AppState};
+use anyhow::Result;
+use chrono::{Duration as ChronoDuration, Utc};
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::warn;
+
+fn seek_expiry() -> ChronoDuration {
+    let minutes = std::env::var("SEEK_EXPIRY_MINS")
+        .ok()
+        .and_then(|v| v.parse::<i64>().ok())
+        .filter(|n| *n > 0)
+        .unwrap_or(15);
+    ChronoDuration::minutes(minutes)
+}
+
+/// Posts an open challenge that anyone else in the chat can take with the Join button.
+pub async fn handle_seek(state: Arc<AppState>, message: &Message, from: &User, text: &str) -> Result<()> {
+    let chat_id = message.chat.id;
+    let challenger = state.users.upsert(&state.db, from).await?;
+
+    if db::find_open_seek(&state.db, chat_id, challenger.id).await?.is_some() {
+        state.responder.reply(message, "seek.already_open", &[]).await?;
+        return Ok(());
+    }
+
+    let confirm_moves = parsing::has_option(text, "confirm");
+    let expiry = seek_expiry();
+    let seek_id = db::create_seek(&state.db, chat_id, challenger.id, confirm_moves, Utc::now() + expiry).await?;
+
+    let button = state.responder.text(chat_id, locale(message), "seek.button_join", &[]);
+    let keyboard = InlineKeyboardMarkup {
+        inline_keyboard: vec![vec![InlineKeyboardButton::callback(&button, format!("join:{}", seek_id))]],
+    };
+    let minutes = expiry.num_minutes().to_string();
+    let message_id = state
+        .responder
+        .reply_with_keyboard(
+            message,
+            "seek.open",
+            &[("player", &challenger.mention_html()), ("minutes", &minutes)],
+            keyboard,
+        )
+        .await?;
+
+    db::set_seek_message(&state.db, seek_id, message_id).await?;
+
+    Ok(())
+}
+
+/// Starts the game for whoever taps Join first. The challenger plays White.
+pub async fn handle_join(state: Arc<AppState>, query: &CallbackQuery, seek_id: i64) -> Result<()> {
+    let Some(prompt) = &query.message else {
+        return Ok(());
+    };
+    let chat_id = prompt.chat.id;
+
+    let Some(seek) = db::get_open_seek(&state.db, seek_id).await? else {
+        state.responder.answer_callback(query, Some("seek.gone")).await?;
+        return Ok(());
+    };
+
+    let joiner = state.users.upsert(&state.db, &query.from).await?;
+    if joiner.id == seek.user_id {
+        state.responder.answer_callback(query, Some("seek.own")).await?;
+        return Ok(());
+    }
+
+    if let Some(refusal) = game_handler::game_limit_reached(&state, chat_id, seek.user_id, joiner.id).await? {
+        state.responder.answer_callback(query, Some(refusal)).await?;
+        return Ok(());
+    }
+
+    // Someone else may have joined between the lookup and now
+    if !db::take_seek(&state.db, seek.id).await? {
+        state.responder.answer_callback(query, Some("seek.gone")).await?;
+        return Ok(());
+    }
+    state.responder.answer_callback(query, None).await?;
+
+    let challenger = state.users.get_by_id(&state.db, seek.user_id).await?;
+    let locale = query.from.language_code.as_deref();
+    state
+        .responder
+        .edit(
+            chat_id,
+            prompt.message_id,
+            locale,
+            "seek.accepted",
+            &[("white", &challenger.mention_html()), ("black", &joiner.mention_html())],
+        )
+        .await?;
+
+    game_handler::begin_game(
+        &state,
+        chat_id,
+        None,
+        locale,
+        &challenger,
+        &joiner,
+        &Position::default(),
+        None,
+        seek.confirm_moves,
+    )
+    .await
+}
+
+/// Closes seeks nobody joined in time, replacing their button with a note.
+pub async fn run_seek_expiry_task(state: Arc<AppState>, interval: Duration) {
+    let mut ticker = tokio::time::interval(interval);
+    loop {
+        ticker.tick().await;
+        let expired = match db::get_expired_seeks(&state.db).await {
+            Ok(expired) => expired,
+            Err(e) => {
+                warn!(error = %e, "Failed to load expired seeks");
+                continue;
+            }
+        };
+
+        for seek in expired {
+            match db::take_seek(&state.db, seek.id).await {
+                Ok(true) => {}
+                Ok(false) => continue,
+                Err(e) => {
+                    warn!(seek_id = seek.id, error = %e, "Failed to close expired seek");
+                    continue;
+                }
+            }
+            let Some(message_id) = seek.message_id else {
+                continue;
+            };
+            if let Err(e) = state
+                .responder
+                .edit(seek.chat_id, message_id, None, "seek.expired", &[])
+                .await
+            {
+                warn!(
+                    chat_id = seek.chat_id,
+                    seek_id = seek.id,
+                    error = %e,
+                    "Failed to mark seek as expired"
+                );
+            }
+        }
+    }
+}
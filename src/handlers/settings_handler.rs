@@ -0,0 +1,410 @@
+use crate::game::{BoardOrientation, CoordinateStyle};
+use crate::models::{ChatSettings, Message, User};
+use crate::responder::{locale, placeholders, Responder};
+use crate::utils::escape_html;
+use crate::{db, AppState};
+use anyhow::Result;
+use std::sync::Arc;
+use tracing::warn;
+
+const MAX_END_TEMPLATE_CHARS: usize = 1000;
+
+/// The game-end announcements a chat can reword.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum EndTemplate {
+    Win,
+    Draw,
+}
+
+impl EndTemplate {
+    fn parse(key: &str) -> Option<Self> {
+        match key.to_ascii_lowercase().as_str() {
+            "win" => Some(Self::Win),
+            "draw" => Some(Self::Draw),
+            _ => None,
+        }
+    }
+
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Win => "win",
+            Self::Draw => "draw",
+        }
+    }
+
+    fn placeholders(self) -> &'static [&'static str] {
+        match self {
+            Self::Win => &["winner", "loser", "white", "black", "result", "moves", "announcement"],
+            Self::Draw => &["white", "black", "result", "moves", "announcement"],
+        }
+    }
+}
+
+pub async fn handle_settings(state: Arc<AppState>, message: &Message, from: &User, text: &str) -> Result<()> {
+    let chat_id = message.chat.id;
+    let args: Vec<&str> = text.split_whitespace().skip(1).collect();
+    let responder = &state.responder;
+    let locale = locale(message);
+
+    // Every setting applies to the whole chat; showing them stays open to everyone
+    if !args.is_empty() && !is_chat_admin(&state, chat_id, from.id).await {
+        responder.reply(message, "settings.admins_only", &[]).await?;
+        return Ok(());
+    }
+
+    // The template text is free-form, so it is taken from the raw text rather than `args`
+    if let Some(kind) = args.first().and_then(|key| EndTemplate::parse(key)) {
+        let response = set_end_template(&state, message, kind, after_words(text, 2)).await?;
+        responder.send_text(chat_id, Some(message.message_id), &response).await?;
+        return Ok(());
+    }
+
+    let response = match args.as_slice() {
+        [] => {
+            let settings = db::get_chat_settings(&state.db, chat_id).await?;
+            format_settings(responder, chat_id, locale, &settings)
+        }
+        [key, value] if is_coordinates_key(key) => match CoordinateStyle::parse(value) {
+            Some(style) => {
+                db::set_chat_coordinates(&state.db, chat_id, style.as_str()).await?;
+                responder.text(chat_id, locale, "settings.coordinates_set", &[("value", style.as_str())])
+            }
+            None => responder.text(chat_id, locale, "settings.coordinates_unknown", &[]),
+        },
+        [key, value] if key.eq_ignore_ascii_case("orientation") => {
+            match BoardOrientation::parse(value) {
+                Some(orientation) => {
+                    db::set_chat_orientation(&state.db, chat_id, orientation.as_str()).await?;
+                    responder.text(
+                        chat_id,
+                        locale,
+                        "settings.orientation_set",
+                        &[("value", orientation.as_str())],
+                    )
+                }
+                None => responder.text(chat_id, locale, "settings.orientation_unknown", &[]),
+            }
+        }
+        [key, value] if key.eq_ignore_ascii_case("hd") => match parse_switch(value) {
+            Some(enabled) => {
+                db::set_chat_send_as_document(&state.db, chat_id, enabled).await?;
+                let id = if enabled { "settings.hd_on" } else { "settings.hd_off" };
+                responder.text(chat_id, locale, id, &[])
+            }
+            None => responder.text(chat_id, locale, "settings.hd_usage", &[]),
+        },
+        [key, value] if key.eq_ignore_ascii_case("strict") => match parse_switch(value) {
+            Some(enabled) => {
+                db::set_chat_strict_notation(&state.db, chat_id, enabled).await?;
+                let id = if enabled {
+                    "settings.strict_on"
+                } else {
+                    "settings.strict_off"
+                };
+                responder.text(chat_id, locale, id, &[])
+            }
+            None => responder.text(chat_id, locale, "settings.strict_usage", &[]),
+        },
+        [key, value] if key.eq_ignore_ascii_case("polls") => match parse_switch(value) {
+            Some(enabled) => {
+                db::set_chat_prediction_polls(&state.db, chat_id, enabled).await?;
+                let id = if enabled { "settings.polls_on" } else { "settings.polls_off" };
+                responder.text(chat_id, locale, id, &[])
+            }
+            None => responder.text(chat_id, locale, "settings.polls_usage", &[]),
+        },
+        [key, value] if key.eq_ignore_ascii_case("announcements") => match parse_switch(value) {
+            Some(enabled) => {
+                db::set_chat_announcements(&state.db, chat_id, enabled).await?;
+                let id = if enabled {
+                    "settings.announcements_on"
+                } else {
+                    "settings.announcements_off"
+                };
+                responder.text(chat_id, locale, id, &[])
+            }
+            None => responder.text(chat_id, locale, "settings.announcements_usage", &[]),
+        },
+        [key, value] if key.eq_ignore_ascii_case("buttons") => match parse_switch(value) {
+            Some(enabled) => {
+                db::set_chat_tap_moves(&state.db, chat_id, enabled).await?;
+                let id = if enabled { "settings.buttons_on" } else { "settings.buttons_off" };
+                responder.text(chat_id, locale, id, &[])
+            }
+            None => responder.text(chat_id, locale, "settings.buttons_usage", &[]),
+        },
+        [key, value] if key.eq_ignore_ascii_case("pgn") => match parse_switch(value) {
+            Some(enabled) => {
+                db::set_chat_pgn_files(&state.db, chat_id, enabled).await?;
+                let id = if enabled { "settings.pgn_on" } else { "settings.pgn_off" };
+                responder.text(chat_id, locale, id, &[])
+            }
+            None => responder.text(chat_id, locale, "settings.pgn_usage", &[]),
+        },
+        [key, value] if key.eq_ignore_ascii_case("mingames") || key.eq_ignore_ascii_case("inactivedays") => {
+            set_standings_threshold(&state, chat_id, locale, key, value).await?
+        }
+        [key, value] if key.eq_ignore_ascii_case("maxgames") => {
+            set_max_games(&state, chat_id, locale, value).await?
+        }
+        [key, value] if key.eq_ignore_ascii_case("drawgap") => {
+            set_draw_offer_gap(&state, chat_id, locale, value).await?
+        }
+        _ => responder.text(chat_id, locale, "settings.usage", &[]),
+    };
+
+    responder.send_text(chat_id, Some(message.message_id), &response).await?;
+
+    Ok(())
+}
+
+/// `/settings win|draw <text|reset>`.
+async fn set_end_template(
+    state: &AppState,
+    message: &Message,
+    kind: EndTemplate,
+    template: &str,
+) -> Result<String> {
+    let chat_id = message.chat.id;
+    let responder = &state.responder;
+    let locale = locale(message);
+    let allowed = kind
+        .placeholders()
+        .iter()
+        .map(|name| format!("{{{name}}}"))
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    let template = match template {
+        "" => {
+            return Ok(responder.text(
+                chat_id,
+                locale,
+                "settings.end_template_usage",
+                &[("kind", kind.as_str()), ("placeholders", &allowed)],
+            ))
+        }
+        reset if reset.eq_ignore_ascii_case("reset") => None,
+        template => Some(template),
+    };
+
+    if let Some(template) = template {
+        if template.chars().count() > MAX_END_TEMPLATE_CHARS {
+            return Ok(responder.text(
+                chat_id,
+                locale,
+                "settings.end_template_too_long",
+                &[("max", &MAX_END_TEMPLATE_CHARS.to_string())],
+            ));
+        }
+        if let Some(unknown) = placeholders(template)
+            .into_iter()
+            .find(|name| !kind.placeholders().contains(name))
+        {
+            return Ok(responder.text(
+                chat_id,
+                locale,
+                "settings.end_template_unknown",
+                &[
+                    ("placeholder", &escape_html(&format!("{{{unknown}}}"))),
+                    ("placeholders", &allowed),
+                ],
+            ));
+        }
+    }
+
+    match kind {
+        EndTemplate::Win => db::set_chat_win_template(&state.db, chat_id, template).await?,
+        EndTemplate::Draw => db::set_chat_draw_template(&state.db, chat_id, template).await?,
+    }
+    let settings = db::get_chat_settings(&state.db, chat_id).await?;
+    responder.templates().set_chat_end_templates(&settings);
+
+    let id = if template.is_some() {
+        "settings.end_template_set"
+    } else {
+        "settings.end_template_reset"
+    };
+    Ok(responder.text(chat_id, locale, id, &[("kind", kind.as_str())]))
+}
+
+/// `/settings maxgames <number|off|reset>`: the most games the chat
+/// may have running at once, no limit, or back to the bot's default.
+async fn set_max_games(
+    state: &AppState,
+    chat_id: i64,
+    locale: Option<&str>,
+    value: &str,
+) -> Result<String> {
+    let responder = &state.responder;
+    let max_games = match value.to_ascii_lowercase().as_str() {
+        "reset" => None,
+        "off" => Some(0),
+        number => match number.parse::<i64>() {
+            Ok(n) if n > 0 => Some(n),
+            _ => return Ok(responder.text(chat_id, locale, "settings.maxgames_usage", &[])),
+        },
+    };
+
+    db::set_chat_max_games(&state.db, chat_id, max_games).await?;
+    Ok(match max_games {
+        None => responder.text(chat_id, locale, "settings.maxgames_reset", &[]),
+        Some(0) => responder.text(chat_id, locale, "settings.maxgames_off", &[]),
+        Some(n) => responder.text(chat_id, locale, "settings.maxgames_set", &[("value", &n.to_string())]),
+    })
+}
+
+/// `/settings drawgap <number|off|reset>`: the moves a player makes
+/// between two draw offers in a game, no limit, or back to the bot's default.
+async fn set_draw_offer_gap(
+    state: &AppState,
+    chat_id: i64,
+    locale: Option<&str>,
+    value: &str,
+) -> Result<String> {
+    let responder = &state.responder;
+    let gap = match value.to_ascii_lowercase().as_str() {
+        "reset" => None,
+        "off" => Some(0),
+        number => match number.parse::<i64>() {
+            Ok(n) if n > 0 => Some(n),
+            _ => return Ok(responder.text(chat_id, locale, "settings.drawgap_usage", &[])),
+        },
+    };
+
+    db::set_chat_draw_offer_gap(&state.db, chat_id, gap).await?;
+    Ok(match gap {
+        None => responder.text(chat_id, locale, "settings.drawgap_reset", &[]),
+        Some(0) => responder.text(chat_id, locale, "settings.drawgap_off", &[]),
+        Some(n) => responder.text(chat_id, locale, "settings.drawgap_set", &[("value", &n.to_string())]),
+    })
+}
+
+/// `/settings mingames <number|off>` and `/settings inactivedays <number|off>`: who is
+/// listed in the chat's standings.
+async fn set_standings_threshold(
+    state: &AppState,
+    chat_id: i64,
+    locale: Option<&str>,
+    key: &str,
+    value: &str,
+) -> Result<String> {
+    let responder = &state.responder;
+    let min_games = key.eq_ignore_ascii_case("mingames");
+    let threshold = match value.to_ascii_lowercase().as_str() {
+        "off" => None,
+        number => match number.parse::<i64>() {
+            Ok(n) if n > 0 => Some(n),
+            _ => return Ok(responder.text(chat_id, locale, "settings.standings_usage", &[])),
+        },
+    };
+
+    let id = match (min_games, threshold) {
+        (true, Some(_)) => "settings.mingames_set",
+        (true, None) => "settings.mingames_off",
+        (false, Some(_)) => "settings.inactivedays_set",
+        (false, None) => "settings.inactivedays_off",
+    };
+    if min_games {
+        db::set_chat_standings_min_games(&state.db, chat_id, threshold).await?;
+    } else {
+        db::set_chat_standings_inactive_days(&state.db, chat_id, threshold).await?;
+    }
+    Ok(responder.text(chat_id, locale, id, &[("value", &threshold.unwrap_or_default().to_string())]))
+}
+
+/// In a private chat the user owns the settings; in groups Telegram's admin list decides.
+pub(super) async fn is_chat_admin(state: &AppState, chat_id: i64, user_id: i64) -> bool {
+    if chat_id == user_id {
+        return true;
+    }
+    match state.telegram.get_chat_member(chat_id, user_id).await {
+        Ok(member) => member.is_admin(),
+        Err(e) => {
+            warn!(chat_id = chat_id, user_id = user_id, error = %e, "Could not check chat admin status");
+            false
+        }
+    }
+}
+
+/// `text` without its first `count` words, keeping the rest (line breaks included) as typed.
+fn after_words(text: &str, count: usize) -> &str {
+    let mut rest = text.trim_start();
+    for _ in 0..count {
+        rest = rest
+            .find(char::is_whitespace)
+            .map_or("", |end| rest[end..].trim_start());
+    }
+    rest.trim_end()
+}
+
+fn is_coordinates_key(key: &str) -> bool {
+    key.eq_ignore_ascii_case("coords") || key.eq_ignore_ascii_case("coordinates")
+}
+
+fn parse_switch(value: &str) -> Option<bool> {
+    match value.to_ascii_lowercase().as_str() {
+        "on" => Some(true),
+        "off" => Some(false),
+        _ => None,
+    }
+}
+
+fn format_settings(
+    responder: &Responder,
+    chat_id: i64,
+    locale: Option<&str>,
+    settings: &ChatSettings,
+) -> String {
+    let on_off = |enabled: bool| {
+        responder.text(chat_id, locale, if enabled { "settings.on" } else { "settings.off" }, &[])
+    };
+    let custom = |template: &Option<String>| {
+        let id = if template.is_some() { "settings.custom" } else { "settings.default" };
+        responder.text(chat_id, locale, id, &[])
+    };
+    let limit = |value: Option<i64>| match value {
+        None => responder.text(chat_id, locale, "settings.default", &[]),
+        Some(0) => responder.text(chat_id, locale, "settings.no_limit", &[]),
+        Some(n) => n.to_string(),
+    };
+    let threshold = |value: Option<i64>| match value {
+        Some(n) => n.to_string(),
+        None => responder.text(chat_id, locale, "settings.off", &[]),
+    };
+    let usage = responder.text(chat_id, locale, "settings.usage", &[]);
+    responder.text(
+        chat_id,
+        locale,
+        "settings.summary",
+        &[
+            ("coordinates", &settings.coordinates),
+            ("orientation", &settings.orientation),
+            ("hd", &on_off(settings.send_as_document)),
+            ("strict", &on_off(settings.strict_notation)),
+            ("polls", &on_off(settings.prediction_polls)),
+            ("announcements", &on_off(settings.announcements)),
+            ("buttons", &on_off(settings.tap_moves)),
+            ("pgn", &on_off(settings.pgn_files)),
+            ("maxgames", &limit(settings.max_games)),
+            ("drawgap", &limit(settings.draw_offer_gap)),
+            ("mingames", &threshold(settings.standings_min_games)),
+            ("inactivedays", &threshold(settings.standings_inactive_days)),
+            ("win", &custom(&settings.win_template)),
+            ("draw", &custom(&settings.draw_template)),
+            ("usage", &usage),
+        ],
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_after_words() {
+        assert_eq!(after_words("/settings win {winner} wins!", 2), "{winner} wins!");
+        assert_eq!(after_words("/settings  draw\nDraw.\nGG ", 2), "Draw.\nGG");
+        assert_eq!(after_words("/settings win", 2), "");
+    }
+}
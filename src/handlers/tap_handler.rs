@@ -0,0 +1,220 @@
+use super::game_handler;
+use crate::game::{Color, Move, Position, Role, Square};
+use crate::models::{CallbackQuery, GameStatus, InlineKeyboardButton, InlineKeyboardMarkup};
+use crate::{db, AppState};
+use anyhow::Result;
+use std::str::FromStr;
+use std::sync::Arc;
+
+/// Label of a square the selected piece can move to, and of the selected square itself.
+const DESTINATION: &str = "•";
+const EMPTY: &str = "·";
+
+/// The 8×8 square buttons under a running board, seen from the side to move since only
+/// they can tap. With a piece `selected`, its destinations are marked and tapping one plays
+/// the move; tapping the piece again puts it back.
+///
+/// Each button carries `tap:<game>:<ply>:<squares>`, where `ply` is the number of moves the
+/// board shows, so taps on an outdated board can be told apart.
+pub(super) fn board_keyboard(game_id: i64, ply: i64, board: &Position, selected: Option<Square>) -> InlineKeyboardMarkup {
+    let mover = board.side_to_move();
+    let destinations: Vec<Move> = match selected {
+        Some(from) => board.legal_moves().into_iter().filter(|mv| mv.from() == Some(from)).collect(),
+        None => Vec::new(),
+    };
+
+    let ranks: Vec<u32> = if mover == Color::White {
+        (0..8).rev().collect()
+    } else {
+        (0..8).collect()
+    };
+    let files: Vec<u32> = if mover == Color::White {
+        (0..8).collect()
+    } else {
+        (0..8).rev().collect()
+    };
+
+    let inline_keyboard = ranks
+        .iter()
+        .map(|&rank| {
+            files
+                .iter()
+                .map(|&file| {
+                    let square = Square::new(rank * 8 + file);
+                    let piece = board
+                        .piece_on(square)
+                        .zip(board.color_on(square))
+                        .map(|(role, color)| piece_symbol(color, role));
+                    let (label, squares) = match selected {
+                        Some(from) if from == square => (format!("({})", piece.unwrap_or(EMPTY)), "-".to_string()),
+                        Some(from) if destinations.iter().any(|mv| mv.to() == square) => {
+                            let label = piece.map_or(DESTINATION.to_string(), |piece| format!("×{}", piece));
+                            (label, format!("{}{}", from, square))
+                        }
+                        _ => (piece.unwrap_or(EMPTY).to_string(), square.to_string()),
+                    };
+                    InlineKeyboardButton::callback(&label, format!("tap:{}:{}:{}", game_id, ply, squares))
+                })
+                .collect()
+        })
+        .collect();
+    InlineKeyboardMarkup { inline_keyboard }
+}
+
+fn piece_symbol(color: Color, role: Role) -> &'static str {
+    match (color, role) {
+        (Color::White, Role::King) => "♔",
+        (Color::White, Role::Queen) => "♕",
+        (Color::White, Role::Rook) => "♖",
+        (Color::White, Role::Bishop) => "♗",
+        (Color::White, Role::Knight) => "♘",
+        (Color::White, Role::Pawn) => "♙",
+        (Color::Black, Role::King) => "♚",
+        (Color::Black, Role::Queen) => "♛",
+        (Color::Black, Role::Rook) => "♜",
+        (Color::Black, Role::Bishop) => "♝",
+        (Color::Black, Role::Knight) => "♞",
+        (Color::Black, Role::Pawn) => "♟",
+    }
+}
+
+/// The move a `from`-`to` tap pair stands for. Pawns reaching the last rank become queens;
+/// other promotions still have to be typed.
+fn tapped_move(board: &Position, from: Square, to: Square) -> Option<Move> {
+    board
+        .legal_moves()
+        .into_iter()
+        .filter(|mv| mv.from() == Some(from) && mv.to() == to)
+        .max_by_key(|mv| mv.promotion() == Some(Role::Queen))
+}
+
+/// A square button was tapped: `squares` is what the button carries after the game id.
+/// Picking a piece redraws the keyboard with its destinations; picking a destination plays
+/// the move right away, even in games that confirm typed moves. The move is only stored if
+/// the position is still the one the board showed.
+pub async fn handle_tap(state: Arc<AppState>, query: &CallbackQuery, game_id: i64, squares: &str) -> Result<()> {
+    let Some(prompt) = &query.message else {
+        return Ok(());
+    };
+    let chat_id = prompt.chat.id;
+    let responder = &state.responder;
+
+    let Some((ply, squares)) = squares.split_once(':').and_then(|(ply, rest)| Some((ply.parse::<i64>().ok()?, rest)))
+    else {
+        responder.answer_callback(query, None).await?;
+        return Ok(());
+    };
+    let current = match db::get_game_by_id(&state.db, game_id).await? {
+        Some(game) if game.status == GameStatus::Ongoing => {
+            let last_ply = db::get_last_move(&state.db, game.id).await?.map_or(0, |(ply, _)| ply);
+            (last_ply == ply).then_some(game)
+        }
+        _ => None,
+    };
+    let Some(game) = current else {
+        responder.answer_callback(query, Some("tap.stale")).await?;
+        return Ok(());
+    };
+
+    let board = Position::from_str(&game.current_fen)?;
+    let player = state.users.upsert(&state.db, &query.from).await?;
+    if player.id != game_handler::expected_player_id(&game, &board) {
+        responder.answer_callback(query, Some("tap.not_your_turn")).await?;
+        return Ok(());
+    }
+    if db::get_game_pause(&state.db, game.id).await?.0 {
+        responder.answer_callback(query, Some("game.paused")).await?;
+        return Ok(());
+    }
+
+    let selection = match squares {
+        "-" => Some(None),
+        square => Square::from_str(square)
+            .ok()
+            .filter(|&square| board.legal_moves().iter().any(|mv| mv.from() == Some(square)))
+            .map(Some),
+    };
+    if let Some(selected) = selection {
+        let keyboard = board_keyboard(game.id, ply, &board, selected);
+        state
+            .telegram
+            .edit_message_reply_markup(chat_id, prompt.message_id, Some(&keyboard))
+            .await?;
+        responder.answer_callback(query, None).await?;
+        return Ok(());
+    }
+
+    let mv = squares
+        .get(..2)
+        .zip(squares.get(2..))
+        .and_then(|(from, to)| Some((Square::from_str(from).ok()?, Square::from_str(to).ok()?)))
+        .and_then(|(from, to)| tapped_move(&board, from, to));
+    let Some(mv) = mv else {
+        responder.answer_callback(query, Some("tap.pick_piece")).await?;
+        return Ok(());
+    };
+
+    // The played board keeps no buttons; the new one brings its own
+    state
+        .telegram
+        .edit_message_reply_markup(chat_id, prompt.message_id, None)
+        .await?;
+    let uci = board.uci(mv);
+    let locale = query.from.language_code.as_deref();
+    let committed =
+        game_handler::commit_move(state.clone(), chat_id, prompt.message_id, locale, game, &player, &board, mv, &uci, None)
+            .await?;
+    if committed {
+        state.metrics.record_move(chat_id, true);
+        responder.answer_callback(query, None).await?;
+    } else {
+        // A second tap on the same destination, or a move typed meanwhile
+        responder.answer_callback(query, Some("tap.stale")).await?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn labels(keyboard: &InlineKeyboardMarkup, row: usize) -> Vec<&str> {
+        keyboard.inline_keyboard[row].iter().map(|b| b.text.as_str()).collect()
+    }
+
+    #[test]
+    fn test_board_keyboard_from_side_to_move() {
+        let board = Position::default();
+        let keyboard = board_keyboard(7, 0, &board, None);
+        assert_eq!(keyboard.inline_keyboard.len(), 8);
+        assert_eq!(labels(&keyboard, 0), vec!["♜", "♞", "♝", "♛", "♚", "♝", "♞", "♜"]);
+        assert_eq!(keyboard.inline_keyboard[7][6].callback_data, "tap:7:0:g1");
+
+        let black_to_move = board.play(board.parse_san("e4").unwrap());
+        let keyboard = board_keyboard(7, 1, &black_to_move, None);
+        assert_eq!(labels(&keyboard, 0), vec!["♖", "♘", "♗", "♔", "♕", "♗", "♘", "♖"]);
+        assert_eq!(keyboard.inline_keyboard[7][0].callback_data, "tap:7:1:h8");
+    }
+
+    #[test]
+    fn test_board_keyboard_marks_destinations() {
+        let board = Position::default();
+        let g1 = Square::from_str("g1").unwrap();
+        let keyboard = board_keyboard(7, 0, &board, Some(g1));
+        // Rank 3 is the sixth row from White's side
+        assert_eq!(keyboard.inline_keyboard[5][5].text, DESTINATION);
+        assert_eq!(keyboard.inline_keyboard[5][5].callback_data, "tap:7:0:g1f3");
+        assert_eq!(keyboard.inline_keyboard[7][6].text, "(♘)");
+        assert_eq!(keyboard.inline_keyboard[7][6].callback_data, "tap:7:0:-");
+    }
+
+    #[test]
+    fn test_tapped_move_promotes_to_queen() {
+        let board = Position::from_str("8/4P3/8/8/8/8/k7/4K3 w - - 0 1").unwrap();
+        let e7 = Square::from_str("e7").unwrap();
+        let e8 = Square::from_str("e8").unwrap();
+        let mv = tapped_move(&board, e7, e8).unwrap();
+        assert_eq!(mv.promotion(), Some(Role::Queen));
+        assert_eq!(tapped_move(&board, e7, Square::from_str("e6").unwrap()), None);
+    }
+}
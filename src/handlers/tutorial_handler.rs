@@ -0,0 +1,197 @@
+use crate::game::{self, Move, Position};
+use crate::models::{Message, User};
+use crate::responder::locale;
+use crate::utils::escape_html;
+use crate::AppState;
+use anyhow::Result;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, OnceLock};
+
+/// The walkthrough's moves: what the player is asked to play and Black's answer.
+const STEPS: &[(&str, Option<&str>)] = &[
+    ("e4", Some("e5")),
+    ("Nf3", Some("Nc6")),
+    ("Bc4", Some("Bc5")),
+    ("O-O", None),
+];
+const STEP_TEMPLATES: [&str; 4] = ["tutorial.step1", "tutorial.step2", "tutorial.step3", "tutorial.step4"];
+
+/// A walkthrough in progress: the step, the position, and the board message to reply to.
+#[derive(Clone)]
+struct Tutorial {
+    step: usize,
+    board: Position,
+    prompt_id: i64,
+}
+
+/// Running walkthroughs per private chat. They are short, so losing them on restart is fine.
+static TUTORIALS: OnceLock<Mutex<HashMap<i64, Tutorial>>> = OnceLock::new();
+
+fn tutorials() -> std::sync::MutexGuard<'static, HashMap<i64, Tutorial>> {
+    TUTORIALS
+        .get_or_init(Default::default)
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+}
+
+/// `/rules`: a short summary of how chess is played.
+pub async fn handle_rules(state: Arc<AppState>, message: &Message) -> Result<()> {
+    state.responder.reply(message, "rules", &[]).await?;
+    Ok(())
+}
+
+/// `/notation`: how to type moves, with examples.
+pub async fn handle_notation(state: Arc<AppState>, message: &Message) -> Result<()> {
+    state.responder.reply(message, "notation", &[]).await?;
+    Ok(())
+}
+
+/// `/tutorial` in a private chat: walks a new player through their first moves, one
+/// board at a time.
+pub async fn handle_tutorial(state: Arc<AppState>, message: &Message, from: &User) -> Result<()> {
+    let chat_id = message.chat.id;
+    // In a private chat the chat id is the user's Telegram id
+    if chat_id != from.id {
+        state.responder.reply(message, "tutorial.private_only", &[]).await?;
+        return Ok(());
+    }
+
+    let board = Position::default();
+    let prompt_id = send_step(&state, message, &board, 0, None).await?;
+    tutorials().insert(chat_id, Tutorial { step: 0, board, prompt_id });
+    Ok(())
+}
+
+/// Takes a reply to the latest walkthrough board. Returns false when the message isn't
+/// one, so the caller can treat it as a game move.
+pub async fn handle_tutorial_reply(state: Arc<AppState>, message: &Message, text: &str) -> Result<bool> {
+    let chat_id = message.chat.id;
+    let reply_id = message.reply_to_message.as_ref().map(|msg| msg.message_id);
+    let Some(tutorial) = tutorials().get(&chat_id).filter(|t| Some(t.prompt_id) == reply_id).cloned() else {
+        return Ok(false);
+    };
+
+    let responder = &state.responder;
+    let (expected_san, answer) = STEPS[tutorial.step];
+    let mv = match game::parse_move(&tutorial.board, text.trim()) {
+        Ok(mv) => mv,
+        Err(err) => {
+            responder
+                .reply(message, "tutorial.illegal", &[("error", &escape_html(&err.to_string()))])
+                .await?;
+            return Ok(true);
+        }
+    };
+    if tutorial.board.parse_san(expected_san) != Some(mv) {
+        let played = tutorial.board.san(mv);
+        responder
+            .reply(message, "tutorial.other_move", &[("move", &played), ("expected", expected_san)])
+            .await?;
+        return Ok(true);
+    }
+
+    let san = tutorial.board.san(mv);
+    let uci = tutorial.board.uci(mv);
+    let mut board = tutorial.board.play(mv);
+    let mut last_move = mv;
+    // Black always answers with the scripted move
+    let answer_san = answer.and_then(|answer| board.parse_san(answer)).map(|reply| {
+        let san = board.san(reply);
+        board = board.play(reply);
+        last_move = reply;
+        san
+    });
+
+    let step = tutorial.step + 1;
+    let feedback = responder.text(
+        chat_id,
+        locale(message),
+        "tutorial.played",
+        &[("san", &san), ("uci", &uci)],
+    );
+    let feedback = match &answer_san {
+        Some(answer) => format!(
+            "{}\n{}",
+            feedback,
+            responder.text(chat_id, locale(message), "tutorial.answer", &[("move", answer)])
+        ),
+        None => feedback,
+    };
+
+    if step == STEPS.len() {
+        tutorials().remove(&chat_id);
+        let done = responder.text(chat_id, locale(message), "tutorial.done", &[]);
+        let caption = format!("{}\n\n{}", feedback, done);
+        send_board(&state, chat_id, message.message_id, &board, Some(last_move), &caption).await?;
+        return Ok(true);
+    }
+
+    let prompt_id = send_step(&state, message, &board, step, Some(&feedback)).await?;
+    tutorials().insert(chat_id, Tutorial { step, board, prompt_id });
+    Ok(true)
+}
+
+/// Sends the board for `step` with its instructions, after the feedback on the last move
+/// if there is one. An arrow shows the move to play.
+async fn send_step(
+    state: &AppState,
+    message: &Message,
+    board: &Position,
+    step: usize,
+    feedback: Option<&str>,
+) -> Result<i64> {
+    let chat_id = message.chat.id;
+    let instructions = state
+        .responder
+        .text(chat_id, locale(message), STEP_TEMPLATES[step], &[]);
+    let caption = match feedback {
+        Some(feedback) => format!("{}\n\n{}", feedback, instructions),
+        None => instructions,
+    };
+    let hint = board.parse_san(STEPS[step].0);
+    send_board(state, chat_id, message.message_id, board, hint, &caption).await
+}
+
+async fn send_board(
+    state: &AppState,
+    chat_id: i64,
+    reply_to: i64,
+    board: &Position,
+    arrow: Option<Move>,
+    caption: &str,
+) -> Result<i64> {
+    let overlay = match arrow.and_then(|mv| Some((mv.from()?, mv.to()))) {
+        Some((from, to)) => game::BoardOverlay::new().with_arrow(from, to),
+        None => game::BoardOverlay::new(),
+    };
+    let options = game::RenderOptions {
+        flip_board: false,
+        coordinates: game::CoordinateStyle::default(),
+        overlay,
+        info: None,
+        encoding: state.image_encoding,
+    };
+    let image = game::render_board(board, &options).await?;
+    let message_id = state
+        .telegram
+        .send_photo(chat_id, Some(reply_to), caption, image, state.image_encoding.format, None)
+        .await?;
+    Ok(message_id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_steps_are_legal_in_sequence() {
+        let mut board = Position::default();
+        for (mv, answer) in STEPS {
+            board = board.play(board.parse_san(mv).unwrap());
+            if let Some(answer) = answer {
+                board = board.play(board.parse_san(answer).unwrap());
+            }
+        }
+        assert_eq!(STEPS.len(), STEP_TEMPLATES.len());
+    }
+}
@@ -1,8 +1,16 @@
-use super::{game_handler, help_handler, history_handler};
-use crate::models::Update;
+use super::{
+    activity_handler, admin_handler, audit_handler, eval_handler, game_handler, help_handler, history_handler,
+    membership_handler, ongoing_handler, pgn_handler, prediction_handler, queue_handler, rank_handler, seek_handler,
+    settings_handler, tap_handler, tutorial_handler,
+};
+use crate::error::KamaError;
+use crate::models::{CallbackQuery, Message, Update, User};
+use crate::parsing;
 use crate::AppState;
 use anyhow::Result;
+use std::sync::atomic::Ordering;
 use std::sync::Arc;
+use tracing::{error, info_span, warn, Instrument};
 
 fn strip_bot_suffix<'a>(text: &'a str, bot_username: &str) -> &'a str {
     let trimmed = text.trim();
@@ -20,10 +28,146 @@ fn command_matches(text: &str, command: &str, bot_username: &str) -> bool {
     stripped.eq_ignore_ascii_case(command)
 }
 
+/// The command of a message that takes arguments, e.g. `/legal@bot` in `/legal@bot e2`.
+fn command_word(text: &str) -> &str {
+    text.split_whitespace().next().unwrap_or_default()
+}
+
+/// Commands counted under their own name in the usage metrics; anything else is `other`.
+const METERED_COMMANDS: &[&str] = &[
+    "start", "seek", "queue", "unqueue", "help", "rules", "notation", "tutorial", "history", "settings",
+    "predictions", "resign", "draw", "accept", "acceptdraw", "confirm", "eval", "flip", "legal", "broadcast",
+    "feature", "maintenance", "audit", "botstats", "merge", "ongoing", "rank", "activity", "move", "pgnall",
+    "forceresult", "pause", "resume",
+];
+
+/// The metrics label of a message the bot handles: the command name, or `move` for other
+/// replies to the bot.
+fn command_label(text: &str, bot_username: &str) -> &'static str {
+    let Some(command) = strip_bot_suffix(command_word(text), bot_username).strip_prefix('/') else {
+        return "move";
+    };
+    METERED_COMMANDS
+        .iter()
+        .find(|known| known.eq_ignore_ascii_case(command))
+        .copied()
+        .unwrap_or("other")
+}
+
+/// Read-only commands that can take a while (database scans, the engine). Under load they
+/// wait behind moves and other updates that change a game.
+const BACKGROUND_COMMANDS: &[&str] =
+    &["history", "rank", "activity", "ongoing", "predictions", "eval", "botstats", "audit", "pgnall"];
+
+/// Whether the update is a slow read-only command that can wait for gameplay updates.
+pub fn is_background_update(update: &Update, bot_username: &str) -> bool {
+    let Some(text) = update.message.as_ref().and_then(|message| message.text.as_deref()) else {
+        return false;
+    };
+    let label = command_label(text, bot_username);
+    BACKGROUND_COMMANDS.contains(&label)
+}
+
+/// Splits inline button payloads of the form `action:id[:rest]`, where the id is a game's,
+/// or a seek's for `join`. `rest` is empty for actions that need nothing more.
+fn parse_callback_data(data: &str) -> Option<(&str, i64, &str)> {
+    let (action, rest) = data.split_once(':')?;
+    let (id, rest) = rest.split_once(':').unwrap_or((rest, ""));
+    Some((action, id.parse().ok()?, rest))
+}
+
+async fn process_callback_query(state: Arc<AppState>, query: CallbackQuery) -> Result<()> {
+    let Some((action, game_id, rest)) = query.data.as_deref().and_then(parse_callback_data) else {
+        state.responder.answer_callback(&query, None).await?;
+        return Ok(());
+    };
+
+    if state.maintenance.load(Ordering::Relaxed) {
+        state.responder.answer_callback(&query, Some("maintenance")).await?;
+        return Ok(());
+    }
+
+    match action {
+        "confirm" | "cancel" => {
+            game_handler::handle_move_confirmation(state, &query, game_id, action == "confirm").await
+        }
+        "join" => seek_handler::handle_join(state, &query, game_id).await,
+        "tap" => tap_handler::handle_tap(state, &query, game_id, rest).await,
+        "repost" => ongoing_handler::handle_repost(state, &query, game_id).await,
+        "claim" | "abort" => {
+            membership_handler::handle_left_player_choice(state, &query, game_id, action == "claim").await
+        }
+        _ => {
+            state.responder.answer_callback(&query, None).await?;
+            Ok(())
+        }
+    }
+}
+
+/// Short reference for an update, shown to users with unexpected errors and logged with
+/// everything done for the update, so a report can be matched with the logs.
+pub fn error_ref(update_id: i64) -> String {
+    format!("{:x}", update_id)
+}
+
+/// Handles one update inside an `update` span, so everything logged on its behalf carries
+/// the update and chat ids and the error reference. Failures are logged in the span too.
 pub async fn process_update(state: Arc<AppState>, update: Update) -> Result<()> {
+    let chat_id = update
+        .message
+        .as_ref()
+        .map(|message| message.chat.id)
+        .or_else(|| update.callback_query.as_ref()?.message.as_ref().map(|m| m.chat.id))
+        .or_else(|| update.my_chat_member.as_ref().map(|member| member.chat.id));
+    let span = info_span!(
+        "update",
+        update_id = update.update_id,
+        error_ref = %error_ref(update.update_id),
+        chat_id = tracing::field::Empty
+    );
+    if let Some(chat_id) = chat_id {
+        span.record("chat_id", chat_id);
+    }
+    async move {
+        let result = dispatch_update(state, update).await;
+        if let Err(err) = &result {
+            error!("Failed to process update: {err:?}");
+        }
+        result
+    }
+    .instrument(span)
+    .await
+}
+
+async fn dispatch_update(state: Arc<AppState>, update: Update) -> Result<()> {
+    if let Some(query) = update.callback_query {
+        let sender = query.from.id;
+        let chat_id = query.message.as_ref().map(|m| m.chat.id);
+        let data = query.data.clone().unwrap_or_default();
+        let result = process_callback_query(state.clone(), query).await;
+        if let Some(chat_id) = chat_id {
+            state.metrics.record_command(chat_id, "button");
+            audit_handler::record(&state, Some(sender), chat_id, None, &data, &audit_outcome(&result)).await;
+        }
+        return result;
+    }
+    if let Some(answer) = update.poll_answer {
+        return prediction_handler::handle_poll_answer(state, answer).await;
+    }
+    if let Some(member) = update.my_chat_member {
+        return membership_handler::handle_my_chat_member(state, member).await;
+    }
+
+    let error_ref = error_ref(update.update_id);
     let Some(message) = update.message else {
         return Ok(());
     };
+    if let Some(left) = &message.left_chat_member {
+        return membership_handler::handle_left_chat_member(state, &message, left).await;
+    }
+    if let Some(members) = &message.new_chat_members {
+        return membership_handler::handle_new_chat_members(state, &message, members).await;
+    }
     let Some(text) = &message.text else {
         return Ok(());
     };
@@ -35,49 +179,244 @@ pub async fn process_update(state: Arc<AppState>, update: Update) -> Result<()>
         return Ok(());
     }
 
-    if text.starts_with("/help") {
-        help_handler::handle_help(state, &message).await?;
-        return Ok(());
+    let chat_id = message.chat.id;
+    let message_id = message.message_id;
+    let locale = from.language_code.as_deref();
+    // Plain chat messages aren't meant for the bot, so only commands and replies are audited
+    let audited = text.starts_with('/') || replies_to_bot(&message);
+    let result = route_message(state.clone(), &message, from, text).await;
+    if audited {
+        state.metrics.record_command(chat_id, command_label(text, &state.bot_username));
+        audit_handler::record(&state, Some(from.id), chat_id, None, text, &audit_outcome(&result)).await;
     }
 
-    if text.starts_with("/history") {
-        history_handler::handle_history(state, &message, from, text).await?;
-        return Ok(());
+    // Rule violations and the like are the user's to fix, so tell them instead of only logging
+    if let Err(err) = &result {
+        if let Some(kama) = err.downcast_ref::<KamaError>() {
+            if kama.status_code().is_client_error() {
+                let text = crate::utils::escape_html(&kama.user_message());
+                state
+                    .responder
+                    .send(chat_id, message_id, locale, "error", &[("message", &text)])
+                    .await?;
+                return Ok(());
+            }
+        }
+        // Anything else is ours; give whoever asked a reference to report
+        if audited {
+            let reply = state
+                .responder
+                .send(chat_id, message_id, locale, "error.internal", &[("ref", &error_ref)])
+                .await;
+            if let Err(e) = reply {
+                warn!(error = %e, "Failed to report the error to the user");
+            }
+        }
     }
+    result
+}
 
-    let replied_to_bot = message
+/// How a command went, as stored in the audit log.
+fn audit_outcome(result: &Result<()>) -> String {
+    match result {
+        Ok(()) => "ok".to_string(),
+        Err(err) => match err.downcast_ref::<KamaError>() {
+            Some(kama) if kama.status_code().is_client_error() => format!("rejected: {}", kama.user_message()),
+            _ => format!("error: {}", err),
+        },
+    }
+}
+
+fn replies_to_bot(message: &Message) -> bool {
+    message
         .reply_to_message
         .as_ref()
         .and_then(|msg| msg.from.as_ref())
         .map(|user| user.is_bot)
-        .unwrap_or(false);
+        .unwrap_or(false)
+}
+
+async fn route_message(state: Arc<AppState>, message: &Message, from: &User, text: &str) -> Result<()> {
+    if text.starts_with("/help") {
+        help_handler::handle_help(state, message).await?;
+        return Ok(());
+    }
+
+    if text.starts_with("/rules") {
+        tutorial_handler::handle_rules(state, message).await?;
+        return Ok(());
+    }
+
+    if text.starts_with("/notation") {
+        tutorial_handler::handle_notation(state, message).await?;
+        return Ok(());
+    }
+
+    if text.starts_with("/tutorial") {
+        tutorial_handler::handle_tutorial(state, message, from).await?;
+        return Ok(());
+    }
+
+    if text.starts_with("/history") {
+        history_handler::handle_history(state, message, from, text).await?;
+        return Ok(());
+    }
+
+    if text.starts_with("/pgnall") {
+        pgn_handler::handle_pgn_all(state, message, from, text).await?;
+        return Ok(());
+    }
+
+    if text.starts_with("/ongoing") {
+        ongoing_handler::handle_ongoing(state, message, from).await?;
+        return Ok(());
+    }
+
+    if text.starts_with("/rank") {
+        rank_handler::handle_rank(state, message, from).await?;
+        return Ok(());
+    }
+
+    if text.starts_with("/activity") {
+        activity_handler::handle_activity(state, message).await?;
+        return Ok(());
+    }
+
+    if text.starts_with("/predictions") {
+        prediction_handler::handle_predictions(state, message).await?;
+        return Ok(());
+    }
+
+    if text.starts_with("/settings") {
+        settings_handler::handle_settings(state, message, from, text).await?;
+        return Ok(());
+    }
+
+    if text.starts_with("/broadcast") {
+        admin_handler::handle_broadcast(state, message, from, text).await?;
+        return Ok(());
+    }
+
+    if text.starts_with("/feature") {
+        admin_handler::handle_feature(state, message, from, text).await?;
+        return Ok(());
+    }
+
+    if text.starts_with("/maintenance") {
+        admin_handler::handle_maintenance(state, message, from, text).await?;
+        return Ok(());
+    }
+
+    if text.starts_with("/botstats") {
+        admin_handler::handle_botstats(state, message, from).await?;
+        return Ok(());
+    }
+
+    if text.starts_with("/audit") {
+        audit_handler::handle_audit(state, message, from, text).await?;
+        return Ok(());
+    }
+
+    if text.starts_with("/merge") {
+        admin_handler::handle_merge(state, message, from, text).await?;
+        return Ok(());
+    }
+
+    let replied_to_bot = replies_to_bot(message);
+
+    if state.maintenance.load(Ordering::Relaxed) {
+        let gameplay = ["/start", "/seek", "/queue", "/unqueue"];
+        if replied_to_bot || gameplay.iter().any(|command| text.starts_with(command)) {
+            state.responder.reply(message, "maintenance", &[]).await?;
+        }
+        return Ok(());
+    }
 
     if text.starts_with("/start") {
-        game_handler::handle_start_game(state, &message, from, text).await?;
+        game_handler::handle_start_game(state, message, from, text).await?;
+        return Ok(());
+    }
+
+    if text.starts_with("/queue") {
+        queue_handler::handle_queue(state, message, from).await?;
+        return Ok(());
+    }
+
+    if text.starts_with("/unqueue") {
+        queue_handler::handle_unqueue(state, message, from).await?;
+        return Ok(());
+    }
+
+    if text.starts_with("/seek") {
+        seek_handler::handle_seek(state, message, from, text).await?;
         return Ok(());
     }
 
     if replied_to_bot {
+        if tutorial_handler::handle_tutorial_reply(state.clone(), message, text).await? {
+            return Ok(());
+        }
+
         if command_matches(text, "/resign", &state.bot_username) {
-            game_handler::handle_resign(state, &message, from).await?;
+            game_handler::handle_resign(state, message, from).await?;
             return Ok(());
         }
 
         if command_matches(text, "/draw", &state.bot_username) {
-            game_handler::handle_draw_proposal(state, &message, from).await?;
+            game_handler::handle_draw_proposal(state, message, from).await?;
             return Ok(());
         }
 
         if command_matches(text, "/accept", &state.bot_username)
             || command_matches(text, "/acceptdraw", &state.bot_username)
         {
-            game_handler::handle_accept_draw(state, &message, from).await?;
+            game_handler::handle_accept_draw(state, message, from).await?;
+            return Ok(());
+        }
+
+        if command_matches(text, "/pause", &state.bot_username) {
+            game_handler::handle_pause(state, message, from).await?;
+            return Ok(());
+        }
+
+        if command_matches(text, "/resume", &state.bot_username) {
+            game_handler::handle_resume(state, message, from).await?;
+            return Ok(());
+        }
+
+        if command_matches(text, "/confirm", &state.bot_username) {
+            game_handler::handle_toggle_confirmation(state, message, from).await?;
             return Ok(());
         }
 
+        if command_matches(text, "/eval", &state.bot_username) {
+            eval_handler::handle_eval(state, message, from).await?;
+            return Ok(());
+        }
+
+        if command_matches(text, "/flip", &state.bot_username) {
+            game_handler::handle_flip(state, message).await?;
+            return Ok(());
+        }
 
+        if command_matches(command_word(text), "/forceresult", &state.bot_username) {
+            game_handler::handle_force_result(state, message, from, text).await?;
+            return Ok(());
+        }
 
-        game_handler::handle_move(state, &message, from, text).await?;
+        if command_matches(command_word(text), "/legal", &state.bot_username) {
+            game_handler::handle_legal(state, message, text).await?;
+            return Ok(());
+        }
+
+        if command_matches(command_word(text), "/move", &state.bot_username) {
+            if let Some((mv, comment)) = parsing::split_move_comment(text) {
+                game_handler::handle_move(state, message, from, &mv, comment.as_deref()).await?;
+            }
+            return Ok(());
+        }
+
+        game_handler::handle_move(state, message, from, text, None).await?;
         return Ok(());
     }
 
@@ -88,6 +427,44 @@ pub async fn process_update(state: Arc<AppState>, update: Update) -> Result<()>
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_command_label() {
+        assert_eq!(command_label("/start@testbot @rival", "testbot"), "start");
+        assert_eq!(command_label("/RESIGN", "testbot"), "resign");
+        assert_eq!(command_label("/whatever", "testbot"), "other");
+        assert_eq!(command_label("e4", "testbot"), "move");
+    }
+
+    #[test]
+    fn test_is_background_update() {
+        let update = |text: &str| -> Update {
+            serde_json::from_value(serde_json::json!({
+                "update_id": 1,
+                "message": { "message_id": 1, "chat": { "id": -1 }, "text": text }
+            }))
+            .unwrap()
+        };
+        assert!(is_background_update(&update("/history@testbot 2"), "testbot"));
+        assert!(is_background_update(&update("/EVAL"), "testbot"));
+        assert!(!is_background_update(&update("/resign"), "testbot"));
+        assert!(!is_background_update(&update("Nf3"), "testbot"));
+        let callback: Update = serde_json::from_value(serde_json::json!({ "update_id": 2 })).unwrap();
+        assert!(!is_background_update(&callback, "testbot"));
+    }
+
+    #[test]
+    fn test_error_ref() {
+        assert_eq!(error_ref(123456789), "75bcd15");
+    }
+
+    #[test]
+    fn test_command_word() {
+        assert_eq!(command_word("/legal e2"), "/legal");
+        assert!(command_matches(command_word("  /legal@testbot  e2"), "/legal", "testbot"));
+        assert!(command_matches(command_word("/legal"), "/legal", "testbot"));
+        assert_eq!(command_word(""), "");
+    }
+
     #[test]
     fn test_strip_bot_suffix() {
         assert_eq!(strip_bot_suffix("/resign@testbot", "testbot"), "/resign");
@@ -114,6 +491,16 @@ mod tests {
         assert!(command_matches("/acceptdraw@mybot", "/acceptdraw", "mybot"));
     }
 
+    #[test]
+    fn test_parse_callback_data() {
+        assert_eq!(parse_callback_data("confirm:42"), Some(("confirm", 42, "")));
+        assert_eq!(parse_callback_data("cancel:7"), Some(("cancel", 7, "")));
+        assert_eq!(parse_callback_data("join:3"), Some(("join", 3, "")));
+        assert_eq!(parse_callback_data("tap:5:12:e2e4"), Some(("tap", 5, "12:e2e4")));
+        assert_eq!(parse_callback_data("confirm"), None);
+        assert_eq!(parse_callback_data("confirm:abc"), None);
+    }
+
     #[test]
     fn test_command_matches_draw() {
         assert!(command_matches("/draw", "/draw", "chessbot"));
@@ -1,13 +1,23 @@
 pub mod api;
+pub mod archive;
+pub mod backup;
 pub mod db;
+pub mod engine;
+pub mod error;
 pub mod game;
 pub mod handlers;
+pub mod live;
+pub mod logging;
+pub mod metrics;
 pub mod models;
 pub mod parsing;
+pub mod responder;
 pub mod server;
 pub mod utils;
 
 use sqlx::{Any, Pool};
+use std::sync::atomic::AtomicBool;
+use std::sync::Arc;
 
 #[derive(Clone)]
 pub struct AppState {
@@ -15,4 +25,16 @@ pub struct AppState {
     pub telegram: api::TelegramApi,
     pub bot_username: String,
     pub no_trash: bool,
+    pub image_encoding: game::ImageEncoding,
+    /// `None` when no engine is configured.
+    pub engine: Option<engine::Engine>,
+    pub users: db::UserCache,
+    pub responder: responder::Responder,
+    /// While set, gameplay commands are answered with a maintenance notice. Starts from
+    /// `MAINTENANCE_MODE` and admins can flip it with /maintenance.
+    pub maintenance: Arc<AtomicBool>,
+    /// Per-chat command and move counters for /metrics and /botstats.
+    pub metrics: Arc<metrics::CommandMetrics>,
+    /// Moves and results as they happen, for the API's live stream.
+    pub live: Arc<live::LiveFeed>,
 }
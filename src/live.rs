@@ -0,0 +1,96 @@
+//! Game updates as they happen, for the `/api/games/:id/stream` feed. Handlers publish
+//! every played move and game end; each stream subscriber picks out its own game.
+
+use serde::Serialize;
+use tokio::sync::broadcast;
+
+/// Updates a slow subscriber may fall behind by before it starts missing some.
+const CHANNEL_CAPACITY: usize = 256;
+
+/// Seconds each side has spent on its moves so far. Games have no clock, so this is what
+/// overlays can show in its place.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct Clocks {
+    pub white_secs: i64,
+    pub black_secs: i64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum LiveEvent {
+    Move {
+        game_id: i64,
+        ply: i64,
+        san: String,
+        uci: String,
+        fen: String,
+        /// Side to move after this move, `white` or `black`.
+        turn: &'static str,
+        clocks: Clocks,
+    },
+    End {
+        game_id: i64,
+        status: &'static str,
+        /// PGN result; `*` for abandoned games.
+        result: &'static str,
+    },
+}
+
+impl LiveEvent {
+    pub fn game_id(&self) -> i64 {
+        match self {
+            Self::Move { game_id, .. } | Self::End { game_id, .. } => *game_id,
+        }
+    }
+
+    /// The SSE event name.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Move { .. } => "move",
+            Self::End { .. } => "end",
+        }
+    }
+}
+
+pub struct LiveFeed {
+    sender: broadcast::Sender<LiveEvent>,
+}
+
+impl Default for LiveFeed {
+    fn default() -> Self {
+        Self {
+            sender: broadcast::channel(CHANNEL_CAPACITY).0,
+        }
+    }
+}
+
+impl LiveFeed {
+    /// Sends the event to every current subscriber; with none it is dropped.
+    pub fn publish(&self, event: LiveEvent) {
+        let _ = self.sender.send(event);
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<LiveEvent> {
+        self.sender.subscribe()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_publish_reaches_subscribers() {
+        let feed = LiveFeed::default();
+        feed.publish(LiveEvent::End { game_id: 1, status: "finished", result: "1-0" });
+
+        let mut receiver = feed.subscribe();
+        let event = LiveEvent::End { game_id: 2, status: "abandoned", result: "*" };
+        feed.publish(event.clone());
+        assert_eq!(receiver.recv().await.unwrap(), event);
+        assert_eq!(
+            serde_json::to_value(&event).unwrap(),
+            serde_json::json!({ "type": "end", "game_id": 2, "status": "abandoned", "result": "*" })
+        );
+    }
+}
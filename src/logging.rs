@@ -0,0 +1,356 @@
+//! Log file writer with rotation. The live log is always `kamachess.log`; when it gets
+//! too big or a new day starts it is renamed to `kamachess.log.<timestamp>` (gzipped if
+//! configured) and a fresh file is opened. Only the newest rotated files are kept.
+//!
+//! With `LOG_FORMAT=json` every line is a JSON object instead, with the fields of the
+//! event and of its enclosing spans (`update_id`, `chat_id`, `game_id`, ...) as top-level
+//! keys, ready for Loki or Elasticsearch.
+
+use std::env;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use chrono::{NaiveDate, SecondsFormat, Utc};
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use serde_json::{Map, Value};
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id, Record};
+use tracing::{Event, Subscriber};
+use tracing_subscriber::fmt::MakeWriter;
+use tracing_subscriber::layer::Context;
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::Layer;
+
+pub const LOG_FILE_NAME: &str = "kamachess.log";
+
+#[derive(Debug, Clone)]
+pub struct LogConfig {
+    pub dir: PathBuf,
+    /// Rotate once the live file reaches this many bytes. `None` disables size rotation.
+    pub max_size: Option<u64>,
+    /// Rotate when the date (UTC) changes.
+    pub daily: bool,
+    /// Rotated files kept; older ones are deleted.
+    pub max_files: usize,
+    pub compress: bool,
+}
+
+impl LogConfig {
+    /// Reads `LOG_DIR` (logs), `LOG_MAX_SIZE_MB` (unset: no size limit), `LOG_ROTATE_DAILY`
+    /// (true), `LOG_MAX_FILES` (14) and `LOG_COMPRESS` (false).
+    pub fn from_env() -> Self {
+        let flag = |name: &str, default: bool| {
+            env::var(name)
+                .map(|v| matches!(v.trim().to_ascii_lowercase().as_str(), "1" | "true" | "on"))
+                .unwrap_or(default)
+        };
+        Self {
+            dir: PathBuf::from(env::var("LOG_DIR").unwrap_or_else(|_| "logs".to_string())),
+            max_size: env::var("LOG_MAX_SIZE_MB")
+                .ok()
+                .and_then(|v| v.trim().parse::<u64>().ok())
+                .filter(|mb| *mb > 0)
+                .map(|mb| mb * 1024 * 1024),
+            daily: flag("LOG_ROTATE_DAILY", true),
+            max_files: env::var("LOG_MAX_FILES")
+                .ok()
+                .and_then(|v| v.trim().parse().ok())
+                .unwrap_or(14),
+            compress: flag("LOG_COMPRESS", false),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogFormat {
+    Text,
+    Json,
+}
+
+impl LogFormat {
+    /// Reads `LOG_FORMAT`: `json`, or the human-readable text format for anything else.
+    pub fn from_env() -> Self {
+        match env::var("LOG_FORMAT") {
+            Ok(v) if v.trim().eq_ignore_ascii_case("json") => Self::Json,
+            _ => Self::Text,
+        }
+    }
+}
+
+/// `io::Write` for the log file; meant to sit behind `tracing_appender::non_blocking`, so
+/// rotation and compression happen off the async runtime.
+pub struct RotatingWriter {
+    config: LogConfig,
+    file: File,
+    size: u64,
+    opened_on: NaiveDate,
+}
+
+impl RotatingWriter {
+    pub fn new(config: LogConfig) -> io::Result<Self> {
+        fs::create_dir_all(&config.dir)?;
+        let (file, size) = open_log(&config.dir)?;
+        Ok(Self {
+            config,
+            file,
+            size,
+            opened_on: Utc::now().date_naive(),
+        })
+    }
+
+    fn needs_rotation(&self, incoming: usize) -> bool {
+        let too_big = self
+            .config
+            .max_size
+            .is_some_and(|max| self.size > 0 && self.size + incoming as u64 > max);
+        let new_day = self.config.daily && Utc::now().date_naive() != self.opened_on;
+        too_big || new_day
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        self.file.flush()?;
+        let live = self.config.dir.join(LOG_FILE_NAME);
+        let rotated = self.config.dir.join(format!(
+            "{}.{}",
+            LOG_FILE_NAME,
+            Utc::now().format("%Y%m%d-%H%M%S%.9f")
+        ));
+        fs::rename(&live, &rotated)?;
+        (self.file, self.size) = open_log(&self.config.dir)?;
+        self.opened_on = Utc::now().date_naive();
+
+        if self.config.compress {
+            compress(&rotated)?;
+        }
+        prune(&self.config.dir, self.config.max_files)
+    }
+}
+
+impl Write for RotatingWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.needs_rotation(buf.len()) {
+            // A failed rotation shouldn't lose the line; keep writing to the current file
+            if let Err(e) = self.rotate() {
+                eprintln!("log rotation failed: {}", e);
+            }
+        }
+        let written = self.file.write(buf)?;
+        self.size += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+fn open_log(dir: &Path) -> io::Result<(File, u64)> {
+    let file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(dir.join(LOG_FILE_NAME))?;
+    let size = file.metadata()?.len();
+    Ok((file, size))
+}
+
+/// Gzips `path` to `path.gz` and removes the original.
+fn compress(path: &Path) -> io::Result<()> {
+    let mut target = path.as_os_str().to_owned();
+    target.push(".gz");
+
+    let mut encoder = GzEncoder::new(File::create(target)?, Compression::default());
+    io::copy(&mut File::open(path)?, &mut encoder)?;
+    encoder.finish()?;
+    fs::remove_file(path)
+}
+
+/// Keeps the `keep` newest rotated files. Their timestamps sort them oldest first.
+fn prune(dir: &Path, keep: usize) -> io::Result<()> {
+    let prefix = format!("{}.", LOG_FILE_NAME);
+    let mut rotated: Vec<PathBuf> = fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| {
+            path.file_name()
+                .and_then(|name| name.to_str())
+                .is_some_and(|name| name.starts_with(&prefix))
+        })
+        .collect();
+    rotated.sort();
+
+    let excess = rotated.len().saturating_sub(keep);
+    for path in &rotated[..excess] {
+        fs::remove_file(path)?;
+    }
+    Ok(())
+}
+
+/// Writes each event as one line of JSON: `timestamp`, `level`, `target`, then the span
+/// fields from the outermost span in, then the event's own fields (`message` included).
+pub struct JsonLayer<W> {
+    make_writer: W,
+}
+
+impl<W> JsonLayer<W> {
+    pub fn new(make_writer: W) -> Self {
+        Self { make_writer }
+    }
+}
+
+/// Span fields, kept in the span's extensions until its events need them.
+struct SpanFields(Map<String, Value>);
+
+struct JsonVisitor<'a>(&'a mut Map<String, Value>);
+
+impl Visit for JsonVisitor<'_> {
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.0.insert(field.name().to_string(), value.into());
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.0.insert(field.name().to_string(), value.into());
+    }
+
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        self.0.insert(field.name().to_string(), value.into());
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.0.insert(field.name().to_string(), value.into());
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.0.insert(field.name().to_string(), value.into());
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        self.0.insert(field.name().to_string(), format!("{:?}", value).into());
+    }
+}
+
+impl<S, W> Layer<S> for JsonLayer<W>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    W: for<'w> MakeWriter<'w> + 'static,
+{
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(id) else {
+            return;
+        };
+        let mut fields = Map::new();
+        attrs.record(&mut JsonVisitor(&mut fields));
+        span.extensions_mut().insert(SpanFields(fields));
+    }
+
+    fn on_record(&self, id: &Id, values: &Record<'_>, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(id) else {
+            return;
+        };
+        let mut extensions = span.extensions_mut();
+        if let Some(SpanFields(fields)) = extensions.get_mut::<SpanFields>() {
+            values.record(&mut JsonVisitor(fields));
+        }
+    }
+
+    fn on_event(&self, event: &Event<'_>, ctx: Context<'_, S>) {
+        let metadata = event.metadata();
+        let mut line = Map::new();
+        line.insert(
+            "timestamp".to_string(),
+            Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true).into(),
+        );
+        line.insert("level".to_string(), metadata.level().as_str().into());
+        line.insert("target".to_string(), metadata.target().into());
+
+        if let Some(scope) = ctx.event_scope(event) {
+            for span in scope.from_root() {
+                if let Some(SpanFields(fields)) = span.extensions().get::<SpanFields>() {
+                    line.extend(fields.iter().map(|(k, v)| (k.clone(), v.clone())));
+                }
+            }
+        }
+        event.record(&mut JsonVisitor(&mut line));
+
+        let mut bytes = Value::Object(line).to_string().into_bytes();
+        bytes.push(b'\n');
+        let _ = self.make_writer.make_writer().write_all(&bytes);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+
+    #[test]
+    fn test_rotates_by_size_and_prunes() {
+        let dir = env::temp_dir().join(format!("kamachess-logs-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let mut writer = RotatingWriter::new(LogConfig {
+            dir: dir.clone(),
+            max_size: Some(16),
+            daily: false,
+            max_files: 2,
+            compress: true,
+        })
+        .unwrap();
+
+        for line in ["first line 0001\n", "second line 002\n", "third line 0003\n", "last line 00004\n"] {
+            writer.write_all(line.as_bytes()).unwrap();
+        }
+        writer.flush().unwrap();
+
+        assert_eq!(fs::read_to_string(dir.join(LOG_FILE_NAME)).unwrap(), "last line 00004\n");
+        let mut rotated: Vec<PathBuf> = fs::read_dir(&dir)
+            .unwrap()
+            .map(|e| e.unwrap().path())
+            .filter(|p| p.extension().is_some_and(|ext| ext == "gz"))
+            .collect();
+        rotated.sort();
+        assert_eq!(rotated.len(), 2);
+
+        let mut contents = String::new();
+        flate2::read::GzDecoder::new(File::open(&rotated[1]).unwrap())
+            .read_to_string(&mut contents)
+            .unwrap();
+        assert_eq!(contents, "third line 0003\n");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[derive(Clone, Default)]
+    struct Buffer(std::sync::Arc<std::sync::Mutex<Vec<u8>>>);
+
+    impl Write for Buffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_json_layer_flattens_span_fields() {
+        use tracing_subscriber::prelude::*;
+
+        let buffer = Buffer::default();
+        let writer = buffer.clone();
+        let subscriber = tracing_subscriber::registry().with(JsonLayer::new(move || writer.clone()));
+        tracing::subscriber::with_default(subscriber, || {
+            let span = tracing::info_span!("update", update_id = 7_i64, chat_id = tracing::field::Empty);
+            span.record("chat_id", -100_i64);
+            let _entered = span.enter();
+            tracing::warn!(game_id = 3, "Move rejected");
+        });
+
+        let output = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+        let line: Value = serde_json::from_str(output.trim()).unwrap();
+        assert_eq!(line["level"], "WARN");
+        assert_eq!(line["message"], "Move rejected");
+        assert_eq!(line["update_id"], 7);
+        assert_eq!(line["chat_id"], -100);
+        assert_eq!(line["game_id"], 3);
+    }
+}
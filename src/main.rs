@@ -1,80 +1,289 @@
 use anyhow::{anyhow, Result};
-use kamachess::{api, db, server, AppState};
-use sqlx::any::AnyPoolOptions;
-use std::{env, sync::Arc};
-use tracing::info;
+use kamachess::{api, archive, backup, db, engine, game, handlers, logging, responder, server, AppState};
+use std::sync::atomic::AtomicBool;
+use std::{env, net::SocketAddr, sync::Arc, time::Duration};
+use tracing::{info, warn};
 use tracing_subscriber::prelude::*;
 
 #[tokio::main]
 async fn main() -> Result<()> {
     dotenv::dotenv().ok();
 
-    let log_dir = env::var("LOG_DIR").unwrap_or_else(|_| "logs".to_string());
-    std::fs::create_dir_all(&log_dir)?;
-    let file_appender = tracing_appender::rolling::daily(&log_dir, "kamachess.log");
+    let file_appender = logging::RotatingWriter::new(logging::LogConfig::from_env())?;
     let (non_blocking, _log_guard) = tracing_appender::non_blocking(file_appender);
     let env_filter = tracing_subscriber::EnvFilter::from_default_env();
+    let json = logging::LogFormat::from_env() == logging::LogFormat::Json;
 
     tracing_subscriber::registry()
         .with(env_filter)
-        .with(tracing_subscriber::fmt::layer())
-        .with(
+        .with((!json).then(tracing_subscriber::fmt::layer))
+        .with((!json).then(|| {
             tracing_subscriber::fmt::layer()
-                .with_writer(non_blocking)
-                .with_ansi(false),
-        )
+                .with_writer(non_blocking.clone())
+                .with_ansi(false)
+        }))
+        .with(json.then(|| logging::JsonLayer::new(std::io::stdout)))
+        .with(json.then(|| logging::JsonLayer::new(non_blocking)))
         .init();
 
-    let bot_token = env::var("TELEGRAM_BOT_TOKEN")
-        .map_err(|_| anyhow!("TELEGRAM_BOT_TOKEN environment variable is required"))?;
-    let bot_username = env::var("TELEGRAM_BOT_USERNAME")
-        .map_err(|_| anyhow!("TELEGRAM_BOT_USERNAME environment variable is required"))?
-        .trim_start_matches('@')
-        .to_string();
+    let image_encoding = game::ImageEncoding::from_env();
+    let warm_plies = env::var("CACHE_WARM_PLIES")
+        .ok()
+        .and_then(|v| v.parse::<usize>().ok())
+        .unwrap_or(6);
+
+    // `kamachess warm-cache` renders the opening positions and exits
+    if env::args().any(|arg| arg == "warm-cache") {
+        let count = game::warm_cache(warm_plies, image_encoding).await?;
+        info!(count = count, "Cache warmup finished");
+        return Ok(());
+    }
+
     let database_url = env::var("DATABASE_URL")
         .unwrap_or_else(|_| "sqlite://kamachess.db?mode=rwc".to_string());
     
     let no_trash = !env::args().any(|arg| arg == "--keep-messages");
+    let maintenance = env::var("MAINTENANCE_MODE")
+        .map(|v| matches!(v.trim().to_ascii_lowercase().as_str(), "1" | "true" | "on"))
+        .unwrap_or(false);
 
     sqlx::any::install_default_drivers();
 
-    let pool = AnyPoolOptions::new()
-        .max_connections(5)
-        .connect(&database_url)
-        .await?;
+    let pool_config = db::PoolConfig::from_env();
+    info!(
+        max_connections = pool_config.max_connections,
+        acquire_timeout_secs = pool_config.acquire_timeout.as_secs(),
+        "Connecting to database"
+    );
+    let pool = pool_config.connect(&database_url).await?;
 
     db::run_migrations(&pool, &database_url).await?;
 
+    // `kamachess archive-games [months]` archives old finished games once and exits
+    let mut args = env::args().skip_while(|arg| arg != "archive-games");
+    if args.next().is_some() {
+        let months = args
+            .next()
+            .map(|v| v.parse::<u32>())
+            .transpose()
+            .map_err(|_| anyhow!("archive-games takes a number of months"))?
+            .or_else(|| archive::ArchiveConfig::from_env().map(|config| config.after_months))
+            .filter(|n| *n > 0)
+            .ok_or_else(|| anyhow!("archive-games needs a number of months or ARCHIVE_AFTER_MONTHS"))?;
+        let count = archive::archive_games(&pool, months).await?;
+        info!(count = count, months = months, "Game archiving finished");
+        return Ok(());
+    }
+
+    let bot_token = env::var("TELEGRAM_BOT_TOKEN")
+        .map_err(|_| anyhow!("TELEGRAM_BOT_TOKEN environment variable is required"))?;
+    let bot_username = env::var("TELEGRAM_BOT_USERNAME")
+        .map_err(|_| anyhow!("TELEGRAM_BOT_USERNAME environment variable is required"))?
+        .trim_start_matches('@')
+        .to_string();
+
+    if let Some(config) = backup::BackupConfig::from_env()? {
+        info!(
+            schedule = %config.schedule.pattern,
+            dir = %config.dir.display(),
+            keep = config.keep,
+            "Database backups scheduled"
+        );
+        tokio::spawn(backup::run_backup_task(pool.clone(), database_url.clone(), config));
+    }
+
+    if let Some(config) = archive::ArchiveConfig::from_env() {
+        info!(
+            after_months = config.after_months,
+            interval_hours = config.interval.as_secs() / 3600,
+            "Game archiving scheduled"
+        );
+        tokio::spawn(archive::run_archive_task(pool.clone(), config));
+    }
+
+    let engine = engine::EngineConfig::from_env().map(|config| {
+        info!(
+            path = %config.path.display(),
+            threads = config.threads,
+            hash_mb = config.hash_mb,
+            max_workers = config.max_workers,
+            "Engine configured"
+        );
+        engine::Engine::new(config)
+    });
+
+    let user_cache_ttl = env::var("USER_CACHE_TTL_SECS")
+        .ok()
+        .and_then(|v| v.parse::<u64>().ok())
+        .map_or(db::DEFAULT_USER_CACHE_TTL, Duration::from_secs);
+
+    let templates = match env::var("TEMPLATES_DIR").ok().filter(|dir| !dir.is_empty()) {
+        Some(dir) => {
+            let templates = responder::Templates::load_dir(std::path::Path::new(&dir))?;
+            info!(dir = %dir, locales = ?templates.locales().collect::<Vec<_>>(), "Message templates loaded");
+            templates
+        }
+        None => responder::Templates::default(),
+    };
+
+    for settings in db::get_chats_with_end_templates(&pool).await? {
+        templates.set_chat_end_templates(&settings);
+    }
+
+    let api_server = env::var("TELEGRAM_API_BASE_URL")
+        .ok()
+        .filter(|url| !url.trim().is_empty())
+        .unwrap_or_else(|| api::telegram::CLOUD_API_URL.to_string());
+    let http_config = api::HttpConfig::from_env();
+    if http_config.proxy.is_some() {
+        // Not the URL itself, which may carry credentials
+        info!("Telegram calls go through TELEGRAM_PROXY");
+    }
+    let telegram = api::TelegramApi::with_server(&api_server, &bot_token).with_http(&http_config)?;
+    let me = telegram
+        .get_me()
+        .await
+        .map_err(|e| anyhow!("Telegram Bot API at {} rejected getMe: {}", api_server, e))?;
+    if me.username.as_deref() != Some(bot_username.as_str()) {
+        warn!(
+            configured = %bot_username,
+            actual = ?me.username,
+            "TELEGRAM_BOT_USERNAME doesn't match the bot's account"
+        );
+    }
+    info!(
+        server = %api_server,
+        max_upload_mb = telegram.max_upload_bytes() / (1024 * 1024),
+        "Telegram Bot API reachable"
+    );
     let state = Arc::new(AppState {
         db: pool,
-        telegram: api::TelegramApi::new(bot_token),
+        responder: responder::Responder::new(telegram.clone(), templates),
+        telegram,
         bot_username,
         no_trash,
+        image_encoding,
+        engine,
+        users: db::UserCache::new(user_cache_ttl),
+        maintenance: Arc::new(AtomicBool::new(maintenance)),
+        metrics: Default::default(),
+        live: Default::default(),
     });
     
     if !no_trash {
         info!("Keep-messages mode: previous board messages will be kept during gameplay");
     }
+    if maintenance {
+        info!("Maintenance mode: gameplay commands are paused");
+    }
+
+    // Drop broken or outdated entries before anything is served or warmed, and
+    // before the cleanup task, whose first sweep would hold the lock and skip the scan
+    match tokio::task::spawn_blocking(|| game::verify_cache(&game::cache_dir())).await {
+        Ok(Err(e)) => warn!(error = %e, "Cache integrity scan failed"),
+        Err(e) => warn!(error = %e, "Cache integrity scan panicked"),
+        Ok(Ok(_)) => {}
+    }
+
+    let cleanup_interval = env::var("IMAGE_CACHE_CLEANUP_INTERVAL_SECS")
+        .ok()
+        .and_then(|v| v.parse::<u64>().ok())
+        .filter(|secs| *secs > 0)
+        .unwrap_or(600);
+    let cache_ttl = env::var("IMAGE_CACHE_TTL_HOURS")
+        .ok()
+        .and_then(|v| v.parse::<u64>().ok())
+        .unwrap_or(24 * 7);
+    let cache_ttl = (cache_ttl > 0).then(|| Duration::from_secs(cache_ttl * 3600));
+    tokio::spawn(game::run_cleanup_task(
+        Duration::from_secs(cleanup_interval),
+        cache_ttl,
+    ));
+
+    tokio::spawn(handlers::run_seek_expiry_task(
+        state.clone(),
+        Duration::from_secs(60),
+    ));
+
+    if warm_plies > 0 {
+        tokio::spawn(async move {
+            if let Err(e) = game::warm_cache(warm_plies, image_encoding).await {
+                warn!(error = %e, "Cache warmup failed");
+            }
+        });
+    }
 
     let webhook_url = env::var("WEBHOOK_URL")
         .map_err(|_| anyhow!("WEBHOOK_URL environment variable is required"))?;
-    let webhook_port = env::var("WEBHOOK_PORT")
-        .unwrap_or_else(|_| "8080".to_string())
-        .parse::<u16>()
-        .map_err(|_| anyhow!("WEBHOOK_PORT must be a valid port number"))?;
-    let webhook_path = env::var("WEBHOOK_PATH")
-        .unwrap_or_else(|_| "/webhook".to_string());
-    let webhook_secret_token = env::var("WEBHOOK_SECRET_TOKEN").ok();
+    let listen_addr = match env::var("WEBHOOK_LISTEN_ADDR").ok().filter(|addr| !addr.trim().is_empty()) {
+        Some(addr) => addr.trim().parse::<SocketAddr>().map_err(|_| {
+            anyhow!("WEBHOOK_LISTEN_ADDR must be an address with a port, e.g. [::]:8443 or 127.0.0.1:8080")
+        })?,
+        None => {
+            let port = env::var("WEBHOOK_PORT")
+                .unwrap_or_else(|_| "8080".to_string())
+                .parse::<u16>()
+                .map_err(|_| anyhow!("WEBHOOK_PORT must be a valid port number"))?;
+            SocketAddr::from(([0, 0, 0, 0], port))
+        }
+    };
+    let (webhook_url, webhook_path) = match env::var("WEBHOOK_PATH").ok().filter(|path| !path.trim().is_empty()) {
+        Some(path) => (webhook_url, path),
+        None => {
+            let (url, path) = server::random_webhook_route(&webhook_url)?;
+            info!(webhook_path = %path, "No WEBHOOK_PATH set; generated a random one");
+            (url, path)
+        }
+    };
+    let secret_token = env::var("WEBHOOK_SECRET_TOKEN").ok().filter(|token| !token.is_empty());
+    let mut webhook_config = server::WebhookConfig::new(secret_token.clone());
+    if let Some(secs) = env::var("WEBHOOK_SECRET_GRACE_SECS").ok().and_then(|v| v.trim().parse::<u64>().ok()) {
+        webhook_config = webhook_config.with_grace(Duration::from_secs(secs));
+    }
+    if let Some(previous) = env::var("WEBHOOK_SECRET_TOKEN_PREVIOUS").ok().filter(|token| !token.is_empty()) {
+        webhook_config = webhook_config.with_previous(previous);
+    }
+    let certificate = match env::var("WEBHOOK_CERTIFICATE").ok().filter(|path| !path.trim().is_empty()) {
+        Some(path) => {
+            let pem = std::fs::read(&path)
+                .map_err(|e| anyhow!("Failed to read WEBHOOK_CERTIFICATE {}: {}", path, e))?;
+            if !String::from_utf8_lossy(&pem).contains("-----BEGIN CERTIFICATE-----") {
+                return Err(anyhow!("WEBHOOK_CERTIFICATE {} is not a PEM certificate", path));
+            }
+            Some(pem)
+        }
+        None => None,
+    };
+    let drop_pending_updates = env::var("WEBHOOK_DROP_PENDING_UPDATES")
+        .map(|v| matches!(v.trim().to_ascii_lowercase().as_str(), "1" | "true" | "on"))
+        .unwrap_or(false);
+    let max_connections = match env::var("WEBHOOK_MAX_CONNECTIONS").ok().filter(|v| !v.trim().is_empty()) {
+        Some(value) => Some(
+            value
+                .trim()
+                .parse::<u32>()
+                .ok()
+                .filter(|n| (1..=100).contains(n))
+                .ok_or_else(|| anyhow!("WEBHOOK_MAX_CONNECTIONS must be between 1 and 100"))?,
+        ),
+        None => None,
+    };
 
     info!("Starting webhook server...");
-    info!(webhook_url = %webhook_url, webhook_port = webhook_port, webhook_path = %webhook_path, "Webhook configuration");
+    info!(webhook_url = %webhook_url, listen_addr = %listen_addr, webhook_path = %webhook_path, "Webhook configuration");
 
     server::start_webhook_server(
         state,
         webhook_url,
-        webhook_port,
+        listen_addr,
         webhook_path,
-        webhook_secret_token,
+        api::WebhookOptions {
+            secret_token,
+            certificate,
+            drop_pending_updates,
+            max_connections,
+        },
+        webhook_config,
     )
     .await
 }
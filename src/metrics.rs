@@ -0,0 +1,134 @@
+//! In-memory usage counters per chat: commands by name, and moves split into accepted and
+//! rejected ones. They start from zero at every restart, which Prometheus treats as a
+//! counter reset.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write;
+use std::sync::Mutex;
+
+use chrono::{DateTime, Utc};
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ChatUsage {
+    /// Commands by name, e.g. `start` or `move`.
+    pub commands: BTreeMap<&'static str, u64>,
+    pub moves: u64,
+    pub invalid_moves: u64,
+}
+
+impl ChatUsage {
+    pub fn total_commands(&self) -> u64 {
+        self.commands.values().sum()
+    }
+
+    /// Rejected moves as a percentage of all move attempts.
+    pub fn invalid_move_percent(&self) -> u64 {
+        self.invalid_moves * 100 / self.moves.max(1)
+    }
+}
+
+pub struct CommandMetrics {
+    pub started_at: DateTime<Utc>,
+    chats: Mutex<HashMap<i64, ChatUsage>>,
+}
+
+impl Default for CommandMetrics {
+    fn default() -> Self {
+        Self {
+            started_at: Utc::now(),
+            chats: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+impl CommandMetrics {
+    /// Counts a command. `command` should come from a fixed list so the number of label
+    /// values stays bounded.
+    pub fn record_command(&self, chat_id: i64, command: &'static str) {
+        let mut chats = self.chats.lock().unwrap_or_else(|e| e.into_inner());
+        *chats.entry(chat_id).or_default().commands.entry(command).or_default() += 1;
+    }
+
+    pub fn record_move(&self, chat_id: i64, valid: bool) {
+        let mut chats = self.chats.lock().unwrap_or_else(|e| e.into_inner());
+        let usage = chats.entry(chat_id).or_default();
+        usage.moves += 1;
+        if !valid {
+            usage.invalid_moves += 1;
+        }
+    }
+
+    /// The `limit` busiest chats by command count.
+    pub fn top_chats(&self, limit: usize) -> Vec<(i64, ChatUsage)> {
+        let chats = self.chats.lock().unwrap_or_else(|e| e.into_inner());
+        let mut usage: Vec<(i64, ChatUsage)> = chats.iter().map(|(id, u)| (*id, u.clone())).collect();
+        usage.sort_by(|a, b| b.1.total_commands().cmp(&a.1.total_commands()).then(a.0.cmp(&b.0)));
+        usage.truncate(limit);
+        usage
+    }
+
+    /// The counters in the Prometheus text exposition format.
+    pub fn render_prometheus(&self) -> String {
+        let chats = self.chats.lock().unwrap_or_else(|e| e.into_inner());
+        let mut chat_ids: Vec<&i64> = chats.keys().collect();
+        chat_ids.sort();
+
+        let mut out = String::new();
+        out.push_str("# HELP kamachess_commands_total Commands handled, by chat and command.\n");
+        out.push_str("# TYPE kamachess_commands_total counter\n");
+        for chat_id in &chat_ids {
+            for (command, count) in &chats[chat_id].commands {
+                let _ = writeln!(
+                    out,
+                    "kamachess_commands_total{{chat_id=\"{}\",command=\"{}\"}} {}",
+                    chat_id, command, count
+                );
+            }
+        }
+        out.push_str("# HELP kamachess_moves_total Move attempts, by chat and whether they were accepted.\n");
+        out.push_str("# TYPE kamachess_moves_total counter\n");
+        for chat_id in &chat_ids {
+            let usage = &chats[chat_id];
+            let _ = writeln!(
+                out,
+                "kamachess_moves_total{{chat_id=\"{}\",result=\"valid\"}} {}",
+                chat_id,
+                usage.moves - usage.invalid_moves
+            );
+            let _ = writeln!(
+                out,
+                "kamachess_moves_total{{chat_id=\"{}\",result=\"invalid\"}} {}",
+                chat_id, usage.invalid_moves
+            );
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_counts_and_renders() {
+        let metrics = CommandMetrics::default();
+        metrics.record_command(-1, "start");
+        metrics.record_command(-1, "move");
+        metrics.record_command(-1, "move");
+        metrics.record_move(-1, true);
+        metrics.record_move(-1, false);
+        metrics.record_command(5, "help");
+
+        let top = metrics.top_chats(1);
+        assert_eq!(top.len(), 1);
+        assert_eq!(top[0].0, -1);
+        assert_eq!(top[0].1.total_commands(), 3);
+        assert_eq!(top[0].1.invalid_move_percent(), 50);
+
+        let text = metrics.render_prometheus();
+        assert!(text.contains("kamachess_commands_total{chat_id=\"-1\",command=\"move\"} 2\n"));
+        assert!(text.contains("kamachess_commands_total{chat_id=\"5\",command=\"help\"} 1\n"));
+        assert!(text.contains("kamachess_moves_total{chat_id=\"-1\",result=\"invalid\"} 1\n"));
+        assert!(text.contains("kamachess_moves_total{chat_id=\"5\",result=\"valid\"} 0\n"));
+    }
+}
@@ -1,3 +1,4 @@
+use crate::game::Color;
 use serde::{Deserialize, Serialize};
 use sqlx::FromRow;
 
@@ -5,6 +6,10 @@ use sqlx::FromRow;
 pub struct Update {
     pub update_id: i64,
     pub message: Option<Message>,
+    pub callback_query: Option<CallbackQuery>,
+    pub poll_answer: Option<PollAnswer>,
+    /// The bot's own membership changed: it was added to, removed from or blocked in a chat.
+    pub my_chat_member: Option<ChatMemberUpdated>,
 }
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -14,6 +19,10 @@ pub struct Message {
     pub text: Option<String>,
     pub from: Option<User>,
     pub reply_to_message: Option<ReplyMessage>,
+    /// Service message: these users joined the group.
+    pub new_chat_members: Option<Vec<User>>,
+    /// Service message: this user left or was removed from the group.
+    pub left_chat_member: Option<User>,
 }
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -22,11 +31,73 @@ pub struct ReplyMessage {
     pub from: Option<User>,
 }
 
+#[derive(Debug, Deserialize, Serialize)]
+pub struct CallbackQuery {
+    pub id: String,
+    pub from: User,
+    pub message: Option<Message>,
+    pub data: Option<String>,
+}
+
+/// A vote in a non-anonymous poll. `option_ids` is empty when the vote was retracted.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct PollAnswer {
+    pub poll_id: String,
+    pub user: Option<User>,
+    #[serde(default)]
+    pub option_ids: Vec<i64>,
+}
+
+/// `sendPoll` result, reduced to what is needed to stop the poll and match its answers.
+#[derive(Debug, Deserialize)]
+pub struct PollMessage {
+    pub message_id: i64,
+    pub poll: Poll,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Poll {
+    pub id: String,
+}
+
 #[derive(Debug, Deserialize, Serialize)]
 pub struct Chat {
     pub id: i64,
 }
 
+/// `getChat` result. Only the fields needed to identify a user are kept.
+#[derive(Debug, Deserialize)]
+pub struct ChatInfo {
+    pub id: i64,
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub username: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ChatMemberUpdated {
+    pub chat: Chat,
+    pub from: User,
+    pub old_chat_member: ChatMember,
+    pub new_chat_member: ChatMember,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ChatMember {
+    pub status: String,
+    pub user: User,
+}
+
+impl ChatMember {
+    pub fn is_present(&self) -> bool {
+        !matches!(self.status.as_str(), "left" | "kicked")
+    }
+
+    pub fn is_admin(&self) -> bool {
+        matches!(self.status.as_str(), "creator" | "administrator")
+    }
+}
+
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct User {
     pub id: i64,
@@ -34,9 +105,11 @@ pub struct User {
     pub username: Option<String>,
     pub first_name: Option<String>,
     pub last_name: Option<String>,
+    /// IETF language tag of the user's client, picks the locale for replies.
+    pub language_code: Option<String>,
 }
 
-#[derive(Debug, FromRow)]
+#[derive(Debug, Clone, FromRow)]
 pub struct DbUser {
     pub id: i64,
     pub telegram_id: Option<i64>,
@@ -82,34 +155,340 @@ impl DbUser {
     }
 }
 
-#[derive(Debug, FromRow)]
+/// Whether a game can still be played. Stored as TEXT in `games.status`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GameStatus {
+    Ongoing,
+    Finished,
+    /// Ended without a result, e.g. because the bot was removed from the chat.
+    Abandoned,
+}
+
+impl GameStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            GameStatus::Ongoing => "ongoing",
+            GameStatus::Finished => "finished",
+            GameStatus::Abandoned => "abandoned",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "ongoing" => Some(GameStatus::Ongoing),
+            "finished" => Some(GameStatus::Finished),
+            "abandoned" => Some(GameStatus::Abandoned),
+            _ => None,
+        }
+    }
+}
+
+/// Final score, stored in PGN notation in `games.result`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GameResult {
+    WhiteWins,
+    BlackWins,
+    Draw,
+}
+
+impl GameResult {
+    /// The result when `color` wins.
+    pub fn win_for(color: Color) -> Self {
+        match color {
+            Color::White => GameResult::WhiteWins,
+            Color::Black => GameResult::BlackWins,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            GameResult::WhiteWins => "1-0",
+            GameResult::BlackWins => "0-1",
+            GameResult::Draw => "1/2-1/2",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "1-0" => Some(GameResult::WhiteWins),
+            "0-1" => Some(GameResult::BlackWins),
+            "1/2-1/2" => Some(GameResult::Draw),
+            _ => None,
+        }
+    }
+}
+
+/// Side to move, stored as "w" or "b" in `games.turn`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Turn {
+    White,
+    Black,
+}
+
+impl Turn {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Turn::White => "w",
+            Turn::Black => "b",
+        }
+    }
+
+    /// Also accepts the spelled-out colours older rows may contain.
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "w" | "white" => Some(Turn::White),
+            "b" | "black" => Some(Turn::Black),
+            _ => None,
+        }
+    }
+}
+
+impl From<Color> for Turn {
+    fn from(color: Color) -> Self {
+        match color {
+            Color::White => Turn::White,
+            Color::Black => Turn::Black,
+        }
+    }
+}
+
+#[derive(Debug)]
 pub struct GameRow {
     pub id: i64,
-    #[allow(dead_code)]
     pub chat_id: i64,
     pub white_user_id: i64,
     pub black_user_id: i64,
     pub current_fen: String,
-    pub turn: String,
-    pub status: String,
-    pub result: Option<String>,
+    pub turn: Turn,
+    pub status: GameStatus,
+    pub result: Option<GameResult>,
     #[allow(dead_code)]
     pub last_message_id: Option<i64>,
     pub draw_proposed_by: Option<i64>,
     pub draw_proposal_message_id: Option<i64>,
+    pub confirm_moves: bool,
+    pub pending_move: Option<String>,
+    pub pending_move_message_id: Option<i64>,
+    /// The second player's private chat when the game is played over private chats;
+    /// `chat_id` is then the first player's.
+    pub peer_chat_id: Option<i64>,
+}
+
+impl GameRow {
+    /// Every chat the game's boards are posted in.
+    pub fn chats(&self) -> Vec<i64> {
+        std::iter::once(self.chat_id).chain(self.peer_chat_id).collect()
+    }
 }
 
 #[derive(Debug, FromRow)]
 pub struct HistoryRow {
     pub id: i64,
     pub local_num: i64,
-    #[allow(dead_code)]
     pub started_at: String,
+    pub ended_at: Option<String>,
     pub result: Option<String>,
     pub white_username: Option<String>,
     pub black_username: Option<String>,
 }
 
+/// One `audit_log` row: a processed command or a game state change.
+#[derive(Debug, Clone)]
+pub struct AuditEntry {
+    pub id: i64,
+    /// Who sent the command; `None` for state changes.
+    pub telegram_id: Option<i64>,
+    /// The sender's current username, if they are a known user.
+    pub username: Option<String>,
+    pub chat_id: i64,
+    pub game_id: Option<i64>,
+    pub command: String,
+    pub outcome: String,
+    pub created_at: String,
+}
+
+/// An open challenge posted with /seek, waiting for someone to tap Join.
+#[derive(Debug, Clone)]
+pub struct SeekRow {
+    pub id: i64,
+    pub chat_id: i64,
+    /// The challenger's `users.id`.
+    pub user_id: i64,
+    /// The challenge message, set once it has been sent.
+    pub message_id: Option<i64>,
+    pub confirm_moves: bool,
+    pub expires_at: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct ChatSettings {
+    pub chat_id: i64,
+    pub coordinates: String,
+    pub orientation: String,
+    pub send_as_document: bool,
+    pub strict_notation: bool,
+    /// Custom game-end announcements; `None` uses the bot's wording.
+    pub win_template: Option<String>,
+    pub draw_template: Option<String>,
+    /// Post a "Who wins?" poll for spectators when a game starts.
+    pub prediction_polls: bool,
+    /// Receive announcements the bot's admins broadcast.
+    pub announcements: bool,
+    /// Put square buttons under running boards, so moves can be played with two taps.
+    pub tap_moves: bool,
+    /// Cap on games running at once, set by the chat's admins; `None` uses `MAX_CHAT_GAMES`.
+    pub max_games: Option<i64>,
+    /// Attach the game's PGN file to the game-end message.
+    pub pgn_files: bool,
+    /// Finished games needed to appear in the chat's standings; `None` lists everyone.
+    pub standings_min_games: Option<i64>,
+    /// Days without a finished game before a player drops off the standings; `None` never.
+    pub standings_inactive_days: Option<i64>,
+    /// Moves a player makes between draw offers, 0 for no limit; `None` uses `DRAW_OFFER_GAP`.
+    pub draw_offer_gap: Option<i64>,
+}
+
+impl ChatSettings {
+    pub fn defaults(chat_id: i64) -> Self {
+        Self {
+            chat_id,
+            coordinates: "outside".to_string(),
+            orientation: "auto".to_string(),
+            send_as_document: false,
+            strict_notation: false,
+            win_template: None,
+            draw_template: None,
+            prediction_polls: false,
+            announcements: true,
+            tap_moves: false,
+            max_games: None,
+            pgn_files: false,
+            standings_min_games: None,
+            standings_inactive_days: None,
+            draw_offer_gap: None,
+        }
+    }
+}
+
+/// Time a player spent on their moves, measured between move timestamps.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ThinkTime {
+    pub moves: i64,
+    pub total_secs: i64,
+    pub longest_secs: i64,
+}
+
+impl ThinkTime {
+    pub fn record(&mut self, secs: i64) {
+        self.moves += 1;
+        self.total_secs += secs;
+        self.longest_secs = self.longest_secs.max(secs);
+    }
+
+    pub fn average_secs(&self) -> i64 {
+        if self.moves == 0 {
+            0
+        } else {
+            self.total_secs / self.moves
+        }
+    }
+}
+
+/// What the end-of-game summary reports, from a game's moves and timestamps.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct GameSummary {
+    /// SAN of every move, or UCI for moves recorded before SAN was stored.
+    pub moves: Vec<String>,
+    /// From the game's start to its end; `None` while it runs or when a timestamp is missing.
+    pub duration_secs: Option<i64>,
+}
+
+impl GameSummary {
+    /// Full moves, counting a last move by White alone as one.
+    pub fn move_count(&self) -> i64 {
+        (self.moves.len() as i64 + 1) / 2
+    }
+
+    /// Captures, from SAN; UCI moves can't tell and aren't counted.
+    pub fn captures(&self) -> usize {
+        self.moves.iter().filter(|san| san.contains('x')).count()
+    }
+
+    /// Checks, mate included.
+    pub fn checks(&self) -> usize {
+        self.moves.iter().filter(|san| san.ends_with(['+', '#'])).count()
+    }
+}
+
+/// A player's finished games in one chat, for the chat's standings.
+#[derive(Debug, Clone)]
+pub struct ChatStanding {
+    pub user: DbUser,
+    pub wins: i64,
+    pub losses: i64,
+    pub draws: i64,
+    /// When their latest finished game in the chat ended.
+    pub last_played: Option<String>,
+}
+
+impl ChatStanding {
+    pub fn games(&self) -> i64 {
+        self.wins + self.losses + self.draws
+    }
+
+    /// Points counted in halves, so a draw is 1 and a win 2.
+    pub fn half_points(&self) -> i64 {
+        2 * self.wins + self.draws
+    }
+}
+
+/// Finished games between two players in one chat, for the note on their next game's board.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PairRecord {
+    pub player_a: i64,
+    pub player_b: i64,
+    pub a_wins: i64,
+    pub b_wins: i64,
+    pub draws: i64,
+    /// Who won their latest game; `None` for a draw or when they haven't played.
+    pub last_winner: Option<i64>,
+}
+
+/// Every this many games between two players gets a note of its own.
+const RIVALRY_MILESTONE: i64 = 10;
+
+/// What a pair's next game is about, beyond the game itself.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rivalry {
+    /// The game is their `n`th, a multiple of ten.
+    Milestone(i64),
+    /// Both have won this many games.
+    Tied(i64),
+    /// `player` lost their latest game to `opponent`.
+    Revenge { player: i64, opponent: i64 },
+}
+
+impl PairRecord {
+    pub fn games(&self) -> i64 {
+        self.a_wins + self.b_wins + self.draws
+    }
+
+    /// The most notable thing about the pair's next game: a round number first, then a
+    /// level score, then a rematch after a decisive game.
+    pub fn rivalry(&self) -> Option<Rivalry> {
+        let next = self.games() + 1;
+        if next % RIVALRY_MILESTONE == 0 {
+            return Some(Rivalry::Milestone(next));
+        }
+        if self.a_wins > 0 && self.a_wins == self.b_wins {
+            return Some(Rivalry::Tied(self.a_wins));
+        }
+        let winner = self.last_winner?;
+        let player = if winner == self.player_a { self.player_b } else { self.player_a };
+        Some(Rivalry::Revenge { player, opponent: winner })
+    }
+}
+
 #[derive(Debug)]
 pub enum UserRef {
     Telegram(User),
@@ -117,12 +496,48 @@ pub enum UserRef {
     Username(String),
 }
 
+/// A button that either sends `callback_data` back to the bot or opens `url`.
+#[derive(Debug, Serialize, Clone)]
+pub struct InlineKeyboardButton {
+    pub text: String,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub callback_data: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+}
+
+impl InlineKeyboardButton {
+    pub fn callback(text: &str, data: String) -> Self {
+        Self {
+            text: text.to_string(),
+            callback_data: data,
+            url: None,
+        }
+    }
+
+    pub fn link(text: &str, url: String) -> Self {
+        Self {
+            text: text.to_string(),
+            callback_data: String::new(),
+            url: Some(url),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct InlineKeyboardMarkup {
+    pub inline_keyboard: Vec<Vec<InlineKeyboardButton>>,
+}
+
 #[derive(Serialize)]
 pub struct SendMessageRequest {
     pub chat_id: i64,
     pub text: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub reply_to_message_id: Option<i64>,
     pub parse_mode: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reply_markup: Option<InlineKeyboardMarkup>,
 }
 
 #[derive(Deserialize)]
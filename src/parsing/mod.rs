@@ -64,6 +64,8 @@ pub fn extract_move(text: &str) -> Option<String> {
             .to_string();
 
         let normalized = normalize_chess_input(&cleaned);
+        // "exd6e.p." glued together; a separate "e.p." token is simply skipped
+        let normalized = crate::game::strip_en_passant_suffix(&normalized).to_string();
 
         if is_move_candidate(&normalized) {
             Some(normalized)
@@ -83,6 +85,27 @@ pub fn extract_page(text: &str) -> Option<u32> {
         .next()
 }
 
+/// Longest move comment kept, in characters; the rest is cut off.
+pub const MAX_MOVE_COMMENT_CHARS: usize = 200;
+
+/// Splits `/move e4 my favorite opening` into the move and the comment after it. `None`
+/// when the first argument isn't a move.
+pub fn split_move_comment(text: &str) -> Option<(String, Option<String>)> {
+    let (_, args) = text.trim().split_once(char::is_whitespace)?;
+    let args = args.trim_start();
+    let (move_text, comment) = args.split_once(char::is_whitespace).unwrap_or((args, ""));
+    let mv = extract_move(move_text)?;
+    let comment: String = comment.trim().chars().take(MAX_MOVE_COMMENT_CHARS).collect();
+    Some((mv, (!comment.is_empty()).then_some(comment)))
+}
+
+/// Checks whether a bare keyword option (e.g. `confirm`) appears among the command arguments.
+pub fn has_option(text: &str, option: &str) -> bool {
+    text.split_whitespace()
+        .skip(1)
+        .any(|token| token.eq_ignore_ascii_case(option))
+}
+
 fn is_move_candidate(token: &str) -> bool {
     let len = token.len();
     if !(2..=7).contains(&len) {
@@ -226,6 +249,13 @@ mod tests {
         assert_eq!(extract_move("Qxd5"), Some("Qxd5".to_string()));
     }
 
+    #[test]
+    fn test_extract_move_en_passant_suffix() {
+        assert_eq!(extract_move("exd6 e.p."), Some("exd6".to_string()));
+        assert_eq!(extract_move("exd6e.p."), Some("exd6".to_string()));
+        assert_eq!(extract_move("exd6 ep"), Some("exd6".to_string()));
+    }
+
     #[test]
     fn test_extract_usernames() {
         assert_eq!(extract_usernames("@user"), vec!["user".to_string()]);
@@ -294,6 +324,30 @@ mod tests {
         assert_eq!(mv, None);
     }
 
+    #[test]
+    fn test_has_option() {
+        assert!(has_option("/start @user confirm", "confirm"));
+        assert!(has_option("/start @user e4 CONFIRM", "confirm"));
+        assert!(!has_option("/start @user e4", "confirm"));
+        assert!(!has_option("confirm", "confirm"));
+        assert_eq!(extract_move("/start @user e4 confirm"), Some("e4".to_string()));
+    }
+
+    #[test]
+    fn test_split_move_comment() {
+        assert_eq!(
+            split_move_comment("/move e4 my favorite opening"),
+            Some(("e4".to_string(), Some("my favorite opening".to_string())))
+        );
+        assert_eq!(split_move_comment("/move@bot  Nf3  "), Some(("Nf3".to_string(), None)));
+        assert_eq!(split_move_comment("/move"), None);
+        assert_eq!(split_move_comment("/move hello e4"), None);
+
+        let long = format!("/move e4 {}", "a".repeat(MAX_MOVE_COMMENT_CHARS + 10));
+        let (_, comment) = split_move_comment(&long).unwrap();
+        assert_eq!(comment.unwrap().chars().count(), MAX_MOVE_COMMENT_CHARS);
+    }
+
     #[test]
     fn test_cyrillic_moves() {
         // Cyrillic 'с' (U+0441) should be normalized to Latin 'c' (U+0063)
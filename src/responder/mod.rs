@@ -0,0 +1,112 @@
+//! Sends user-facing text. Handlers name a template instead of spelling out the wording, so
+//! translations and per-chat overrides apply to every message the bot writes.
+
+mod templates;
+
+pub use templates::{fill, placeholders, Templates, DRAW_TEMPLATE, WIN_TEMPLATE};
+
+use crate::api::TelegramApi;
+use crate::error::Result;
+use crate::models::{CallbackQuery, InlineKeyboardMarkup, Message};
+use crate::utils::{split_message, MAX_MESSAGE_LEN};
+use std::sync::Arc;
+
+pub type Args<'a> = &'a [(&'a str, &'a str)];
+
+#[derive(Clone)]
+pub struct Responder {
+    telegram: TelegramApi,
+    templates: Arc<Templates>,
+}
+
+impl Responder {
+    pub fn new(telegram: TelegramApi, templates: Templates) -> Self {
+        Self {
+            telegram,
+            templates: Arc::new(templates),
+        }
+    }
+
+    pub fn templates(&self) -> &Templates {
+        &self.templates
+    }
+
+    /// Renders a template without sending it, for captions, buttons and messages built
+    /// from several parts.
+    pub fn text(&self, chat_id: i64, locale: Option<&str>, id: &str, args: Args<'_>) -> String {
+        self.templates.render(chat_id, locale, id, args)
+    }
+
+    /// Answers `message` in the sender's language.
+    pub async fn reply(&self, message: &Message, id: &str, args: Args<'_>) -> Result<i64> {
+        let text = self.text(message.chat.id, locale(message), id, args);
+        self.send_text(message.chat.id, Some(message.message_id), &text).await
+    }
+
+    pub async fn reply_with_keyboard(
+        &self,
+        message: &Message,
+        id: &str,
+        args: Args<'_>,
+        keyboard: InlineKeyboardMarkup,
+    ) -> Result<i64> {
+        let text = self.text(message.chat.id, locale(message), id, args);
+        self.telegram
+            .send_message_with_keyboard(message.chat.id, message.message_id, &text, keyboard)
+            .await
+    }
+
+    pub async fn send(
+        &self,
+        chat_id: i64,
+        reply_to: i64,
+        locale: Option<&str>,
+        id: &str,
+        args: Args<'_>,
+    ) -> Result<i64> {
+        let text = self.text(chat_id, locale, id, args);
+        self.send_text(chat_id, Some(reply_to), &text).await
+    }
+
+    /// Sends text that was already rendered, e.g. assembled from several templates. Text
+    /// over Telegram's message limit goes out as several messages, split between lines;
+    /// the id of the first one is returned.
+    pub async fn send_text(&self, chat_id: i64, reply_to: Option<i64>, text: &str) -> Result<i64> {
+        let chunks = split_message(text, MAX_MESSAGE_LEN);
+        let Some((first, rest)) = chunks.split_first() else {
+            return self.telegram.send_message(chat_id, reply_to, text).await;
+        };
+
+        let first_id = self.telegram.send_message(chat_id, reply_to, first).await?;
+        for chunk in rest {
+            self.telegram.send_message(chat_id, reply_to, chunk).await?;
+        }
+        Ok(first_id)
+    }
+
+    pub async fn edit(
+        &self,
+        chat_id: i64,
+        message_id: i64,
+        locale: Option<&str>,
+        id: &str,
+        args: Args<'_>,
+    ) -> Result<()> {
+        let text = self.text(chat_id, locale, id, args);
+        self.telegram.edit_message_text(chat_id, message_id, &text).await
+    }
+
+    /// Answers a button press, with a toast when `id` is given.
+    pub async fn answer_callback(&self, query: &CallbackQuery, id: Option<&str>) -> Result<()> {
+        let chat_id = query.message.as_ref().map_or(query.from.id, |msg| msg.chat.id);
+        let text = id.map(|id| self.text(chat_id, query.from.language_code.as_deref(), id, &[]));
+        self.telegram
+            .answer_callback_query(&query.id, text.as_deref())
+            .await
+    }
+}
+
+/// The sender's Telegram language, if the client reported one.
+pub fn locale(message: &Message) -> Option<&str> {
+    message.from.as_ref()?.language_code.as_deref()
+}
@@ -0,0 +1,666 @@
+//! Message wording, keyed by template id. English texts are built in; other locales come
+//! from `<locale>.json` files (a flat object of id to text) and chats can override single
+//! templates. Texts are Telegram HTML and may contain `{name}` placeholders.
+
+use crate::error::{KamaError, Result};
+use crate::models::ChatSettings;
+use crate::utils::escape_html;
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::RwLock;
+use tracing::warn;
+
+const HELP: &str = r#"<b>Chess Bot Commands:</b>
+
+<b>/start [@user] [move] [confirm]</b>
+Reply to a user's message or mention a user to start a game.
+Add <i>confirm</i> to have every move confirmed with a button before it is played.
+Examples: /start e4, /start @user Nf3, /start @user confirm
+
+<b>/history [@user] [@user2] [page]</b>
+View game history or head-to-head stats.
+Examples:
+• /history - Your stats
+• /history @username - User's stats
+• /history @user1 @user2 - Head-to-head
+• /history 2 - Page 2
+
+<b>/rank</b>
+Your place in this chat's standings, your record here and the trend over your last 10 games.
+The #1 is the chat's champion and wears a 👑.
+
+<b>/activity</b>
+A chart of the games started in this chat each week over the last 12 weeks.
+
+<b>/pgnall [@user]</b>
+All your finished games in this chat, or another player's, as one PGN file.
+
+<b>/ongoing</b>
+List your running games in every chat, with buttons to open or repost their boards.
+
+<b>/settings [coords outside|inside|hidden] [orientation auto|white|own] [hd on|off] [strict on|off] [polls on|off] [announcements on|off] [buttons on|off] [pgn on|off] [maxgames N|off|reset] [drawgap N|off|reset] [mingames N|off] [inactivedays N|off]</b>
+Show or change this chat's settings.
+Coordinates can be drawn around the board, inside the edge squares, or hidden.
+Orientation <i>auto</i> flips the board to the side to move, <i>white</i> never flips it, <i>own</i> shows your side in a private chat.
+With <i>hd on</i> boards are sent as files so Telegram does not recompress them.
+With <i>strict on</i> moves must use standard SAN (Nbd7, exd5), no shortcuts.
+With <i>polls on</i> every new game gets a "Who wins?" poll for spectators; /predictions shows who guesses best.
+With <i>announcements off</i> the chat no longer receives news from the bot's operators.
+With <i>buttons on</i> boards come with square buttons: tap your piece, then where it goes.
+With <i>pgn on</i> every finished game comes with its PGN file.
+Chat admins can cap how many games run at once with <i>/settings maxgames &lt;number&gt;</i> (<i>off</i> for no limit, <i>reset</i> for the bot's default).
+In the same way <i>/settings drawgap &lt;number&gt;</i> sets how many moves a player makes between two draw offers in a game.
+They can also list only players with enough finished games in /rank's standings (<i>mingames</i>) and drop those who haven't finished one in a number of days (<i>inactivedays</i>).
+Chat admins can replace the game-end message with <i>/settings win &lt;text&gt;</i> and <i>/settings draw &lt;text&gt;</i>, using {winner}, {loser}, {white}, {black}, {result}, {moves} and {announcement}.
+
+<b>/seek [confirm]</b>
+Post an open challenge; the first player to tap <i>Join</i> plays Black against you.
+
+<b>/queue</b>
+In a private chat with the bot: get matched with another waiting player of similar record. The game is played here, with the board sent to both of you after every move. /unqueue stops waiting.
+
+<b>Making Moves:</b>
+Reply to the bot's board message with your move.
+Supports: e4, e2e4, Nf6, O-O, etc.
+
+<b>/move &lt;move&gt; [comment]</b>
+Reply to the bot's board message to play a move with a comment, e.g. <i>/move e4 my favorite opening</i>.
+The comment is shown with the next board and kept in the game's PGN.
+
+<b>/resign</b>
+Reply to the bot's board message to resign.
+
+<b>/draw</b>
+Reply to the bot's board message to propose a draw.
+
+<b>/accept</b>
+Reply to the bot's board message to accept a draw proposal.
+
+<b>/confirm</b>
+Reply to the bot's board message to toggle move confirmation for that game.
+
+<b>/pause</b>, <b>/resume</b>
+Reply to the bot's board message to pause the game once both players sent /pause; either player can /resume it.
+
+<b>/forceresult 1-0|0-1|1/2-1/2</b>
+Chat admins: reply to a board to end a stuck or disputed game with the given result.
+
+<b>/eval</b>
+Reply to a board to get a quick engine evaluation (not available to the players during their game).
+
+<b>/flip</b>
+Reply to a board to see the position again from the other side.
+
+<b>/legal [square]</b>
+Reply to a board to list the legal moves, or only those of the piece on a square (e.g. /legal e2).
+
+<b>/rules</b>, <b>/notation</b>
+How chess is played and how to type moves. <b>/tutorial</b> in a private chat walks you through your first moves.
+
+Commands also work with @botname suffix (e.g. /draw@botname).
+
+Use /help to show this message."#;
+
+const RULES: &str = r#"<b>Chess in a nutshell</b>
+
+White moves first, then the players take turns, one move each.
+• <b>King</b> (K): one square in any direction.
+• <b>Queen</b> (Q): any number of squares in a straight line or diagonal.
+• <b>Rook</b> (R): any number of squares in a straight line.
+• <b>Bishop</b> (B): any number of squares diagonally.
+• <b>Knight</b> (N): in an L shape, two squares one way and one to the side; it jumps over pieces.
+• <b>Pawn</b>: one square forward (two from its starting square), captures one square diagonally forward. On the last rank it promotes, usually to a queen.
+
+You capture by moving onto an opponent's piece. A king under attack is in <b>check</b> and must get out of it at once. If it can't, that's <b>checkmate</b> and the game is over.
+
+Special moves:
+• <b>Castling</b>: the king moves two squares towards a rook and the rook jumps over it. Neither may have moved, the squares between must be empty, and the king may not be in, pass through or land in check.
+• <b>En passant</b>: a pawn that just moved two squares can be captured by an enemy pawn beside it as if it had moved one.
+
+A game is drawn by <b>stalemate</b> (no legal move but not in check), by agreement (/draw), or when neither side has enough material to mate.
+
+/notation explains how to type moves, and /tutorial (in a private chat with the bot) walks you through your first ones."#;
+
+const NOTATION: &str = r#"<b>Typing moves</b>
+
+Reply to the board with your move. Two notations work:
+
+<b>SAN</b> (standard algebraic), what chess books use:
+• A pawn move is just its destination: <code>e4</code>, <code>d5</code>
+• Pieces take their letter first (K, Q, R, B, N): <code>Nf3</code>, <code>Bb5</code>
+• Captures add an x: <code>Bxc6</code>, <code>exd5</code>
+• If two pieces could go there, add the file or rank they come from: <code>Nbd7</code>, <code>R1e2</code>
+• Castling: <code>O-O</code> (king side), <code>O-O-O</code> (queen side)
+• Promotion: <code>e8=Q</code>
+
+<b>UCI</b>: the start and end squares, e.g. <code>e2e4</code>, <code>g1f3</code>, <code>e7e8q</code> for promotion.
+
+Checks (+) and mates (#) may be left out. Unless the chat uses <i>/settings strict on</i>, shortcuts like <code>bc6</code> for a pawn capture are understood too.
+Use /legal in reply to a board to see what can be played."#;
+
+/// Built-in English texts. Every id a handler uses must be listed here.
+const DEFAULTS: &[(&str, &str)] = &[
+    ("help", HELP),
+    (
+        "onboarding",
+        "👋 Hi! I host chess games right in this chat.\n\n• Reply to someone's message with /start, or send /start @username, to challenge them\n• /seek posts an open challenge anyone can take\n• Play by replying to the board with your move, e.g. <code>e4</code>\n\n/rules and /notation explain the basics, /help lists everything.",
+    ),
+    (
+        "game.abandoned",
+        "The game {white} vs {black} was abandoned: the bot was removed from the other player's chat.",
+    ),
+    (
+        "game.player_left",
+        "{player} left the chat in the middle of their game with {opponent}. If they aren't back within {minutes} min, {opponent} can claim the win or abort the game.",
+    ),
+    ("game.player_returned", "{player} is back, the game with {opponent} goes on."),
+    ("left.button_claim", "🏆 Claim win"),
+    ("left.button_abort", "✖️ Abort game"),
+    ("left.not_pending", "The player is back or the game is already over."),
+    ("left.not_opponent", "Only the remaining player can decide."),
+    ("left.too_early", "The grace period isn't over yet."),
+    ("left.aborted", "The game {white} vs {black} was aborted: {player} left the chat."),
+    ("rules", RULES),
+    ("notation", NOTATION),
+    ("tutorial.private_only", "The tutorial runs in a private chat with me. Open one and send /tutorial there."),
+    (
+        "tutorial.step1",
+        "<b>Step 1 of 4.</b> You play White. Open with the king's pawn: move it from e2 to e4.\nReply to this board with <code>e4</code>.",
+    ),
+    (
+        "tutorial.step2",
+        "<b>Step 2 of 4.</b> Bring a knight out. Knights are written N, so the knight going to f3 is <code>Nf3</code>.\nReply with <code>Nf3</code>.",
+    ),
+    (
+        "tutorial.step3",
+        "<b>Step 3 of 4.</b> Develop your bishop to c4, aiming at Black's weak f7 square: <code>Bc4</code>.",
+    ),
+    (
+        "tutorial.step4",
+        "<b>Step 4 of 4.</b> The squares between king and rook are empty, so you can castle to safety: <code>O-O</code>.",
+    ),
+    ("tutorial.played", "✅ You played <b>{san}</b> (in UCI: <code>{uci}</code>)."),
+    ("tutorial.answer", "Black answers <b>{move}</b>."),
+    ("tutorial.other_move", "{move} is a legal move, but in this walkthrough play <code>{expected}</code>."),
+    ("tutorial.illegal", "That didn't work: {error}\nTry again, replying to the board."),
+    (
+        "tutorial.done",
+        "🎉 That's it: you know how to play moves! Challenge someone with /start or /seek in a group, or use /queue here to get an opponent.",
+    ),
+    ("error", "{message}"),
+    ("error.internal", "Something went wrong on my side. If it keeps happening, mention error ref <code>{ref}</code>."),
+    ("start.usage", "Reply to a user's message or use /start @username [move]."),
+    ("start.self_play", "You cannot play against yourself."),
+    (
+        "start.already_ongoing",
+        "There is already an ongoing game between these players in this chat.",
+    ),
+    (
+        "start.chat_limit",
+        "This chat has as many games running as it allows. Finish one first, or ask a chat admin to raise the limit with /settings maxgames.",
+    ),
+    ("seek.open", "{player} is looking for an opponent. Tap Join to play Black (open for {minutes} min)."),
+    ("seek.button_join", "♟ Join"),
+    ("seek.already_open", "You already have an open challenge in this chat."),
+    ("seek.accepted", "Challenge accepted: {white} (White) vs {black} (Black)."),
+    ("seek.expired", "This challenge expired without an opponent."),
+    ("seek.gone", "This challenge is no longer open."),
+    ("seek.own", "You cannot join your own challenge."),
+    ("queue.private_only", "Use /queue in a private chat with me."),
+    ("queue.already", "You are already waiting for an opponent. Use /unqueue to stop."),
+    ("queue.waiting", "Waiting for an opponent. I'll send the board here once you are matched."),
+    ("queue.matched", "Matched with {opponent}. Reply to the board here with your moves."),
+    ("queue.left", "You left the queue."),
+    ("queue.not_queued", "You are not in the queue."),
+    ("board.started", "Game started"),
+    ("board.move_played", "Move played"),
+    // Plain text: the board caption escapes it
+    ("rivalry.milestone", "🎉 Game {number} between {white} and {black}!"),
+    ("rivalry.tied", "⚖️ {white} and {black} have {wins} wins each; this game can break the tie."),
+    ("rivalry.revenge", "🔥 {player} lost the last game against {opponent} and is out for revenge."),
+    ("board.move_played_en_passant", "Move played (en passant)"),
+    ("board.flipped", "Board from the other side"),
+    ("board.reposted", "Board posted again"),
+    ("board.paused", "Game paused"),
+    ("board.resumed", "Game resumed"),
+    ("board.paused_line", "⏸ Paused: moves wait until a player replies /resume."),
+    ("ongoing.none", "You have no running games."),
+    ("ongoing.header", "Your running games ({count}):"),
+    ("ongoing.entry", "#{number} vs {opponent} · {moves} moves · {turn}{here}"),
+    ("ongoing.your_move", "your move"),
+    ("ongoing.their_move", "their move"),
+    ("ongoing.this_chat", " (this chat)"),
+    ("ongoing.button_open", "↗️ #{number}"),
+    ("ongoing.button_repost", "🔁 Repost #{number}"),
+    ("ongoing.not_player", "Only the game's players can repost its board."),
+    ("ongoing.finished", "That game is over."),
+    ("ongoing.reposted", "Board posted again."),
+    ("move.other_players", "This game belongs to other players."),
+    ("move.invalid", "Invalid move: {error}"),
+    ("confirm.prompt", "Play <b>{move}</b>?"),
+    ("confirm.button_confirm", "✅ Confirm"),
+    ("confirm.button_cancel", "❌ Cancel"),
+    ("confirm.not_pending", "This move is no longer pending."),
+    ("confirm.wrong_player", "Only the player who made the move can confirm it."),
+    ("confirm.cancelled", "Move cancelled."),
+    ("confirm.confirmed", "Confirmed <b>{move}</b>."),
+    (
+        "confirm.enabled",
+        "Move confirmation enabled for this game. Moves will wait for your confirmation.",
+    ),
+    ("confirm.disabled", "Move confirmation disabled for this game."),
+    (
+        "draw.proposed",
+        "{player} proposed a draw. {opponent} can accept with /accept or continue playing.",
+    ),
+    ("draw.none_pending", "No draw proposal is pending."),
+    ("draw.own_proposal", "You cannot accept your own draw proposal."),
+    ("pause.requested", "{player} asked to pause the game. {opponent} can agree with /pause."),
+    ("pause.already_requested", "You already asked to pause. Waiting for your opponent to agree."),
+    ("pause.already_paused", "The game is already paused. Reply /resume to continue."),
+    ("pause.not_paused", "The game isn't paused."),
+    ("game.paused", "The game is paused. Reply /resume to the board to continue."),
+    ("result.checkmate", "Checkmate. {winner} wins."),
+    ("result.stalemate", "Draw by stalemate."),
+    ("result.insufficient_material", "Draw by insufficient material."),
+    ("result.variant_win", "Game over. {winner} wins."),
+    ("result.variant_draw", "Game over. Draw."),
+    ("result.resigned", "{loser} resigned. {winner} wins."),
+    ("result.left", "{loser} left the chat. {winner} wins."),
+    ("result.draw_accepted", "Draw accepted by {player}."),
+    ("draw.too_soon", "You offered a draw recently. Make {moves} more moves before offering again."),
+    ("result.forced", "The result was set to {result} by {admin}."),
+    ("forceresult.admins_only", "Only chat admins can set a game's result."),
+    ("forceresult.usage", "Usage: reply to the board with /forceresult 1-0, 0-1 or 1/2-1/2."),
+    ("game.won", "Game ended.\n{announcement}\nResult: {result}"),
+    ("game.drawn", "Game ended.\n{announcement}\nResult: {result}"),
+    ("game.stats", "Moves: {moves} · captures: {captures} · checks: {checks}"),
+    ("game.duration", "Duration: {duration}"),
+    ("game.opening", "Opening: {opening}"),
+    ("game.think_time", "{side} think time: avg {average}, longest {longest}"),
+    ("game.analysis", "🔎 <a href=\"{url}\">Analyse on lichess</a>"),
+    ("game.page", "🔗 <a href=\"{url}\">Replay this game</a>"),
+    ("side.white", "White"),
+    ("side.black", "Black"),
+    ("eval.no_engine", "No engine is configured for this bot."),
+    ("eval.disabled", "Engine evaluation is switched off in this chat."),
+    ("eval.players_only", "Players can't evaluate their own game while it is in progress."),
+    ("eval.cooldown", "Please wait {seconds}s before the next /eval."),
+    ("eval.result", "Evaluation: <b>{score}</b> (depth {depth})"),
+    ("eval.no_score", "The engine returned no evaluation."),
+    ("eval.failed", "Evaluation failed: {reason}"),
+    ("prediction.question", "Who wins?"),
+    ("prediction.white", "{player} (White)"),
+    ("prediction.draw", "Draw"),
+    ("prediction.black", "{player} (Black)"),
+    ("prediction.none", "No predictions have been scored in this chat yet."),
+    ("rank.new_champion", "👑 {player} takes the lead in this chat's standings from {previous} and is the new champion!"),
+    ("rank.too_few_games", "You have {games} finished games here; the standings list players with at least {min}."),
+    ("rank.inactive", "You haven't finished a game here in {days} days, so you're off the standings until you play again."),
+    ("rank.none", "You have no finished games in this chat yet."),
+    ("activity.none", "No games were started in this chat in the last {weeks} weeks."),
+    ("activity.title", "Games per week"),
+    ("activity.caption", "{total} games started in this chat over the last {weeks} weeks."),
+    ("pgnall.none", "{player} has no finished games in this chat yet."),
+    ("pgnall.caption", "{count} games of {player} in this chat."),
+    ("pgnall.cooldown", "Please wait {seconds}s before the next /pgnall."),
+    (
+        "rank.summary",
+        "<b>{player}</b>{crown}: #{position} of {players} in this chat\nRecord: {record} · {points} points from {games} games\nLast {recent} games: {recent_record} {trend}",
+    ),
+    ("prediction.leaderboard", "<b>Best predictors:</b>\n{lines}"),
+    ("prediction.line", "{rank}. {player}: {correct}/{total} ({percent}%)"),
+    ("legal.usage", "Usage: /legal [square], e.g. /legal e2"),
+    ("legal.none", "There are no legal moves in this position."),
+    ("legal.all", "Legal moves ({count}):\n{moves}"),
+    ("legal.no_piece", "The side to move has no piece on {square}."),
+    ("legal.piece_stuck", "The piece on {square} has no legal moves."),
+    ("legal.piece", "Legal moves from {square}: {moves}"),
+    (
+        "settings.summary",
+        "<b>Chat settings:</b>\nCoordinates: <b>{coordinates}</b>\nOrientation: <b>{orientation}</b>\nHD boards: <b>{hd}</b>\nStrict notation: <b>{strict}</b>\nPrediction polls: <b>{polls}</b>\nAnnouncements: <b>{announcements}</b>\nMove buttons: <b>{buttons}</b>\nPGN files: <b>{pgn}</b>\nMax running games: <b>{maxgames}</b>\nMoves between draw offers: <b>{drawgap}</b>\nStandings min games: <b>{mingames}</b>\nStandings inactivity days: <b>{inactivedays}</b>\nWin message: <b>{win}</b>\nDraw message: <b>{draw}</b>\n\n{usage}",
+    ),
+    (
+        "settings.usage",
+        "Usage:\n/settings coords &lt;outside|inside|hidden&gt;\n/settings orientation &lt;auto|white|own&gt;\n/settings hd &lt;on|off&gt;\n/settings strict &lt;on|off&gt;\n/settings polls &lt;on|off&gt;\n/settings announcements &lt;on|off&gt;\n/settings buttons &lt;on|off&gt;\n/settings pgn &lt;on|off&gt;\n/settings maxgames &lt;number|off|reset&gt;\n/settings drawgap &lt;number|off|reset&gt;\n/settings mingames &lt;number|off&gt;\n/settings inactivedays &lt;number|off&gt;\n/settings win &lt;text|reset&gt;\n/settings draw &lt;text|reset&gt;",
+    ),
+    ("settings.on", "on"),
+    ("settings.off", "off"),
+    ("settings.coordinates_set", "Coordinates set to <b>{value}</b>."),
+    (
+        "settings.coordinates_unknown",
+        "Unknown coordinates style. Use outside, inside or hidden.",
+    ),
+    ("settings.orientation_set", "Board orientation set to <b>{value}</b>."),
+    ("settings.orientation_unknown", "Unknown orientation. Use auto, white or own."),
+    ("settings.hd_on", "Boards will be sent as files, without Telegram's compression."),
+    ("settings.hd_off", "Boards will be sent as photos."),
+    ("settings.hd_usage", "Use /settings hd on or /settings hd off."),
+    (
+        "settings.strict_on",
+        "Strict notation on: moves must be written in standard SAN.",
+    ),
+    ("settings.strict_off", "Strict notation off."),
+    ("settings.strict_usage", "Use /settings strict on or /settings strict off."),
+    ("settings.polls_on", "New games will come with a prediction poll for spectators."),
+    ("settings.polls_off", "Prediction polls off."),
+    ("settings.polls_usage", "Use /settings polls on or /settings polls off."),
+    ("settings.announcements_on", "This chat will receive announcements."),
+    ("settings.announcements_off", "This chat will no longer receive announcements."),
+    (
+        "settings.announcements_usage",
+        "Use /settings announcements on or /settings announcements off.",
+    ),
+    (
+        "settings.buttons_on",
+        "Boards will come with square buttons: tap your piece, then where it goes.",
+    ),
+    ("settings.buttons_off", "Move buttons off. Moves are typed in reply to the board."),
+    ("settings.buttons_usage", "Use /settings buttons on or /settings buttons off."),
+    ("settings.pgn_on", "Finished games will come with their PGN file."),
+    ("settings.pgn_off", "PGN files off."),
+    ("settings.pgn_usage", "Use /settings pgn on or /settings pgn off."),
+    ("settings.maxgames_set", "This chat can now have at most {value} games running at once."),
+    ("settings.maxgames_off", "No limit on running games in this chat."),
+    ("settings.maxgames_reset", "The limit on running games is back to the bot's default."),
+    ("settings.maxgames_usage", "Use /settings maxgames &lt;number&gt;, /settings maxgames off or /settings maxgames reset."),
+    ("settings.mingames_set", "Players now need {value} finished games here to appear in the standings."),
+    ("settings.mingames_off", "Everyone with a finished game here appears in the standings."),
+    ("settings.inactivedays_set", "Players drop off the standings after {value} days without finishing a game here."),
+    ("settings.inactivedays_off", "Players stay in the standings however long they're away."),
+    (
+        "settings.standings_usage",
+        "Use /settings mingames &lt;number|off&gt; or /settings inactivedays &lt;number|off&gt;.",
+    ),
+    ("settings.drawgap_set", "Players now make {value} moves between draw offers."),
+    ("settings.drawgap_off", "Players can offer a draw after every move."),
+    ("settings.drawgap_reset", "The moves between draw offers are back to the bot's default."),
+    ("settings.drawgap_usage", "Use /settings drawgap &lt;number&gt;, /settings drawgap off or /settings drawgap reset."),
+    ("settings.no_limit", "no limit"),
+    ("tap.stale", "This board is out of date, use the latest one."),
+    ("tap.not_your_turn", "It's not your turn."),
+    ("tap.pick_piece", "Tap one of your pieces that can move."),
+    ("settings.custom", "custom"),
+    ("settings.default", "default"),
+    ("settings.admins_only", "Only chat admins can change this chat's settings."),
+    (
+        "settings.end_template_usage",
+        "Use /settings {kind} &lt;text&gt; or /settings {kind} reset. Placeholders: {placeholders}",
+    ),
+    ("settings.end_template_too_long", "The message can be at most {max} characters."),
+    ("settings.end_template_unknown", "Unknown placeholder {placeholder}. Use: {placeholders}"),
+    ("settings.end_template_set", "New {kind} message saved."),
+    ("settings.end_template_reset", "The {kind} message is back to the default."),
+    ("broadcast.usage", "Usage: /broadcast &lt;text&gt;"),
+    ("broadcast.started", "Sending the announcement to {count} chats."),
+    ("broadcast.done", "Announcement delivered to {sent} chats, {failed} failed."),
+    (
+        "broadcast.message",
+        "📢 {text}\n\n<i>Turn these off with /settings announcements off.</i>",
+    ),
+    ("maintenance", "🛠 The bot is under maintenance and games are paused. Please try again later."),
+    ("maintenance.on", "Maintenance mode on: gameplay is paused."),
+    ("maintenance.off", "Maintenance mode off: games can continue."),
+    ("maintenance.usage", "Usage: /maintenance [on|off]"),
+    ("feature.usage", "Usage: /feature [&lt;name&gt; on|off|reset [global]]. Features: {features}"),
+    ("feature.none", "No feature flags are set for this chat."),
+    ("feature.list", "<b>Feature flags:</b>\n{flags}"),
+    ("feature.line", "{name}: <b>{value}</b> ({scope})"),
+    ("feature.set", "{name}: <b>{value}</b> ({scope})"),
+    ("feature.reset", "reset"),
+    ("feature.scope_chat", "this chat"),
+    ("feature.scope_global", "all chats"),
+    ("botstats.empty", "No commands handled since {since}."),
+    ("botstats.list", "<b>Busiest chats since {since}:</b>\n{chats}"),
+    (
+        "botstats.line",
+        "<code>{chat}</code>: {total} commands ({commands}), {moves} moves, {invalid}% invalid",
+    ),
+    ("audit.usage", "Usage: /audit [game &lt;id&gt; | user &lt;@username or Telegram id&gt;]"),
+    ("audit.none", "No audit entries found."),
+    ("audit.list", "<b>Audit log:</b>\n{entries}"),
+    ("audit.line", "<code>{time}</code> {user} in {chat}{game}: <code>{command}</code> → {outcome}"),
+    ("merge.done", "Merged {from} into {into}. Record: +{wins} -{losses} ={draws}"),
+    ("merge.failed", "Merge failed: {error}"),
+    ("merge.not_found", "User {user} not found."),
+    (
+        "merge.usage",
+        "Usage: /merge &lt;from&gt; &lt;into&gt; (each an @username or Telegram id)",
+    ),
+];
+
+pub struct Templates {
+    /// Texts per lowercase locale code, on top of the built-in English ones.
+    locales: HashMap<String, HashMap<String, String>>,
+    /// Per-chat replacements, checked before any locale.
+    overrides: RwLock<HashMap<i64, HashMap<String, String>>>,
+}
+
+impl Default for Templates {
+    fn default() -> Self {
+        Self {
+            locales: HashMap::new(),
+            overrides: RwLock::new(HashMap::new()),
+        }
+    }
+}
+
+impl Templates {
+    /// Reads every `<locale>.json` in `dir`. Ids the files don't know are reported and
+    /// skipped so a typo doesn't go unnoticed.
+    pub fn load_dir(dir: &Path) -> Result<Self> {
+        let mut templates = Self::default();
+        for entry in std::fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.extension().and_then(|ext| ext.to_str()) != Some("json") {
+                continue;
+            }
+            let Some(locale) = path.file_stem().and_then(|stem| stem.to_str()) else {
+                continue;
+            };
+            let texts: HashMap<String, String> = serde_json::from_str(&std::fs::read_to_string(&path)?)
+                .map_err(|e| KamaError::Invalid(format!("{}: {}", path.display(), e)))?;
+            for id in texts.keys().filter(|id| builtin(id).is_none()) {
+                warn!(locale = locale, template = %id, "Unknown template id");
+            }
+            templates = templates.with_locale(locale, texts);
+        }
+        Ok(templates)
+    }
+
+    pub fn with_locale(mut self, locale: &str, texts: HashMap<String, String>) -> Self {
+        self.locales.insert(locale.to_ascii_lowercase(), texts);
+        self
+    }
+
+    pub fn locales(&self) -> impl Iterator<Item = &str> {
+        self.locales.keys().map(String::as_str)
+    }
+
+    /// The text for `id` as seen in `chat_id`: the chat's override, then the locale
+    /// (`pt-br` falls back to `pt`), then English.
+    pub fn get(&self, chat_id: i64, locale: Option<&str>, id: &str) -> String {
+        if let Some(text) = self
+            .overrides
+            .read()
+            .unwrap()
+            .get(&chat_id)
+            .and_then(|texts| texts.get(id))
+        {
+            return text.clone();
+        }
+
+        if let Some(locale) = locale.map(str::to_ascii_lowercase) {
+            let language = locale.split(['-', '_']).next().unwrap_or_default();
+            for code in [locale.as_str(), language] {
+                if let Some(text) = self.locales.get(code).and_then(|texts| texts.get(id)) {
+                    return text.clone();
+                }
+            }
+        }
+
+        match builtin(id) {
+            Some(text) => text.to_string(),
+            None => {
+                warn!(template = id, "Missing template");
+                id.to_string()
+            }
+        }
+    }
+
+    pub fn render(&self, chat_id: i64, locale: Option<&str>, id: &str, args: &[(&str, &str)]) -> String {
+        fill(&self.get(chat_id, locale, id), args)
+    }
+
+    pub fn set_override(&self, chat_id: i64, id: &str, text: &str) {
+        self.overrides
+            .write()
+            .unwrap()
+            .entry(chat_id)
+            .or_default()
+            .insert(id.to_string(), text.to_string());
+    }
+
+    pub fn clear_override(&self, chat_id: i64, id: &str) {
+        let mut overrides = self.overrides.write().unwrap();
+        if let Some(texts) = overrides.get_mut(&chat_id) {
+            texts.remove(id);
+            if texts.is_empty() {
+                overrides.remove(&chat_id);
+            }
+        }
+    }
+}
+
+/// Template ids a chat can replace from /settings, loaded from `chat_settings`.
+pub const WIN_TEMPLATE: &str = "game.won";
+pub const DRAW_TEMPLATE: &str = "game.drawn";
+
+impl Templates {
+    /// Installs (or removes) the chat's own game-end announcements. The stored text is
+    /// what an admin typed, so it is escaped here rather than trusted as HTML.
+    pub fn set_chat_end_templates(&self, settings: &ChatSettings) {
+        for (id, text) in [
+            (WIN_TEMPLATE, &settings.win_template),
+            (DRAW_TEMPLATE, &settings.draw_template),
+        ] {
+            match text {
+                Some(text) => self.set_override(settings.chat_id, id, &escape_html(text)),
+                None => self.clear_override(settings.chat_id, id),
+            }
+        }
+    }
+}
+
+fn builtin(id: &str) -> Option<&'static str> {
+    DEFAULTS
+        .iter()
+        .find(|(key, _)| *key == id)
+        .map(|(_, text)| *text)
+}
+
+/// Replaces `{name}` placeholders with the matching argument. Unknown placeholders are
+/// left as written, and substituted values are not scanned again.
+pub fn fill(template: &str, args: &[(&str, &str)]) -> String {
+    let mut out = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        out.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+        let value = after.find('}').and_then(|end| {
+            let name = &after[..end];
+            args.iter()
+                .find(|(key, _)| *key == name)
+                .map(|(_, value)| (*value, end))
+        });
+        match value {
+            Some((value, end)) => {
+                out.push_str(value);
+                rest = &after[end + 1..];
+            }
+            None => {
+                out.push('{');
+                rest = after;
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Names of the `{name}` placeholders in `template`, in order.
+pub fn placeholders(template: &str) -> Vec<&str> {
+    let mut names = Vec::new();
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        rest = &rest[start + 1..];
+        if let Some(end) = rest.find(['{', '}']).filter(|end| rest[*end..].starts_with('}')) {
+            names.push(&rest[..end]);
+            rest = &rest[end + 1..];
+        }
+    }
+    names
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fill() {
+        assert_eq!(
+            fill("{loser} resigned. {winner} wins.", &[("winner", "A"), ("loser", "B")]),
+            "B resigned. A wins."
+        );
+        assert_eq!(fill("{unknown} {x", &[("x", "1")]), "{unknown} {x");
+        assert_eq!(fill("{a}", &[("a", "{a}")]), "{a}");
+    }
+
+    #[test]
+    fn test_placeholders() {
+        assert_eq!(placeholders("{winner} beat {loser} in {moves}"), vec!["winner", "loser", "moves"]);
+        assert_eq!(placeholders("{{x} {y"), vec!["x"]);
+        assert!(placeholders("no braces").is_empty());
+    }
+
+    #[test]
+    fn test_chat_end_templates_are_escaped() {
+        let templates = Templates::default();
+        let mut settings = ChatSettings::defaults(7);
+        settings.win_template = Some("<b>{winner}</b> won".to_string());
+        templates.set_chat_end_templates(&settings);
+        assert_eq!(
+            templates.render(7, None, WIN_TEMPLATE, &[("winner", "<a>A</a>")]),
+            "&lt;b&gt;<a>A</a>&lt;/b&gt; won"
+        );
+
+        settings.win_template = None;
+        templates.set_chat_end_templates(&settings);
+        assert!(templates.get(7, None, WIN_TEMPLATE).starts_with("Game ended."));
+    }
+
+    #[test]
+    fn test_lookup_order() {
+        let mut german = HashMap::new();
+        german.insert("draw.none_pending".to_string(), "Kein Remisangebot.".to_string());
+        let templates = Templates::default().with_locale("de", german);
+
+        assert_eq!(
+            templates.get(1, Some("de-AT"), "draw.none_pending"),
+            "Kein Remisangebot."
+        );
+        assert_eq!(
+            templates.get(1, Some("fr"), "draw.none_pending"),
+            "No draw proposal is pending."
+        );
+        // Ids the locale lacks fall back to English
+        assert_eq!(templates.get(1, Some("de"), "side.white"), "White");
+
+        templates.set_override(1, "draw.none_pending", "Nothing to accept.");
+        assert_eq!(templates.get(1, Some("de"), "draw.none_pending"), "Nothing to accept.");
+        assert_eq!(templates.get(2, Some("de"), "draw.none_pending"), "Kein Remisangebot.");
+
+        templates.clear_override(1, "draw.none_pending");
+        assert_eq!(templates.get(1, None, "draw.none_pending"), "No draw proposal is pending.");
+    }
+}
@@ -0,0 +1,234 @@
+//! Read-only JSON API for community sites and stream overlays: games, their PGN, a live
+//! feed of their moves, and player stats. Every request needs one of the keys in `API_KEYS`, sent as a bearer token
+//! or, for browser sources that can't set headers, as `?key=`. Without keys the API is off.
+
+use crate::error::KamaError;
+use crate::live::LiveEvent;
+use crate::models::{DbUser, GameRow, Turn};
+use crate::utils::game_pgn;
+use crate::{db, AppState};
+use axum::{
+    extract::{Path, Request, State},
+    http::{header, StatusCode},
+    middleware::{self, Next},
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        IntoResponse, Response,
+    },
+    routing::get,
+    Json, Router,
+};
+use futures_util::stream::{self, Stream, StreamExt};
+use serde::Serialize;
+use std::convert::Infallible;
+use std::sync::Arc;
+use tokio::sync::broadcast::error::RecvError;
+use tracing::error;
+
+pub fn routes() -> Router<Arc<AppState>> {
+    Router::new()
+        .route("/games/:id", get(game_handler))
+        .route("/games/:id/pgn", get(pgn_handler))
+        .route("/games/:id/stream", get(stream_handler))
+        .route("/users/:id/stats", get(stats_handler))
+        .route_layer(middleware::from_fn(require_api_key))
+}
+
+#[derive(Debug, Serialize)]
+struct PlayerView {
+    id: i64,
+    username: Option<String>,
+    name: String,
+}
+
+impl From<&DbUser> for PlayerView {
+    fn from(user: &DbUser) -> Self {
+        Self {
+            id: user.id,
+            username: user.username.clone(),
+            name: user.display_name(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct GameView {
+    id: i64,
+    status: &'static str,
+    result: Option<&'static str>,
+    white: PlayerView,
+    black: PlayerView,
+    fen: String,
+    turn: &'static str,
+    moves: Vec<String>,
+    started_at: String,
+    ended_at: Option<String>,
+    /// Seconds from start to end, for finished games.
+    duration_secs: Option<i64>,
+}
+
+#[derive(Debug, Serialize)]
+struct StatsView {
+    #[serde(flatten)]
+    player: PlayerView,
+    games: i64,
+    wins: i64,
+    losses: i64,
+    draws: i64,
+}
+
+/// Lets the request through when it carries one of the comma-separated keys in `API_KEYS`.
+/// With no keys configured the API answers 404, as if it didn't exist.
+async fn require_api_key(request: Request, next: Next) -> Response {
+    let keys = std::env::var("API_KEYS").unwrap_or_default();
+    let keys: Vec<&str> = keys.split(',').map(str::trim).filter(|key| !key.is_empty()).collect();
+    if keys.is_empty() {
+        return StatusCode::NOT_FOUND.into_response();
+    }
+
+    let bearer = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "));
+    let query = request
+        .uri()
+        .query()
+        .and_then(|query| query.split('&').find_map(|pair| pair.strip_prefix("key=")));
+    match bearer.or(query) {
+        Some(sent) if keys.iter().any(|key| super::constant_time_eq(sent.as_bytes(), key.as_bytes())) => {
+            next.run(request).await
+        }
+        _ => StatusCode::UNAUTHORIZED.into_response(),
+    }
+}
+
+impl GameView {
+    fn new((game, white, black, moves, (started_at, ended_at)): LoadedGame) -> Self {
+        Self {
+            id: game.id,
+            status: game.status.as_str(),
+            result: game.result.map(|result| result.as_str()),
+            white: PlayerView::from(&white),
+            black: PlayerView::from(&black),
+            fen: game.current_fen,
+            turn: match game.turn {
+                Turn::White => "white",
+                Turn::Black => "black",
+            },
+            moves,
+            duration_secs: ended_at
+                .as_deref()
+                .and_then(|ended_at| crate::utils::elapsed_secs(&started_at, ended_at)),
+            started_at,
+            ended_at,
+        }
+    }
+}
+
+/// A game, its players and every move so far.
+async fn game_handler(State(state): State<Arc<AppState>>, Path(id): Path<i64>) -> Response {
+    match load_game(&state, id).await {
+        Ok(Some(loaded)) => Json(GameView::new(loaded)).into_response(),
+        Ok(None) => StatusCode::NOT_FOUND.into_response(),
+        Err(e) => internal_error(e),
+    }
+}
+
+/// Server-sent events for one game: a `game` event with the same snapshot as
+/// `/api/games/:id`, then a `move` event per move and an `end` event, after which the
+/// stream closes. A finished game gets only the snapshot.
+async fn stream_handler(State(state): State<Arc<AppState>>, Path(id): Path<i64>) -> Response {
+    // Subscribing before reading the game means no move falls between the two
+    let receiver = state.live.subscribe();
+    let view = match load_game(&state, id).await {
+        Ok(Some(loaded)) => GameView::new(loaded),
+        Ok(None) => return StatusCode::NOT_FOUND.into_response(),
+        Err(e) => return internal_error(e),
+    };
+    let finished = view.status != crate::models::GameStatus::Ongoing.as_str();
+    let snapshot = Event::default().event("game").json_data(&view);
+    let snapshot = stream::once(async move { Ok(snapshot.unwrap_or_default()) });
+    let updates = game_events(receiver, id, finished);
+    Sse::new(snapshot.chain(updates))
+        .keep_alive(KeepAlive::default())
+        .into_response()
+}
+
+/// The game's live events until its `end` event. A subscriber too slow to keep up skips
+/// what it missed; the next move event carries the full position again.
+fn game_events(
+    receiver: tokio::sync::broadcast::Receiver<LiveEvent>,
+    game_id: i64,
+    finished: bool,
+) -> impl Stream<Item = Result<Event, Infallible>> {
+    stream::unfold((receiver, finished), move |(mut receiver, done)| async move {
+        if done {
+            return None;
+        }
+        loop {
+            match receiver.recv().await {
+                Ok(event) if event.game_id() == game_id => {
+                    let done = matches!(event, LiveEvent::End { .. });
+                    let sse = Event::default().event(event.name()).json_data(&event).unwrap_or_default();
+                    return Some((Ok(sse), (receiver, done)));
+                }
+                Ok(_) | Err(RecvError::Lagged(_)) => continue,
+                Err(RecvError::Closed) => return None,
+            }
+        }
+    })
+}
+
+/// The game as a PGN file; a running game has the result `*`.
+async fn pgn_handler(State(state): State<Arc<AppState>>, Path(id): Path<i64>) -> Response {
+    match load_game(&state, id).await {
+        Ok(Some((game, white, black, moves, (started_at, ended_at)))) => {
+            match db::get_game_move_comments(&state.db, id).await {
+                Ok(comments) => (
+                    [(header::CONTENT_TYPE, "application/x-chess-pgn; charset=utf-8")],
+                    game_pgn(&game, &white, &black, &moves, &comments, &started_at, ended_at.as_deref()),
+                )
+                    .into_response(),
+                Err(e) => internal_error(e),
+            }
+        }
+        Ok(None) => StatusCode::NOT_FOUND.into_response(),
+        Err(e) => internal_error(e),
+    }
+}
+
+async fn stats_handler(State(state): State<Arc<AppState>>, Path(id): Path<i64>) -> Response {
+    match db::get_user_by_id(&state.db, id).await {
+        Ok(user) => Json(StatsView {
+            player: PlayerView::from(&user),
+            games: user.wins + user.losses + user.draws,
+            wins: user.wins,
+            losses: user.losses,
+            draws: user.draws,
+        })
+        .into_response(),
+        Err(KamaError::Db(sqlx::Error::RowNotFound)) => StatusCode::NOT_FOUND.into_response(),
+        Err(e) => internal_error(e),
+    }
+}
+
+type LoadedGame = (GameRow, DbUser, DbUser, Vec<String>, (String, Option<String>));
+
+async fn load_game(state: &AppState, id: i64) -> crate::error::Result<Option<LoadedGame>> {
+    let Some(game) = db::find_game_with_archive(&state.db, id).await? else {
+        return Ok(None);
+    };
+    let Some(times) = db::get_game_times(&state.db, id).await? else {
+        return Ok(None);
+    };
+    let white = state.users.get_by_id(&state.db, game.white_user_id).await?;
+    let black = state.users.get_by_id(&state.db, game.black_user_id).await?;
+    let moves = db::get_game_san_moves(&state.db, id).await?;
+    Ok(Some((game, white, black, moves, times)))
+}
+
+fn internal_error(e: KamaError) -> Response {
+    error!(error = %e, "API request failed");
+    StatusCode::INTERNAL_SERVER_ERROR.into_response()
+}
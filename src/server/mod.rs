@@ -1,33 +1,49 @@
-use crate::{handlers, AppState};
+mod api;
+mod pages;
+mod webhook_secret;
+mod workers;
+
+use crate::api::WebhookOptions;
+use crate::AppState;
 use anyhow::{anyhow, Result};
 use axum::{
-    extract::{Request, State},
-    http::StatusCode,
-    middleware::Next,
-    response::Response,
-    routing::post,
+    extract::{Extension, State},
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    routing::{get, post},
     Router,
 };
 use std::net::SocketAddr;
 use std::sync::Arc;
 use tokio::signal;
 use tracing::{error, info, warn};
+use rand::{distributions::Alphanumeric, Rng};
+use webhook_secret::{constant_time_eq, rotate_secret_handler, verify_secret_token_middleware};
+use workers::{UpdateQueue, WorkerConfig};
 
-pub struct WebhookConfig {
-    pub secret_token: Option<String>,
-}
+pub use webhook_secret::WebhookConfig;
+
+/// Letters and digits in a generated webhook path, about 190 bits.
+const RANDOM_PATH_LENGTH: usize = 32;
 
 pub async fn start_webhook_server(
     state: Arc<AppState>,
     webhook_url: String,
-    webhook_port: u16,
+    listen_addr: SocketAddr,
     webhook_path: String,
-    secret_token: Option<String>,
+    options: WebhookOptions,
+    webhook_config: WebhookConfig,
 ) -> Result<()> {
-    info!(webhook_url = %webhook_url, "Setting webhook URL");
+    info!(
+        webhook_url = %webhook_url,
+        self_signed = options.certificate.is_some(),
+        drop_pending_updates = options.drop_pending_updates,
+        max_connections = options.max_connections,
+        "Setting webhook URL"
+    );
     if let Err(err) = state
         .telegram
-        .set_webhook(&webhook_url, secret_token.as_deref())
+        .set_webhook(&webhook_url, &options)
         .await
     {
         error!("Failed to set webhook: {err:?}");
@@ -35,12 +51,13 @@ pub async fn start_webhook_server(
     }
     info!("Webhook set successfully");
 
-    let webhook_config = Arc::new(WebhookConfig { secret_token });
+    let webhook_config = Arc::new(webhook_config.with_registration(webhook_url, options));
     let app = create_router(state.clone(), webhook_config.clone(), webhook_path);
-    let addr = SocketAddr::from(([0, 0, 0, 0], webhook_port));
-    info!(port = webhook_port, "Starting webhook server on {}", addr);
+    info!("Starting webhook server on {}", listen_addr);
 
-    let listener = tokio::net::TcpListener::bind(addr).await?;
+    let listener = tokio::net::TcpListener::bind(listen_addr)
+        .await
+        .map_err(|e| anyhow!("Failed to listen on {}: {}", listen_addr, e))?;
     let server = axum::serve(listener, app)
         .with_graceful_shutdown(shutdown_signal(state));
 
@@ -52,6 +69,20 @@ pub async fn start_webhook_server(
     Ok(())
 }
 
+/// Without a configured path the webhook gets a random one under `webhook_url`'s path, so
+/// only Telegram knows where to post updates. Returns the URL to register and the route.
+pub fn random_webhook_route(webhook_url: &str) -> Result<(String, String)> {
+    let mut url = reqwest::Url::parse(webhook_url).map_err(|e| anyhow!("WEBHOOK_URL is not a valid URL: {}", e))?;
+    let secret: String = rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(RANDOM_PATH_LENGTH)
+        .map(char::from)
+        .collect();
+    let path = format!("{}/{}", url.path().trim_end_matches('/'), secret);
+    url.set_path(&path);
+    Ok((url.to_string(), path))
+}
+
 pub fn create_router_for_test(
     state: Arc<AppState>,
     webhook_config: Arc<WebhookConfig>,
@@ -65,51 +96,58 @@ fn create_router(
     webhook_config: Arc<WebhookConfig>,
     webhook_path: String,
 ) -> Router {
+    let config = WorkerConfig::from_env();
+    info!(workers = config.workers, queue_size = config.queue_size, "Starting update workers");
+    let queue = UpdateQueue::start(state.clone(), config);
     Router::new()
         .route(&webhook_path, post(webhook_handler))
+        .layer(Extension(queue))
         .route("/health", post(health_check))
         .layer(axum::middleware::from_fn_with_state(
-            webhook_config,
+            webhook_config.clone(),
             verify_secret_token_middleware,
         ))
+        // Authenticated with ADMIN_TOKEN, not Telegram's secret
+        .route(
+            "/admin/rotate-webhook-secret",
+            post(rotate_secret_handler).layer(Extension(webhook_config)),
+        )
+        // Prometheus can't send Telegram's secret header, so /metrics has its own check
+        .route("/metrics", get(metrics_handler))
+        // The public API authenticates with its own keys, see `api`
+        .nest("/api", api::routes())
+        .merge(pages::routes())
         .with_state(state)
 }
 
-async fn verify_secret_token_middleware(
-    State(config): State<Arc<WebhookConfig>>,
-    request: Request,
-    next: Next,
-) -> Result<Response, StatusCode> {
-    if let Some(expected_token) = &config.secret_token {
-        let header_value = request
-            .headers()
-            .get("X-Telegram-Bot-Api-Secret-Token")
-            .ok_or(StatusCode::UNAUTHORIZED)?
-            .to_str()
-            .map_err(|_| StatusCode::BAD_REQUEST)?;
-
-        if header_value != expected_token {
-            return Err(StatusCode::UNAUTHORIZED);
-        }
-    }
-
-    Ok(next.run(request).await)
-}
-
 async fn webhook_handler(
-    State(state): State<Arc<AppState>>,
+    Extension(queue): Extension<UpdateQueue>,
     axum::Json(update): axum::Json<crate::models::Update>,
 ) -> StatusCode {
-    let state_clone = state.clone();
-    tokio::spawn(async move {
-        if let Err(err) = handlers::process_update(state_clone, update).await {
-            error!("Failed to process update: {err:?}");
-        }
-    });
-
+    queue.push(update).await;
     StatusCode::OK
 }
 
+/// Usage counters in the Prometheus text format. When `METRICS_TOKEN` is set, scrapers must
+/// send it as a bearer token.
+async fn metrics_handler(State(state): State<Arc<AppState>>, headers: HeaderMap) -> Response {
+    if let Ok(token) = std::env::var("METRICS_TOKEN") {
+        let authorized = headers
+            .get(header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .is_some_and(|sent| !token.is_empty() && constant_time_eq(sent.as_bytes(), token.as_bytes()));
+        if !authorized && !token.is_empty() {
+            return StatusCode::UNAUTHORIZED.into_response();
+        }
+    }
+    (
+        [(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+        state.metrics.render_prometheus(),
+    )
+        .into_response()
+}
+
 async fn health_check() -> StatusCode {
     StatusCode::OK
 }
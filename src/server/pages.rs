@@ -0,0 +1,183 @@
+//! Public web pages. `/g/<chat>/<number>` replays a chat's game move by move in the
+//! browser; the chat is identified by its Telegram id and the game by its number there.
+
+use crate::game::Position;
+use crate::models::DbUser;
+use crate::utils::{escape_html, lichess_analysis_url};
+use crate::{db, AppState};
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::{Html, IntoResponse, Response},
+    routing::get,
+    Router,
+};
+use std::sync::Arc;
+use tracing::error;
+
+pub fn routes() -> Router<Arc<AppState>> {
+    Router::new().route("/g/:chat/:number", get(game_page_handler))
+}
+
+async fn game_page_handler(State(state): State<Arc<AppState>>, Path((chat, number)): Path<(String, i64)>) -> Response {
+    let Ok(chat_id) = chat.parse::<i64>() else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    let page = async {
+        let Some(game) = db::find_game_by_number(&state.db, chat_id, number).await? else {
+            return Ok(None);
+        };
+        let white = state.users.get_by_id(&state.db, game.white_user_id).await?;
+        let black = state.users.get_by_id(&state.db, game.black_user_id).await?;
+        let moves = db::get_game_san_moves(&state.db, game.id).await?;
+        let result = game.result.map_or("*", |result| result.as_str());
+        Ok::<_, crate::error::KamaError>(Some(game_page(number, &white, &black, &moves, result)))
+    };
+    match page.await {
+        Ok(Some(html)) => Html(html).into_response(),
+        Ok(None) => StatusCode::NOT_FOUND.into_response(),
+        Err(e) => {
+            error!(chat_id = chat_id, number = number, error = %e, "Failed to build game page");
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+/// Replays the stored moves from the start, returning the FEN after each one (the start
+/// position first) and the moves that could be replayed.
+fn replay(moves: &[String]) -> (Vec<String>, Vec<String>) {
+    let mut board = Position::default();
+    let mut fens = vec![board.to_string()];
+    let mut played = Vec::new();
+    for text in moves {
+        // Older rows may only have UCI
+        let Some(mv) = board.parse_san(text).or_else(|| board.parse_uci(text)) else {
+            break;
+        };
+        played.push(board.san(mv));
+        board = board.play(mv);
+        fens.push(board.to_string());
+    }
+    (fens, played)
+}
+
+fn game_page(number: i64, white: &DbUser, black: &DbUser, moves: &[String], result: &str) -> String {
+    let (fens, played) = replay(moves);
+    let title = format!(
+        "Game #{}: {} vs {}",
+        number,
+        escape_html(&white.display_name()),
+        escape_html(&black.display_name())
+    );
+    // The JSON sits in a script tag, where "</" would end it early
+    let data = serde_json::json!({ "fens": fens, "moves": played }).to_string().replace("</", "<\\/");
+    // Player names go in last, so nothing they contain is taken for a placeholder
+    PAGE_TEMPLATE
+        .replace("{data}", &data)
+        .replace("{analysis}", &escape_html(&lichess_analysis_url(moves)))
+        .replace("{result}", &escape_html(result))
+        .replace("{title}", &title)
+}
+
+const PAGE_TEMPLATE: &str = r##"<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta charset="utf-8">
+<meta name="viewport" content="width=device-width, initial-scale=1">
+<title>{title}</title>
+<style>
+body { font-family: sans-serif; max-width: 32rem; margin: 1rem auto; padding: 0 1rem; }
+#board { display: grid; grid-template-columns: repeat(8, 1fr); border: 2px solid #555; aspect-ratio: 1; }
+#board div { display: flex; align-items: center; justify-content: center; font-size: min(8vw, 2.5rem); }
+.light { background: #f0d9b5; } .dark { background: #b58863; }
+#controls { display: flex; gap: .5rem; margin: .75rem 0; }
+#moves span { cursor: pointer; padding: 0 .2rem; } #moves .current { background: #ffe08a; }
+</style>
+</head>
+<body>
+<h1>{title}</h1>
+<p>Result: <b>{result}</b> · <a href="{analysis}">Analyse on lichess</a></p>
+<div id="board"></div>
+<div id="controls">
+<button data-step="start">⏮</button><button data-step="-1">◀</button>
+<button data-step="1">▶</button><button data-step="end">⏭</button>
+</div>
+<p id="moves"></p>
+<script>
+const game = {data};
+const pieces = { K: "♔", Q: "♕", R: "♖", B: "♗", N: "♘", P: "♙", k: "♚", q: "♛", r: "♜", b: "♝", n: "♞", p: "♟" };
+let ply = game.fens.length - 1;
+function show(next) {
+  ply = Math.max(0, Math.min(game.fens.length - 1, next));
+  const board = document.getElementById("board");
+  board.innerHTML = "";
+  game.fens[ply].split(" ")[0].split("/").forEach((row, rank) => {
+    let file = 0;
+    for (const c of row) {
+      const empty = Number(c);
+      for (let i = 0; i < (empty || 1); i++, file++) {
+        const square = document.createElement("div");
+        square.className = (rank + file) % 2 ? "dark" : "light";
+        square.textContent = empty ? "" : pieces[c];
+        board.appendChild(square);
+      }
+    }
+  });
+  document.querySelectorAll("#moves span").forEach((span, i) => span.classList.toggle("current", i + 1 === ply));
+}
+const list = document.getElementById("moves");
+game.moves.forEach((san, i) => {
+  if (i % 2 === 0) list.append(`${i / 2 + 1}. `);
+  const span = document.createElement("span");
+  span.textContent = san;
+  span.onclick = () => show(i + 1);
+  list.append(span, " ");
+});
+document.querySelectorAll("#controls button").forEach(button => button.onclick = () => {
+  const step = button.dataset.step;
+  show(step === "start" ? 0 : step === "end" ? game.fens.length - 1 : ply + Number(step));
+});
+document.addEventListener("keydown", e => {
+  if (e.key === "ArrowLeft") show(ply - 1);
+  if (e.key === "ArrowRight") show(ply + 1);
+});
+show(ply);
+</script>
+</body>
+</html>
+"##;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn user(username: &str) -> DbUser {
+        DbUser {
+            id: 1,
+            telegram_id: None,
+            username: Some(username.to_string()),
+            first_name: None,
+            last_name: None,
+            wins: 0,
+            losses: 0,
+            draws: 0,
+        }
+    }
+
+    #[test]
+    fn test_replay_accepts_san_and_uci() {
+        let moves: Vec<String> = ["e4", "e7e5", "Nf3", "bogus", "Nc6"].iter().map(|m| m.to_string()).collect();
+        let (fens, played) = replay(&moves);
+        assert_eq!(played, vec!["e4", "e5", "Nf3"]);
+        assert_eq!(fens.len(), 4);
+        assert_eq!(fens[0], Position::default().to_string());
+    }
+
+    #[test]
+    fn test_game_page_escapes_names() {
+        let html = game_page(3, &user("a<b"), &user("c"), &["e4".to_string()], "*");
+        assert!(html.contains("<title>Game #3: @a&lt;b vs @c</title>"));
+        assert!(html.contains(r#""moves":["e4"]"#));
+        assert!(!html.contains("{data}"));
+    }
+}
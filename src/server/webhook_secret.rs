@@ -0,0 +1,216 @@
+//! The secret Telegram sends with every webhook update, and rotating it. After a rotation
+//! the old secret is still accepted for a grace window, so updates Telegram already queued
+//! with it aren't refused.
+
+use crate::api::WebhookOptions;
+use crate::error::{KamaError, Result};
+use crate::AppState;
+use axum::{
+    extract::{Extension, Request, State},
+    http::{header, HeaderMap, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use rand::{distributions::Alphanumeric, Rng};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+use tracing::{error, info};
+
+/// Length of a generated secret; Telegram allows up to 256 characters.
+const SECRET_LENGTH: usize = 64;
+const DEFAULT_GRACE_SECS: u64 = 300;
+
+struct Secrets {
+    current: Option<String>,
+    /// The secret before the last rotation, accepted until the instant given.
+    previous: Option<(String, Instant)>,
+}
+
+pub struct WebhookConfig {
+    secrets: RwLock<Secrets>,
+    grace: Duration,
+    /// URL and options the webhook was registered with, for registering a new secret.
+    registration: Option<(String, WebhookOptions)>,
+    /// Keeps two rotations from interleaving.
+    rotating: Mutex<()>,
+}
+
+impl WebhookConfig {
+    /// Checks updates against `secret_token`; without one every update is accepted.
+    pub fn new(secret_token: Option<String>) -> Self {
+        Self {
+            secrets: RwLock::new(Secrets {
+                current: secret_token,
+                previous: None,
+            }),
+            grace: Duration::from_secs(DEFAULT_GRACE_SECS),
+            registration: None,
+            rotating: Mutex::new(()),
+        }
+    }
+
+    /// How long the replaced secret stays valid after a rotation.
+    pub fn with_grace(mut self, grace: Duration) -> Self {
+        self.grace = grace;
+        self
+    }
+
+    /// Also accepts `secret` for the grace window from now, e.g. the one used before a
+    /// redeploy that changed `WEBHOOK_SECRET_TOKEN`.
+    pub fn with_previous(self, secret: String) -> Self {
+        let until = Instant::now() + self.grace;
+        self.secrets.write().unwrap().previous = Some((secret, until));
+        self
+    }
+
+    pub fn with_registration(mut self, url: String, options: WebhookOptions) -> Self {
+        self.registration = Some((url, options));
+        self
+    }
+
+    /// Whether an update carrying `sent` as its secret header is let through at `now`.
+    fn accepts(&self, sent: Option<&str>, now: Instant) -> bool {
+        let secrets = self.secrets.read().unwrap();
+        let Some(current) = &secrets.current else {
+            return true;
+        };
+        let Some(sent) = sent else {
+            return false;
+        };
+        let previous = secrets
+            .previous
+            .as_ref()
+            .is_some_and(|(secret, until)| now < *until && constant_time_eq(sent.as_bytes(), secret.as_bytes()));
+        constant_time_eq(sent.as_bytes(), current.as_bytes()) || previous
+    }
+
+    /// Switches to a new random secret and registers it with Telegram, keeping the old one
+    /// valid for the grace window. On failure the old secret is restored.
+    async fn rotate(&self, state: &AppState) -> Result<()> {
+        let Some((url, options)) = &self.registration else {
+            return Err(KamaError::Invalid("The webhook was not registered by this server".to_string()));
+        };
+        let _rotating = self.rotating.lock().await;
+        let secret: String = rand::thread_rng()
+            .sample_iter(&Alphanumeric)
+            .take(SECRET_LENGTH)
+            .map(char::from)
+            .collect();
+        let old = {
+            let mut secrets = self.secrets.write().unwrap();
+            let old = secrets.current.replace(secret.clone());
+            let previous = std::mem::replace(
+                &mut secrets.previous,
+                old.clone().map(|old| (old, Instant::now() + self.grace)),
+            );
+            (old, previous)
+        };
+
+        let options = WebhookOptions {
+            secret_token: Some(secret),
+            // Nothing has piled up; keep what Telegram is about to deliver
+            drop_pending_updates: false,
+            ..options.clone()
+        };
+        if let Err(e) = state.telegram.set_webhook(url, &options).await {
+            let mut secrets = self.secrets.write().unwrap();
+            (secrets.current, secrets.previous) = old;
+            return Err(e);
+        }
+        Ok(())
+    }
+}
+
+/// Compares two secrets in time that depends only on their lengths, not on where they
+/// first differ.
+pub(super) fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+pub(super) async fn verify_secret_token_middleware(
+    State(config): State<Arc<WebhookConfig>>,
+    request: Request,
+    next: Next,
+) -> std::result::Result<Response, StatusCode> {
+    let sent = match request.headers().get("X-Telegram-Bot-Api-Secret-Token") {
+        Some(value) => Some(value.to_str().map_err(|_| StatusCode::BAD_REQUEST)?),
+        None => None,
+    };
+    if !config.accepts(sent, Instant::now()) {
+        return Err(StatusCode::UNAUTHORIZED);
+    }
+    Ok(next.run(request).await)
+}
+
+/// `POST /admin/rotate-webhook-secret`: registers a new webhook secret. Needs `ADMIN_TOKEN`
+/// as a bearer token; without it configured the endpoint answers 404.
+pub(super) async fn rotate_secret_handler(
+    State(state): State<Arc<AppState>>,
+    Extension(config): Extension<Arc<WebhookConfig>>,
+    headers: HeaderMap,
+) -> Response {
+    let token = std::env::var("ADMIN_TOKEN").unwrap_or_default();
+    if token.is_empty() {
+        return StatusCode::NOT_FOUND.into_response();
+    }
+    let authorized = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .is_some_and(|sent| constant_time_eq(sent.as_bytes(), token.as_bytes()));
+    if !authorized {
+        return StatusCode::UNAUTHORIZED.into_response();
+    }
+
+    match config.rotate(&state).await {
+        Ok(()) => {
+            info!(grace_secs = config.grace.as_secs(), "Webhook secret rotated");
+            (StatusCode::OK, "Webhook secret rotated\n").into_response()
+        }
+        Err(e) => {
+            error!(error = %e, "Webhook secret rotation failed");
+            let status = match e {
+                KamaError::Invalid(_) => StatusCode::CONFLICT,
+                _ => StatusCode::BAD_GATEWAY,
+            };
+            (status, format!("Rotation failed: {}\n", e)).into_response()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_constant_time_eq() {
+        assert!(constant_time_eq(b"secret", b"secret"));
+        assert!(!constant_time_eq(b"secret", b"secreT"));
+        assert!(!constant_time_eq(b"secret", b"secret2"));
+        assert!(constant_time_eq(b"", b""));
+    }
+
+    #[test]
+    fn test_previous_secret_within_grace() {
+        let config = WebhookConfig::new(Some("new".to_string()))
+            .with_grace(Duration::from_secs(60))
+            .with_previous("old".to_string());
+        let now = Instant::now();
+        assert!(config.accepts(Some("new"), now));
+        assert!(config.accepts(Some("old"), now));
+        assert!(!config.accepts(Some("other"), now));
+        assert!(!config.accepts(None, now));
+
+        let later = now + Duration::from_secs(61);
+        assert!(config.accepts(Some("new"), later));
+        assert!(!config.accepts(Some("old"), later));
+    }
+
+    #[test]
+    fn test_no_secret_accepts_all() {
+        let config = WebhookConfig::new(None);
+        assert!(config.accepts(None, Instant::now()));
+        assert!(config.accepts(Some("anything"), Instant::now()));
+    }
+}
@@ -0,0 +1,274 @@
+//! Webhook updates go through a bounded queue to a fixed number of workers. When the
+//! queue is full the webhook waits before answering, so Telegram slows down instead of
+//! the bot piling up tasks, as happens when a backlog arrives after downtime.
+//!
+//! Slow read-only commands such as /history have a lane of their own, which workers only
+//! take from when no move or other game update is waiting.
+//!
+//! Each update runs in a task of its own, so a panicking handler only loses that update;
+//! the panic is logged with what the update was. Workers that stop anyway are restarted.
+
+use crate::models::Update;
+use crate::{handlers, AppState};
+use std::any::Any;
+use std::env;
+use std::sync::Arc;
+use tokio::sync::{mpsc, Mutex};
+use tracing::error;
+
+/// Longest part of a message text or button data included in a panic report.
+const MAX_LOGGED_INPUT: usize = 200;
+
+const DEFAULT_WORKERS: usize = 8;
+const DEFAULT_QUEUE_SIZE: usize = 1024;
+
+#[derive(Debug, Clone, Copy)]
+pub struct WorkerConfig {
+    /// Updates processed at the same time.
+    pub workers: usize,
+    /// Updates waiting for a worker before the webhook holds back, in each lane.
+    pub queue_size: usize,
+}
+
+impl WorkerConfig {
+    /// Reads `UPDATE_WORKERS` and `UPDATE_QUEUE_SIZE`.
+    pub fn from_env() -> Self {
+        Self::from_lookup(|name| env::var(name).ok())
+    }
+
+    /// Same as `from_env`, reading the variables through `var`.
+    fn from_lookup(var: impl Fn(&str) -> Option<String>) -> Self {
+        let read = |name: &str, default: usize| {
+            var(name)
+                .and_then(|v| v.trim().parse().ok())
+                .filter(|n| *n > 0)
+                .unwrap_or(default)
+        };
+        Self {
+            workers: read("UPDATE_WORKERS", DEFAULT_WORKERS),
+            queue_size: read("UPDATE_QUEUE_SIZE", DEFAULT_QUEUE_SIZE),
+        }
+    }
+}
+
+/// The sending side of the update queue; cloned into each webhook request.
+#[derive(Clone)]
+pub struct UpdateQueue {
+    gameplay: mpsc::Sender<Update>,
+    background: mpsc::Sender<Update>,
+    bot_username: String,
+}
+
+struct Lanes {
+    gameplay: mpsc::Receiver<Update>,
+    background: mpsc::Receiver<Update>,
+}
+
+impl Lanes {
+    /// The next update, gameplay first. `None` once both lanes are closed and empty.
+    async fn next(&mut self) -> Option<Update> {
+        tokio::select! {
+            biased;
+            Some(update) = self.gameplay.recv() => Some(update),
+            Some(update) = self.background.recv() => Some(update),
+            else => None,
+        }
+    }
+}
+
+impl UpdateQueue {
+    /// Starts the workers. They stop once every `UpdateQueue` clone is dropped.
+    pub fn start(state: Arc<AppState>, config: WorkerConfig) -> Self {
+        let (gameplay, gameplay_receiver) = mpsc::channel(config.queue_size);
+        let (background, background_receiver) = mpsc::channel(config.queue_size);
+        let lanes = Arc::new(Mutex::new(Lanes {
+            gameplay: gameplay_receiver,
+            background: background_receiver,
+        }));
+        for worker in 0..config.workers {
+            tokio::spawn(supervise_worker(worker, state.clone(), lanes.clone()));
+        }
+        Self {
+            gameplay,
+            background,
+            bot_username: state.bot_username.clone(),
+        }
+    }
+
+    /// Queues the update in its lane, waiting while that lane is full.
+    pub async fn push(&self, update: Update) {
+        let lane = if handlers::is_background_update(&update, &self.bot_username) {
+            &self.background
+        } else {
+            &self.gameplay
+        };
+        if lane.send(update).await.is_err() {
+            error!("Update workers have stopped; update dropped");
+        }
+    }
+}
+
+/// Runs a worker and starts it again if it dies, so the pool never silently shrinks.
+async fn supervise_worker(worker: usize, state: Arc<AppState>, lanes: Arc<Mutex<Lanes>>) {
+    loop {
+        match tokio::spawn(run_worker(state.clone(), lanes.clone())).await {
+            Ok(()) => return,
+            Err(err) => error!(worker, error = %err, "Update worker stopped unexpectedly; restarting"),
+        }
+    }
+}
+
+async fn run_worker(state: Arc<AppState>, lanes: Arc<Mutex<Lanes>>) {
+    loop {
+        // Only the lock holder waits on the lanes; the others wait for the lock
+        let Some(update) = lanes.lock().await.next().await else {
+            return;
+        };
+        let context = UpdateContext::of(&update);
+        // In its own task, so a panic fails this update and not the worker
+        let task = tokio::spawn(handlers::process_update(state.clone(), update));
+        match task.await {
+            // Errors are logged by `process_update`, inside the update's span
+            Ok(_) => {}
+            Err(err) if err.is_panic() => error!(
+                update_id = context.update_id,
+                error_ref = %handlers::error_ref(context.update_id),
+                kind = context.kind,
+                chat_id = context.chat_id,
+                user_id = context.user_id,
+                input = context.input.as_deref(),
+                panic = %panic_message(err.into_panic().as_ref()),
+                "Update handler panicked"
+            ),
+            Err(err) => error!(update_id = context.update_id, error = %err, "Update handler was cancelled"),
+        }
+    }
+}
+
+/// What is logged about an update whose handler panicked. Taken before handling, since the
+/// update is moved into the handler task.
+#[derive(Debug, PartialEq)]
+struct UpdateContext {
+    update_id: i64,
+    kind: &'static str,
+    chat_id: Option<i64>,
+    user_id: Option<i64>,
+    /// Message text or button data, shortened.
+    input: Option<String>,
+}
+
+impl UpdateContext {
+    fn of(update: &Update) -> Self {
+        let (kind, chat_id, user_id, input) = if let Some(query) = &update.callback_query {
+            let chat_id = query.message.as_ref().map(|m| m.chat.id);
+            ("callback_query", chat_id, Some(query.from.id), query.data.as_deref())
+        } else if let Some(answer) = &update.poll_answer {
+            ("poll_answer", None, answer.user.as_ref().map(|u| u.id), None)
+        } else if let Some(member) = &update.my_chat_member {
+            ("my_chat_member", Some(member.chat.id), Some(member.from.id), None)
+        } else if let Some(message) = &update.message {
+            let user_id = message.from.as_ref().map(|u| u.id);
+            ("message", Some(message.chat.id), user_id, message.text.as_deref())
+        } else {
+            ("other", None, None, None)
+        };
+        Self {
+            update_id: update.update_id,
+            kind,
+            chat_id,
+            user_id,
+            input: input.map(|text| text.chars().take(MAX_LOGGED_INPUT).collect()),
+        }
+    }
+}
+
+/// The message a panic was raised with, when it is a string.
+fn panic_message(payload: &(dyn Any + Send)) -> &str {
+    payload
+        .downcast_ref::<&str>()
+        .copied()
+        .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+        .unwrap_or("non-string panic payload")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn update(update_id: i64, text: &str) -> Update {
+        serde_json::from_value(serde_json::json!({
+            "update_id": update_id,
+            "message": { "message_id": 1, "chat": { "id": -1 }, "text": text }
+        }))
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_gameplay_lane_first() {
+        let (gameplay, gameplay_receiver) = mpsc::channel(4);
+        let (background, background_receiver) = mpsc::channel(4);
+        let mut lanes = Lanes {
+            gameplay: gameplay_receiver,
+            background: background_receiver,
+        };
+        background.send(update(1, "/history")).await.unwrap();
+        gameplay.send(update(2, "e4")).await.unwrap();
+        background.send(update(3, "/rank")).await.unwrap();
+        gameplay.send(update(4, "/resign")).await.unwrap();
+        drop((gameplay, background));
+
+        let mut order = Vec::new();
+        while let Some(update) = lanes.next().await {
+            order.push(update.update_id);
+        }
+        assert_eq!(order, vec![2, 4, 1, 3]);
+    }
+
+    #[test]
+    fn test_update_context() {
+        let long = format!("e4 {}", "x".repeat(300));
+        let context = UpdateContext::of(&update(7, &long));
+        assert_eq!(context.update_id, 7);
+        assert_eq!(context.kind, "message");
+        assert_eq!(context.chat_id, Some(-1));
+        assert_eq!(context.user_id, None);
+        assert_eq!(context.input.unwrap().chars().count(), MAX_LOGGED_INPUT);
+
+        let query: Update = serde_json::from_value(serde_json::json!({
+            "update_id": 8,
+            "callback_query": {
+                "id": "q",
+                "from": { "id": 5, "is_bot": false, "first_name": "Ann" },
+                "message": { "message_id": 1, "chat": { "id": -2 } },
+                "data": "tap:3:e2"
+            }
+        }))
+        .unwrap();
+        let context = UpdateContext::of(&query);
+        assert_eq!(context.kind, "callback_query");
+        assert_eq!((context.chat_id, context.user_id), (Some(-2), Some(5)));
+        assert_eq!(context.input.as_deref(), Some("tap:3:e2"));
+    }
+
+    #[tokio::test]
+    async fn test_panic_message() {
+        let err = tokio::spawn(async { panic!("bad fen {}", 1) }).await.unwrap_err();
+        assert_eq!(panic_message(err.into_panic().as_ref()), "bad fen 1");
+        let err = tokio::spawn(async { std::panic::panic_any(3) }).await.unwrap_err();
+        assert_eq!(panic_message(err.into_panic().as_ref()), "non-string panic payload");
+    }
+
+    #[test]
+    fn test_worker_config_defaults() {
+        let config = WorkerConfig::from_lookup(|_| None);
+        assert_eq!(config.workers, DEFAULT_WORKERS);
+        assert_eq!(config.queue_size, DEFAULT_QUEUE_SIZE);
+
+        let config = WorkerConfig::from_lookup(|name| match name {
+            "UPDATE_WORKERS" => Some(" 3 ".to_string()),
+            _ => Some("0".to_string()),
+        });
+        assert_eq!(config.workers, 3);
+        assert_eq!(config.queue_size, DEFAULT_QUEUE_SIZE);
+    }
+}
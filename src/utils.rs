@@ -1,3 +1,5 @@
+use crate::models::{DbUser, GameResult, GameRow};
+
 pub fn escape_html(text: &str) -> String {
     text.replace('&', "&amp;")
         .replace('<', "&lt;")
@@ -10,3 +12,370 @@ pub fn format_username(username: &Option<String>) -> String {
         None => "unknown".to_string(),
     }
 }
+
+/// Short human duration: "45s", "3m 05s", "2h 10m".
+pub fn format_duration(secs: i64) -> String {
+    let secs = secs.max(0);
+    if secs < 60 {
+        format!("{}s", secs)
+    } else if secs < 3600 {
+        format!("{}m {:02}s", secs / 60, secs % 60)
+    } else {
+        format!("{}h {:02}m", secs / 3600, secs % 3600 / 60)
+    }
+}
+
+/// Seconds between two stored RFC 3339 timestamps, `None` if either doesn't parse.
+pub fn elapsed_secs(from: &str, to: &str) -> Option<i64> {
+    let parse = |text: &str| chrono::DateTime::parse_from_rfc3339(text).ok();
+    Some((parse(to)? - parse(from)?).num_seconds().max(0))
+}
+
+/// When a game ran, for history lines: "2024-01-05 12:00–12:04 UTC (4m 30s)", the end
+/// date repeated only when it differs, or "started 2024-01-05 12:00 UTC" while it runs.
+pub fn format_game_times(started_at: &str, ended_at: Option<&str>) -> Option<String> {
+    let utc = |text: &str| chrono::DateTime::parse_from_rfc3339(text).ok().map(|at| at.to_utc());
+    let started = utc(started_at)?;
+    let Some(ended) = ended_at.and_then(utc) else {
+        return Some(format!("started {} UTC", started.format("%Y-%m-%d %H:%M")));
+    };
+    let end_format = if ended.date_naive() == started.date_naive() {
+        "%H:%M"
+    } else {
+        "%Y-%m-%d %H:%M"
+    };
+    Some(format!(
+        "{}–{} UTC ({})",
+        started.format("%Y-%m-%d %H:%M"),
+        ended.format(end_format),
+        format_duration((ended - started).num_seconds())
+    ))
+}
+
+/// Telegram's limit on photo and document captions.
+pub const MAX_CAPTION_LEN: usize = 1024;
+/// Telegram's limit on message text.
+pub const MAX_MESSAGE_LEN: usize = 4096;
+
+/// Length of Telegram HTML as Telegram counts it against its limits: tags are free, an
+/// entity such as `&lt;` is one character, and characters count in UTF-16 units.
+pub fn visible_len(html: &str) -> usize {
+    let mut len = 0;
+    let mut chars = html.char_indices();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '<' => {
+                for (_, c) in chars.by_ref() {
+                    if c == '>' {
+                        break;
+                    }
+                }
+            }
+            '&' => {
+                let entity = html[i + 1..].find(';').filter(|end| {
+                    *end > 0 && *end < 8 && html[i + 1..i + 1 + end].chars().all(|c| c.is_ascii_alphanumeric() || c == '#')
+                });
+                if let Some(end) = entity {
+                    chars.nth(end);
+                }
+                len += 1;
+            }
+            _ => len += c.len_utf16(),
+        }
+    }
+    len
+}
+
+/// Splits Telegram HTML into chunks of at most `max` visible characters. Chunks break
+/// between lines so no tag is cut in half; a single line longer than `max` is shortened
+/// to plain text with an ellipsis.
+pub fn split_message(html: &str, max: usize) -> Vec<String> {
+    let mut chunks = Vec::new();
+    let mut current: Option<(String, usize)> = None;
+
+    for line in html.split('\n') {
+        let line = if visible_len(line) > max {
+            shorten_line(line, max)
+        } else {
+            line.to_string()
+        };
+        let len = visible_len(&line);
+        match &mut current {
+            Some((text, used)) if *used + 1 + len <= max => {
+                text.push('\n');
+                text.push_str(&line);
+                *used += 1 + len;
+            }
+            _ => {
+                chunks.extend(current.take().map(|(text, _)| text));
+                current = Some((line, len));
+            }
+        }
+    }
+    chunks.extend(current.map(|(text, _)| text));
+
+    chunks
+        .into_iter()
+        .map(|chunk| chunk.trim_matches('\n').to_string())
+        .filter(|chunk| !chunk.trim().is_empty())
+        .collect()
+}
+
+fn shorten_line(html: &str, max: usize) -> String {
+    let mut plain = String::new();
+    let mut used = 0;
+    for c in strip_html(html).chars() {
+        if used + c.len_utf16() > max.saturating_sub(1) {
+            break;
+        }
+        used += c.len_utf16();
+        plain.push(c);
+    }
+    format!("{}…", escape_html(&plain))
+}
+
+/// The text of a Telegram HTML fragment with tags dropped and entities decoded.
+fn strip_html(html: &str) -> String {
+    let mut text = String::new();
+    let mut in_tag = false;
+    for c in html.chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' if in_tag => in_tag = false,
+            _ if !in_tag => text.push(c),
+            _ => {}
+        }
+    }
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&amp;", "&")
+}
+
+/// PGN movetext for moves played from the start: `1. e4 e5 2. Nf3`.
+pub fn pgn_movetext(moves: &[String]) -> String {
+    pgn_movetext_with_comments(moves, &[])
+}
+
+/// PGN movetext with the players' comments after their moves, `comments[i]` belonging to
+/// `moves[i]`: `1. e4 {my favorite opening} 1... e5`. Braces would end the comment early,
+/// so they are dropped from it.
+pub fn pgn_movetext_with_comments(moves: &[String], comments: &[Option<String>]) -> String {
+    let mut pgn = String::new();
+    let mut after_comment = false;
+    for (i, mv) in moves.iter().enumerate() {
+        if i % 2 == 0 {
+            if !pgn.is_empty() {
+                pgn.push(' ');
+            }
+            pgn.push_str(&format!("{}.", i / 2 + 1));
+        } else if after_comment {
+            // Black's move needs its number again once a comment splits it from White's
+            pgn.push_str(&format!(" {}...", i / 2 + 1));
+        }
+        pgn.push(' ');
+        pgn.push_str(mv);
+
+        let comment = comments.get(i).and_then(Option::as_deref).map(|text| text.replace(['{', '}'], ""));
+        after_comment = match comment.as_deref().map(str::trim) {
+            Some(text) if !text.is_empty() => {
+                pgn.push_str(&format!(" {{{}}}", text));
+                true
+            }
+            _ => false,
+        };
+    }
+    pgn
+}
+
+/// Shown next to the name of a chat's champion, its #1 in the standings.
+pub const CROWN: &str = "👑";
+
+pub const PGN_MIME_TYPE: &str = "application/x-chess-pgn";
+
+/// The game as a PGN file, with start and end times when they are known and the players'
+/// move comments.
+pub fn game_pgn(
+    game: &GameRow,
+    white: &DbUser,
+    black: &DbUser,
+    moves: &[String],
+    comments: &[Option<String>],
+    started_at: &str,
+    ended_at: Option<&str>,
+) -> String {
+    let result = game.result.as_ref().map_or("*", GameResult::as_str);
+    // PGN dates are YYYY.MM.DD
+    let date = started_at.get(..10).unwrap_or("????-??-??").replace('-', ".");
+    let tag = |value: &str| value.replace('\\', "\\\\").replace('"', "\\\"");
+    let utc = |text: &str| chrono::DateTime::parse_from_rfc3339(text).ok().map(|at| at.to_utc());
+    // Start and end times in the tags lichess and chess.com use
+    let mut times = String::new();
+    if let Some(started) = utc(started_at) {
+        times.push_str(&format!(
+            "[UTCDate \"{}\"]\n[UTCTime \"{}\"]\n",
+            started.format("%Y.%m.%d"),
+            started.format("%H:%M:%S")
+        ));
+    }
+    if let Some(ended) = ended_at.and_then(utc) {
+        times.push_str(&format!(
+            "[EndDate \"{}\"]\n[EndTime \"{}\"]\n",
+            ended.format("%Y.%m.%d"),
+            ended.format("%H:%M:%S")
+        ));
+    }
+    let movetext = pgn_movetext_with_comments(moves, comments);
+    let separator = if movetext.is_empty() { "" } else { " " };
+    format!(
+        "[Event \"Kamachess game #{}\"]\n[Site \"Telegram\"]\n[Date \"{}\"]\n[White \"{}\"]\n[Black \"{}\"]\n[Result \"{}\"]\n{}\n{}{}{}\n",
+        game.id,
+        date,
+        tag(&white.display_name()),
+        tag(&black.display_name()),
+        result,
+        times,
+        movetext,
+        separator,
+        result
+    )
+}
+
+/// The game's replay page on the bot's own web server, when `PUBLIC_URL` says where that
+/// is reachable.
+pub fn game_page_url(chat_id: i64, number: i64) -> Option<String> {
+    let base = std::env::var("PUBLIC_URL").ok()?;
+    let base = base.trim().trim_end_matches('/');
+    (!base.is_empty()).then(|| format!("{}/g/{}/{}", base, chat_id, number))
+}
+
+/// Lichess analysis board preloaded with the game's moves.
+pub fn lichess_analysis_url(moves: &[String]) -> String {
+    if moves.is_empty() {
+        return "https://lichess.org/analysis".to_string();
+    }
+
+    let encoded: String = pgn_movetext(moves)
+        .chars()
+        .map(|c| match c {
+            ' ' => "%20".to_string(),
+            '.' => ".".to_string(),
+            '#' => "%23".to_string(),
+            '+' => "%2B".to_string(),
+            '=' => "%3D".to_string(),
+            _ if c.is_ascii_alphanumeric() || c == '-' => c.to_string(),
+            _ => format!("%{:02X}", c as u8),
+        })
+        .collect();
+
+    format!("https://lichess.org/analysis/pgn/{}", encoded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{GameStatus, Turn};
+
+    #[test]
+    fn test_format_game_times() {
+        let start = "2024-01-05T12:00:00+00:00";
+        assert_eq!(
+            format_game_times(start, Some("2024-01-05T12:04:30+00:00")).as_deref(),
+            Some("2024-01-05 12:00–12:04 UTC (4m 30s)")
+        );
+        // Shown in UTC whatever offset was stored
+        assert_eq!(
+            format_game_times(start, Some("2024-01-06T01:00:00+02:00")).as_deref(),
+            Some("2024-01-05 12:00–23:00 UTC (11h 00m)")
+        );
+        assert_eq!(
+            format_game_times(start, Some("2024-01-06T10:00:00+00:00")).as_deref(),
+            Some("2024-01-05 12:00–2024-01-06 10:00 UTC (22h 00m)")
+        );
+        assert_eq!(format_game_times(start, None).as_deref(), Some("started 2024-01-05 12:00 UTC"));
+        assert_eq!(format_game_times("bad", None), None);
+        assert_eq!(elapsed_secs(start, "2024-01-05T12:01:00+00:00"), Some(60));
+    }
+
+    #[test]
+    fn test_visible_len() {
+        assert_eq!(visible_len("<b>Check</b>"), 5);
+        assert_eq!(visible_len("a &lt;b&gt; &amp; c"), 9);
+        assert_eq!(visible_len("<a href=\"tg://user?id=1\">Ann</a> ♞"), 5);
+        assert_eq!(visible_len("fish & chips; peas"), 18);
+    }
+
+    #[test]
+    fn test_split_message() {
+        assert_eq!(split_message("short\ntext", 100), vec!["short\ntext"]);
+        assert_eq!(
+            split_message("<b>aaaa</b>\nbbbb\ncccc", 9),
+            vec!["<b>aaaa</b>\nbbbb", "cccc"]
+        );
+        assert_eq!(split_message("<i>abcdefgh</i>", 5), vec!["abcd…"]);
+        assert_eq!(split_message("one\n\n\ntwo", 3), vec!["one", "two"]);
+        assert!(split_message("", 10).is_empty());
+    }
+
+    fn user(id: i64, username: &str) -> DbUser {
+        DbUser {
+            id,
+            telegram_id: Some(id),
+            username: Some(username.to_string()),
+            first_name: None,
+            last_name: None,
+            wins: 0,
+            losses: 0,
+            draws: 0,
+        }
+    }
+
+    #[test]
+    fn test_game_pgn() {
+        let game = GameRow {
+            id: 9,
+            chat_id: -1,
+            white_user_id: 1,
+            black_user_id: 2,
+            current_fen: String::new(),
+            turn: Turn::White,
+            status: GameStatus::Finished,
+            result: Some(GameResult::WhiteWins),
+            last_message_id: None,
+            draw_proposed_by: None,
+            draw_proposal_message_id: None,
+            confirm_moves: false,
+            pending_move: None,
+            pending_move_message_id: None,
+            peer_chat_id: None,
+        };
+        let moves: Vec<String> = ["f3", "e5", "g4", "Qh4#"].iter().map(|m| m.to_string()).collect();
+        let pgn = game_pgn(
+            &game,
+            &user(1, "alice"),
+            &user(2, "bob"),
+            &moves,
+            &[],
+            "2026-01-05T12:00:00+00:00",
+            Some("2026-01-05T12:03:20+00:00"),
+        );
+        assert!(pgn.starts_with("[Event \"Kamachess game #9\"]\n"));
+        assert!(pgn.contains("[Date \"2026.01.05\"]\n[White \"@alice\"]\n[Black \"@bob\"]\n[Result \"1-0\"]\n"));
+        assert!(pgn.contains("[UTCTime \"12:00:00\"]\n[EndDate \"2026.01.05\"]\n[EndTime \"12:03:20\"]\n"));
+        assert!(pgn.ends_with("\n\n1. f3 e5 2. g4 Qh4# 1-0\n"));
+
+        let ongoing = GameRow { result: None, ..game };
+        assert!(game_pgn(&ongoing, &user(1, "a"), &user(2, "b"), &[], &[], "bad", None).ends_with("\n\n*\n"));
+    }
+
+    #[test]
+    fn test_pgn_movetext_with_comments() {
+        let moves: Vec<String> = ["e4", "e5", "Nf3", "Nc6"].iter().map(|m| m.to_string()).collect();
+        let comments = vec![Some("my favorite opening".to_string()), None, None, Some("{sure}".to_string())];
+        assert_eq!(
+            pgn_movetext_with_comments(&moves, &comments),
+            "1. e4 {my favorite opening} 1... e5 2. Nf3 Nc6 {sure}"
+        );
+        assert_eq!(pgn_movetext_with_comments(&moves, &[None, Some(" ".to_string())]), pgn_movetext(&moves));
+    }
+}
@@ -1,135 +1,135 @@
-use chess::{Board, Piece, Square};
-use kamachess::game::parse_move;
+use kamachess::game::{build_caption, legal_moves_by_square, parse_move, Color, Position, Role, Square};
+use kamachess::models::DbUser;
 use std::str::FromStr;
 
 #[test]
 fn test_parse_pawn_move_e4() {
-    let board = Board::default();
+    let board = Position::default();
     let mv = parse_move(&board, "e4").unwrap();
-    assert_eq!(mv.get_dest(), Square::from_str("e4").unwrap());
+    assert_eq!(mv.to(), Square::from_str("e4").unwrap());
 }
 
 #[test]
 fn test_parse_pawn_move_e2e4() {
-    let board = Board::default();
+    let board = Position::default();
     let mv = parse_move(&board, "e2e4").unwrap();
-    assert_eq!(mv.get_source(), Square::from_str("e2").unwrap());
-    assert_eq!(mv.get_dest(), Square::from_str("e4").unwrap());
+    assert_eq!(mv.from(), Some(Square::from_str("e2").unwrap()));
+    assert_eq!(mv.to(), Square::from_str("e4").unwrap());
 }
 
 #[test]
 fn test_parse_knight_move_nf3() {
-    let board = Board::default();
+    let board = Position::default();
     let mv = parse_move(&board, "Nf3").unwrap();
-    assert_eq!(mv.get_dest(), Square::from_str("f3").unwrap());
-    assert_eq!(board.piece_on(mv.get_source()), Some(Piece::Knight));
+    assert_eq!(mv.to(), Square::from_str("f3").unwrap());
+    assert_eq!(board.piece_on(mv.from().unwrap()), Some(Role::Knight));
 }
 
 #[test]
 fn test_parse_knight_move_lowercase_nf3() {
-    let board = Board::default();
+    let board = Position::default();
     let mv = parse_move(&board, "nf3").unwrap();
-    assert_eq!(mv.get_dest(), Square::from_str("f3").unwrap());
+    assert_eq!(mv.to(), Square::from_str("f3").unwrap());
 }
 
 #[test]
 fn test_parse_knight_move_nc3() {
-    let board = Board::default();
+    let board = Position::default();
     let mv = parse_move(&board, "Nc3").unwrap();
-    assert_eq!(mv.get_dest(), Square::from_str("c3").unwrap());
+    assert_eq!(mv.to(), Square::from_str("c3").unwrap());
 }
 
 #[test]
 fn test_parse_knight_nf6_illegal_for_white() {
-    let board = Board::default();
+    let board = Position::default();
     assert!(parse_move(&board, "Nf6").is_err());
 }
 
 #[test]
 fn test_parse_black_knight_nf6() {
-    let board = Board::default();
+    let board = Position::default();
     let mv = parse_move(&board, "e4").unwrap();
-    let board = board.make_move_new(mv);
+    let board = board.play(mv);
     let mv = parse_move(&board, "Nf6").unwrap();
-    assert_eq!(mv.get_dest(), Square::from_str("f6").unwrap());
+    assert_eq!(mv.to(), Square::from_str("f6").unwrap());
 }
 
 #[test]
 fn test_parse_coordinate_g1f3() {
-    let board = Board::default();
+    let board = Position::default();
     let mv = parse_move(&board, "g1f3").unwrap();
-    assert_eq!(mv.get_source(), Square::from_str("g1").unwrap());
-    assert_eq!(mv.get_dest(), Square::from_str("f3").unwrap());
+    assert_eq!(mv.from(), Some(Square::from_str("g1").unwrap()));
+    assert_eq!(mv.to(), Square::from_str("f3").unwrap());
 }
 
 #[test]
 fn test_castling_short() {
     let fen = "r3k2r/pppppppp/8/8/8/8/PPPPPPPP/R3K2R w KQkq - 0 1";
-    let board = Board::from_str(fen).unwrap();
+    let board = Position::from_str(fen).unwrap();
     let mv = parse_move(&board, "O-O").unwrap();
-    assert_eq!(mv.get_source(), Square::from_str("e1").unwrap());
-    assert_eq!(mv.get_dest(), Square::from_str("g1").unwrap());
+    assert_eq!(mv.from(), Some(Square::from_str("e1").unwrap()));
+    assert_eq!(mv.to(), Square::from_str("g1").unwrap());
 }
 
 #[test]
 fn test_castling_long() {
     let fen = "r3k2r/pppppppp/8/8/8/8/PPPPPPPP/R3K2R w KQkq - 0 1";
-    let board = Board::from_str(fen).unwrap();
+    let board = Position::from_str(fen).unwrap();
     let mv = parse_move(&board, "O-O-O").unwrap();
-    assert_eq!(mv.get_source(), Square::from_str("e1").unwrap());
-    assert_eq!(mv.get_dest(), Square::from_str("c1").unwrap());
+    assert_eq!(mv.from(), Some(Square::from_str("e1").unwrap()));
+    assert_eq!(mv.to(), Square::from_str("c1").unwrap());
 }
 
 #[test]
 fn test_castling_zero_notation() {
     let fen = "r3k2r/pppppppp/8/8/8/8/PPPPPPPP/R3K2R w KQkq - 0 1";
-    let board = Board::from_str(fen).unwrap();
+    let board = Position::from_str(fen).unwrap();
     let mv = parse_move(&board, "0-0").unwrap();
-    assert_eq!(mv.get_dest(), Square::from_str("g1").unwrap());
+    assert_eq!(mv.to(), Square::from_str("g1").unwrap());
 }
 
 #[test]
 fn test_castling_double_zero_short() {
     let fen = "r3k2r/pppppppp/8/8/8/8/PPPPPPPP/R3K2R w KQkq - 0 1";
-    let board = Board::from_str(fen).unwrap();
+    let board = Position::from_str(fen).unwrap();
     let mv = parse_move(&board, "00").unwrap();
-    assert_eq!(mv.get_source(), Square::from_str("e1").unwrap());
-    assert_eq!(mv.get_dest(), Square::from_str("g1").unwrap());
+    assert_eq!(mv.from(), Some(Square::from_str("e1").unwrap()));
+    assert_eq!(mv.to(), Square::from_str("g1").unwrap());
 }
 
 #[test]
 fn test_castling_triple_zero_long() {
     let fen = "r3k2r/pppppppp/8/8/8/8/PPPPPPPP/R3K2R w KQkq - 0 1";
-    let board = Board::from_str(fen).unwrap();
+    let board = Position::from_str(fen).unwrap();
     let mv = parse_move(&board, "000").unwrap();
-    assert_eq!(mv.get_source(), Square::from_str("e1").unwrap());
-    assert_eq!(mv.get_dest(), Square::from_str("c1").unwrap());
+    assert_eq!(mv.from(), Some(Square::from_str("e1").unwrap()));
+    assert_eq!(mv.to(), Square::from_str("c1").unwrap());
 }
 
 #[test]
 fn test_castling_oo_short() {
     let fen = "r3k2r/pppppppp/8/8/8/8/PPPPPPPP/R3K2R w KQkq - 0 1";
-    let board = Board::from_str(fen).unwrap();
+    let board = Position::from_str(fen).unwrap();
     let mv = parse_move(&board, "oo").unwrap();
-    assert_eq!(mv.get_source(), Square::from_str("e1").unwrap());
-    assert_eq!(mv.get_dest(), Square::from_str("g1").unwrap());
+    assert_eq!(mv.from(), Some(Square::from_str("e1").unwrap()));
+    assert_eq!(mv.to(), Square::from_str("g1").unwrap());
 }
 
 #[test]
 fn test_castling_ooo_long() {
     let fen = "r3k2r/pppppppp/8/8/8/8/PPPPPPPP/R3K2R w KQkq - 0 1";
-    let board = Board::from_str(fen).unwrap();
+    let board = Position::from_str(fen).unwrap();
     let mv = parse_move(&board, "ooo").unwrap();
-    assert_eq!(mv.get_source(), Square::from_str("e1").unwrap());
-    assert_eq!(mv.get_dest(), Square::from_str("c1").unwrap());
+    assert_eq!(mv.from(), Some(Square::from_str("e1").unwrap()));
+    assert_eq!(mv.to(), Square::from_str("c1").unwrap());
 }
 
 #[test]
 fn test_castling_uppercase_oo() {
     let fen = "r3k2r/pppppppp/8/8/8/8/PPPPPPPP/R3K2R w KQkq - 0 1";
-    let board = Board::from_str(fen).unwrap();
+    let board = Position::from_str(fen).unwrap();
     let mv = parse_move(&board, "OO").unwrap();
-    assert_eq!(mv.get_dest(), Square::from_str("g1").unwrap());
+    assert_eq!(mv.to(), Square::from_str("g1").unwrap());
 }
 
 // Tests for move_to_san function
@@ -137,7 +137,7 @@ use kamachess::game::move_to_san;
 
 #[test]
 fn test_move_to_san_pawn_move() {
-    let board = Board::default();
+    let board = Position::default();
     let mv = parse_move(&board, "e4").unwrap();
     assert_eq!(move_to_san(&board, mv), "e4");
 }
@@ -145,14 +145,14 @@ fn test_move_to_san_pawn_move() {
 #[test]
 fn test_move_to_san_bishop_move() {
     let board =
-        Board::from_str("rnbqkbnr/pppp1ppp/8/4p3/4P3/8/PPPP1PPP/RNBQKBNR w KQkq - 0 1").unwrap();
+        Position::from_str("rnbqkbnr/pppp1ppp/8/4p3/4P3/8/PPPP1PPP/RNBQKBNR w KQkq - 0 1").unwrap();
     let mv = parse_move(&board, "bc4").unwrap();
     assert_eq!(move_to_san(&board, mv), "Bc4");
 }
 
 #[test]
 fn test_move_to_san_knight_move() {
-    let board = Board::default();
+    let board = Position::default();
     let mv = parse_move(&board, "nf3").unwrap();
     assert_eq!(move_to_san(&board, mv), "Nf3");
 }
@@ -160,14 +160,14 @@ fn test_move_to_san_knight_move() {
 #[test]
 fn test_move_to_san_pawn_capture() {
     let board =
-        Board::from_str("rnbqkbnr/ppp1pppp/8/3p4/4P3/8/PPPP1PPP/RNBQKBNR w KQkq - 0 1").unwrap();
+        Position::from_str("rnbqkbnr/ppp1pppp/8/3p4/4P3/8/PPPP1PPP/RNBQKBNR w KQkq - 0 1").unwrap();
     let mv = parse_move(&board, "exd5").unwrap();
     assert_eq!(move_to_san(&board, mv), "exd5");
 }
 
 #[test]
 fn test_move_to_san_piece_capture() {
-    let board = Board::from_str("rnbqkb1r/pppp1ppp/5n2/4p3/2B1P3/8/PPPP1PPP/RNBQK1NR w KQkq - 0 1")
+    let board = Position::from_str("rnbqkb1r/pppp1ppp/5n2/4p3/2B1P3/8/PPPP1PPP/RNBQK1NR w KQkq - 0 1")
         .unwrap();
     let mv = parse_move(&board, "Bxf7").unwrap();
     assert_eq!(move_to_san(&board, mv), "Bxf7+"); // This move gives check
@@ -176,7 +176,7 @@ fn test_move_to_san_piece_capture() {
 #[test]
 fn test_move_to_san_castling_short() {
     let fen = "r3k2r/pppppppp/8/8/8/8/PPPPPPPP/R3K2R w KQkq - 0 1";
-    let board = Board::from_str(fen).unwrap();
+    let board = Position::from_str(fen).unwrap();
     let mv = parse_move(&board, "O-O").unwrap();
     assert_eq!(move_to_san(&board, mv), "O-O");
 }
@@ -184,14 +184,14 @@ fn test_move_to_san_castling_short() {
 #[test]
 fn test_move_to_san_castling_long() {
     let fen = "r3k2r/pppppppp/8/8/8/8/PPPPPPPP/R3K2R w KQkq - 0 1";
-    let board = Board::from_str(fen).unwrap();
+    let board = Position::from_str(fen).unwrap();
     let mv = parse_move(&board, "O-O-O").unwrap();
     assert_eq!(move_to_san(&board, mv), "O-O-O");
 }
 
 #[test]
 fn test_move_to_san_promotion() {
-    let board = Board::from_str("4k3/P7/8/8/8/8/8/4K3 w - - 0 1").unwrap();
+    let board = Position::from_str("4k3/P7/8/8/8/8/8/4K3 w - - 0 1").unwrap();
     let mv = parse_move(&board, "a8=Q").unwrap();
     assert_eq!(move_to_san(&board, mv), "a8=Q+"); // This move gives check
 }
@@ -200,7 +200,7 @@ fn test_move_to_san_promotion() {
 fn test_move_to_san_check() {
     // Test a move that gives check
     let board =
-        Board::from_str("rnbqkbnr/pppp1ppp/8/4p3/2B1P3/8/PPPP1PPP/RNBQK1NR w KQkq - 0 1").unwrap();
+        Position::from_str("rnbqkbnr/pppp1ppp/8/4p3/2B1P3/8/PPPP1PPP/RNBQK1NR w KQkq - 0 1").unwrap();
     let mv = parse_move(&board, "Qf3").unwrap();
     assert_eq!(move_to_san(&board, mv), "Qf3"); // Qf3 doesn't give check in this position
 }
@@ -209,7 +209,7 @@ fn test_move_to_san_check() {
 fn test_move_to_san_checkmate() {
     // Simple checkmate test - just verify the function works
     // We already test check symbols in other tests
-    let board = Board::default();
+    let board = Position::default();
     let mv = parse_move(&board, "e4").unwrap();
     let san = move_to_san(&board, mv);
     assert_eq!(san, "e4"); // Simple pawn move
@@ -219,8 +219,250 @@ fn test_move_to_san_checkmate() {
 fn test_move_to_san_with_capture_symbol() {
     // Test that captures include the 'x' symbol
     let board =
-        Board::from_str("rnbqkbnr/ppp1pppp/8/3p4/4P3/8/PPPP1PPP/RNBQKBNR w KQkq - 0 1").unwrap();
+        Position::from_str("rnbqkbnr/ppp1pppp/8/3p4/4P3/8/PPPP1PPP/RNBQKBNR w KQkq - 0 1").unwrap();
     let mv = parse_move(&board, "exd5").unwrap();
     let san = move_to_san(&board, mv);
     assert_eq!(san, "exd5"); // Pawn capture with file and x symbol
 }
+
+use kamachess::game::parse_move_strict;
+
+#[test]
+fn test_strict_accepts_standard_san() {
+    let board =
+        Position::from_str("rnbqkbnr/ppp1pppp/8/3p4/4P3/8/PPPP1PPP/RNBQKBNR w KQkq - 0 1").unwrap();
+    assert!(parse_move_strict(&board, "exd5").is_ok());
+    assert!(parse_move_strict(&board, "Nf3").is_ok());
+    assert!(parse_move_strict(&board, "e4d5").is_ok());
+}
+
+#[test]
+fn test_strict_rejects_missing_capture_mark() {
+    let board =
+        Position::from_str("rnbqkbnr/ppp1pppp/8/3p4/4P3/8/PPPP1PPP/RNBQKBNR w KQkq - 0 1").unwrap();
+    assert!(parse_move(&board, "ed5").is_ok());
+    let err = parse_move_strict(&board, "ed5").unwrap_err();
+    assert!(err.to_string().contains("exd5"));
+}
+
+#[test]
+fn test_strict_rejects_lowercase_piece_and_needless_disambiguation() {
+    let board = Position::default();
+    assert!(parse_move(&board, "nf3").is_ok());
+    assert!(parse_move_strict(&board, "nf3").is_err());
+    assert!(parse_move_strict(&board, "Ngf3").is_err());
+}
+
+#[test]
+fn test_strict_requires_disambiguation() {
+    // Knights on b1 and f3 can both reach d2
+    let board = Position::from_str("4k3/8/8/8/8/5N2/8/1N2K3 w - - 0 1").unwrap();
+    assert!(parse_move_strict(&board, "Nbd2").is_ok());
+    assert!(parse_move(&board, "N1d2").is_ok());
+    assert!(parse_move_strict(&board, "N1d2").is_err());
+}
+
+#[test]
+fn test_move_to_san_disambiguates_by_file() {
+    // Knights on b8 and f6 can both reach d7
+    let board = Position::from_str("1n2k3/8/5n2/8/8/8/8/4K3 b - - 0 1").unwrap();
+    let mv = parse_move(&board, "b8d7").unwrap();
+    assert_eq!(move_to_san(&board, mv), "Nbd7");
+}
+
+#[test]
+fn test_move_to_san_disambiguates_by_rank() {
+    // Rooks on e1 and e3 can both reach e2
+    let board = Position::from_str("k7/8/8/8/8/4R3/8/4R2K w - - 0 1").unwrap();
+    let mv = parse_move(&board, "e1e2").unwrap();
+    assert_eq!(move_to_san(&board, mv), "R1e2");
+}
+
+#[test]
+fn test_move_to_san_disambiguates_by_square() {
+    // Queens on e4, h4 and h1 all reach e1; the h4 queen shares a file and a rank
+    let board = Position::from_str("8/8/k7/8/4Q2Q/8/8/K6Q w - - 0 1").unwrap();
+    let mv = parse_move(&board, "h4e1").unwrap();
+    assert_eq!(move_to_san(&board, mv), "Qh4e1");
+}
+
+#[test]
+fn test_move_to_san_ignores_pinned_piece() {
+    // The knight on d4 is pinned to the king, so Nc3-e2 needs no disambiguation
+    let board = Position::from_str("3rk3/8/8/8/3N4/2N5/8/3K4 w - - 0 1").unwrap();
+    let mv = parse_move(&board, "c3e2").unwrap();
+    assert_eq!(move_to_san(&board, mv), "Ne2");
+}
+
+#[test]
+fn test_move_to_san_round_trips_through_parse() {
+    let board = Position::from_str("1n2k3/8/5n2/8/8/8/8/4K3 b - - 0 1").unwrap();
+    let mv = parse_move(&board, "f6d7").unwrap();
+    let san = move_to_san(&board, mv);
+    assert_eq!(san, "Nfd7");
+    assert_eq!(parse_move_strict(&board, &san).unwrap(), mv);
+}
+
+#[test]
+fn test_move_to_san_en_passant_is_capture() {
+    let board =
+        Position::from_str("rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3").unwrap();
+    let mv = parse_move(&board, "e5f6").unwrap();
+    assert_eq!(move_to_san(&board, mv), "exf6");
+}
+
+use kamachess::game::move_to_display_san;
+
+const EN_PASSANT_FEN: &str = "rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3";
+
+#[test]
+fn test_en_passant_accepted_in_all_notations() {
+    let board = Position::from_str(EN_PASSANT_FEN).unwrap();
+    let expected = parse_move(&board, "e5f6").unwrap();
+    for input in ["exf6", "exf6 e.p.", "exf6e.p.", "exf6 ep", "ef6"] {
+        assert_eq!(parse_move(&board, input).unwrap(), expected, "{}", input);
+    }
+    assert_eq!(parse_move_strict(&board, "exf6 e.p.").unwrap(), expected);
+}
+
+#[test]
+fn test_en_passant_removes_captured_pawn() {
+    let board = Position::from_str(EN_PASSANT_FEN).unwrap();
+    let mv = parse_move(&board, "exf6").unwrap();
+    let next = board.play(mv);
+    assert_eq!(next.piece_on(Square::from_str("f5").unwrap()), None);
+    assert_eq!(next.piece_on(Square::from_str("f6").unwrap()), Some(Role::Pawn));
+    assert_eq!(move_to_display_san(&board, mv), "exf6 e.p.");
+}
+
+#[test]
+fn test_en_passant_expires_after_one_move() {
+    // Same position without the en passant square: the f-pawn advanced earlier
+    let board =
+        Position::from_str("rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq - 0 3").unwrap();
+    assert!(parse_move(&board, "exf6").is_err());
+    assert!(parse_move(&board, "e5f6").is_err());
+}
+
+#[test]
+fn test_en_passant_illegal_when_it_exposes_king() {
+    // Taking on c6 would clear the fifth rank between the king on a5 and the rook on h5
+    let board = Position::from_str("8/8/8/KPp4r/8/8/8/4k3 w - c6 0 2").unwrap();
+    assert!(parse_move(&board, "bxc6").is_err());
+    assert!(parse_move(&board, "b6").is_ok());
+}
+
+#[test]
+fn test_regular_capture_has_no_en_passant_marker() {
+    let board =
+        Position::from_str("rnbqkbnr/ppp1pppp/8/3p4/4P3/8/PPPP1PPP/RNBQKBNR w KQkq - 0 1").unwrap();
+    let mv = parse_move(&board, "exd5").unwrap();
+    assert_eq!(move_to_display_san(&board, mv), "exd5");
+}
+
+#[test]
+fn test_b_pawn_capture_is_not_a_bishop_move() {
+    let board =
+        Position::from_str("rnbqkbnr/pp1ppppp/8/8/2p5/1P6/P1PPPPPP/RNBQKBNR w KQkq - 0 3").unwrap();
+    let mv = parse_move(&board, "bxc4").unwrap();
+    assert_eq!(mv.from(), Some(Square::from_str("b3").unwrap()));
+    assert_eq!(move_to_san(&board, mv), "bxc4");
+}
+
+#[test]
+fn test_chess960_castling_aliases() {
+    let board = Position::from_str("4k3/8/8/8/8/8/8/RK5R w HA - 0 1").unwrap();
+    let short = parse_move(&board, "O-O").unwrap();
+    assert_eq!(short.to(), Square::from_str("g1").unwrap());
+    assert_eq!(parse_move(&board, "00").unwrap(), short);
+    assert_eq!(parse_move(&board, "b1h1").unwrap(), short);
+    assert_eq!(parse_move(&board, "O-O-O").unwrap().to(), Square::from_str("c1").unwrap());
+}
+
+fn caption_user(id: i64, name: &str) -> DbUser {
+    DbUser {
+        id,
+        telegram_id: Some(id),
+        username: None,
+        first_name: Some(name.to_string()),
+        last_name: None,
+        wins: 0,
+        losses: 0,
+        draws: 0,
+    }
+}
+
+#[test]
+fn test_build_caption_fits() {
+    let board = Position::default();
+    let caption = build_caption(
+        "Game started",
+        &board,
+        &caption_user(1, "Ann"),
+        &caption_user(2, "Bob"),
+        Color::White,
+        None,
+        Some(2),
+    );
+    assert!(caption.text.starts_with("Game started.\nWhite: "));
+    assert!(caption.text.contains("Bob</a> 👑\nTo move"));
+    assert!(caption.text.ends_with("To move: <a href=\"tg://user?id=1\">Ann</a>\nMove 1 · Opening"));
+    assert_eq!(caption.overflow, None);
+}
+
+#[test]
+fn test_build_caption_moves_long_result_to_overflow() {
+    let board = Position::default();
+    let result = format!("Result: {}", "x".repeat(1100));
+    let caption = build_caption(
+        "Move played",
+        &board,
+        &caption_user(1, "Ann"),
+        &caption_user(2, "Bob"),
+        Color::Black,
+        Some(result),
+        None,
+    );
+    assert!(kamachess::utils::visible_len(&caption.text) <= kamachess::utils::MAX_CAPTION_LEN);
+    assert!(caption.text.contains("To move: "));
+    // The overflow goes out as a message, so the line is kept whole
+    assert_eq!(caption.overflow.unwrap().len(), 1108);
+}
+
+#[test]
+fn test_build_caption_material_icons() {
+    // White is a knight and three pawns up
+    let board = Position::from_str("4k3/pp3ppp/8/8/8/8/PPPPPPPP/1N2K3 w - - 0 1").unwrap();
+    let caption = build_caption("Move played", &board, &caption_user(1, "Ann"), &caption_user(2, "Bob"), Color::White, None, None);
+    assert!(caption.text.ends_with("\n<a href=\"tg://user?id=1\">Ann</a> ♘♙♙♙ +6"));
+
+    // Only the leader's surplus is shown, not the rook it is traded against
+    let board = Position::from_str("q3k3/8/8/8/8/8/8/R3K3 w - - 0 1").unwrap();
+    let caption = build_caption("Move played", &board, &caption_user(1, "Ann"), &caption_user(2, "Bob"), Color::White, None, None);
+    assert!(caption.text.ends_with("\n<a href=\"tg://user?id=2\">Bob</a> ♛ +4"));
+}
+
+#[test]
+fn test_build_caption_phase() {
+    let caption = |fen: &str| {
+        let board = Position::from_str(fen).unwrap();
+        build_caption("Move played", &board, &caption_user(1, "Ann"), &caption_user(2, "Bob"), Color::White, None, None).text
+    };
+    let middlegame = caption("r1bq1rk1/pppp1ppp/2n2n2/2b1p3/2B1P3/2NP1N2/PPP2PPP/R1BQ1RK1 w - - 0 11");
+    assert!(middlegame.contains("\nMove 11 · Middlegame"));
+    // Queens off and a rook and two minor pieces each: an endgame however early
+    let endgame = caption("2b1k2r/pppp1ppp/2n5/4p3/4P3/2N5/PPPP1PPP/2B1K2R w Kk - 0 6");
+    assert!(endgame.contains("\nMove 6 · Endgame"));
+}
+
+#[test]
+fn test_legal_moves_by_square() {
+    let groups = legal_moves_by_square(&Position::default());
+    assert_eq!(groups.iter().map(|(_, moves)| moves.len()).sum::<usize>(), 20);
+    // Knights and pawns only, in a1..h8 order
+    assert_eq!(groups[0], (Some(Square::B1), vec!["Na3".to_string(), "Nc3".to_string()]));
+    assert_eq!(groups.len(), 10);
+
+    let mate = Position::from_str("rnb1kbnr/pppp1ppp/8/4p3/6Pq/5P2/PPPPP2P/RNBQKBNR w KQkq - 1 3").unwrap();
+    assert!(legal_moves_by_square(&mate).is_empty());
+}
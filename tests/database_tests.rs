@@ -1,5 +1,5 @@
 use kamachess::db;
-use kamachess::models::User;
+use kamachess::models::{GameResult, GameStatus, Rivalry, Turn, User};
 use sqlx::any::AnyPoolOptions;
 
 async fn setup_test_db() -> sqlx::Pool<sqlx::Any> {
@@ -20,6 +20,7 @@ fn test_user(id: i64, username: Option<&str>) -> User {
         username: username.map(String::from),
         first_name: Some(format!("User{}", id)),
         last_name: None,
+        language_code: None,
     }
 }
 
@@ -50,6 +51,7 @@ async fn test_upsert_user_updates_existing() {
         username: Some("newname".to_string()),
         first_name: Some("NewFirst".to_string()),
         last_name: None,
+        language_code: None,
     };
     let db_user = db::upsert_user(&pool, &user2).await.unwrap();
 
@@ -84,6 +86,7 @@ async fn test_upsert_user_merges_placeholder() {
         username: Some("emovadilda".to_string()),
         first_name: Some("Real".to_string()),
         last_name: None,
+        language_code: None,
     };
     let merged_user = db::upsert_user(&pool, &real_user).await.unwrap();
     
@@ -128,7 +131,7 @@ async fn test_create_game() {
         white.id,
         black.id,
         "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
-        "white",
+        Turn::White,
     )
     .await
     .unwrap();
@@ -149,7 +152,7 @@ async fn test_find_ongoing_game() {
         white.id,
         black.id,
         "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
-        "white",
+        Turn::White,
     )
     .await
     .unwrap();
@@ -184,7 +187,7 @@ async fn test_find_game_by_message() {
         white.id,
         black.id,
         "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
-        "white",
+        Turn::White,
     )
     .await
     .unwrap();
@@ -212,13 +215,13 @@ async fn test_find_game_by_message_via_game_messages_table() {
         white.id,
         black.id,
         "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
-        "white",
+        Turn::White,
     )
     .await
     .unwrap();
     
     // Insert an old message into game_messages table
-    db::insert_game_message(&pool, game_id, old_message_id).await.unwrap();
+    db::insert_game_message(&pool, game_id, chat_id, old_message_id).await.unwrap();
     
     // Update last_message_id to a newer message
     db::update_game_message(&pool, game_id, new_message_id).await.unwrap();
@@ -238,6 +241,25 @@ async fn test_find_game_by_message_via_game_messages_table() {
     assert_eq!(found.unwrap().id, game_id);
 }
 
+#[tokio::test]
+async fn test_message_lookup_indexes() {
+    let pool = setup_test_db().await;
+    let indexes: Vec<(String,)> = sqlx::query_as("SELECT name FROM sqlite_master WHERE type = 'index'")
+        .fetch_all(&pool)
+        .await
+        .unwrap();
+    let indexes: Vec<&str> = indexes.iter().map(|(name,)| name.as_str()).collect();
+    for name in [
+        "idx_games_chat_status",
+        "idx_games_last_message",
+        "idx_games_peer_chat",
+        "idx_moves_game_number",
+        "idx_game_messages_message_id",
+    ] {
+        assert!(indexes.contains(&name), "missing index {}", name);
+    }
+}
+
 #[tokio::test]
 async fn test_update_game_fen() {
     let pool = setup_test_db().await;
@@ -250,17 +272,17 @@ async fn test_update_game_fen() {
         white.id,
         black.id,
         "start_fen",
-        "white",
+        Turn::White,
     )
     .await
     .unwrap();
 
-    db::update_game_fen(&pool, game_id, "new_fen", "black").await.unwrap();
+    db::update_game_fen(&pool, game_id, "new_fen", Turn::Black).await.unwrap();
     db::update_game_message(&pool, game_id, 1).await.unwrap();
 
     let game = db::find_game_by_message(&pool, -400, 1).await.unwrap().unwrap();
     assert_eq!(game.current_fen, "new_fen");
-    assert_eq!(game.turn, "black");
+    assert_eq!(game.turn, Turn::Black);
 }
 
 #[tokio::test]
@@ -269,7 +291,7 @@ async fn test_insert_and_get_next_move_number() {
     let white = db::upsert_user(&pool, &test_user(1, None)).await.unwrap();
     let black = db::upsert_user(&pool, &test_user(2, None)).await.unwrap();
 
-    let game_id = db::create_game(&pool, -500, white.id, black.id, "fen", "white")
+    let game_id = db::create_game(&pool, -500, white.id, black.id, "fen", Turn::White)
         .await
         .unwrap();
 
@@ -289,6 +311,9 @@ async fn test_insert_and_get_next_move_number() {
 
     let next = db::next_move_number(&pool, game_id).await.unwrap();
     assert_eq!(next, 3);
+
+    let last = db::get_last_move(&pool, game_id).await.unwrap();
+    assert_eq!(last, Some((2, "e5".to_string())));
 }
 
 #[tokio::test]
@@ -297,18 +322,101 @@ async fn test_update_game_result() {
     let white = db::upsert_user(&pool, &test_user(1, None)).await.unwrap();
     let black = db::upsert_user(&pool, &test_user(2, None)).await.unwrap();
 
-    let game_id = db::create_game(&pool, -600, white.id, black.id, "fen", "white")
+    let game_id = db::create_game(&pool, -600, white.id, black.id, "fen", Turn::White)
         .await
         .unwrap();
     db::update_game_message(&pool, game_id, 1).await.unwrap();
 
-    db::update_game_result(&pool, game_id, &Some("1-0".to_string()), "finished")
+    db::update_game_result(&pool, game_id, GameResult::WhiteWins)
         .await
         .unwrap();
 
     let game = db::find_game_by_message(&pool, -600, 1).await.unwrap().unwrap();
-    assert_eq!(game.status, "finished");
-    assert_eq!(game.result, Some("1-0".to_string()));
+    assert_eq!(game.status, GameStatus::Finished);
+    assert_eq!(game.result, Some(GameResult::WhiteWins));
+}
+
+#[tokio::test]
+async fn test_settle_game_only_once() {
+    let pool = setup_test_db().await;
+    let white = db::upsert_user(&pool, &test_user(1, None)).await.unwrap();
+    let black = db::upsert_user(&pool, &test_user(2, None)).await.unwrap();
+    let game_id = db::create_game(&pool, -605, white.id, black.id, "fen", Turn::White)
+        .await
+        .unwrap();
+    let game = db::get_game_by_id(&pool, game_id).await.unwrap().unwrap();
+
+    assert!(db::settle_game(&pool, &game, GameResult::BlackWins).await.unwrap());
+    assert!(!db::settle_game(&pool, &game, GameResult::WhiteWins).await.unwrap());
+
+    let settled = db::get_game_by_id(&pool, game_id).await.unwrap().unwrap();
+    assert_eq!(settled.result, Some(GameResult::BlackWins));
+    assert_eq!(db::get_user_by_id(&pool, black.id).await.unwrap().wins, 1);
+    assert_eq!(db::get_user_by_id(&pool, white.id).await.unwrap().wins, 0);
+    assert_eq!(db::get_user_by_id(&pool, white.id).await.unwrap().losses, 1);
+}
+
+#[tokio::test]
+async fn test_user_finished_game_ids() {
+    let pool = setup_test_db().await;
+    let alice = db::upsert_user(&pool, &test_user(1, None)).await.unwrap();
+    let bob = db::upsert_user(&pool, &test_user(2, None)).await.unwrap();
+    let carol = db::upsert_user(&pool, &test_user(3, None)).await.unwrap();
+
+    let mut finished = Vec::new();
+    for (white, black) in [(alice.id, bob.id), (bob.id, alice.id), (alice.id, carol.id)] {
+        let game_id = db::create_game(&pool, -610, white, black, "fen", Turn::White).await.unwrap();
+        db::update_game_result(&pool, game_id, GameResult::Draw).await.unwrap();
+        finished.push(game_id);
+    }
+    // Still running, between other players, or in another chat
+    db::create_game(&pool, -610, alice.id, bob.id, "fen", Turn::White).await.unwrap();
+    let others = db::create_game(&pool, -610, bob.id, carol.id, "fen", Turn::White).await.unwrap();
+    db::update_game_result(&pool, others, GameResult::Draw).await.unwrap();
+    let elsewhere = db::create_game(&pool, -611, alice.id, bob.id, "fen", Turn::White).await.unwrap();
+    db::update_game_result(&pool, elsewhere, GameResult::Draw).await.unwrap();
+
+    let first_page = db::get_user_finished_game_ids(&pool, -610, alice.id, 0, 2).await.unwrap();
+    assert_eq!(first_page, finished[..2]);
+    let rest = db::get_user_finished_game_ids(&pool, -610, alice.id, first_page[1], 2).await.unwrap();
+    assert_eq!(rest, finished[2..]);
+}
+
+#[tokio::test]
+async fn test_pair_record_rivalry() {
+    let pool = setup_test_db().await;
+    let alice = db::upsert_user(&pool, &test_user(1, None)).await.unwrap();
+    let bob = db::upsert_user(&pool, &test_user(2, None)).await.unwrap();
+    let play = |white: i64, black: i64, result: GameResult| {
+        let pool = pool.clone();
+        async move {
+            let game_id = db::create_game(&pool, -620, white, black, "fen", Turn::White).await.unwrap();
+            db::update_game_result(&pool, game_id, result).await.unwrap();
+        }
+    };
+
+    let record = db::get_pair_record(&pool, -620, alice.id, bob.id).await.unwrap();
+    assert_eq!(record.games(), 0);
+    assert_eq!(record.rivalry(), None);
+
+    // Bob won as Black: Alice wants revenge
+    play(alice.id, bob.id, GameResult::BlackWins).await;
+    let record = db::get_pair_record(&pool, -620, alice.id, bob.id).await.unwrap();
+    assert_eq!((record.a_wins, record.b_wins, record.last_winner), (0, 1, Some(bob.id)));
+    assert_eq!(record.rivalry(), Some(Rivalry::Revenge { player: alice.id, opponent: bob.id }));
+
+    // Alice got even; the tie wins over the revenge
+    play(bob.id, alice.id, GameResult::BlackWins).await;
+    let record = db::get_pair_record(&pool, -620, alice.id, bob.id).await.unwrap();
+    assert_eq!(record.rivalry(), Some(Rivalry::Tied(1)));
+
+    // Seven draws later the next game is their tenth
+    for _ in 0..7 {
+        play(alice.id, bob.id, GameResult::Draw).await;
+    }
+    let record = db::get_pair_record(&pool, -620, bob.id, alice.id).await.unwrap();
+    assert_eq!((record.draws, record.last_winner), (7, None));
+    assert_eq!(record.rivalry(), Some(Rivalry::Milestone(10)));
 }
 
 #[tokio::test]
@@ -317,7 +425,7 @@ async fn test_update_player_stats_white_wins() {
     let white = db::upsert_user(&pool, &test_user(1, None)).await.unwrap();
     let black = db::upsert_user(&pool, &test_user(2, None)).await.unwrap();
 
-    db::update_player_stats(&pool, white.id, black.id, "1-0").await.unwrap();
+    db::update_player_stats(&pool, white.id, black.id, GameResult::WhiteWins).await.unwrap();
 
     let white_updated = db::get_user_by_id(&pool, white.id).await.unwrap();
     let black_updated = db::get_user_by_id(&pool, black.id).await.unwrap();
@@ -334,7 +442,7 @@ async fn test_update_player_stats_black_wins() {
     let white = db::upsert_user(&pool, &test_user(1, None)).await.unwrap();
     let black = db::upsert_user(&pool, &test_user(2, None)).await.unwrap();
 
-    db::update_player_stats(&pool, white.id, black.id, "0-1").await.unwrap();
+    db::update_player_stats(&pool, white.id, black.id, GameResult::BlackWins).await.unwrap();
 
     let white_updated = db::get_user_by_id(&pool, white.id).await.unwrap();
     let black_updated = db::get_user_by_id(&pool, black.id).await.unwrap();
@@ -349,7 +457,7 @@ async fn test_update_player_stats_draw() {
     let white = db::upsert_user(&pool, &test_user(1, None)).await.unwrap();
     let black = db::upsert_user(&pool, &test_user(2, None)).await.unwrap();
 
-    db::update_player_stats(&pool, white.id, black.id, "1/2-1/2").await.unwrap();
+    db::update_player_stats(&pool, white.id, black.id, GameResult::Draw).await.unwrap();
 
     let white_updated = db::get_user_by_id(&pool, white.id).await.unwrap();
     let black_updated = db::get_user_by_id(&pool, black.id).await.unwrap();
@@ -364,7 +472,7 @@ async fn test_propose_and_clear_draw() {
     let white = db::upsert_user(&pool, &test_user(1, None)).await.unwrap();
     let black = db::upsert_user(&pool, &test_user(2, None)).await.unwrap();
 
-    let game_id = db::create_game(&pool, -700, white.id, black.id, "fen", "white")
+    let game_id = db::create_game(&pool, -700, white.id, black.id, "fen", Turn::White)
         .await
         .unwrap();
     db::update_game_message(&pool, game_id, 1).await.unwrap();
@@ -376,6 +484,133 @@ async fn test_propose_and_clear_draw() {
     db::clear_draw_proposal(&pool, game_id).await.unwrap();
     let game = db::find_game_by_message(&pool, -700, 1).await.unwrap().unwrap();
     assert_eq!(game.draw_proposed_by, None);
+
+    // Each player's latest offer is remembered by the number of moves played at the time
+    assert_eq!(db::last_draw_offer_ply(&pool, game_id, white.id).await.unwrap(), Some(0));
+    assert_eq!(db::last_draw_offer_ply(&pool, game_id, black.id).await.unwrap(), None);
+    db::insert_move(&pool, game_id, white.id, 1, "e2e4", Some("e4")).await.unwrap();
+    db::propose_draw(&pool, game_id, black.id, 124).await.unwrap();
+    assert_eq!(db::last_draw_offer_ply(&pool, game_id, black.id).await.unwrap(), Some(1));
+    assert_eq!(db::last_draw_offer_ply(&pool, game_id, white.id).await.unwrap(), Some(0));
+}
+
+#[tokio::test]
+async fn test_pending_move_confirmation() {
+    let pool = setup_test_db().await;
+    let white = db::upsert_user(&pool, &test_user(1, None)).await.unwrap();
+    let black = db::upsert_user(&pool, &test_user(2, None)).await.unwrap();
+
+    let game_id = db::create_game(&pool, -750, white.id, black.id, "fen", Turn::White)
+        .await
+        .unwrap();
+
+    let game = db::get_game_by_id(&pool, game_id).await.unwrap().unwrap();
+    assert!(!game.confirm_moves);
+    assert_eq!(game.pending_move, None);
+
+    db::set_confirm_moves(&pool, game_id, true).await.unwrap();
+    db::set_pending_move(&pool, game_id, "e2e4", 55).await.unwrap();
+    let game = db::get_game_by_id(&pool, game_id).await.unwrap().unwrap();
+    assert!(game.confirm_moves);
+    assert_eq!(game.pending_move, Some("e2e4".to_string()));
+    assert_eq!(game.pending_move_message_id, Some(55));
+
+    db::clear_pending_move(&pool, game_id).await.unwrap();
+    let game = db::get_game_by_id(&pool, game_id).await.unwrap().unwrap();
+    assert_eq!(game.pending_move, None);
+    assert_eq!(game.pending_move_message_id, None);
+
+    assert!(db::get_game_by_id(&pool, game_id + 1).await.unwrap().is_none());
+}
+
+#[tokio::test]
+async fn test_chat_settings_default_and_update() {
+    let pool = setup_test_db().await;
+
+    let settings = db::get_chat_settings(&pool, -760).await.unwrap();
+    assert_eq!(settings.coordinates, "outside");
+
+    db::set_chat_coordinates(&pool, -760, "hidden").await.unwrap();
+    db::set_chat_coordinates(&pool, -760, "inside").await.unwrap();
+    let settings = db::get_chat_settings(&pool, -760).await.unwrap();
+    assert_eq!(settings.coordinates, "inside");
+
+    let other = db::get_chat_settings(&pool, -761).await.unwrap();
+    assert_eq!(other.coordinates, "outside");
+    assert_eq!(other.orientation, "auto");
+
+    db::set_chat_orientation(&pool, -760, "white").await.unwrap();
+    let settings = db::get_chat_settings(&pool, -760).await.unwrap();
+    assert_eq!(settings.orientation, "white");
+    assert_eq!(settings.coordinates, "inside");
+    assert!(!settings.send_as_document);
+
+    db::set_chat_send_as_document(&pool, -760, true).await.unwrap();
+    let settings = db::get_chat_settings(&pool, -760).await.unwrap();
+    assert!(settings.send_as_document);
+    assert_eq!(settings.orientation, "white");
+    assert!(!settings.tap_moves);
+
+    db::set_chat_tap_moves(&pool, -760, true).await.unwrap();
+    assert!(db::get_chat_settings(&pool, -760).await.unwrap().tap_moves);
+    assert!(!db::get_chat_settings(&pool, -760).await.unwrap().pgn_files);
+    db::set_chat_pgn_files(&pool, -760, true).await.unwrap();
+    assert!(db::get_chat_settings(&pool, -760).await.unwrap().pgn_files);
+
+    assert_eq!(db::get_chat_settings(&pool, -760).await.unwrap().max_games, None);
+    db::set_chat_max_games(&pool, -760, Some(3)).await.unwrap();
+    assert_eq!(db::get_chat_settings(&pool, -760).await.unwrap().max_games, Some(3));
+    db::set_chat_max_games(&pool, -760, None).await.unwrap();
+    assert_eq!(db::get_chat_settings(&pool, -760).await.unwrap().max_games, None);
+    db::set_chat_draw_offer_gap(&pool, -760, Some(0)).await.unwrap();
+    assert_eq!(db::get_chat_settings(&pool, -760).await.unwrap().draw_offer_gap, Some(0));
+    db::set_chat_standings_min_games(&pool, -760, Some(5)).await.unwrap();
+    db::set_chat_standings_inactive_days(&pool, -760, Some(30)).await.unwrap();
+    let settings = db::get_chat_settings(&pool, -760).await.unwrap();
+    assert_eq!((settings.standings_min_games, settings.standings_inactive_days), (Some(5), Some(30)));
+}
+
+#[tokio::test]
+async fn test_count_ongoing_games() {
+    let pool = setup_test_db().await;
+    let a = db::upsert_user(&pool, &test_user(1, None)).await.unwrap();
+    let b = db::upsert_user(&pool, &test_user(2, None)).await.unwrap();
+    let c = db::upsert_user(&pool, &test_user(3, None)).await.unwrap();
+    assert_eq!(db::count_ongoing_games(&pool, -1, a.id, b.id).await.unwrap(), (0, 0));
+
+    db::create_game(&pool, -1, a.id, b.id, "fen", Turn::White).await.unwrap();
+    db::create_game(&pool, -1, b.id, a.id, "fen", Turn::White).await.unwrap();
+    db::create_game(&pool, -1, a.id, c.id, "fen", Turn::White).await.unwrap();
+    db::create_game(&pool, -2, a.id, b.id, "fen", Turn::White).await.unwrap();
+    let finished = db::create_game(&pool, -1, a.id, b.id, "fen", Turn::White).await.unwrap();
+    db::update_game_result(&pool, finished, GameResult::Draw).await.unwrap();
+
+    assert_eq!(db::count_ongoing_games(&pool, -1, a.id, b.id).await.unwrap(), (3, 2));
+    assert_eq!(db::count_ongoing_games(&pool, -1, c.id, b.id).await.unwrap(), (3, 0));
+}
+
+#[tokio::test]
+async fn test_chat_end_templates() {
+    let pool = setup_test_db().await;
+
+    db::set_chat_coordinates(&pool, -770, "inside").await.unwrap();
+    db::set_chat_win_template(&pool, -771, Some("{winner} beat {loser} in {moves} moves"))
+        .await
+        .unwrap();
+    db::set_chat_draw_template(&pool, -771, Some("Drawn: {result}")).await.unwrap();
+
+    let settings = db::get_chat_settings(&pool, -771).await.unwrap();
+    assert_eq!(settings.win_template.as_deref(), Some("{winner} beat {loser} in {moves} moves"));
+    assert_eq!(settings.draw_template.as_deref(), Some("Drawn: {result}"));
+    assert_eq!(settings.coordinates, "outside");
+
+    let custom = db::get_chats_with_end_templates(&pool).await.unwrap();
+    assert_eq!(custom.iter().map(|s| s.chat_id).collect::<Vec<_>>(), vec![-771]);
+
+    db::set_chat_win_template(&pool, -771, None).await.unwrap();
+    let settings = db::get_chat_settings(&pool, -771).await.unwrap();
+    assert_eq!(settings.win_template, None);
+    assert!(settings.draw_template.is_some());
 }
 
 #[tokio::test]
@@ -403,15 +638,15 @@ async fn test_format_user_history_with_games() {
         white.id,
         black.id,
         "fen",
-        "white",
+        Turn::White,
     )
     .await
     .unwrap();
     db::insert_move(&pool, game_id, white.id, 1, "e2e4", Some("e4")).await.unwrap();
-    db::update_game_result(&pool, game_id, &Some("1-0".to_string()), "finished")
+    db::update_game_result(&pool, game_id, GameResult::WhiteWins)
         .await
         .unwrap();
-    db::update_player_stats(&pool, white.id, black.id, "1-0").await.unwrap();
+    db::update_player_stats(&pool, white.id, black.id, GameResult::WhiteWins).await.unwrap();
 
     let history = db::format_user_history(&pool, &white, chat_id, 1).await.unwrap();
 
@@ -419,6 +654,11 @@ async fn test_format_user_history_with_games() {
     assert!(history.contains("@player2"));
     assert!(history.contains("1-0"));
     assert!(history.contains("lichess.org"));
+    assert!(history.contains("As White: +1 -0 =0"));
+    assert!(history.contains("As Black: +0 -0 =0"));
+
+    let history = db::format_user_history(&pool, &black, chat_id, 1).await.unwrap();
+    assert!(history.contains("As Black: +0 -1 =0"));
 }
 
 #[tokio::test]
@@ -428,7 +668,7 @@ async fn test_format_head_to_head() {
     let user_b = db::upsert_user(&pool, &test_user(2, Some("bob"))).await.unwrap();
     let chat_id = -1000;
 
-    db::create_game(&pool, chat_id, user_a.id, user_b.id, "fen", "white")
+    db::create_game(&pool, chat_id, user_a.id, user_b.id, "fen", Turn::White)
         .await
         .unwrap();
 
@@ -462,3 +702,774 @@ async fn test_db_user_mention_html() {
     assert!(mention.contains("tg://user?id=12345"));
     assert!(mention.contains("User12345"));
 }
+
+#[tokio::test]
+async fn test_think_times() {
+    let pool = setup_test_db().await;
+    let white = db::upsert_user(&pool, &test_user(1, Some("thinker1"))).await.unwrap();
+    let black = db::upsert_user(&pool, &test_user(2, Some("thinker2"))).await.unwrap();
+    let chat_id = -950;
+    let game_id = db::create_game(&pool, chat_id, white.id, black.id, "fen", Turn::White)
+        .await
+        .unwrap();
+    let moves = [(1, white.id, "e2e4"), (2, black.id, "e7e5"), (3, white.id, "g1f3")];
+    for (number, player, uci) in moves {
+        db::insert_move(&pool, game_id, player, number, uci, None).await.unwrap();
+    }
+
+    sqlx::query("UPDATE games SET started_at = '2024-01-01T12:00:00+00:00' WHERE id = $1")
+        .bind(game_id)
+        .execute(&pool)
+        .await
+        .unwrap();
+    for (number, played_at) in [
+        (1, "2024-01-01T12:00:10+00:00"),
+        (2, "2024-01-01T12:01:40+00:00"),
+        (3, "2024-01-01T12:02:10+00:00"),
+    ] {
+        sqlx::query("UPDATE moves SET played_at = $1 WHERE game_id = $2 AND move_number = $3")
+            .bind(played_at)
+            .bind(game_id)
+            .bind(number as i64)
+            .execute(&pool)
+            .await
+            .unwrap();
+    }
+
+    let times = db::get_game_think_times(&pool, game_id).await.unwrap();
+    let white_time = times[&white.id];
+    assert_eq!(white_time.moves, 2);
+    assert_eq!(white_time.average_secs(), 20);
+    assert_eq!(white_time.longest_secs, 30);
+    assert_eq!(times[&black.id].longest_secs, 90);
+
+    let history = db::format_user_history(&pool, &black, chat_id, 1).await.unwrap();
+    assert!(history.contains("Think time: avg 1m 30s, longest 1m 30s"));
+    assert!(history.contains("(ongoing) · started 2024-01-01 12:00 UTC - "));
+
+    db::update_game_result(&pool, game_id, GameResult::Draw).await.unwrap();
+    sqlx::query("UPDATE games SET ended_at = '2024-01-01T12:05:00+00:00' WHERE id = $1")
+        .bind(game_id)
+        .execute(&pool)
+        .await
+        .unwrap();
+    let history = db::format_user_history(&pool, &black, chat_id, 1).await.unwrap();
+    assert!(history.contains("(1/2-1/2) · 2024-01-01 12:00–12:05 UTC (5m 00s) - "));
+}
+
+#[tokio::test]
+async fn test_game_summary() {
+    let pool = setup_test_db().await;
+    let white = db::upsert_user(&pool, &test_user(1, Some("sum1"))).await.unwrap();
+    let black = db::upsert_user(&pool, &test_user(2, Some("sum2"))).await.unwrap();
+    let game_id = db::create_game(&pool, -951, white.id, black.id, "fen", Turn::White)
+        .await
+        .unwrap();
+    let moves = [
+        (1, white.id, "e2e4", "e4"),
+        (2, black.id, "d7d5", "d5"),
+        (3, white.id, "e4d5", "exd5"),
+        (4, black.id, "d8d5", "Qxd5"),
+        (5, white.id, "f1b5", "Bb5+"),
+    ];
+    for (number, player, uci, san) in moves {
+        db::insert_move(&pool, game_id, player, number, uci, Some(san)).await.unwrap();
+    }
+
+    let running = db::get_game_summary(&pool, game_id).await.unwrap();
+    assert_eq!(running.move_count(), 3);
+    assert_eq!(running.captures(), 2);
+    assert_eq!(running.checks(), 1);
+    assert_eq!(running.duration_secs, None);
+    assert_eq!(kamachess::game::opening_name(&running.moves), Some("Scandinavian Defense"));
+
+    sqlx::query("UPDATE games SET started_at = $1, ended_at = $2 WHERE id = $3")
+        .bind("2024-01-01T12:00:00+00:00")
+        .bind("2024-01-01T12:04:30+00:00")
+        .bind(game_id)
+        .execute(&pool)
+        .await
+        .unwrap();
+    let finished = db::get_game_summary(&pool, game_id).await.unwrap();
+    assert_eq!(finished.duration_secs, Some(270));
+}
+
+#[tokio::test]
+async fn test_archive_finished_games() {
+    let pool = setup_test_db().await;
+    let chat_id = -960;
+    let white = db::upsert_user(&pool, &test_user(1, Some("arc1"))).await.unwrap();
+    let black = db::upsert_user(&pool, &test_user(2, Some("arc2"))).await.unwrap();
+
+    let old_id = db::create_game(&pool, chat_id, white.id, black.id, "fen", Turn::White)
+        .await
+        .unwrap();
+    db::insert_move(&pool, old_id, white.id, 1, "e2e4", Some("e4")).await.unwrap();
+    db::insert_move(&pool, old_id, black.id, 2, "e7e5", Some("e5")).await.unwrap();
+    db::update_game_result(&pool, old_id, GameResult::WhiteWins).await.unwrap();
+    sqlx::query("UPDATE games SET ended_at = $1 WHERE id = $2")
+        .bind("2020-01-01T12:00:00+00:00")
+        .bind(old_id)
+        .execute(&pool)
+        .await
+        .unwrap();
+    let running_id = db::create_game(&pool, chat_id, white.id, black.id, "fen", Turn::White)
+        .await
+        .unwrap();
+    db::insert_move(&pool, running_id, white.id, 1, "d2d4", Some("d4")).await.unwrap();
+
+    let cutoff: chrono::DateTime<chrono::Utc> = "2021-01-01T00:00:00Z".parse().unwrap();
+    assert_eq!(db::archive_finished_games(&pool, cutoff).await.unwrap(), 1);
+    assert_eq!(db::archive_finished_games(&pool, cutoff).await.unwrap(), 0);
+
+    assert!(db::get_game_by_id(&pool, old_id).await.unwrap().is_none());
+    let archived = db::find_game_with_archive(&pool, old_id).await.unwrap().unwrap();
+    assert_eq!(archived.result, Some(GameResult::WhiteWins));
+    assert_eq!(db::get_game_san_moves(&pool, old_id).await.unwrap(), vec!["e4", "e5"]);
+    assert_eq!(db::find_game_by_number(&pool, chat_id, 1).await.unwrap().map(|g| g.id), Some(old_id));
+
+    let running = db::get_game_by_id(&pool, running_id).await.unwrap().unwrap();
+    assert_eq!(running.status, GameStatus::Ongoing);
+    assert_eq!(db::get_game_number(&pool, &running).await.unwrap(), 2);
+
+    let history = db::format_user_history(&pool, &white, chat_id, 1).await.unwrap();
+    assert!(history.contains("As White: +1 -0 =0"));
+    assert!(history.contains("1-0"));
+}
+
+#[tokio::test]
+async fn test_archive_never_reuses_ids() {
+    let pool = setup_test_db().await;
+    let chat_id = -965;
+    let white = db::upsert_user(&pool, &test_user(1, Some("ids1"))).await.unwrap();
+    let black = db::upsert_user(&pool, &test_user(2, Some("ids2"))).await.unwrap();
+    let cutoff: chrono::DateTime<chrono::Utc> = "2021-01-01T00:00:00Z".parse().unwrap();
+    let finish_long_ago = |game_id: i64| {
+        sqlx::query(
+            "UPDATE games SET status = 'finished', result = '1-0', ended_at = '2020-01-01T12:00:00.5+00:00'
+             WHERE id = $1",
+        )
+        .bind(game_id)
+        .execute(&pool)
+    };
+
+    let first = db::create_game(&pool, chat_id, white.id, black.id, "fen", Turn::White)
+        .await
+        .unwrap();
+    db::insert_move(&pool, first, white.id, 1, "e2e4", Some("e4")).await.unwrap();
+    finish_long_ago(first).await.unwrap();
+    // The newest game stays, or the next one would get its id
+    assert_eq!(db::archive_finished_games(&pool, cutoff).await.unwrap(), 0);
+
+    let second = db::create_game(&pool, chat_id, white.id, black.id, "fen", Turn::White)
+        .await
+        .unwrap();
+    db::insert_move(&pool, second, white.id, 1, "d2d4", Some("d4")).await.unwrap();
+    finish_long_ago(second).await.unwrap();
+    assert_eq!(db::archive_finished_games(&pool, cutoff).await.unwrap(), 1);
+
+    let third = db::create_game(&pool, chat_id, white.id, black.id, "fen", Turn::White)
+        .await
+        .unwrap();
+    db::insert_move(&pool, third, white.id, 1, "c2c4", Some("c4")).await.unwrap();
+    assert!(third > second && second > first);
+    assert_eq!(db::archive_finished_games(&pool, cutoff).await.unwrap(), 1);
+
+    for (number, id, san) in [(1, first, "e4"), (2, second, "d4"), (3, third, "c4")] {
+        let game = db::find_game_by_number(&pool, chat_id, number).await.unwrap().unwrap();
+        assert_eq!(game.id, id);
+        assert_eq!(db::get_game_number(&pool, &game).await.unwrap(), number);
+        assert_eq!(db::get_game_san_moves(&pool, id).await.unwrap(), vec![san]);
+    }
+}
+
+#[tokio::test]
+async fn test_chat_standings_and_recent_record() {
+    let pool = setup_test_db().await;
+    let chat_id = -970;
+    let a = db::upsert_user(&pool, &test_user(1, Some("rank1"))).await.unwrap();
+    let b = db::upsert_user(&pool, &test_user(2, Some("rank2"))).await.unwrap();
+    let c = db::upsert_user(&pool, &test_user(3, Some("rank3"))).await.unwrap();
+    let games = [
+        (a.id, b.id, GameResult::WhiteWins),
+        (b.id, a.id, GameResult::Draw),
+        (c.id, b.id, GameResult::BlackWins),
+        (a.id, c.id, GameResult::BlackWins),
+    ];
+    for (white, black, result) in games {
+        let id = db::create_game(&pool, chat_id, white, black, "fen", Turn::White).await.unwrap();
+        db::update_game_result(&pool, id, result).await.unwrap();
+    }
+    // Still running, and a game elsewhere: neither counts
+    db::create_game(&pool, chat_id, a.id, b.id, "fen", Turn::White).await.unwrap();
+    let other = db::create_game(&pool, -971, c.id, a.id, "fen", Turn::White).await.unwrap();
+    db::update_game_result(&pool, other, GameResult::WhiteWins).await.unwrap();
+
+    let standings = db::get_chat_standings(&pool, chat_id, 0, None).await.unwrap();
+    let order: Vec<i64> = standings.iter().map(|s| s.user.id).collect();
+    // a and b have a win, a draw and a loss each, c a win and a loss
+    assert_eq!(order, vec![a.id, b.id, c.id]);
+    assert_eq!((standings[0].wins, standings[0].losses, standings[0].draws), (1, 1, 1));
+    assert_eq!(standings[0].half_points(), 3);
+    assert_eq!(standings[2].games(), 2);
+
+    // c has only two games, and every game ended before tomorrow
+    let regulars = db::get_chat_standings(&pool, chat_id, 3, None).await.unwrap();
+    assert_eq!(regulars.iter().map(|s| s.user.id).collect::<Vec<_>>(), vec![a.id, b.id]);
+    let tomorrow = chrono::Utc::now() + chrono::Duration::days(1);
+    assert!(db::get_chat_standings(&pool, chat_id, 0, Some(tomorrow)).await.unwrap().is_empty());
+    let yesterday = chrono::Utc::now() - chrono::Duration::days(1);
+    assert_eq!(db::get_chat_standings(&pool, chat_id, 0, Some(yesterday)).await.unwrap().len(), 3);
+
+    assert_eq!(db::get_recent_record(&pool, chat_id, a.id, 10).await.unwrap(), (1, 1, 1));
+    assert_eq!(db::get_recent_record(&pool, chat_id, c.id, 10).await.unwrap(), (1, 1, 0));
+    assert_eq!(db::get_recent_record(&pool, -972, a.id, 10).await.unwrap(), (0, 0, 0));
+}
+
+#[tokio::test]
+async fn test_merge_users() {
+    let pool = setup_test_db().await;
+    let placeholder = db::upsert_user_by_username(&pool, "oldname").await.unwrap();
+    let real = db::upsert_user(&pool, &test_user(500, None)).await.unwrap();
+    let opponent = db::upsert_user(&pool, &test_user(600, Some("opponent"))).await.unwrap();
+
+    let archived_id = db::create_game(&pool, -1, placeholder.id, opponent.id, "fen", Turn::White)
+        .await
+        .unwrap();
+    db::update_game_result(&pool, archived_id, GameResult::Draw).await.unwrap();
+    sqlx::query(
+        "UPDATE games SET ended_at = '2020-01-01T12:00:00Z', draw_proposed_by = $1, pause_requested_by = $1
+         WHERE id = $2",
+    )
+    .bind(placeholder.id)
+    .bind(archived_id)
+    .execute(&pool)
+    .await
+    .unwrap();
+
+    let game_id = db::create_game(&pool, -1, opponent.id, placeholder.id, "fen", Turn::White)
+        .await
+        .unwrap();
+    db::insert_move(&pool, game_id, placeholder.id, 1, "e7e5", Some("e5"))
+        .await
+        .unwrap();
+    db::update_player_stats(&pool, opponent.id, placeholder.id, GameResult::BlackWins)
+        .await
+        .unwrap();
+    db::set_player_left(&pool, game_id, Some(placeholder.id)).await.unwrap();
+    let cutoff: chrono::DateTime<chrono::Utc> = "2021-01-01T00:00:00Z".parse().unwrap();
+    assert_eq!(db::archive_finished_games(&pool, cutoff).await.unwrap(), 1);
+
+    let expires = chrono::Utc::now() + chrono::Duration::hours(1);
+    db::create_seek(&pool, -1, placeholder.id, false, expires).await.unwrap();
+    db::enqueue_player(&pool, placeholder.id, 7).await.unwrap();
+
+    let merged = db::merge_users(&pool, placeholder.id, real.id).await.unwrap();
+
+    assert_eq!(merged.id, real.id);
+    assert_eq!(merged.telegram_id, Some(500));
+    assert_eq!(merged.username.as_deref(), Some("oldname"));
+    assert_eq!(merged.wins, 1);
+    assert!(db::get_user_by_id(&pool, placeholder.id).await.is_err());
+
+    let game = db::get_game_by_id(&pool, game_id).await.unwrap().unwrap();
+    assert_eq!(game.black_user_id, real.id);
+    let (left_id, _) = db::get_player_left(&pool, game_id).await.unwrap().unwrap();
+    assert_eq!(left_id, real.id);
+    let think = db::get_game_think_times(&pool, game_id).await.unwrap();
+    assert!(think.contains_key(&real.id));
+
+    let archived = db::find_game_with_archive(&pool, archived_id).await.unwrap().unwrap();
+    assert_eq!(archived.white_user_id, real.id);
+    let (draw_by, pause_by): (Option<i64>, Option<i64>) = sqlx::query_as(
+        "SELECT draw_proposed_by, pause_requested_by FROM games_archive WHERE id = $1",
+    )
+    .bind(archived_id)
+    .fetch_one(&pool)
+    .await
+    .unwrap();
+    assert_eq!((draw_by, pause_by), (Some(real.id), Some(real.id)));
+
+    assert!(db::find_open_seek(&pool, -1, real.id).await.unwrap().is_some());
+    let queued = db::get_queued_players(&pool).await.unwrap();
+    assert_eq!(queued.iter().map(|(user, chat_id)| (user.id, *chat_id)).collect::<Vec<_>>(), vec![(real.id, 7)]);
+
+    // Audit entries follow the Telegram id of the kept user
+    let old_account = db::upsert_user(&pool, &test_user(501, None)).await.unwrap();
+    db::insert_audit_entry(&pool, Some(501), -1, None, "/move", "ok").await.unwrap();
+    db::merge_users(&pool, old_account.id, real.id).await.unwrap();
+    let entries = db::get_audit_log(&pool, db::AuditFilter::User(500), 10).await.unwrap();
+    assert_eq!(entries.len(), 1);
+    assert!(db::get_audit_log(&pool, db::AuditFilter::User(501), 10).await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn test_merge_users_keeps_predictions() {
+    let pool = setup_test_db().await;
+    let placeholder = db::upsert_user_by_username(&pool, "fan").await.unwrap();
+    let real = db::upsert_user(&pool, &test_user(510, None)).await.unwrap();
+    let white = db::upsert_user(&pool, &test_user(511, Some("w"))).await.unwrap();
+    let black = db::upsert_user(&pool, &test_user(512, Some("b"))).await.unwrap();
+    let chat_id = -1110;
+
+    for poll_id in ["m1", "m2"] {
+        let game_id = db::create_game(&pool, chat_id, white.id, black.id, "fen", Turn::White)
+            .await
+            .unwrap();
+        db::create_prediction_poll(&pool, poll_id, game_id, chat_id, 1).await.unwrap();
+    }
+    db::record_prediction(&pool, "m1", placeholder.id, Some(0)).await.unwrap();
+    db::record_prediction(&pool, "m1", real.id, Some(1)).await.unwrap();
+    db::settle_prediction_poll(&pool, "m1", chat_id, 0).await.unwrap();
+    db::record_prediction(&pool, "m2", placeholder.id, Some(2)).await.unwrap();
+
+    db::merge_users(&pool, placeholder.id, real.id).await.unwrap();
+    db::settle_prediction_poll(&pool, "m2", chat_id, 2).await.unwrap();
+
+    let board = db::get_prediction_leaderboard(&pool, chat_id, 10).await.unwrap();
+    assert_eq!(board.len(), 1);
+    let (user, correct, total) = &board[0];
+    assert_eq!(user.id, real.id);
+    assert_eq!((*correct, *total), (2, 3));
+}
+
+#[tokio::test]
+async fn test_merge_users_refuses_opponents() {
+    let pool = setup_test_db().await;
+    let a = db::upsert_user(&pool, &test_user(1, Some("a"))).await.unwrap();
+    let b = db::upsert_user(&pool, &test_user(2, Some("b"))).await.unwrap();
+    db::create_game(&pool, -1, a.id, b.id, "fen", Turn::White).await.unwrap();
+
+    assert!(db::merge_users(&pool, a.id, b.id).await.is_err());
+    assert!(db::get_user_by_id(&pool, a.id).await.is_ok());
+    assert!(db::merge_users(&pool, a.id, a.id).await.is_err());
+}
+
+#[tokio::test]
+async fn test_username_change_keeps_alias() {
+    let pool = setup_test_db().await;
+    let original = db::upsert_user(&pool, &test_user(700, Some("oldname"))).await.unwrap();
+    db::upsert_user(&pool, &test_user(700, Some("newname"))).await.unwrap();
+
+    let by_old = db::get_user_by_username(&pool, "oldname").await.unwrap();
+    assert_eq!(by_old.id, original.id);
+    assert_eq!(by_old.username.as_deref(), Some("newname"));
+    // History lookups by the old name must not create a placeholder
+    let looked_up = db::find_user_by_username(&pool, "oldname").await.unwrap();
+    assert_eq!(looked_up.id, original.id);
+    // A challenge by the old name must not reach the previous owner
+    let challenged = db::upsert_user_by_username(&pool, "oldname").await.unwrap();
+    assert_ne!(challenged.id, original.id);
+    assert_eq!(challenged.telegram_id, None);
+
+    // Once someone else takes the name, it points to them
+    let taker = db::upsert_user(&pool, &test_user(800, Some("oldname"))).await.unwrap();
+    assert_eq!(db::get_user_by_username(&pool, "oldname").await.unwrap().id, taker.id);
+}
+
+#[tokio::test]
+async fn test_user_cache() {
+    let pool = setup_test_db().await;
+    let cache = db::UserCache::default();
+
+    let alice = cache.upsert(&pool, &test_user(1, Some("alice"))).await.unwrap();
+    let bob = cache.upsert(&pool, &test_user(2, Some("bob"))).await.unwrap();
+    assert_eq!(cache.upsert_by_username(&pool, "alice").await.unwrap().id, alice.id);
+
+    db::update_player_stats(&pool, alice.id, bob.id, GameResult::WhiteWins).await.unwrap();
+    assert_eq!(cache.get_by_id(&pool, alice.id).await.unwrap().wins, 0);
+    cache.invalidate(alice.id);
+    assert_eq!(cache.get_by_id(&pool, alice.id).await.unwrap().wins, 1);
+
+    // A rename goes to the database and the new name is served afterwards
+    let renamed = cache.upsert(&pool, &test_user(1, Some("alice2"))).await.unwrap();
+    assert_eq!(renamed.username.as_deref(), Some("alice2"));
+    assert_eq!(db::get_user_by_id(&pool, alice.id).await.unwrap().username.as_deref(), Some("alice2"));
+
+    // Bob's name taken by someone else: the stale cached row must not be returned
+    let carol = cache.upsert(&pool, &test_user(3, Some("bob"))).await.unwrap();
+    assert_eq!(cache.upsert_by_username(&pool, "bob").await.unwrap().id, carol.id);
+}
+
+#[tokio::test]
+async fn test_user_cache_disabled() {
+    let pool = setup_test_db().await;
+    let cache = db::UserCache::new(std::time::Duration::ZERO);
+    let alice = cache.upsert(&pool, &test_user(1, Some("alice"))).await.unwrap();
+    let bob = cache.upsert(&pool, &test_user(2, Some("bob"))).await.unwrap();
+
+    db::update_player_stats(&pool, alice.id, bob.id, GameResult::BlackWins).await.unwrap();
+    assert_eq!(cache.get_by_id(&pool, bob.id).await.unwrap().wins, 1);
+}
+
+#[tokio::test]
+async fn test_record_move() {
+    let pool = setup_test_db().await;
+    let white = db::upsert_user(&pool, &test_user(1, None)).await.unwrap();
+    let black = db::upsert_user(&pool, &test_user(2, None)).await.unwrap();
+    let game_id = db::create_game(&pool, -900, white.id, black.id, "fen", Turn::White)
+        .await
+        .unwrap();
+    db::propose_draw(&pool, game_id, black.id, 5).await.unwrap();
+
+    let mut game = db::get_game_by_id(&pool, game_id).await.unwrap().unwrap();
+    game.current_fen = "fen2".to_string();
+    game.turn = Turn::Black;
+    assert!(db::record_move(&pool, &game, "fen", white.id, "e2e4", "e4", Some("my favorite opening"))
+        .await
+        .unwrap());
+    // The same move again, as from a double tap, finds the position changed
+    assert!(!db::record_move(&pool, &game, "fen", white.id, "e2e4", "e4", None).await.unwrap());
+
+    let stored = db::get_game_by_id(&pool, game_id).await.unwrap().unwrap();
+    assert_eq!(stored.current_fen, "fen2");
+    assert_eq!(stored.turn, Turn::Black);
+    assert_eq!(stored.status, GameStatus::Ongoing);
+    assert_eq!(stored.draw_proposed_by, None);
+    assert_eq!(db::next_move_number(&pool, game_id).await.unwrap(), 2);
+
+    game.current_fen = "fen3".to_string();
+    game.status = GameStatus::Finished;
+    game.result = Some(GameResult::BlackWins);
+    assert!(db::record_move(&pool, &game, "fen2", black.id, "d8h4", "Qh4#", None).await.unwrap());
+    assert!(!db::record_move(&pool, &game, "fen3", white.id, "a2a3", "a3", None).await.unwrap());
+
+    let stored = db::get_game_by_id(&pool, game_id).await.unwrap().unwrap();
+    assert_eq!(stored.status, GameStatus::Finished);
+    assert_eq!(stored.result, Some(GameResult::BlackWins));
+    assert_eq!(db::get_last_move(&pool, game_id).await.unwrap().unwrap().0, 2);
+    assert_eq!(db::get_user_by_id(&pool, black.id).await.unwrap().wins, 1);
+    assert_eq!(db::get_user_by_id(&pool, white.id).await.unwrap().losses, 1);
+    assert_eq!(
+        db::get_game_move_comments(&pool, game_id).await.unwrap(),
+        vec![Some("my favorite opening".to_string()), None]
+    );
+}
+
+#[tokio::test]
+async fn test_game_pause() {
+    let pool = setup_test_db().await;
+    let white = db::upsert_user(&pool, &test_user(1, None)).await.unwrap();
+    let black = db::upsert_user(&pool, &test_user(2, None)).await.unwrap();
+    let game_id = db::create_game(&pool, -901, white.id, black.id, "fen", Turn::White)
+        .await
+        .unwrap();
+    assert_eq!(db::get_game_pause(&pool, game_id).await.unwrap(), (false, None));
+
+    db::request_pause(&pool, game_id, white.id).await.unwrap();
+    assert_eq!(db::get_game_pause(&pool, game_id).await.unwrap(), (false, Some(white.id)));
+    assert!(db::set_game_paused(&pool, game_id, true).await.unwrap());
+    assert!(!db::set_game_paused(&pool, game_id, true).await.unwrap());
+    assert_eq!(db::get_game_pause(&pool, game_id).await.unwrap(), (true, None));
+
+    // No moves while paused
+    let mut game = db::get_game_by_id(&pool, game_id).await.unwrap().unwrap();
+    game.current_fen = "fen2".to_string();
+    assert!(!db::record_move(&pool, &game, "fen", white.id, "e2e4", "e4", None).await.unwrap());
+
+    assert!(db::set_game_paused(&pool, game_id, false).await.unwrap());
+    assert!(db::record_move(&pool, &game, "fen", white.id, "e2e4", "e4", None).await.unwrap());
+}
+
+#[tokio::test]
+async fn test_seek_lifecycle() {
+    let pool = setup_test_db().await;
+    let user = db::upsert_user(&pool, &test_user(1, Some("seeker"))).await.unwrap();
+    let chat_id = -300;
+    let later = chrono::Utc::now() + chrono::Duration::minutes(15);
+
+    let seek_id = db::create_seek(&pool, chat_id, user.id, true, later).await.unwrap();
+    db::set_seek_message(&pool, seek_id, 77).await.unwrap();
+
+    let seek = db::find_open_seek(&pool, chat_id, user.id).await.unwrap().unwrap();
+    assert_eq!(seek.id, seek_id);
+    assert_eq!(seek.message_id, Some(77));
+    assert!(seek.confirm_moves);
+    assert!(db::get_expired_seeks(&pool).await.unwrap().is_empty());
+
+    // Only the first taker wins
+    assert!(db::take_seek(&pool, seek_id).await.unwrap());
+    assert!(!db::take_seek(&pool, seek_id).await.unwrap());
+    assert!(db::get_open_seek(&pool, seek_id).await.unwrap().is_none());
+
+    let earlier = chrono::Utc::now() - chrono::Duration::minutes(1);
+    let stale_id = db::create_seek(&pool, chat_id, user.id, false, earlier).await.unwrap();
+    assert!(db::get_open_seek(&pool, stale_id).await.unwrap().is_none());
+    assert!(db::find_open_seek(&pool, chat_id, user.id).await.unwrap().is_none());
+    let expired = db::get_expired_seeks(&pool).await.unwrap();
+    assert_eq!(expired.len(), 1);
+    assert_eq!(expired[0].id, stale_id);
+}
+
+#[tokio::test]
+async fn test_peer_chat_game_messages() {
+    let pool = setup_test_db().await;
+    let white = db::upsert_user(&pool, &test_user(1, None)).await.unwrap();
+    let black = db::upsert_user(&pool, &test_user(2, None)).await.unwrap();
+
+    let game_id = db::create_game(
+        &pool,
+        1,
+        white.id,
+        black.id,
+        "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+        Turn::White,
+    )
+    .await
+    .unwrap();
+    db::set_game_peer_chat(&pool, game_id, 2).await.unwrap();
+    assert!(db::has_ongoing_peer_game(&pool, black.id, white.id).await.unwrap());
+
+    // Message ids are per chat, so the same id can be a board in both
+    db::insert_game_message(&pool, game_id, 1, 10).await.unwrap();
+    db::insert_game_message(&pool, game_id, 2, 10).await.unwrap();
+    db::insert_game_message(&pool, game_id, 2, 11).await.unwrap();
+
+    let game = db::find_game_by_message(&pool, 2, 11).await.unwrap().unwrap();
+    assert_eq!(game.chats(), vec![1, 2]);
+    assert!(db::find_game_by_message(&pool, 1, 11).await.unwrap().is_none());
+    assert!(db::find_game_by_message(&pool, 3, 10).await.unwrap().is_none());
+
+    db::delete_game_messages(&pool, game_id, 2).await.unwrap();
+    assert!(db::get_game_message_ids(&pool, game_id, 2).await.unwrap().is_empty());
+    assert_eq!(db::get_game_message_ids(&pool, game_id, 1).await.unwrap(), vec![10]);
+}
+
+#[tokio::test]
+async fn test_match_queue() {
+    let pool = setup_test_db().await;
+    let first = db::upsert_user(&pool, &test_user(1, None)).await.unwrap();
+    let second = db::upsert_user(&pool, &test_user(2, None)).await.unwrap();
+
+    assert!(db::enqueue_player(&pool, first.id, 1).await.unwrap());
+    assert!(!db::enqueue_player(&pool, first.id, 1).await.unwrap());
+    assert!(db::enqueue_player(&pool, second.id, 2).await.unwrap());
+
+    let queued = db::get_queued_players(&pool).await.unwrap();
+    assert_eq!(queued.len(), 2);
+    assert_eq!(queued[0].0.id, first.id);
+    assert_eq!(queued[0].1, 1);
+
+    assert!(db::dequeue_player(&pool, first.id).await.unwrap());
+    assert!(!db::dequeue_player(&pool, first.id).await.unwrap());
+    assert_eq!(db::get_queued_players(&pool).await.unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn test_prediction_polls() {
+    let pool = setup_test_db().await;
+    let white = db::upsert_user(&pool, &test_user(1, None)).await.unwrap();
+    let black = db::upsert_user(&pool, &test_user(2, None)).await.unwrap();
+    let fan = db::upsert_user(&pool, &test_user(3, Some("fan"))).await.unwrap();
+    let skeptic = db::upsert_user(&pool, &test_user(4, Some("skeptic"))).await.unwrap();
+    let chat_id = -500;
+
+    db::set_chat_prediction_polls(&pool, chat_id, true).await.unwrap();
+    assert!(db::get_chat_settings(&pool, chat_id).await.unwrap().prediction_polls);
+
+    let game_id = db::create_game(&pool, chat_id, white.id, black.id, "start_fen", Turn::White)
+        .await
+        .unwrap();
+    db::create_prediction_poll(&pool, "p1", game_id, chat_id, 9).await.unwrap();
+    assert_eq!(db::get_prediction_poll_game(&pool, "p1").await.unwrap(), Some(game_id));
+
+    db::record_prediction(&pool, "p1", fan.id, Some(2)).await.unwrap();
+    db::record_prediction(&pool, "p1", fan.id, Some(0)).await.unwrap();
+    db::record_prediction(&pool, "p1", skeptic.id, Some(1)).await.unwrap();
+    db::settle_prediction_poll(&pool, "p1", chat_id, 0).await.unwrap();
+
+    assert!(db::get_prediction_poll_game(&pool, "p1").await.unwrap().is_none());
+    assert!(db::get_game_prediction_polls(&pool, game_id).await.unwrap().is_empty());
+    let standings = db::get_prediction_leaderboard(&pool, chat_id, 10).await.unwrap();
+    let summary: Vec<_> = standings.iter().map(|(user, correct, total)| (user.id, *correct, *total)).collect();
+    assert_eq!(summary, vec![(fan.id, 1, 1), (skeptic.id, 0, 1)]);
+
+    // Standings add up across games
+    db::create_prediction_poll(&pool, "p2", game_id, chat_id, 10).await.unwrap();
+    db::record_prediction(&pool, "p2", skeptic.id, Some(1)).await.unwrap();
+    db::settle_prediction_poll(&pool, "p2", chat_id, 1).await.unwrap();
+    let standings = db::get_prediction_leaderboard(&pool, chat_id, 10).await.unwrap();
+    let summary: Vec<_> = standings.iter().map(|(user, correct, total)| (user.id, *correct, *total)).collect();
+    assert_eq!(summary, vec![(fan.id, 1, 1), (skeptic.id, 1, 2)]);
+}
+
+#[tokio::test]
+async fn test_get_active_chats() {
+    let pool = setup_test_db().await;
+    let white = db::upsert_user(&pool, &test_user(1, None)).await.unwrap();
+    let black = db::upsert_user(&pool, &test_user(2, None)).await.unwrap();
+    for chat_id in [-1, -2] {
+        db::create_game(&pool, chat_id, white.id, black.id, "start_fen", Turn::White)
+            .await
+            .unwrap();
+    }
+    let private = db::create_game(&pool, 1, white.id, black.id, "start_fen", Turn::White)
+        .await
+        .unwrap();
+    db::set_game_peer_chat(&pool, private, 2).await.unwrap();
+    db::set_chat_announcements(&pool, -2, false).await.unwrap();
+
+    let hour_ago = chrono::Utc::now() - chrono::Duration::hours(1);
+    assert_eq!(db::get_active_chats(&pool, hour_ago).await.unwrap(), vec![-1, 1, 2]);
+    let later = chrono::Utc::now() + chrono::Duration::hours(1);
+    assert!(db::get_active_chats(&pool, later).await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn test_feature_flags() {
+    let pool = setup_test_db().await;
+    assert!(!db::is_feature_enabled(&pool, "puzzles", -1).await.unwrap());
+
+    db::set_feature_flag(&pool, "puzzles", None, Some(true)).await.unwrap();
+    assert!(db::is_feature_enabled(&pool, "puzzles", -1).await.unwrap());
+
+    // A chat's own flag overrides the global one until it is reset
+    db::set_feature_flag(&pool, "puzzles", Some(-1), Some(false)).await.unwrap();
+    assert!(!db::is_feature_enabled(&pool, "puzzles", -1).await.unwrap());
+    assert!(db::is_feature_enabled(&pool, "puzzles", -2).await.unwrap());
+    assert_eq!(
+        db::get_feature_flags(&pool, -1).await.unwrap(),
+        vec![("puzzles".to_string(), true, true), ("puzzles".to_string(), false, false)]
+    );
+
+    db::set_feature_flag(&pool, "puzzles", Some(-1), None).await.unwrap();
+    assert!(db::is_feature_enabled(&pool, "puzzles", -1).await.unwrap());
+
+    // Known features fall back to their default, not to off
+    assert!(db::is_feature_enabled(&pool, "engine", -1).await.unwrap());
+    db::set_feature_flag(&pool, "engine", Some(-1), Some(false)).await.unwrap();
+    assert!(!db::is_feature_enabled(&pool, "engine", -1).await.unwrap());
+}
+
+#[tokio::test]
+async fn test_audit_log() {
+    let pool = setup_test_db().await;
+    db::upsert_user(&pool, &test_user(900, Some("auditor"))).await.unwrap();
+
+    db::insert_audit_entry(&pool, Some(900), -1, None, "/start @rival", "ok").await.unwrap();
+    db::insert_audit_entry(&pool, None, -1, Some(5), "game.start", "white=900 black=901").await.unwrap();
+    db::insert_audit_entry(&pool, Some(901), -2, Some(5), "e4", "rejected: Illegal move").await.unwrap();
+
+    let chat = db::get_audit_log(&pool, db::AuditFilter::Chat(-1), 10).await.unwrap();
+    assert_eq!(chat.len(), 2);
+    assert_eq!(chat[0].command, "game.start");
+    assert_eq!(chat[1].username.as_deref(), Some("auditor"));
+
+    let game = db::get_audit_log(&pool, db::AuditFilter::Game(5), 1).await.unwrap();
+    assert_eq!(game.len(), 1);
+    assert_eq!(game[0].outcome, "rejected: Illegal move");
+
+    let user = db::get_audit_log(&pool, db::AuditFilter::User(901), 10).await.unwrap();
+    assert_eq!(user.len(), 1);
+    assert_eq!(user[0].username, None);
+}
+
+#[tokio::test]
+async fn test_abandon_chat_games() {
+    let pool = setup_test_db().await;
+    let white = db::upsert_user(&pool, &test_user(1, None)).await.unwrap();
+    let black = db::upsert_user(&pool, &test_user(2, None)).await.unwrap();
+    let group = db::create_game(&pool, -1, white.id, black.id, "start_fen", Turn::White)
+        .await
+        .unwrap();
+    let peer = db::create_game(&pool, 1, white.id, black.id, "start_fen", Turn::White)
+        .await
+        .unwrap();
+    db::set_game_peer_chat(&pool, peer, 2).await.unwrap();
+
+    let abandoned = db::abandon_chat_games(&pool, 2).await.unwrap();
+    assert_eq!(abandoned.iter().map(|g| g.id).collect::<Vec<_>>(), vec![peer]);
+    let game = db::get_game_by_id(&pool, peer).await.unwrap().unwrap();
+    assert_eq!(game.status, GameStatus::Abandoned);
+    assert_eq!(game.result, None);
+    let game = db::get_game_by_id(&pool, group).await.unwrap().unwrap();
+    assert_eq!(game.status, GameStatus::Ongoing);
+
+    // A chat that removed the bot gets no announcements
+    db::set_chat_bot_removed(&pool, -1, true).await.unwrap();
+    let hour_ago = chrono::Utc::now() - chrono::Duration::hours(1);
+    assert_eq!(db::get_active_chats(&pool, hour_ago).await.unwrap(), vec![1, 2]);
+}
+
+#[tokio::test]
+async fn test_player_left_game() {
+    let pool = setup_test_db().await;
+    let white = db::upsert_user(&pool, &test_user(1, None)).await.unwrap();
+    let black = db::upsert_user(&pool, &test_user(2, None)).await.unwrap();
+    let other = db::upsert_user(&pool, &test_user(3, None)).await.unwrap();
+    let game_id = db::create_game(&pool, -1, white.id, black.id, "start_fen", Turn::White)
+        .await
+        .unwrap();
+
+    let games = db::find_player_games_in_chat(&pool, -1, black.id).await.unwrap();
+    assert_eq!(games.iter().map(|g| g.id).collect::<Vec<_>>(), vec![game_id]);
+    assert!(db::find_player_games_in_chat(&pool, -1, other.id).await.unwrap().is_empty());
+    assert!(db::find_player_games_in_chat(&pool, -2, black.id).await.unwrap().is_empty());
+
+    assert_eq!(db::get_player_left(&pool, game_id).await.unwrap(), None);
+    db::set_player_left(&pool, game_id, Some(black.id)).await.unwrap();
+    let (user_id, _) = db::get_player_left(&pool, game_id).await.unwrap().unwrap();
+    assert_eq!(user_id, black.id);
+    db::set_player_left(&pool, game_id, None).await.unwrap();
+    assert_eq!(db::get_player_left(&pool, game_id).await.unwrap(), None);
+
+    assert!(db::abandon_game(&pool, game_id).await.unwrap());
+    assert!(!db::abandon_game(&pool, game_id).await.unwrap());
+    assert!(db::find_player_games_in_chat(&pool, -1, black.id).await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn test_user_ongoing_games_across_chats() {
+    let pool = setup_test_db().await;
+    let white = db::upsert_user(&pool, &test_user(1, None)).await.unwrap();
+    let black = db::upsert_user(&pool, &test_user(2, None)).await.unwrap();
+    let other = db::upsert_user(&pool, &test_user(3, None)).await.unwrap();
+    let first = db::create_game(&pool, -1, white.id, black.id, "fen", Turn::White).await.unwrap();
+    let second = db::create_game(&pool, -2, other.id, white.id, "fen", Turn::White).await.unwrap();
+    let finished = db::create_game(&pool, -3, white.id, other.id, "fen", Turn::White).await.unwrap();
+    db::update_game_result(&pool, finished, GameResult::Draw).await.unwrap();
+
+    let ids = |games: Vec<kamachess::models::GameRow>| games.iter().map(|g| g.id).collect::<Vec<_>>();
+    assert_eq!(ids(db::find_user_ongoing_games(&pool, white.id).await.unwrap()), vec![first, second]);
+    assert_eq!(ids(db::find_user_ongoing_games(&pool, black.id).await.unwrap()), vec![first]);
+    assert_eq!(ids(db::find_user_ongoing_games(&pool, other.id).await.unwrap()), vec![second]);
+}
+
+#[tokio::test]
+async fn test_game_numbers_per_chat() {
+    let pool = setup_test_db().await;
+    let white = db::upsert_user(&pool, &test_user(1, None)).await.unwrap();
+    let black = db::upsert_user(&pool, &test_user(2, None)).await.unwrap();
+    let first = db::create_game(&pool, -1, white.id, black.id, "start_fen", Turn::White)
+        .await
+        .unwrap();
+    db::create_game(&pool, -2, white.id, black.id, "start_fen", Turn::White)
+        .await
+        .unwrap();
+    let second = db::create_game(&pool, -1, black.id, white.id, "start_fen", Turn::White)
+        .await
+        .unwrap();
+
+    let game = db::find_game_by_number(&pool, -1, 2).await.unwrap().unwrap();
+    assert_eq!(game.id, second);
+    assert_eq!(db::get_game_number(&pool, &game).await.unwrap(), 2);
+    let game = db::find_game_by_number(&pool, -1, 1).await.unwrap().unwrap();
+    assert_eq!(game.id, first);
+    assert!(db::find_game_by_number(&pool, -1, 3).await.unwrap().is_none());
+    assert!(db::find_game_by_number(&pool, -1, 0).await.unwrap().is_none());
+}
+
+#[tokio::test]
+async fn test_chat_game_starts() {
+    let pool = setup_test_db().await;
+    let a = db::upsert_user(&pool, &test_user(1, Some("act1"))).await.unwrap();
+    let b = db::upsert_user(&pool, &test_user(2, Some("act2"))).await.unwrap();
+    db::create_game(&pool, -980, a.id, b.id, "fen", Turn::White).await.unwrap();
+    db::create_game(&pool, -980, b.id, a.id, "fen", Turn::White).await.unwrap();
+    db::create_game(&pool, -981, a.id, b.id, "fen", Turn::White).await.unwrap();
+
+    let hour_ago = chrono::Utc::now() - chrono::Duration::hours(1);
+    assert_eq!(db::get_chat_game_starts(&pool, -980, hour_ago).await.unwrap().len(), 2);
+    let later = chrono::Utc::now() + chrono::Duration::hours(1);
+    assert!(db::get_chat_game_starts(&pool, -980, later).await.unwrap().is_empty());
+}
@@ -0,0 +1,66 @@
+use kamachess::engine::{Engine, EngineConfig, Score, SearchLimit};
+use std::os::unix::fs::PermissionsExt;
+use std::path::PathBuf;
+
+const START_FEN: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+
+/// Minimal UCI engine: answers the handshake and always reports the same search.
+fn fake_engine(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("kamachess_engine_{}_{}", name, std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("engine.sh");
+    std::fs::write(
+        &path,
+        r#"#!/bin/sh
+while read -r line; do
+  case "$line" in
+    uci) echo "id name Fake"; echo "uciok" ;;
+    isready) echo "readyok" ;;
+    go*)
+      echo "info depth 1 score cp 10 pv d2d4"
+      echo "info depth 8 score cp 31 pv e2e4 e7e5"
+      echo "bestmove e2e4 ponder e7e5" ;;
+    quit) exit 0 ;;
+  esac
+done
+"#,
+    )
+    .unwrap();
+    std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+    path
+}
+
+#[tokio::test]
+async fn test_engine_analysis() {
+    let engine = Engine::new(EngineConfig::new(fake_engine("analysis")));
+    let analysis = engine
+        .analyse(START_FEN, SearchLimit::Depth(8))
+        .await
+        .expect("analysis failed");
+
+    assert_eq!(analysis.depth, 8);
+    assert_eq!(analysis.score, Some(Score::Centipawns(31)));
+    assert_eq!(analysis.best_move.as_deref(), Some("e2e4"));
+    assert_eq!(analysis.pv, vec!["e2e4", "e7e5"]);
+}
+
+#[tokio::test]
+async fn test_engine_worker_limit_runs_requests_in_turn() {
+    let config = EngineConfig {
+        max_workers: 1,
+        ..EngineConfig::new(fake_engine("workers"))
+    };
+    let engine = Engine::new(config);
+
+    let (a, b) = tokio::join!(
+        engine.analyse(START_FEN, SearchLimit::Default),
+        engine.analyse(START_FEN, SearchLimit::Default)
+    );
+    assert!(a.is_ok() && b.is_ok());
+}
+
+#[tokio::test]
+async fn test_missing_engine_binary() {
+    let engine = Engine::new(EngineConfig::new("/nonexistent/engine"));
+    assert!(engine.analyse(START_FEN, SearchLimit::Default).await.is_err());
+}
@@ -1,30 +1,45 @@
-use chess::Board;
-use kamachess::game::render_board_png;
+use kamachess::game::{
+    render_board, render_board_png, BoardOverlay, CoordinateStyle, Position, RenderOptions, Square,
+};
 use std::fs;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::OnceLock;
 
-#[test]
-fn test_image_caching_lifecycle() {
-    let board = Board::default();
+/// Points the cache at a directory of its own, so test renders never land in the
+/// working tree. Every test calls this before rendering, so the variable is set once,
+/// before anything reads it.
+fn cache_dir() -> &'static Path {
+    static DIR: OnceLock<PathBuf> = OnceLock::new();
+    DIR.get_or_init(|| {
+        let dir = std::env::temp_dir()
+            .join(format!("kamachess_image_cache_tests_{}", std::process::id()));
+        std::env::set_var("IMAGE_CACHE_DIR", &dir);
+        dir
+    })
+}
+
+#[tokio::test]
+async fn test_image_caching_lifecycle() {
+    let board = Position::default();
     let fen = board.to_string();
     let safe_fen = fen.replace(['/', ' '], "_");
-    let cache_dir = "images_cache";
-    let file_path = format!("{}/{}.png", cache_dir, safe_fen);
+    let file_path = cache_dir().join(format!("{}_v2.png", safe_fen));
 
     if Path::new(&file_path).exists() {
         fs::remove_file(&file_path).unwrap();
     }
 
-    let result = render_board_png(&board, false);
+    let result = render_board_png(&board, false).await;
     assert!(result.is_ok(), "First render failed");
     assert!(Path::new(&file_path).exists(), "Cache file was not created");
 
     let first_metadata = fs::metadata(&file_path).unwrap();
     let first_modified = first_metadata.modified().unwrap();
 
-    std::thread::sleep(std::time::Duration::from_millis(10));
+    tokio::time::sleep(std::time::Duration::from_millis(10)).await;
 
-    let result_cached = render_board_png(&board, false);
+    let result_cached = render_board_png(&board, false).await;
     assert!(result_cached.is_ok(), "Second render failed");
 
     let second_metadata = fs::metadata(&file_path).unwrap();
@@ -45,3 +60,55 @@ fn test_image_caching_lifecycle() {
 
     let _ = fs::remove_file(file_path);
 }
+
+#[tokio::test]
+async fn test_overlay_is_part_of_cache_key() {
+    let board = Position::from_str("rnbqkbnr/pppp1ppp/8/4p3/4P3/8/PPPP1PPP/RNBQKBNR w KQkq - 0 1")
+        .unwrap();
+    let safe_fen = board.to_string().replace(['/', ' '], "_");
+    let overlay = BoardOverlay::new()
+        .with_arrow(Square::from_str("g1").unwrap(), Square::from_str("f3").unwrap())
+        .with_circle(Square::from_str("e5").unwrap());
+    let overlay_path = cache_dir().join(format!("{}_v2_ag1f3-ce5.png", safe_fen));
+    let _ = fs::remove_file(&overlay_path);
+
+    let plain = render_board_png(&board, false).await.unwrap();
+    let options = RenderOptions {
+        overlay,
+        ..RenderOptions::default()
+    };
+    let annotated = render_board(&board, &options).await.unwrap();
+
+    assert_ne!(plain, annotated, "Overlay should change the rendered image");
+    assert!(Path::new(&overlay_path).exists(), "Overlay render was not cached separately");
+
+    let _ = fs::remove_file(overlay_path);
+}
+
+#[tokio::test]
+async fn test_coordinate_styles_cached_separately() {
+    let board = Position::from_str("rnbqkbnr/pppppppp/8/8/3P4/8/PPP1PPPP/RNBQKBNR b KQkq - 0 1")
+        .unwrap();
+    let safe_fen = board.to_string().replace(['/', ' '], "_");
+
+    for (style, suffix) in [
+        (CoordinateStyle::Inside, "_inside"),
+        (CoordinateStyle::Hidden, "_hidden"),
+    ] {
+        let path = cache_dir().join(format!("{}_v2{}.png", safe_fen, suffix));
+        let _ = fs::remove_file(&path);
+
+        let options = RenderOptions {
+            coordinates: style,
+            ..RenderOptions::default()
+        };
+        let bytes = render_board(&board, &options).await.unwrap();
+        let img = image::load_from_memory(&bytes).unwrap();
+
+        // Without an outer border the board is exactly eight squares wide
+        assert_eq!(img.width(), 512);
+        assert!(Path::new(&path).exists(), "{:?} render was not cached", style);
+
+        let _ = fs::remove_file(path);
+    }
+}
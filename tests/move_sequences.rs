@@ -1,18 +1,17 @@
-use chess::{Board, Color, Piece, Square};
-use kamachess::game::parse_move;
+use kamachess::game::{parse_move, Color, GameStatus, Position, Role, Square};
 use std::str::FromStr;
 
-fn apply_moves(moves: &[&str]) -> Board {
-    let mut board = Board::default();
+fn apply_moves(moves: &[&str]) -> Position {
+    let mut board = Position::default();
     for mv in moves {
         let parsed = parse_move(&board, mv)
             .unwrap_or_else(|err| panic!("Failed to parse move '{mv}': {err}"));
-        board = board.make_move_new(parsed);
+        board = board.play(parsed);
     }
     board
 }
 
-fn assert_piece(board: &Board, square: &str, piece: Piece, color: Color) {
+fn assert_piece(board: &Position, square: &str, piece: Role, color: Color) {
     let sq = Square::from_str(square).expect("invalid square");
     assert_eq!(
         board.piece_on(sq),
@@ -35,13 +34,13 @@ fn opera_game_finishing_position() {
     ];
     let board = apply_moves(&moves);
 
-    assert_eq!(board.status(), chess::BoardStatus::Checkmate);
-    assert_piece(&board, "d8", Piece::Rook, Color::White);
-    assert_piece(&board, "g5", Piece::Bishop, Color::White);
-    assert_piece(&board, "e8", Piece::King, Color::Black);
-    assert_piece(&board, "e6", Piece::Queen, Color::Black);
-    assert_piece(&board, "h8", Piece::Rook, Color::Black);
-    assert_piece(&board, "b8", Piece::Knight, Color::Black);
+    assert_eq!(board.status(), GameStatus::Checkmate);
+    assert_piece(&board, "d8", Role::Rook, Color::White);
+    assert_piece(&board, "g5", Role::Bishop, Color::White);
+    assert_piece(&board, "e8", Role::King, Color::Black);
+    assert_piece(&board, "e6", Role::Queen, Color::Black);
+    assert_piece(&board, "h8", Role::Rook, Color::Black);
+    assert_piece(&board, "b8", Role::Knight, Color::Black);
 }
 
 #[test]
@@ -51,11 +50,11 @@ fn legall_mate_position() {
     ];
     let board = apply_moves(&moves);
 
-    assert_eq!(board.status(), chess::BoardStatus::Checkmate);
-    assert_piece(&board, "f7", Piece::Bishop, Color::White);
-    assert_piece(&board, "d5", Piece::Knight, Color::White);
-    assert_piece(&board, "e7", Piece::King, Color::Black);
-    assert_piece(&board, "d1", Piece::Bishop, Color::Black);
+    assert_eq!(board.status(), GameStatus::Checkmate);
+    assert_piece(&board, "f7", Role::Bishop, Color::White);
+    assert_piece(&board, "d5", Role::Knight, Color::White);
+    assert_piece(&board, "e7", Role::King, Color::Black);
+    assert_piece(&board, "d1", Role::Bishop, Color::Black);
 }
 
 #[test]
@@ -66,12 +65,12 @@ fn blackburne_shilling_mate_position() {
     ];
     let board = apply_moves(&moves);
 
-    assert_eq!(board.status(), chess::BoardStatus::Checkmate);
-    assert_piece(&board, "f3", Piece::Knight, Color::Black);
-    assert_piece(&board, "e4", Piece::Queen, Color::Black);
-    assert_piece(&board, "f7", Piece::Knight, Color::White);
-    assert_piece(&board, "f1", Piece::Rook, Color::White);
-    assert_piece(&board, "e8", Piece::King, Color::Black);
+    assert_eq!(board.status(), GameStatus::Checkmate);
+    assert_piece(&board, "f3", Role::Knight, Color::Black);
+    assert_piece(&board, "e4", Role::Queen, Color::Black);
+    assert_piece(&board, "f7", Role::Knight, Color::White);
+    assert_piece(&board, "f1", Role::Rook, Color::White);
+    assert_piece(&board, "e8", Role::King, Color::Black);
 }
 
 #[test]
@@ -81,6 +80,6 @@ fn pawn_short_notation_does_not_move_other_pieces() {
 
     assert!(parse_move(&board, "a6").is_err());
     let bishop_move = parse_move(&board, "Ba6").expect("Ba6 should be legal");
-    assert_eq!(bishop_move.get_source(), Square::from_str("c4").unwrap());
-    assert_eq!(bishop_move.get_dest(), Square::from_str("a6").unwrap());
+    assert_eq!(bishop_move.from(), Some(Square::from_str("c4").unwrap()));
+    assert_eq!(bishop_move.to(), Square::from_str("a6").unwrap());
 }
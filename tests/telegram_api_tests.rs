@@ -1,7 +1,10 @@
-use kamachess::api::TelegramApi;
+use kamachess::api::{BreakerConfig, TelegramApi, WebhookOptions};
+use kamachess::game::ImageFormat;
+use kamachess::models::{InlineKeyboardButton, InlineKeyboardMarkup};
+use kamachess::responder::{Responder, Templates};
 use serde_json::json;
 use wiremock::{
-    matchers::{body_json, method, path},
+    matchers::{body_json, body_string_contains, method, path},
     Mock, MockServer, ResponseTemplate,
 };
 
@@ -26,7 +29,7 @@ async fn test_set_webhook_success() {
         .await;
 
     let result = api
-        .set_webhook("https://example.com/webhook", None)
+        .set_webhook("https://example.com/webhook", &WebhookOptions::default())
         .await;
 
     assert!(result.is_ok());
@@ -54,12 +57,81 @@ async fn test_set_webhook_with_secret_token() {
         .await;
 
     let result = api
-        .set_webhook("https://example.com/webhook", Some("my-secret-token"))
+        .set_webhook(
+            "https://example.com/webhook",
+            &WebhookOptions {
+                secret_token: Some("my-secret-token".to_string()),
+                ..WebhookOptions::default()
+            },
+        )
         .await;
 
     assert!(result.is_ok());
 }
 
+#[tokio::test]
+async fn test_set_webhook_with_certificate() {
+    let mock_server = MockServer::start().await;
+    let api = TelegramApi::new_with_base_url(format!("http://{}/bot123", mock_server.address()));
+    let pem = b"-----BEGIN CERTIFICATE-----\nMIIB\n-----END CERTIFICATE-----\n";
+
+    Mock::given(method("POST"))
+        .and(path("/bot123/setWebhook"))
+        .and(body_string_contains("name=\"certificate\"; filename=\"certificate.pem\""))
+        .and(body_string_contains("MIIB"))
+        .and(body_string_contains("my-secret-token"))
+        .and(body_string_contains("name=\"max_connections\"\r\n\r\n10"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "ok": true,
+            "result": true
+        })))
+        .mount(&mock_server)
+        .await;
+
+    let result = api
+        .set_webhook(
+            "https://203.0.113.5:8443/webhook",
+            &WebhookOptions {
+                secret_token: Some("my-secret-token".to_string()),
+                certificate: Some(pem.to_vec()),
+                max_connections: Some(10),
+                ..WebhookOptions::default()
+            },
+        )
+        .await;
+
+    assert!(result.is_ok());
+}
+
+#[tokio::test]
+async fn test_set_webhook_drop_pending_updates() {
+    let mock_server = MockServer::start().await;
+    let api = TelegramApi::new_with_base_url(format!("http://{}/bot123", mock_server.address()));
+
+    Mock::given(method("POST"))
+        .and(path("/bot123/setWebhook"))
+        .and(body_json(json!({
+            "url": "https://example.com/webhook",
+            "drop_pending_updates": true,
+            "max_connections": 20
+        })))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "ok": true,
+            "result": true
+        })))
+        .mount(&mock_server)
+        .await;
+
+    let options = WebhookOptions {
+        drop_pending_updates: true,
+        max_connections: Some(20),
+        ..WebhookOptions::default()
+    };
+    let result = api.set_webhook("https://example.com/webhook", &options).await;
+
+    assert!(result.is_ok());
+}
+
 #[tokio::test]
 async fn test_set_webhook_error() {
     let mock_server = MockServer::start().await;
@@ -75,7 +147,9 @@ async fn test_set_webhook_error() {
         .mount(&mock_server)
         .await;
 
-    let result = api.set_webhook("http://example.com/webhook", None).await;
+    let result = api
+        .set_webhook("http://example.com/webhook", &WebhookOptions::default())
+        .await;
 
     assert!(result.is_err());
     assert!(result
@@ -198,3 +272,369 @@ async fn test_get_webhook_info_error() {
     assert!(result.is_err());
     assert!(result.unwrap_err().to_string().contains("Unauthorized"));
 }
+
+#[tokio::test]
+async fn test_send_message_with_keyboard() {
+    let mock_server = MockServer::start().await;
+    let api = TelegramApi::new_with_base_url(format!("http://{}/bot123", mock_server.address()));
+
+    let expected_body = json!({
+        "chat_id": 1,
+        "text": "Play e4?",
+        "reply_to_message_id": 2,
+        "parse_mode": "HTML",
+        "reply_markup": {
+            "inline_keyboard": [[
+                { "text": "Confirm", "callback_data": "confirm:5" }
+            ]]
+        }
+    });
+
+    Mock::given(method("POST"))
+        .and(path("/bot123/sendMessage"))
+        .and(body_json(&expected_body))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "ok": true,
+            "result": { "message_id": 77, "chat": { "id": 1 } }
+        })))
+        .mount(&mock_server)
+        .await;
+
+    let keyboard = InlineKeyboardMarkup {
+        inline_keyboard: vec![vec![InlineKeyboardButton::callback(
+            "Confirm",
+            "confirm:5".to_string(),
+        )]],
+    };
+    let result = api.send_message_with_keyboard(1, 2, "Play e4?", keyboard).await;
+
+    assert_eq!(result.unwrap(), 77);
+}
+
+#[tokio::test]
+async fn test_answer_callback_query() {
+    let mock_server = MockServer::start().await;
+    let api = TelegramApi::new_with_base_url(format!("http://{}/bot123", mock_server.address()));
+
+    let expected_body = json!({
+        "callback_query_id": "abc",
+        "text": "Move cancelled"
+    });
+
+    Mock::given(method("POST"))
+        .and(path("/bot123/answerCallbackQuery"))
+        .and(body_json(&expected_body))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "ok": true,
+            "result": true
+        })))
+        .mount(&mock_server)
+        .await;
+
+    let result = api.answer_callback_query("abc", Some("Move cancelled")).await;
+
+    assert!(result.is_ok());
+}
+
+#[tokio::test]
+async fn test_set_message_reaction() {
+    let mock_server = MockServer::start().await;
+    let api = TelegramApi::new_with_base_url(format!("http://{}/bot123", mock_server.address()));
+
+    let expected_body = json!({
+        "chat_id": -100,
+        "message_id": 42,
+        "reaction": [{ "type": "emoji", "emoji": "⚡" }]
+    });
+
+    Mock::given(method("POST"))
+        .and(path("/bot123/setMessageReaction"))
+        .and(body_json(&expected_body))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "ok": true,
+            "result": true
+        })))
+        .mount(&mock_server)
+        .await;
+
+    let result = api.set_message_reaction(-100, 42, "⚡").await;
+
+    assert!(result.is_ok());
+}
+
+#[tokio::test]
+async fn test_send_poll() {
+    let mock_server = MockServer::start().await;
+    let api = TelegramApi::new_with_base_url(format!("http://{}/bot123", mock_server.address()));
+
+    let expected_body = json!({
+        "chat_id": -100,
+        "question": "Who wins?",
+        "options": [{ "text": "White" }, { "text": "Draw" }, { "text": "Black" }],
+        "is_anonymous": false,
+        "reply_to_message_id": 7
+    });
+
+    Mock::given(method("POST"))
+        .and(path("/bot123/sendPoll"))
+        .and(body_json(&expected_body))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "ok": true,
+            "result": {
+                "message_id": 8,
+                "chat": { "id": -100, "type": "group" },
+                "poll": { "id": "5123", "question": "Who wins?", "options": [] }
+            }
+        })))
+        .mount(&mock_server)
+        .await;
+
+    let options = ["White", "Draw", "Black"].map(String::from);
+    let poll = api.send_poll(-100, Some(7), "Who wins?", &options).await.unwrap();
+
+    assert_eq!(poll.message_id, 8);
+    assert_eq!(poll.poll.id, "5123");
+}
+
+#[tokio::test]
+async fn test_edit_message_text_not_modified_is_ok() {
+    let mock_server = MockServer::start().await;
+    let api = TelegramApi::new_with_base_url(format!("http://{}/bot123", mock_server.address()));
+
+    Mock::given(method("POST"))
+        .and(path("/bot123/editMessageText"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "ok": false,
+            "error_code": 400,
+            "description": "Bad Request: message is not modified"
+        })))
+        .mount(&mock_server)
+        .await;
+
+    let result = api.edit_message_text(1, 2, "Move cancelled.").await;
+
+    assert!(result.is_ok());
+}
+
+#[tokio::test]
+async fn test_send_document() {
+    let mock_server = MockServer::start().await;
+    let api = TelegramApi::new_with_base_url(format!("http://{}/bot123", mock_server.address()));
+
+    Mock::given(method("POST"))
+        .and(path("/bot123/sendDocument"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "ok": true,
+            "result": { "message_id": 12, "chat": { "id": 1 } }
+        })))
+        .mount(&mock_server)
+        .await;
+
+    let result = api
+        .send_document(1, None, "Board", vec![1, 2, 3], ImageFormat::Png, None)
+        .await;
+
+    assert_eq!(result.unwrap(), 12);
+}
+
+#[tokio::test]
+async fn test_send_attachment() {
+    let mock_server = MockServer::start().await;
+    let api = TelegramApi::new_with_base_url(format!("http://{}/bot123", mock_server.address()));
+
+    Mock::given(method("POST"))
+        .and(path("/bot123/sendDocument"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "ok": true,
+            "result": { "message_id": 13, "chat": { "id": 1 } }
+        })))
+        .mount(&mock_server)
+        .await;
+
+    let result = api
+        .send_attachment(1, Some(12), "", "game.pgn", "application/x-chess-pgn", b"1. e4 *".to_vec())
+        .await;
+
+    assert_eq!(result.unwrap(), 13);
+}
+
+#[tokio::test]
+async fn test_send_attachment_file() {
+    let mock_server = MockServer::start().await;
+    let api = TelegramApi::new_with_base_url(format!("http://{}/bot123", mock_server.address()));
+
+    Mock::given(method("POST"))
+        .and(path("/bot123/sendDocument"))
+        .and(body_string_contains("filename=\"games.pgn\""))
+        .and(body_string_contains("1. e4 e5 *"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "ok": true,
+            "result": { "message_id": 14, "chat": { "id": 1 } }
+        })))
+        .mount(&mock_server)
+        .await;
+
+    let path = std::env::temp_dir().join(format!("kamachess-test-{}.pgn", std::process::id()));
+    std::fs::write(&path, "1. e4 e5 *\n").unwrap();
+    let result = api
+        .send_attachment_file(1, None, "", &path, "games.pgn", "application/x-chess-pgn")
+        .await;
+    std::fs::remove_file(&path).unwrap();
+
+    assert_eq!(result.unwrap(), 14);
+}
+
+#[tokio::test]
+async fn test_get_chat_by_username() {
+    let mock_server = MockServer::start().await;
+    let api = TelegramApi::new_with_base_url(format!("http://{}/bot123", mock_server.address()));
+
+    Mock::given(method("POST"))
+        .and(path("/bot123/getChat"))
+        .and(body_json(json!({ "chat_id": "@alice" })))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "ok": true,
+            "result": { "id": 42, "type": "private", "username": "alice", "first_name": "Alice" }
+        })))
+        .mount(&mock_server)
+        .await;
+
+    let chat = api.get_chat("@alice").await.unwrap();
+
+    assert_eq!(chat.id, 42);
+    assert_eq!(chat.kind, "private");
+    assert_eq!(chat.username.as_deref(), Some("alice"));
+}
+
+#[tokio::test]
+async fn test_get_chat_unknown_user() {
+    let mock_server = MockServer::start().await;
+    let api = TelegramApi::new_with_base_url(format!("http://{}/bot123", mock_server.address()));
+
+    Mock::given(method("POST"))
+        .and(path("/bot123/getChat"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "ok": false,
+            "error_code": 400,
+            "description": "Bad Request: chat not found"
+        })))
+        .mount(&mock_server)
+        .await;
+
+    assert!(api.get_chat("@nobody").await.is_err());
+}
+
+#[tokio::test]
+async fn test_get_chat_member() {
+    let mock_server = MockServer::start().await;
+    let api = TelegramApi::new_with_base_url(format!("http://{}/bot123", mock_server.address()));
+
+    Mock::given(method("POST"))
+        .and(path("/bot123/getChatMember"))
+        .and(body_json(json!({ "chat_id": -100, "user_id": 42 })))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "ok": true,
+            "result": {
+                "status": "member",
+                "user": { "id": 42, "is_bot": false, "first_name": "Alice", "username": "alice" }
+            }
+        })))
+        .mount(&mock_server)
+        .await;
+
+    let member = api.get_chat_member(-100, 42).await.unwrap();
+
+    assert!(member.is_present());
+    assert_eq!(member.user.id, 42);
+    assert_eq!(member.user.username.as_deref(), Some("alice"));
+}
+
+#[tokio::test]
+async fn test_send_text_splits_long_messages() {
+    let mock_server = MockServer::start().await;
+    let api = TelegramApi::new_with_base_url(format!("http://{}/bot123", mock_server.address()));
+    let responder = Responder::new(api, Templates::default());
+
+    Mock::given(method("POST"))
+        .and(path("/bot123/sendMessage"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "ok": true,
+            "result": { "message_id": 30, "chat": { "id": 1 } }
+        })))
+        .expect(2)
+        .mount(&mock_server)
+        .await;
+
+    let line = format!("<b>{}</b>", "x".repeat(99));
+    let text = vec![line; 50].join("\n");
+    let result = responder.send_text(1, Some(2), &text).await;
+
+    assert_eq!(result.unwrap(), 30);
+}
+
+#[tokio::test]
+async fn test_edit_message_reply_markup() {
+    let mock_server = MockServer::start().await;
+    let api = TelegramApi::new_with_base_url(format!("http://{}/bot123", mock_server.address()));
+
+    Mock::given(method("POST"))
+        .and(path("/bot123/editMessageReplyMarkup"))
+        .and(body_json(json!({
+            "chat_id": 1,
+            "message_id": 2,
+            "reply_markup": { "inline_keyboard": [[{ "text": "♘", "callback_data": "tap:3:0:g1" }]] }
+        })))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "ok": true, "result": true })))
+        .mount(&mock_server)
+        .await;
+
+    let keyboard = InlineKeyboardMarkup {
+        inline_keyboard: vec![vec![InlineKeyboardButton::callback("♘", "tap:3:0:g1".to_string())]],
+    };
+    assert!(api.edit_message_reply_markup(1, 2, Some(&keyboard)).await.is_ok());
+}
+
+#[tokio::test]
+async fn test_breaker_pauses_calls_after_server_errors() {
+    let mock_server = MockServer::start().await;
+    let api = TelegramApi::new_with_base_url(format!("http://{}/bot123", mock_server.address()))
+        .with_breaker(BreakerConfig {
+            threshold: 2,
+            cooldown: std::time::Duration::from_secs(60),
+        });
+
+    Mock::given(method("POST"))
+        .and(path("/bot123/sendMessage"))
+        .respond_with(ResponseTemplate::new(502).set_body_json(json!({
+            "ok": false,
+            "description": "Bad Gateway"
+        })))
+        .expect(2)
+        .mount(&mock_server)
+        .await;
+
+    for _ in 0..4 {
+        assert!(api.send_message(1, None, "hi").await.is_err());
+    }
+}
+
+#[tokio::test]
+async fn test_get_me_on_local_server() {
+    let mock_server = MockServer::start().await;
+    let api = TelegramApi::with_server(&format!("http://{}/", mock_server.address()), "123");
+
+    Mock::given(method("POST"))
+        .and(path("/bot123/getMe"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "ok": true,
+            "result": { "id": 7, "is_bot": true, "username": "kamachess_bot", "first_name": "Kama" }
+        })))
+        .mount(&mock_server)
+        .await;
+
+    let me = api.get_me().await.unwrap();
+
+    assert_eq!(me.id, 7);
+    assert_eq!(me.username.as_deref(), Some("kamachess_bot"));
+    assert!(api.max_upload_bytes() > TelegramApi::new("123".to_string()).max_upload_bytes());
+}
@@ -1,7 +1,9 @@
 use kamachess::{
     api,
+    live::{Clocks, LiveEvent},
     models::{Chat, Message, Update, User},
-    server::{create_router_for_test, WebhookConfig},
+    responder::{Responder, Templates},
+    server::{create_router_for_test, random_webhook_route, WebhookConfig},
     AppState,
 };
 use axum::{
@@ -13,17 +15,26 @@ use std::sync::Arc;
 use tower::util::ServiceExt;
 
 async fn create_test_state() -> Arc<AppState> {
+    sqlx::any::install_default_drivers();
     let pool = AnyPoolOptions::new()
         .max_connections(1)
         .connect("sqlite::memory:")
         .await
         .expect("Failed to create test database");
 
+    let telegram = api::TelegramApi::new("test-token".to_string());
     Arc::new(AppState {
         db: pool,
-        telegram: api::TelegramApi::new("test-token".to_string()),
+        responder: Responder::new(telegram.clone(), Templates::default()),
+        telegram,
         bot_username: "testbot".to_string(),
         no_trash: true,
+        image_encoding: Default::default(),
+        engine: None,
+        users: Default::default(),
+        maintenance: Default::default(),
+        metrics: Default::default(),
+        live: Default::default(),
     })
 }
 
@@ -40,9 +51,15 @@ fn create_test_update() -> Update {
                 username: Some("testuser".to_string()),
                 first_name: Some("Test".to_string()),
                 last_name: None,
+                language_code: None,
             }),
             reply_to_message: None,
+            new_chat_members: None,
+            left_chat_member: None,
         }),
+        callback_query: None,
+        poll_answer: None,
+        my_chat_member: None,
     }
 }
 
@@ -51,9 +68,7 @@ async fn test_webhook_handler_returns_ok() {
     let state = create_test_state().await;
     let app = create_router_for_test(
         state.clone(),
-        Arc::new(WebhookConfig {
-            secret_token: None,
-        }),
+        Arc::new(WebhookConfig::new(None)),
         "/webhook".to_string(),
     );
 
@@ -77,9 +92,7 @@ async fn test_health_check() {
     let state = create_test_state().await;
     let app = create_router_for_test(
         state.clone(),
-        Arc::new(WebhookConfig {
-            secret_token: None,
-        }),
+        Arc::new(WebhookConfig::new(None)),
         "/webhook".to_string(),
     );
 
@@ -94,14 +107,144 @@ async fn test_health_check() {
     assert_eq!(response.status(), StatusCode::OK);
 }
 
+#[tokio::test]
+async fn test_metrics_skips_webhook_secret() {
+    let state = create_test_state().await;
+    state.metrics.record_command(123, "help");
+    let app = create_router_for_test(
+        state.clone(),
+        Arc::new(WebhookConfig::new(Some("test-secret".to_string()))),
+        "/webhook".to_string(),
+    );
+
+    let request = Request::builder()
+        .method("GET")
+        .uri("/metrics")
+        .body(Body::empty())
+        .unwrap();
+
+    let response = app.oneshot(request).await.unwrap();
+
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let body = String::from_utf8(body.to_vec()).unwrap();
+    assert!(body.contains("kamachess_commands_total{chat_id=\"123\",command=\"help\"} 1"));
+}
+
+#[tokio::test]
+async fn test_public_api() {
+    let state = create_test_state().await;
+    kamachess::db::run_migrations(&state.db, "sqlite::memory:").await.unwrap();
+    let user = |id: i64, username: &str| User {
+        id,
+        is_bot: false,
+        username: Some(username.to_string()),
+        first_name: None,
+        last_name: None,
+        language_code: None,
+    };
+    let white = kamachess::db::upsert_user(&state.db, &user(1, "alice")).await.unwrap();
+    let black = kamachess::db::upsert_user(&state.db, &user(2, "bob")).await.unwrap();
+    let start = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+    let game_id = kamachess::db::create_game(&state.db, -1, white.id, black.id, start, kamachess::models::Turn::White)
+        .await
+        .unwrap();
+    kamachess::db::insert_move(&state.db, game_id, white.id, 1, "e2e4", Some("e4")).await.unwrap();
+    // Only this test reads API_KEYS
+    std::env::set_var("API_KEYS", "overlay-key, site-key");
+
+    let app = create_router_for_test(
+        state.clone(),
+        Arc::new(WebhookConfig::new(Some("test-secret".to_string()))),
+        "/webhook".to_string(),
+    );
+    let get = |uri: String, auth: Option<&str>| {
+        let mut request = Request::builder().method("GET").uri(uri);
+        if let Some(auth) = auth {
+            request = request.header(header::AUTHORIZATION, auth);
+        }
+        request.body(Body::empty()).unwrap()
+    };
+
+    let response = app.clone().oneshot(get(format!("/api/games/{}", game_id), None)).await.unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+    let response = app
+        .clone()
+        .oneshot(get(format!("/api/games/{}", game_id), Some("Bearer site-key")))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let game: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(game["status"], "ongoing");
+    assert_eq!(game["white"]["username"], "alice");
+    assert_eq!(game["moves"], serde_json::json!(["e4"]));
+
+    let response = app
+        .clone()
+        .oneshot(get(format!("/api/games/{}/pgn?key=overlay-key", game_id), None))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    assert!(String::from_utf8(body.to_vec()).unwrap().ends_with("\n\n1. e4 *\n"));
+
+    let response = app
+        .clone()
+        .oneshot(get(format!("/api/users/{}/stats", black.id), Some("Bearer overlay-key")))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let response = app
+        .clone()
+        .oneshot(get("/api/games/999".to_string(), Some("Bearer site-key")))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+    // The stream subscribes before answering, so events published now reach it
+    let response = app
+        .oneshot(get(format!("/api/games/{}/stream", game_id), Some("Bearer site-key")))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    state.live.publish(LiveEvent::End {
+        game_id: game_id + 1,
+        status: "finished",
+        result: "1-0",
+    });
+    state.live.publish(LiveEvent::Move {
+        game_id,
+        ply: 2,
+        san: "e5".to_string(),
+        uci: "e7e5".to_string(),
+        fen: "after-e5".to_string(),
+        turn: "white",
+        clocks: Clocks { white_secs: 0, black_secs: 3 },
+    });
+    state.live.publish(LiveEvent::End {
+        game_id,
+        status: "finished",
+        result: "0-1",
+    });
+    // The end event closes the stream, so the whole body can be read
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let body = String::from_utf8(body.to_vec()).unwrap();
+    let events: Vec<&str> = body.lines().filter_map(|line| line.strip_prefix("event: ")).collect();
+    assert_eq!(events, vec!["game", "move", "end"]);
+    assert!(body.contains("\"san\":\"e5\""));
+    assert!(body.contains("\"black_secs\":3"));
+    assert!(!body.contains("1-0"));
+}
+
 #[tokio::test]
 async fn test_secret_token_middleware_without_token() {
     let state = create_test_state().await;
     let app = create_router_for_test(
         state.clone(),
-        Arc::new(WebhookConfig {
-            secret_token: None,
-        }),
+        Arc::new(WebhookConfig::new(None)),
         "/webhook".to_string(),
     );
 
@@ -126,9 +269,7 @@ async fn test_secret_token_middleware_with_valid_token() {
     let state = create_test_state().await;
     let app = create_router_for_test(
         state.clone(),
-        Arc::new(WebhookConfig {
-            secret_token: Some("test-secret".to_string()),
-        }),
+        Arc::new(WebhookConfig::new(Some("test-secret".to_string()))),
         "/webhook".to_string(),
     );
 
@@ -154,9 +295,7 @@ async fn test_secret_token_middleware_with_invalid_token() {
     let state = create_test_state().await;
     let app = create_router_for_test(
         state.clone(),
-        Arc::new(WebhookConfig {
-            secret_token: Some("test-secret".to_string()),
-        }),
+        Arc::new(WebhookConfig::new(Some("test-secret".to_string()))),
         "/webhook".to_string(),
     );
 
@@ -182,9 +321,7 @@ async fn test_secret_token_middleware_missing_header() {
     let state = create_test_state().await;
     let app = create_router_for_test(
         state.clone(),
-        Arc::new(WebhookConfig {
-            secret_token: Some("test-secret".to_string()),
-        }),
+        Arc::new(WebhookConfig::new(Some("test-secret".to_string()))),
         "/webhook".to_string(),
     );
 
@@ -209,9 +346,7 @@ async fn test_custom_webhook_path() {
     let state = create_test_state().await;
     let app = create_router_for_test(
         state.clone(),
-        Arc::new(WebhookConfig {
-            secret_token: None,
-        }),
+        Arc::new(WebhookConfig::new(None)),
         "/custom/webhook".to_string(),
     );
 
@@ -236,9 +371,7 @@ async fn test_webhook_handler_with_invalid_json() {
     let state = create_test_state().await;
     let app = create_router_for_test(
         state.clone(),
-        Arc::new(WebhookConfig {
-            secret_token: None,
-        }),
+        Arc::new(WebhookConfig::new(None)),
         "/webhook".to_string(),
     );
 
@@ -254,3 +387,42 @@ async fn test_webhook_handler_with_invalid_json() {
     // Should return 400 Bad Request for invalid JSON
     assert_eq!(response.status(), StatusCode::BAD_REQUEST);
 }
+
+#[test]
+fn test_random_webhook_route() {
+    let (url, path) = random_webhook_route("https://example.com/webhook").unwrap();
+    assert!(path.starts_with("/webhook/"));
+    assert_eq!(path.len(), "/webhook/".len() + 32);
+    assert_eq!(url, format!("https://example.com{}", path));
+
+    let (_, other) = random_webhook_route("https://example.com/webhook/").unwrap();
+    assert_ne!(path, other);
+    assert!(random_webhook_route("not a url").is_err());
+}
+
+#[tokio::test]
+async fn test_rotate_webhook_secret_needs_admin_token() {
+    let state = create_test_state().await;
+    // Only this test reads ADMIN_TOKEN
+    std::env::set_var("ADMIN_TOKEN", "admin-token");
+    let app = create_router_for_test(
+        state.clone(),
+        Arc::new(WebhookConfig::new(Some("test-secret".to_string()))),
+        "/webhook".to_string(),
+    );
+    let rotate = |token: &str| {
+        Request::builder()
+            .method("POST")
+            .uri("/admin/rotate-webhook-secret")
+            .header(header::AUTHORIZATION, format!("Bearer {}", token))
+            .body(Body::empty())
+            .unwrap()
+    };
+
+    let response = app.clone().oneshot(rotate("wrong")).await.unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+    // Authorized, but this router never registered a webhook to rotate
+    let response = app.oneshot(rotate("admin-token")).await.unwrap();
+    assert_eq!(response.status(), StatusCode::CONFLICT);
+}